@@ -49,5 +49,6 @@ def main(Input, Output, prepare=None):
         except:
             print(json.dumps({"verdict": "INVALID_OUTPUT_FORMAT", "reason": "\n".join(logs)}))
         else:
-            ok = out.check(data, logs.append)
-            print(json.dumps({"verdict": "OK" if ok else "WRONG_ANSWER", "reason": "\n".join(logs)}))
+            res = out.check(data, logs.append)
+            ok, code = res if isinstance(res, tuple) else (res, None)
+            print(json.dumps({"verdict": "OK" if ok else "WRONG_ANSWER", "reason": "\n".join(logs), "reason_code": None if ok else code}))
@@ -69,7 +69,8 @@ class Output:
         Determine whether the output of the user's solution program (self) is correct and should be
         accepted. `data` is the value generated by the `Input.data` function for the corresponding
         challenge input. In case of an incorrect output you can use the `log` function to provide
-        additional information on why the answer was wrong.
+        additional information on why the answer was wrong. To also report a machine readable reason
+        code (e.g. "PARTIAL_OUTPUT" or "WRONG_OUTPUT"), return a tuple `(False, code)` instead.
         """
 
         if self.sum < data:
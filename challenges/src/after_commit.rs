@@ -0,0 +1,109 @@
+use std::{
+    future::Future,
+    mem,
+    sync::{Arc, Mutex},
+};
+
+use futures::future::BoxFuture;
+use poem::{Endpoint, IntoResponse, Middleware, Request, Response, Result};
+
+/// Side effects that must only happen once the database transaction they
+/// depend on has been committed, e.g. publishing events to other services.
+///
+/// Endpoints get the `AfterCommit` of their request via `Data<&AfterCommit>`.
+/// Its actions are spawned by [`AfterCommitMiddleware`] after the transaction
+/// of the request has been committed and are dropped if it is rolled back.
+#[derive(Clone, Default)]
+pub struct AfterCommit(Arc<Mutex<Vec<BoxFuture<'static, ()>>>>);
+
+impl AfterCommit {
+    /// Run `action` once the transaction has been committed.
+    pub fn defer(&self, action: impl Future<Output = ()> + Send + 'static) {
+        self.0.lock().unwrap().push(Box::pin(action));
+    }
+
+    /// Spawn all deferred actions. Call this after the transaction has been
+    /// committed.
+    pub fn spawn(&self) {
+        for action in mem::take(&mut *self.0.lock().unwrap()) {
+            tokio::spawn(action);
+        }
+    }
+}
+
+/// Provide an [`AfterCommit`] to each request and spawn its actions if the
+/// request has been successful.
+///
+/// Must wrap the `DbTransactionMiddleware`, which commits the transaction iff
+/// the response is neither a client nor a server error and fails the request
+/// if the commit fails.
+pub struct AfterCommitMiddleware;
+
+impl<E: Endpoint> Middleware<E> for AfterCommitMiddleware {
+    type Output = AfterCommitEndpoint<E>;
+
+    fn transform(&self, ep: E) -> Self::Output {
+        AfterCommitEndpoint { inner: ep }
+    }
+}
+
+pub struct AfterCommitEndpoint<E> {
+    inner: E,
+}
+
+#[poem::async_trait]
+impl<E: Endpoint> Endpoint for AfterCommitEndpoint<E> {
+    type Output = Response;
+
+    async fn call(&self, mut req: Request) -> Result<Self::Output> {
+        let after_commit = AfterCommit::default();
+        req.extensions_mut().insert(after_commit.clone());
+        let resp = self.inner.call(req).await?.into_response();
+        if !resp.status().is_client_error() && !resp.status().is_server_error() {
+            after_commit.spawn();
+        }
+        Ok(resp)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use poem::{handler, http::StatusCode, web::Data, EndpointExt};
+
+    use super::*;
+
+    static RUNS: AtomicUsize = AtomicUsize::new(0);
+
+    #[handler]
+    fn endpoint(after_commit: Data<&AfterCommit>, req: &Request) -> StatusCode {
+        after_commit.defer(async {
+            RUNS.fetch_add(1, Ordering::SeqCst);
+        });
+        if req.uri().path() == "/fail" {
+            StatusCode::CONFLICT
+        } else {
+            StatusCode::OK
+        }
+    }
+
+    #[tokio::test]
+    async fn actions_only_run_after_success() {
+        let app = endpoint.with(AfterCommitMiddleware);
+
+        app.call(Request::builder().uri_str("/fail").finish())
+            .await
+            .unwrap();
+        tokio::task::yield_now().await;
+        assert_eq!(RUNS.load(Ordering::SeqCst), 0);
+
+        app.call(Request::builder().uri_str("/ok").finish())
+            .await
+            .unwrap();
+        for _ in 0..10 {
+            tokio::task::yield_now().await;
+        }
+        assert_eq!(RUNS.load(Ordering::SeqCst), 1);
+    }
+}
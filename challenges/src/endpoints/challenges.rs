@@ -1,14 +1,24 @@
-use std::sync::Arc;
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
 
-use chrono::Utc;
+use chrono::{NaiveDateTime, Utc};
 use entity::{
-    challenges_challenge_categories, challenges_challenges, challenges_tasks,
+    challenges_challenge_categories, challenges_challenges, challenges_coding_challenges,
+    challenges_matchings, challenges_multiple_choice_quizes, challenges_questions,
+    challenges_subtasks, challenges_tasks, challenges_user_subtasks,
     sea_orm_active_enums::ChallengesSubtaskType,
 };
+use fnct::format::JsonFormatter;
 use lib::{
     auth::{AdminAuth, VerifiedUserAuth},
-    services::Services,
-    SharedState,
+    config::Config,
+    services::{
+        events::{ChallengeCreated, Event},
+        Services,
+    },
+    Cache, SharedState,
 };
 use poem::web::Data;
 use poem_ext::{db::DbTxn, patch_value::PatchValue, response, responses::ErrorResponse};
@@ -17,26 +27,57 @@ use poem_openapi::{
     payload::Json,
     OpenApi,
 };
+use sandkasten_client::SandkastenClient;
 use schemas::challenges::{
     challenges::{
         Category, Challenge, CreateCategoryRequest, CreateChallengeRequest, UpdateCategoryRequest,
         UpdateChallengeRequest,
     },
+    export::{
+        ChallengeExport, CodingChallengeExport, InvalidSubtask, InvalidSubtaskReason,
+        MatchingExport, MultipleChoiceQuestionExport, QuestionExport, SubtaskExport,
+        CHALLENGE_EXPORT_VERSION,
+    },
+    multiple_choice::split_answers,
+    sort::{SortBy, SortOrder},
     subtasks::SubtaskStats,
 };
 use sea_orm::{
-    ActiveModelTrait, ColumnTrait, DatabaseTransaction, EntityTrait, ModelTrait, QueryFilter,
-    QueryOrder, Set, Unchanged,
+    sea_query::{self, Expr, Func, SimpleExpr},
+    ActiveModelTrait, ColumnTrait, DatabaseTransaction, DbErr, EntityTrait, ModelTrait,
+    QueryFilter, QueryOrder, QuerySelect, Set, Unchanged,
 };
+use tokio::sync::Semaphore;
+use tracing::warn;
 use uuid::Uuid;
 
-use super::Tags;
-use crate::services::subtasks::{
-    get_user_subtasks, stat_subtasks, stat_subtasks_prepare, QuerySubtasksFilter,
+use super::{
+    coding_challenges::{check_challenge, random_test_seed, CheckChallenge, CheckError},
+    matchings::{check_matching, InvalidMatchingError},
+    question::{check_answers, check_regex},
+    Tags,
 };
+use crate::{
+    after_commit::AfterCommit,
+    services::{
+        coding_challenges::{get_additional_solutions, set_additional_solutions},
+        judge::{get_environments, get_executor_config, Judge},
+        subtasks::{
+            get_subtask_tags, get_user_subtasks, set_subtask_tags, solved_count, sort_order,
+            stat_subtasks, stat_subtasks_prepare, QuerySubtasksFilter,
+        },
+    },
+};
+
+/// Maximum number of challenges returned by `list_similar_challenges`.
+const SIMILAR_CHALLENGES: u64 = 5;
 
 pub struct Challenges {
     pub state: Arc<SharedState>,
+    pub config: Arc<Config>,
+    pub sandkasten: SandkastenClient,
+    pub sandkasten_lock: Arc<Semaphore>,
+    pub judge_cache: Cache<JsonFormatter>,
 }
 
 #[OpenApi(tag = "Tags::Challenges")]
@@ -120,12 +161,14 @@ impl Challenges {
         db: Data<&DbTxn>,
         _auth: AdminAuth,
     ) -> CreateCategory::Response<AdminAuth> {
+        let now = Utc::now().naive_utc();
         CreateCategory::ok(
             challenges_challenge_categories::ActiveModel {
                 id: Set(Uuid::new_v4()),
                 title: Set(data.0.title),
                 description: Set(data.0.description),
-                creation_timestamp: Set(Utc::now().naive_utc()),
+                creation_timestamp: Set(now),
+                updated_at: Set(now),
             }
             .insert(&***db)
             .await?
@@ -149,6 +192,7 @@ impl Challenges {
                     title: data.0.title.update(category.title),
                     description: data.0.description.update(category.description),
                     creation_timestamp: Unchanged(category.creation_timestamp),
+                    updated_at: Set(Utc::now().naive_utc()),
                 }
                 .update(&***db)
                 .await?
@@ -178,19 +222,53 @@ impl Challenges {
     }
 
     /// List all challenges in a category.
+    #[allow(clippy::too_many_arguments)]
     #[oai(path = "/categories/:category_id/challenges", method = "get")]
     async fn list_challenges(
         &self,
         category_id: Path<Uuid>,
         /// Filter by challenge title
         title: Query<Option<String>>,
+        /// Sort challenges by this property (default: position).
+        sort: Query<Option<SortBy>>,
+        /// The sort order (default: ascending).
+        order: Query<Option<SortOrder>>,
+        /// Whether to include archived challenges (admins only).
+        include_archived: Query<Option<bool>>,
+        /// Whether to include unlisted challenges (admins only).
+        include_unlisted: Query<Option<bool>>,
         db: Data<&DbTxn>,
-        _auth: VerifiedUserAuth,
+        auth: VerifiedUserAuth,
     ) -> ListChallenges::Response<VerifiedUserAuth> {
         let mut query = challenges_challenges::Entity::find()
             .find_also_related(challenges_tasks::Entity)
-            .filter(challenges_challenges::Column::CategoryId.eq(category_id.0))
-            .order_by_asc(challenges_challenges::Column::Title);
+            .filter(challenges_challenges::Column::CategoryId.eq(category_id.0));
+        if !(auth.0.admin && include_archived.0.unwrap_or(false)) {
+            query = query.filter(challenges_challenges::Column::Archived.eq(false));
+        }
+        if !(auth.0.admin && include_unlisted.0.unwrap_or(false)) {
+            query = query.filter(challenges_challenges::Column::Listed.eq(true));
+        }
+        let order = sort_order(order.0);
+        query = match sort.0.unwrap_or(SortBy::Position) {
+            SortBy::Position => query.order_by(challenges_challenges::Column::Position, order),
+            SortBy::Title => query.order_by(challenges_challenges::Column::Title, order),
+            SortBy::Created => query.order_by(challenges_tasks::Column::CreationTimestamp, order),
+            SortBy::Xp => query.order_by(challenge_xp(), order),
+            SortBy::Solved => query.order_by(
+                solved_count(
+                    Expr::col((
+                        challenges_user_subtasks::Entity,
+                        challenges_user_subtasks::Column::SubtaskId,
+                    ))
+                    .in_subquery(challenge_subtasks(Expr::col(
+                        challenges_subtasks::Column::Id,
+                    ))),
+                ),
+                order,
+            ),
+        }
+        .order_by_asc(challenges_challenges::Column::Title);
         if let Some(title) = title.0 {
             query = query.filter(challenges_challenges::Column::Title.contains(title));
         }
@@ -205,6 +283,8 @@ impl Challenges {
     }
 
     /// Get a challenge by id.
+    ///
+    /// Archived challenges are only returned to admins.
     #[oai(
         path = "/categories/:category_id/challenges/:challenge_id",
         method = "get"
@@ -214,11 +294,78 @@ impl Challenges {
         category_id: Path<Uuid>,
         challenge_id: Path<Uuid>,
         db: Data<&DbTxn>,
-        _auth: VerifiedUserAuth,
+        auth: VerifiedUserAuth,
     ) -> GetChallenge::Response<VerifiedUserAuth> {
         match get_challenge(&db, category_id.0, challenge_id.0).await? {
-            Some((challenge, task)) => GetChallenge::ok(Challenge::from(challenge, task)),
-            None => GetChallenge::challenge_not_found(),
+            Some((challenge, task)) if auth.0.admin || !challenge.archived => {
+                GetChallenge::ok(Challenge::from(challenge, task))
+            }
+            _ => GetChallenge::challenge_not_found(),
+        }
+    }
+
+    /// List challenges that share skills with a challenge.
+    ///
+    /// The challenges are ordered by the number of shared skills. Archived and
+    /// unlisted challenges are never included.
+    #[oai(
+        path = "/categories/:category_id/challenges/:challenge_id/similar",
+        method = "get"
+    )]
+    async fn list_similar_challenges(
+        &self,
+        category_id: Path<Uuid>,
+        challenge_id: Path<Uuid>,
+        db: Data<&DbTxn>,
+        _auth: VerifiedUserAuth,
+    ) -> ListSimilarChallenges::Response<VerifiedUserAuth> {
+        let Some((challenge, _)) = get_challenge(&db, category_id.0, challenge_id.0).await? else {
+            return ListSimilarChallenges::challenge_not_found();
+        };
+
+        let skills = challenge.skill_ids;
+        ListSimilarChallenges::ok(
+            challenges_challenges::Entity::find()
+                .find_also_related(challenges_tasks::Entity)
+                .filter(challenges_challenges::Column::TaskId.ne(challenge.task_id))
+                .filter(challenges_challenges::Column::Archived.eq(false))
+                .filter(challenges_challenges::Column::Listed.eq(true))
+                .filter(Expr::cust_with_values(
+                    r#""challenges_challenges"."skill_ids" && $1::text[]"#,
+                    [skills.clone()],
+                ))
+                .order_by_desc(Expr::cust_with_values(
+                    r#"cardinality(array(select unnest("challenges_challenges"."skill_ids") intersect select unnest($1::text[])))"#,
+                    [skills],
+                ))
+                .order_by_asc(challenges_challenges::Column::Title)
+                .limit(SIMILAR_CHALLENGES)
+                .all(&***db)
+                .await?
+                .into_iter()
+                .filter_map(|(challenge, task)| Some(Challenge::from(challenge, task?)))
+                .collect(),
+        )
+    }
+
+    /// Export a challenge including all of its subtasks.
+    #[oai(
+        path = "/categories/:category_id/challenges/:challenge_id/export",
+        method = "get"
+    )]
+    async fn export_challenge(
+        &self,
+        category_id: Path<Uuid>,
+        challenge_id: Path<Uuid>,
+        db: Data<&DbTxn>,
+        _auth: AdminAuth,
+    ) -> ExportChallenge::Response<AdminAuth> {
+        match get_challenge(&db, category_id.0, challenge_id.0).await? {
+            Some((challenge, task)) => {
+                let subtasks = export_subtasks(&db, task.id).await?;
+                ExportChallenge::ok(ChallengeExport::from(challenge, subtasks))
+            }
+            None => ExportChallenge::challenge_not_found(),
         }
     }
 
@@ -229,6 +376,7 @@ impl Challenges {
         category_id: Path<Uuid>,
         data: Json<CreateChallengeRequest>,
         db: Data<&DbTxn>,
+        after_commit: Data<&AfterCommit>,
         auth: AdminAuth,
     ) -> CreateChallenge::Response<AdminAuth> {
         let category = match get_category(&db, category_id.0).await? {
@@ -240,6 +388,9 @@ impl Challenges {
         if !not_found.is_empty() {
             return CreateChallenge::skills_not_found(not_found.into_iter().cloned().collect());
         }
+        if !valid_skill_weights(&data.0.skills, data.0.skill_weights.as_deref()) {
+            return CreateChallenge::invalid_skill_weights();
+        }
 
         let task = challenges_tasks::ActiveModel {
             id: Set(Uuid::new_v4()),
@@ -253,15 +404,104 @@ impl Challenges {
             task_id: Set(task.id),
             category_id: Set(category.id),
             skill_ids: Set(data.0.skills),
+            skill_weights: Set(data
+                .0
+                .skill_weights
+                .map(|weights| weights.into_iter().map(Into::into).collect())),
             title: Set(data.0.title),
             description: Set(data.0.description),
+            archived: Set(false),
+            position: Set(next_position(&db, category.id).await?),
+            updated_at: Set(task.creation_timestamp),
+            listed: Set(data.0.listed),
         }
         .insert(&***db)
         .await?;
 
+        publish_challenge_created(&after_commit, &self.state.services, &challenge, &task);
+
         CreateChallenge::ok(Challenge::from(challenge, task))
     }
 
+    /// Create a new challenge from an exported challenge.
+    ///
+    /// All subtasks are validated (including running the evaluators of coding
+    /// challenges) before the challenge is created. If any subtask is invalid,
+    /// nothing is imported.
+    #[oai(path = "/categories/:category_id/challenges/import", method = "post")]
+    async fn import_challenge(
+        &self,
+        category_id: Path<Uuid>,
+        data: Json<ChallengeExport>,
+        db: Data<&DbTxn>,
+        after_commit: Data<&AfterCommit>,
+        auth: AdminAuth,
+    ) -> ImportChallenge::Response<AdminAuth> {
+        let data = data.0;
+        if data.version != CHALLENGE_EXPORT_VERSION {
+            return ImportChallenge::unsupported_version(CHALLENGE_EXPORT_VERSION);
+        }
+
+        let category = match get_category(&db, category_id.0).await? {
+            Some(category) => category,
+            None => return ImportChallenge::category_not_found(),
+        };
+
+        let not_found = check_skills(&self.state.services, &data.skills).await?;
+        if !not_found.is_empty() {
+            return ImportChallenge::skills_not_found(not_found.into_iter().cloned().collect());
+        }
+        if !valid_skill_weights(&data.skills, data.skill_weights.as_deref()) {
+            return ImportChallenge::invalid_skill_weights();
+        }
+
+        let subtask_ids = data
+            .subtasks
+            .iter()
+            .map(|_| Uuid::new_v4())
+            .collect::<Vec<_>>();
+        for (index, (subtask, &subtask_id)) in data.subtasks.iter().zip(&subtask_ids).enumerate() {
+            if let Err(reason) = self.validate_subtask(subtask, subtask_id).await? {
+                return ImportChallenge::invalid_subtask(InvalidSubtask { index, reason });
+            }
+        }
+
+        let now = Utc::now().naive_utc();
+        let task = challenges_tasks::ActiveModel {
+            id: Set(Uuid::new_v4()),
+            creator: Set(auth.0.id),
+            creation_timestamp: Set(now),
+        }
+        .insert(&***db)
+        .await?;
+
+        let challenge = challenges_challenges::ActiveModel {
+            task_id: Set(task.id),
+            category_id: Set(category.id),
+            skill_ids: Set(data.skills),
+            skill_weights: Set(data
+                .skill_weights
+                .map(|weights| weights.into_iter().map(Into::into).collect())),
+            title: Set(data.title),
+            description: Set(data.description),
+            archived: Set(false),
+            position: Set(next_position(&db, category.id).await?),
+            updated_at: Set(task.creation_timestamp),
+            listed: Set(data.listed),
+        }
+        .insert(&***db)
+        .await?;
+
+        for (subtask, subtask_id) in data.subtasks.into_iter().zip(subtask_ids) {
+            self.import_subtask(&db, subtask, subtask_id, &task, auth.0.id, now)
+                .await?;
+        }
+
+        publish_challenge_created(&after_commit, &self.state.services, &challenge, &task);
+
+        ImportChallenge::ok(Challenge::from(challenge, task))
+    }
+
     /// Update a challenge.
     #[oai(
         path = "/categories/:category_id/challenges/:challenge_id",
@@ -291,12 +531,36 @@ impl Challenges {
                         );
                     }
                 }
+                let skill_weights = match data.0.skill_weights {
+                    PatchValue::Set(weights) => {
+                        weights.map(|weights| weights.into_iter().map(Into::into).collect())
+                    }
+                    PatchValue::Unchanged => challenge.skill_weights.clone(),
+                };
+                let valid = valid_skill_weights(
+                    data.0.skills.get_new(&challenge.skill_ids),
+                    skill_weights.as_deref(),
+                );
+                if !valid {
+                    return UpdateChallenge::invalid_skill_weights();
+                }
                 let challenge = challenges_challenges::ActiveModel {
                     task_id: Unchanged(challenge.task_id),
                     category_id: data.0.category.update(challenge.category_id),
                     skill_ids: data.0.skills.update(challenge.skill_ids),
+                    skill_weights: Set(skill_weights),
                     title: data.0.title.update(challenge.title),
                     description: data.0.description.update(challenge.description),
+                    archived: data.0.archived.update(challenge.archived),
+                    // move the challenge to the end of its new category
+                    position: match data.0.category {
+                        PatchValue::Set(category) if category != challenge.category_id => {
+                            Set(next_position(&db, category).await?)
+                        }
+                        _ => Unchanged(challenge.position),
+                    },
+                    updated_at: Set(Utc::now().naive_utc()),
+                    listed: data.0.listed.update(challenge.listed),
                 }
                 .update(&***db)
                 .await?;
@@ -313,7 +577,52 @@ impl Challenges {
         }
     }
 
-    /// Delete a challenge.
+    /// Change the order of the challenges in a category.
+    ///
+    /// The request must contain the ids of all challenges in the category
+    /// (including archived ones) in the new order.
+    #[oai(path = "/categories/:category_id/challenges/order", method = "put")]
+    async fn reorder_challenges(
+        &self,
+        category_id: Path<Uuid>,
+        data: Json<Vec<Uuid>>,
+        db: Data<&DbTxn>,
+        _auth: AdminAuth,
+    ) -> ReorderChallenges::Response<AdminAuth> {
+        if get_category(&db, category_id.0).await?.is_none() {
+            return ReorderChallenges::category_not_found();
+        }
+
+        let challenges = challenges_challenges::Entity::find()
+            .filter(challenges_challenges::Column::CategoryId.eq(category_id.0))
+            .all(&***db)
+            .await?;
+        let expected = challenges.iter().map(|c| c.task_id).collect::<HashSet<_>>();
+        let actual = data.0.iter().copied().collect::<HashSet<_>>();
+        if actual.len() != data.0.len() || actual != expected {
+            return ReorderChallenges::invalid_challenges();
+        }
+
+        let now = Utc::now().naive_utc();
+        for (position, task_id) in data.0.into_iter().enumerate() {
+            challenges_challenges::ActiveModel {
+                task_id: Unchanged(task_id),
+                position: Set(position as _),
+                updated_at: Set(now),
+                ..Default::default()
+            }
+            .update(&***db)
+            .await?;
+        }
+
+        ReorderChallenges::ok()
+    }
+
+    /// Archive or delete a challenge.
+    ///
+    /// By default the challenge is only archived and hidden from normal users.
+    /// Set `permanent` to delete the challenge including all of its subtasks
+    /// and submissions.
     #[oai(
         path = "/categories/:category_id/challenges/:challenge_id",
         method = "delete"
@@ -322,19 +631,273 @@ impl Challenges {
         &self,
         category_id: Path<Uuid>,
         challenge_id: Path<Uuid>,
+        /// Whether to delete the challenge permanently instead of archiving it.
+        permanent: Query<Option<bool>>,
         db: Data<&DbTxn>,
         _auth: AdminAuth,
     ) -> DeleteChallenge::Response<AdminAuth> {
         match get_challenge(&db, category_id.0, challenge_id.0).await? {
-            Some((_, task)) => {
+            Some((_, task)) if permanent.0.unwrap_or(false) => {
                 task.delete(&***db).await?;
                 DeleteChallenge::ok()
             }
+            Some((challenge, _)) => {
+                challenges_challenges::ActiveModel {
+                    task_id: Unchanged(challenge.task_id),
+                    archived: Set(true),
+                    updated_at: Set(Utc::now().naive_utc()),
+                    ..Default::default()
+                }
+                .update(&***db)
+                .await?;
+                DeleteChallenge::ok()
+            }
             None => DeleteChallenge::challenge_not_found(),
         }
     }
 }
 
+impl Challenges {
+    /// Check that an imported subtask is valid.
+    async fn validate_subtask(
+        &self,
+        subtask: &SubtaskExport,
+        subtask_id: Uuid,
+    ) -> Result<Result<(), InvalidSubtaskReason>, ErrorResponse> {
+        Ok(match subtask {
+            SubtaskExport::CodingChallenge(CodingChallengeExport { challenge, .. }) => {
+                let limits = self
+                    .config
+                    .challenges
+                    .coding_challenges
+                    .content_limits(true);
+                if challenge.oversized_field(limits).is_some() {
+                    return Ok(Err(InvalidSubtaskReason::ContentTooLarge));
+                }
+                let config = get_executor_config(&self.judge_cache, &self.sandkasten).await?;
+                if challenge.time_limit > config.time_limit {
+                    return Ok(Err(InvalidSubtaskReason::TimeLimitExceeded));
+                }
+                if challenge.memory_limit > config.memory_limit {
+                    return Ok(Err(InvalidSubtaskReason::MemoryLimitExceeded));
+                }
+                let environments = get_environments(
+                    &self.judge_cache,
+                    &self.sandkasten,
+                    self.config
+                        .challenges
+                        .coding_challenges
+                        .environments_cache_ttl(),
+                )
+                .await?;
+                if !environments.contains_key(&challenge.evaluator_environment) {
+                    return Ok(Err(InvalidSubtaskReason::EnvironmentNotFound));
+                }
+                check_challenge(CheckChallenge {
+                    judge: self.get_judge(&challenge.evaluator, &challenge.evaluator_environment),
+                    challenge_id: subtask_id,
+                    solution_environment: &challenge.solution_environment,
+                    solution_code: &challenge.solution_code,
+                    additional_solutions: &challenge.additional_solutions,
+                    time_limit: challenge.time_limit,
+                    memory_limit: challenge.memory_limit,
+                    static_tests: challenge.static_tests,
+                    random_tests: challenge.random_tests,
+                    test_seed: challenge.test_seed.unwrap_or_else(random_test_seed),
+                    max_testcases: self.config.challenges.coding_challenges.max_testcases,
+                })
+                .await?
+                .map_err(|err| match err {
+                    CheckError::NoExamples => InvalidSubtaskReason::NoExamples,
+                    CheckError::EnvironmentNotFound => InvalidSubtaskReason::EnvironmentNotFound,
+                    CheckError::EvaluatorFailed(_) => InvalidSubtaskReason::EvaluatorFailed,
+                    CheckError::EvaluatorTimeout(_) => InvalidSubtaskReason::EvaluatorTimeout,
+                    CheckError::InvalidOutput(_) => InvalidSubtaskReason::InvalidOutput,
+                    CheckError::TestcaseFailed(_) => InvalidSubtaskReason::TestcaseFailed,
+                    CheckError::TooManyTestcases(_) => InvalidSubtaskReason::TooManyTestcases,
+                })
+            }
+            SubtaskExport::Matching(MatchingExport { matching, .. }) => {
+                check_matching(&matching.left, &matching.right, &matching.solution).map_err(|err| {
+                    match err {
+                        InvalidMatchingError::LeftRightDifferentLength => {
+                            InvalidSubtaskReason::LeftRightDifferentLength
+                        }
+                        InvalidMatchingError::SolutionDifferentLength => {
+                            InvalidSubtaskReason::SolutionDifferentLength
+                        }
+                        InvalidMatchingError::InvalidIndex(_) => InvalidSubtaskReason::InvalidIndex,
+                        InvalidMatchingError::RightEntriesNotMatched(_) => {
+                            InvalidSubtaskReason::RightEntriesNotMatched
+                        }
+                        InvalidMatchingError::DuplicateEntries(..) => {
+                            InvalidSubtaskReason::DuplicateEntries
+                        }
+                    }
+                })
+            }
+            SubtaskExport::MultipleChoiceQuestion(MultipleChoiceQuestionExport { mcq, .. }) => {
+                let correct_cnt = mcq.answers.iter().filter(|x| x.correct).count();
+                if mcq.single_choice && correct_cnt != 1 {
+                    Err(InvalidSubtaskReason::InvalidSingleChoice)
+                } else if correct_cnt == 0 {
+                    Err(InvalidSubtaskReason::InvalidMultipleChoice)
+                } else {
+                    Ok(())
+                }
+            }
+            SubtaskExport::Question(QuestionExport { question, .. }) => {
+                if question.match_regex {
+                    if !check_regex(&question.answers, question.case_sensitive) {
+                        return Ok(Err(InvalidSubtaskReason::InvalidRegex));
+                    }
+                } else if !check_answers(
+                    &question.answers,
+                    question.ascii_letters,
+                    question.digits,
+                    question.punctuation,
+                ) {
+                    return Ok(Err(InvalidSubtaskReason::InvalidChar));
+                }
+                Ok(())
+            }
+        })
+    }
+
+    /// Create an imported subtask which has already been validated.
+    async fn import_subtask(
+        &self,
+        db: &DatabaseTransaction,
+        subtask: SubtaskExport,
+        subtask_id: Uuid,
+        task: &challenges_tasks::Model,
+        creator: Uuid,
+        now: NaiveDateTime,
+    ) -> Result<(), DbErr> {
+        let (ty, data, enabled, retired) = match &subtask {
+            SubtaskExport::CodingChallenge(x) => (
+                ChallengesSubtaskType::CodingChallenge,
+                &x.challenge.subtask,
+                x.enabled,
+                x.retired,
+            ),
+            SubtaskExport::Matching(x) => (
+                ChallengesSubtaskType::Matching,
+                &x.matching.subtask,
+                x.enabled,
+                x.retired,
+            ),
+            SubtaskExport::MultipleChoiceQuestion(x) => (
+                ChallengesSubtaskType::MultipleChoiceQuestion,
+                &x.mcq.subtask,
+                x.enabled,
+                x.retired,
+            ),
+            SubtaskExport::Question(x) => (
+                ChallengesSubtaskType::Question,
+                &x.question.subtask,
+                x.enabled,
+                x.retired,
+            ),
+        };
+        let quizzes = &self.config.challenges.quizzes;
+        challenges_subtasks::ActiveModel {
+            id: Set(subtask_id),
+            task_id: Set(task.id),
+            ty: Set(ty),
+            creator: Set(creator),
+            creation_timestamp: Set(now),
+            xp: Set(data.xp.unwrap_or(quizzes.max_xp) as _),
+            coins: Set(data.coins.unwrap_or(quizzes.max_coins) as _),
+            enabled: Set(enabled),
+            retired: Set(retired),
+            max_attempts: Set(data.max_attempts.map(|x| x as _)),
+            disabled_timestamp: Set((!enabled).then_some(now)),
+        }
+        .insert(db)
+        .await?;
+        set_subtask_tags(db, subtask_id, data.tags.clone()).await?;
+
+        match subtask {
+            SubtaskExport::CodingChallenge(CodingChallengeExport { challenge, .. }) => {
+                challenges_coding_challenges::ActiveModel {
+                    subtask_id: Set(subtask_id),
+                    time_limit: Set(challenge.time_limit as _),
+                    memory_limit: Set(challenge.memory_limit as _),
+                    static_tests: Set(challenge.static_tests as _),
+                    random_tests: Set(challenge.random_tests as _),
+                    test_seed: Set(challenge.test_seed.map(|x| x as _)),
+                    reveal_solution_on_solve: Set(challenge.reveal_solution_on_solve),
+                    evaluator: Set(challenge.evaluator),
+                    evaluator_environment: Set(challenge.evaluator_environment),
+                    description: Set(challenge.description),
+                    solution_environment: Set(challenge.solution_environment),
+                    solution_code: Set(challenge.solution_code),
+                }
+                .insert(db)
+                .await?;
+                set_additional_solutions(db, subtask_id, challenge.additional_solutions).await?;
+            }
+            SubtaskExport::Matching(MatchingExport { matching, .. }) => {
+                challenges_matchings::ActiveModel {
+                    subtask_id: Set(subtask_id),
+                    left: Set(matching.left),
+                    right: Set(matching.right),
+                    solution: Set(matching.solution.into_iter().map(|x| x as _).collect()),
+                }
+                .insert(db)
+                .await?;
+            }
+            SubtaskExport::MultipleChoiceQuestion(MultipleChoiceQuestionExport { mcq, .. }) => {
+                let (answers, correct) = split_answers(mcq.answers);
+                challenges_multiple_choice_quizes::ActiveModel {
+                    subtask_id: Set(subtask_id),
+                    question: Set(mcq.question),
+                    answers: Set(answers),
+                    correct_answers: Set(correct),
+                    single_choice: Set(mcq.single_choice),
+                    shuffle: Set(mcq.shuffle),
+                }
+                .insert(db)
+                .await?;
+            }
+            SubtaskExport::Question(QuestionExport { question, .. }) => {
+                challenges_questions::ActiveModel {
+                    subtask_id: Set(subtask_id),
+                    question: Set(question.question),
+                    answers: Set(question.answers),
+                    match_regex: Set(question.match_regex),
+                    case_sensitive: Set(question.case_sensitive),
+                    ascii_letters: Set(question.ascii_letters),
+                    digits: Set(question.digits),
+                    punctuation: Set(question.punctuation),
+                    blocks: Set(question.blocks),
+                }
+                .insert(db)
+                .await?;
+            }
+        }
+        Ok(())
+    }
+
+    fn get_judge<'a>(&'a self, evaluator: &'a str, evaluator_environment: &'a str) -> Judge<'a> {
+        Judge {
+            sandkasten: &self.sandkasten,
+            sandkasten_lock: &self.sandkasten_lock,
+            evaluator,
+            evaluator_environment,
+            cache: &self.judge_cache,
+            evaluator_timeout: self.config.challenges.coding_challenges.evaluator_timeout,
+            retry: self.config.challenges.coding_challenges.sandkasten_retry,
+            cache_ttl: self
+                .config
+                .challenges
+                .coding_challenges
+                .evaluator_cache_ttl(),
+        }
+    }
+}
+
 response!(ListCategories = {
     Ok(200) => Vec<Category>,
 });
@@ -375,12 +938,40 @@ response!(GetChallenge = {
     ChallengeNotFound(404, error),
 });
 
+response!(ListSimilarChallenges = {
+    Ok(200) => Vec<Challenge>,
+    /// Challenge does not exist.
+    ChallengeNotFound(404, error),
+});
+
+response!(ExportChallenge = {
+    Ok(200) => ChallengeExport,
+    /// Challenge does not exist.
+    ChallengeNotFound(404, error),
+});
+
 response!(CreateChallenge = {
     Ok(201) => Challenge,
     /// Category does not exist.
     CategoryNotFound(404, error),
     /// One or more skills do not exist.
     SkillsNotFound(404, error) => Vec<String>,
+    /// The number of skill weights does not match the number of skills or all weights are zero.
+    InvalidSkillWeights(400, error),
+});
+
+response!(ImportChallenge = {
+    Ok(201) => Challenge,
+    /// The version of the export format is not supported.
+    UnsupportedVersion(400, error) => u32,
+    /// Category does not exist.
+    CategoryNotFound(404, error),
+    /// One or more skills do not exist.
+    SkillsNotFound(404, error) => Vec<String>,
+    /// The number of skill weights does not match the number of skills or all weights are zero.
+    InvalidSkillWeights(400, error),
+    /// One of the subtasks is invalid.
+    InvalidSubtask(400, error) => InvalidSubtask,
 });
 
 response!(UpdateChallenge = {
@@ -391,6 +982,16 @@ response!(UpdateChallenge = {
     CategoryNotFound(404, error),
     /// One or more skills do not exist.
     SkillsNotFound(404, error) => Vec<String>,
+    /// The number of skill weights does not match the number of skills or all weights are zero.
+    InvalidSkillWeights(400, error),
+});
+
+response!(ReorderChallenges = {
+    Ok(200),
+    /// Category does not exist.
+    CategoryNotFound(404, error),
+    /// The list of challenges does not match the challenges in the category.
+    InvalidChallenges(400, error),
 });
 
 response!(DeleteChallenge = {
@@ -410,6 +1011,19 @@ async fn get_category(
     )
 }
 
+/// Return the position for a new challenge at the end of a category.
+async fn next_position(db: &DatabaseTransaction, category_id: Uuid) -> Result<i32, DbErr> {
+    Ok(challenges_challenges::Entity::find()
+        .select_only()
+        .expr(Expr::col(challenges_challenges::Column::Position).max())
+        .filter(challenges_challenges::Column::CategoryId.eq(category_id))
+        .into_tuple::<Option<i32>>()
+        .one(db)
+        .await?
+        .flatten()
+        .map_or(0, |position| position + 1))
+}
+
 async fn get_challenge(
     db: &DatabaseTransaction,
     category_id: Uuid,
@@ -428,6 +1042,85 @@ async fn get_challenge(
     )
 }
 
+/// Return all subtasks of a task in the order in which they have been created.
+async fn export_subtasks(
+    db: &DatabaseTransaction,
+    task_id: Uuid,
+) -> Result<Vec<SubtaskExport>, DbErr> {
+    let subtasks = challenges_subtasks::Entity::find()
+        .filter(challenges_subtasks::Column::TaskId.eq(task_id))
+        .order_by_asc(challenges_subtasks::Column::CreationTimestamp)
+        .order_by_asc(challenges_subtasks::Column::Id)
+        .all(db)
+        .await?;
+    let ids = subtasks.iter().map(|x| x.id).collect::<Vec<_>>();
+    let mut tags = get_subtask_tags(db, ids.iter().copied()).await?;
+
+    let mut coding_challenges = challenges_coding_challenges::Entity::find()
+        .filter(challenges_coding_challenges::Column::SubtaskId.is_in(ids.iter().copied()))
+        .all(db)
+        .await?
+        .into_iter()
+        .map(|x| (x.subtask_id, x))
+        .collect::<HashMap<_, _>>();
+    let mut additional_solutions = get_additional_solutions(db, ids.iter().copied()).await?;
+    let mut matchings = challenges_matchings::Entity::find()
+        .filter(challenges_matchings::Column::SubtaskId.is_in(ids.iter().copied()))
+        .all(db)
+        .await?
+        .into_iter()
+        .map(|x| (x.subtask_id, x))
+        .collect::<HashMap<_, _>>();
+    let mut mcqs = challenges_multiple_choice_quizes::Entity::find()
+        .filter(challenges_multiple_choice_quizes::Column::SubtaskId.is_in(ids.iter().copied()))
+        .all(db)
+        .await?
+        .into_iter()
+        .map(|x| (x.subtask_id, x))
+        .collect::<HashMap<_, _>>();
+    let mut questions = challenges_questions::Entity::find()
+        .filter(challenges_questions::Column::SubtaskId.is_in(ids.iter().copied()))
+        .all(db)
+        .await?
+        .into_iter()
+        .map(|x| (x.subtask_id, x))
+        .collect::<HashMap<_, _>>();
+
+    Ok(subtasks
+        .into_iter()
+        .filter_map(|subtask| {
+            let tags = tags.remove(&subtask.id).unwrap_or_default();
+            Some(match subtask.ty {
+                ChallengesSubtaskType::CodingChallenge => {
+                    SubtaskExport::CodingChallenge(CodingChallengeExport::from(
+                        coding_challenges.remove(&subtask.id)?,
+                        additional_solutions.remove(&subtask.id).unwrap_or_default(),
+                        subtask,
+                        tags,
+                    ))
+                }
+                ChallengesSubtaskType::Matching => SubtaskExport::Matching(MatchingExport::from(
+                    matchings.remove(&subtask.id)?,
+                    subtask,
+                    tags,
+                )),
+                ChallengesSubtaskType::MultipleChoiceQuestion => {
+                    SubtaskExport::MultipleChoiceQuestion(MultipleChoiceQuestionExport::from(
+                        mcqs.remove(&subtask.id)?,
+                        subtask,
+                        tags,
+                    ))
+                }
+                ChallengesSubtaskType::Question => SubtaskExport::Question(QuestionExport::from(
+                    questions.remove(&subtask.id)?,
+                    subtask,
+                    tags,
+                )),
+            })
+        })
+        .collect())
+}
+
 async fn check_skills<'a>(
     services: &'_ Services,
     skill_ids: &'a [String],
@@ -438,3 +1131,70 @@ async fn check_skills<'a>(
         .filter(|&x| !skills.contains_key(x))
         .collect())
 }
+
+/// Publish a `challenge_created` event once the transaction has been
+/// committed. Delivery is best-effort, so failures are only logged. Unlisted
+/// challenges are not announced.
+fn publish_challenge_created(
+    after_commit: &AfterCommit,
+    services: &Services,
+    challenge: &challenges_challenges::Model,
+    task: &challenges_tasks::Model,
+) {
+    if !challenge.listed {
+        return;
+    }
+    let event = Event::ChallengeCreated(ChallengeCreated {
+        challenge_id: challenge.task_id,
+        category_id: challenge.category_id,
+        creator: task.creator,
+    });
+    let events = services.events.clone();
+    let challenge_id = challenge.task_id;
+    after_commit.defer(async move {
+        if let Err(err) = events.publish(event).await {
+            warn!("failed to publish challenge_created event for {challenge_id}: {err}");
+        }
+    });
+}
+
+/// Check that there is exactly one weight per skill and that at least one of
+/// the weights is positive.
+fn valid_skill_weights<T: Copy + Into<i64>>(skills: &[String], weights: Option<&[T]>) -> bool {
+    weights.is_none_or(|weights| {
+        weights.len() == skills.len() && weights.iter().any(|&weight| weight.into() > 0)
+    })
+}
+
+/// Return a subquery that selects `column` from all subtasks of the challenge.
+fn challenge_subtasks(column: impl Into<SimpleExpr>) -> sea_query::SelectStatement {
+    sea_query::Query::select()
+        .expr(column)
+        .from(challenges_subtasks::Entity)
+        .and_where(
+            Expr::col((
+                challenges_subtasks::Entity,
+                challenges_subtasks::Column::TaskId,
+            ))
+            .equals((
+                challenges_challenges::Entity,
+                challenges_challenges::Column::TaskId,
+            )),
+        )
+        .to_owned()
+}
+
+/// Return a subquery that computes the total number of xp of a challenge.
+fn challenge_xp() -> SimpleExpr {
+    Func::coalesce([
+        SimpleExpr::SubQuery(
+            None,
+            Box::new(
+                challenge_subtasks(Expr::col(challenges_subtasks::Column::Xp).sum())
+                    .into_sub_query_statement(),
+            ),
+        ),
+        Expr::val(0).into(),
+    ])
+    .into()
+}
@@ -1,8 +1,14 @@
+use poem_ext::response;
 use poem_openapi::{payload::PlainText, OpenApi};
+use schemas::challenges::coding_challenges::EvaluatorInfo;
+use sha2::{Digest, Sha256};
 
 use crate::{
     endpoints::Tags,
-    services::judge::{EVALUATOR_LIBRARY, EVALUATOR_TEMPLATE},
+    services::judge::{
+        EVALUATOR_LIBRARY, EVALUATOR_LIBRARY_VERSION, EVALUATOR_TEMPLATE,
+        EVALUATOR_TEMPLATE_VERSION,
+    },
 };
 
 pub struct Api;
@@ -20,4 +26,56 @@ impl Api {
     async fn get_evaluator_lib(&self) -> PlainText<&'static str> {
         PlainText(EVALUATOR_LIBRARY)
     }
+
+    /// Return the versions and hashes of the evaluator template and library.
+    ///
+    /// Clients can use this to check whether their copies are up to date.
+    #[oai(path = "/coding_challenges/evaluator/info", method = "get")]
+    async fn get_evaluator_info(&self) -> GetEvaluatorInfo::Response {
+        GetEvaluatorInfo::ok(EvaluatorInfo {
+            lib_version: EVALUATOR_LIBRARY_VERSION,
+            template_version: EVALUATOR_TEMPLATE_VERSION,
+            lib_sha256: sha256(EVALUATOR_LIBRARY),
+            template_sha256: sha256(EVALUATOR_TEMPLATE),
+        })
+    }
+}
+
+response!(GetEvaluatorInfo = {
+    Ok(200) => EvaluatorInfo,
+});
+
+fn sha256(data: &str) -> String {
+    format!("{:x}", Sha256::digest(data))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn evaluator_versions() {
+        assert_eq!(
+            (
+                EVALUATOR_LIBRARY_VERSION,
+                sha256(EVALUATOR_LIBRARY).as_str()
+            ),
+            (
+                2,
+                "66f9207bf53052f4d4758b79da296b03bd536f3725c1e7f16976b81e785e8fb4"
+            ),
+            "lib.py has changed, bump EVALUATOR_LIBRARY_VERSION and update this test"
+        );
+        assert_eq!(
+            (
+                EVALUATOR_TEMPLATE_VERSION,
+                sha256(EVALUATOR_TEMPLATE).as_str()
+            ),
+            (
+                2,
+                "cf3706ea5dadfecba67b69103defae097b3e5966f4d1ea5613c9a08ca7fa539f"
+            ),
+            "template.py has changed, bump EVALUATOR_TEMPLATE_VERSION and update this test"
+        );
+    }
 }
@@ -1,43 +1,57 @@
 use std::sync::Arc;
 
 use chrono::{DateTime, Utc};
-use entity::{challenges_coding_challenges, sea_orm_active_enums::ChallengesSubtaskType};
+use entity::{
+    challenges_coding_challenge_result, challenges_coding_challenge_submissions,
+    challenges_coding_challenges,
+    sea_orm_active_enums::{ChallengesSubtaskType, ChallengesVerdict},
+};
 use fnct::format::JsonFormatter;
 use lib::{
-    auth::{AdminAuth, VerifiedUserAuth},
+    auth::{AdminAuth, User, VerifiedUserAuth},
     config::Config,
+    services::ServiceResult,
     Cache, SharedState,
 };
 use poem::web::Data;
-use poem_ext::{db::DbTxn, response};
+use poem_ext::{db::DbTxn, patch_value::PatchValue, response};
 use poem_openapi::{
     param::{Path, Query},
     payload::Json,
     OpenApi,
 };
-use sandkasten_client::SandkastenClient;
+use sandkasten_client::{schemas::programs::RunResult, SandkastenClient};
 use schemas::challenges::coding_challenges::{
-    CodingChallenge, CodingChallengeSummary, CreateCodingChallengeRequest, Example,
-    SubmissionContent, UpdateCodingChallengeRequest,
+    CheckResult, CodingChallenge, CodingChallengeStats, CodingChallengeSummary,
+    CreateCodingChallengeRequest, EnvironmentStats, Example, OversizedField, RawExample,
+    SeedExample, SubmissionContent, UpdateCodingChallengeRequest, MAX_SEED_EXAMPLE_BYTES,
+};
+use sea_orm::{
+    sea_query::{Alias, Expr},
+    ActiveModelTrait, ColumnTrait, EntityTrait, QueryFilter, QueryOrder, QuerySelect, Set,
+    Unchanged,
 };
-use sea_orm::{ActiveModelTrait, Set, Unchanged};
+use tokio::sync::Semaphore;
 use tracing::error;
 use uuid::Uuid;
 
-use super::{_CheckError, check_challenge, CheckChallenge};
+use super::{_CheckError, check_challenge, random_test_seed, CheckChallenge};
 use crate::{
     endpoints::Tags,
     services::{
-        judge::{self, get_executor_config, Judge},
+        coding_challenges::{get_additional_solutions, set_additional_solutions},
+        judge::{self, get_environments, get_executor_config, Judge},
         subtasks::{
-            create_subtask, query_subtask, query_subtask_admin, query_subtasks, update_subtask,
-            CreateSubtaskError, QuerySubtaskAdminError, QuerySubtasksFilter, UpdateSubtaskError,
+            create_subtask, get_subtask, get_user_subtask, hearts_cost, query_subtask,
+            query_subtask_admin, query_subtasks, update_subtask, CreateSubtaskError,
+            QuerySubtaskAdminError, QuerySubtasksFilter, UpdateSubtaskError, UserSubtaskExt,
         },
     },
 };
 
 pub struct Api {
     pub sandkasten: SandkastenClient,
+    pub sandkasten_lock: Arc<Semaphore>,
     pub judge_cache: Cache<JsonFormatter>,
     pub config: Arc<Config>,
     pub state: Arc<SharedState>,
@@ -66,6 +80,7 @@ impl Api {
         db: Data<&DbTxn>,
         auth: VerifiedUserAuth,
     ) -> ListCodingChallenges::Response<VerifiedUserAuth> {
+        let premium = self.has_premium(&auth.0).await?;
         ListCodingChallenges::ok(
             query_subtasks::<challenges_coding_challenges::Entity, _>(
                 &db,
@@ -79,8 +94,13 @@ impl Api {
                     retired: retired.0,
                     creator: creator.0,
                     ty: None,
+                    tags: Vec::new(),
+                    include_locked: false,
+                },
+                |cc, subtask| {
+                    let hearts_cost = hearts_cost(&self.config, &auth.0, premium, &subtask);
+                    CodingChallengeSummary::from(cc, subtask, hearts_cost)
                 },
-                CodingChallengeSummary::from,
             )
             .await?,
         )
@@ -95,12 +115,16 @@ impl Api {
         db: Data<&DbTxn>,
         auth: VerifiedUserAuth,
     ) -> GetCodingChallenge::Response<VerifiedUserAuth> {
+        let premium = self.has_premium(&auth.0).await?;
         match query_subtask::<challenges_coding_challenges::Entity, _>(
             &db,
             &auth.0,
             task_id.0,
             subtask_id.0,
-            CodingChallenge::from,
+            |cc, subtask| {
+                let hearts_cost = hearts_cost(&self.config, &auth.0, premium, &subtask);
+                CodingChallenge::from(cc, subtask, hearts_cost)
+            },
         )
         .await?
         {
@@ -134,7 +158,7 @@ impl Api {
             None => return GetExamples::subtask_not_found(),
         };
 
-        let judge = self.get_judge(&cc.evaluator);
+        let judge = self.get_judge(&cc.evaluator, &cc.evaluator_environment);
 
         let examples = match judge.examples().await {
             Err(judge::Error::EvaluatorFailed(err) | judge::Error::InvalidOutput(err)) => {
@@ -144,6 +168,10 @@ impl Api {
                 );
                 return GetExamples::evaluator_failed();
             }
+            Err(judge::Error::EvaluatorTimeout(err)) => {
+                error!("evaluator for {} timed out: {:?}", subtask_id.0, err);
+                return GetExamples::evaluator_timeout();
+            }
             x => x?,
         };
         let mut out = Vec::with_capacity(examples.len());
@@ -156,10 +184,18 @@ impl Api {
                     Some(cc.time_limit as _),
                     Some(cc.memory_limit as _),
                 )
-                .await?;
+                .await;
             let example = match example {
-                Ok(example) => example,
-                Err(err) => {
+                Ok(Ok(example)) => example,
+                Err(judge::Error::EvaluatorTimeout(err)) => {
+                    error!(
+                        "evaluator for {} timed out on example {}: {:?}",
+                        subtask_id.0, seed, err
+                    );
+                    return GetExamples::evaluator_timeout();
+                }
+                Err(err) => return Err(err.into()),
+                Ok(Err(err)) => {
                     error!(
                         "example generation for {} failed on example {}: {:?}",
                         subtask_id.0, seed, err
@@ -173,6 +209,142 @@ impl Api {
         GetExamples::ok(out)
     }
 
+    /// Get the inputs the evaluator generates for the examples of a coding
+    /// challenge without running the solution.
+    #[oai(
+        path = "/tasks/:task_id/coding_challenges/:subtask_id/examples/raw",
+        method = "get"
+    )]
+    async fn get_raw_examples(
+        &self,
+        task_id: Path<Uuid>,
+        subtask_id: Path<Uuid>,
+        db: Data<&DbTxn>,
+        auth: VerifiedUserAuth,
+    ) -> GetRawExamples::Response<VerifiedUserAuth> {
+        let cc = match query_subtask_admin::<challenges_coding_challenges::Entity, _>(
+            &db,
+            &auth.0,
+            task_id.0,
+            subtask_id.0,
+            |cc, _| cc,
+        )
+        .await?
+        {
+            Ok(cc) => cc,
+            Err(QuerySubtaskAdminError::NotFound) => return GetRawExamples::subtask_not_found(),
+            Err(QuerySubtaskAdminError::NoAccess) => return GetRawExamples::forbidden(),
+        };
+
+        let judge = self.get_judge(&cc.evaluator, &cc.evaluator_environment);
+
+        let examples = match judge.examples().await {
+            Err(judge::Error::EvaluatorFailed(err) | judge::Error::InvalidOutput(err)) => {
+                error!(
+                    "evaluator for {} failed to execute: {:?}",
+                    subtask_id.0, err
+                );
+                return GetRawExamples::evaluator_failed();
+            }
+            Err(judge::Error::EvaluatorTimeout(err)) => {
+                error!("evaluator for {} timed out: {:?}", subtask_id.0, err);
+                return GetRawExamples::evaluator_timeout();
+            }
+            x => x?,
+        };
+        let mut out = Vec::with_capacity(examples.len());
+        for seed in examples {
+            let input = match judge.generate(&seed).await {
+                Err(judge::Error::EvaluatorFailed(err) | judge::Error::InvalidOutput(err)) => {
+                    error!(
+                        "evaluator for {} failed to generate example {}: {:?}",
+                        subtask_id.0, seed, err
+                    );
+                    return GetRawExamples::evaluator_failed();
+                }
+                Err(judge::Error::EvaluatorTimeout(err)) => {
+                    error!(
+                        "evaluator for {} timed out on example {}: {:?}",
+                        subtask_id.0, seed, err
+                    );
+                    return GetRawExamples::evaluator_timeout();
+                }
+                x => x?,
+            };
+            out.push(RawExample {
+                id: seed,
+                input: input.input,
+            });
+        }
+
+        GetRawExamples::ok(out)
+    }
+
+    /// Generate the input for an arbitrary seed and run the solution of a
+    /// coding challenge on it.
+    ///
+    /// This can be used to inspect the test cases of static and random tests
+    /// (e.g. `_static_0_{subtask_id}`). Inputs and outputs are truncated to
+    /// 65536 bytes.
+    #[oai(
+        path = "/tasks/:task_id/coding_challenges/:subtask_id/seed/:seed",
+        method = "get"
+    )]
+    async fn get_seed_example(
+        &self,
+        task_id: Path<Uuid>,
+        subtask_id: Path<Uuid>,
+        seed: Path<String>,
+        db: Data<&DbTxn>,
+        auth: VerifiedUserAuth,
+    ) -> GetSeedExample::Response<VerifiedUserAuth> {
+        let cc = match query_subtask_admin::<challenges_coding_challenges::Entity, _>(
+            &db,
+            &auth.0,
+            task_id.0,
+            subtask_id.0,
+            |cc, _| cc,
+        )
+        .await?
+        {
+            Ok(cc) => cc,
+            Err(QuerySubtaskAdminError::NotFound) => return GetSeedExample::subtask_not_found(),
+            Err(QuerySubtaskAdminError::NoAccess) => return GetSeedExample::forbidden(),
+        };
+
+        let example = self
+            .get_judge(&cc.evaluator, &cc.evaluator_environment)
+            .get_example_checked(
+                &seed.0,
+                &cc.solution_environment,
+                &cc.solution_code,
+                Some(cc.time_limit as _),
+                Some(cc.memory_limit as _),
+            )
+            .await;
+        match example {
+            Ok(Ok(example)) => GetSeedExample::ok(example.into()),
+            Ok(Err(result)) => {
+                GetSeedExample::solution_failed(result.truncate_output(MAX_SEED_EXAMPLE_BYTES))
+            }
+            Err(judge::Error::EvaluatorFailed(err) | judge::Error::InvalidOutput(err)) => {
+                error!(
+                    "evaluator for {} failed to generate seed {}: {:?}",
+                    subtask_id.0, seed.0, err
+                );
+                GetSeedExample::evaluator_failed()
+            }
+            Err(judge::Error::EvaluatorTimeout(err)) => {
+                error!(
+                    "evaluator for {} timed out on seed {}: {:?}",
+                    subtask_id.0, seed.0, err
+                );
+                GetSeedExample::evaluator_timeout()
+            }
+            Err(err) => Err(err.into()),
+        }
+    }
+
     /// Get the evaluator of a coding challenge by id.
     #[oai(
         path = "/tasks/:task_id/coding_challenges/:subtask_id/evaluator",
@@ -201,6 +373,10 @@ impl Api {
     }
 
     /// Get the solution of a coding challenge by id.
+    ///
+    /// Admins and the creator of the challenge can always see the solution.
+    /// Other users can only see it after they have solved the challenge
+    /// themselves and only if `reveal_solution_on_solve` is set.
     #[oai(
         path = "/tasks/:task_id/coding_challenges/:subtask_id/solution",
         method = "get"
@@ -212,7 +388,45 @@ impl Api {
         db: Data<&DbTxn>,
         auth: VerifiedUserAuth,
     ) -> GetSolution::Response<VerifiedUserAuth> {
-        match query_subtask_admin::<challenges_coding_challenges::Entity, _>(
+        let Some((cc, subtask)) =
+            get_subtask::<challenges_coding_challenges::Entity>(&db, task_id.0, subtask_id.0)
+                .await?
+        else {
+            return GetSolution::subtask_not_found();
+        };
+
+        if !(auth.0.admin || auth.0.id == subtask.creator) {
+            if !subtask.enabled {
+                return GetSolution::subtask_not_found();
+            }
+            let solved = get_user_subtask(&db, auth.0.id, subtask.id)
+                .await?
+                .is_solved();
+            if !(cc.reveal_solution_on_solve && solved) {
+                return GetSolution::forbidden();
+            }
+        }
+
+        GetSolution::ok(SubmissionContent {
+            environment: cc.solution_environment,
+            code: cc.solution_code,
+        })
+    }
+
+    /// Return statistics about the judged submissions of a coding challenge
+    /// grouped by solution environment.
+    #[oai(
+        path = "/tasks/:task_id/coding_challenges/:subtask_id/stats",
+        method = "get"
+    )]
+    async fn get_stats(
+        &self,
+        task_id: Path<Uuid>,
+        subtask_id: Path<Uuid>,
+        db: Data<&DbTxn>,
+        auth: VerifiedUserAuth,
+    ) -> GetStats::Response<VerifiedUserAuth> {
+        let cc = match query_subtask_admin::<challenges_coding_challenges::Entity, _>(
             &db,
             &auth.0,
             task_id.0,
@@ -221,13 +435,68 @@ impl Api {
         )
         .await?
         {
-            Ok(cc) => GetSolution::ok(SubmissionContent {
-                environment: cc.solution_environment,
-                code: cc.solution_code,
-            }),
-            Err(QuerySubtaskAdminError::NotFound) => GetSolution::subtask_not_found(),
-            Err(QuerySubtaskAdminError::NoAccess) => GetSolution::forbidden(),
-        }
+            Ok(cc) => cc,
+            Err(QuerySubtaskAdminError::NotFound) => return GetStats::subtask_not_found(),
+            Err(QuerySubtaskAdminError::NoAccess) => return GetStats::forbidden(),
+        };
+
+        let accepted = Expr::case(
+            challenges_coding_challenge_result::Column::Verdict.eq(ChallengesVerdict::Ok),
+            1,
+        )
+        .finally(0);
+        let median_run_time = Expr::cust_with_expr(
+            "percentile_cont(0.5) WITHIN GROUP (ORDER BY $1)",
+            Expr::col((
+                challenges_coding_challenge_result::Entity,
+                challenges_coding_challenge_result::Column::RunTime,
+            )),
+        );
+        let environments = challenges_coding_challenge_submissions::Entity::find()
+            .select_only()
+            .column(challenges_coding_challenge_submissions::Column::Environment)
+            .column_as(
+                challenges_coding_challenge_submissions::Column::Id.count(),
+                "submissions",
+            )
+            .column_as(
+                Expr::expr(accepted).sum().cast_as(Alias::new("int8")),
+                "accepted",
+            )
+            .column_as(
+                Expr::expr(median_run_time).cast_as(Alias::new("int8")),
+                "median_run_time",
+            )
+            .inner_join(challenges_coding_challenge_result::Entity)
+            .filter(challenges_coding_challenge_submissions::Column::SubtaskId.eq(cc.subtask_id))
+            .group_by(challenges_coding_challenge_submissions::Column::Environment)
+            .order_by_asc(challenges_coding_challenge_submissions::Column::Environment)
+            .into_tuple::<(String, i64, i64, Option<i64>)>()
+            .all(&***db)
+            .await?
+            .into_iter()
+            .map(
+                |(environment, submissions, accepted, median_run_time)| EnvironmentStats {
+                    environment,
+                    submissions: submissions as _,
+                    accepted: accepted as _,
+                    median_run_time: median_run_time.map(|x| x as _),
+                },
+            )
+            .collect::<Vec<_>>();
+
+        let submissions = environments.iter().map(|x| x.submissions).sum::<u64>();
+        let accepted = environments.iter().map(|x| x.accepted).sum::<u64>();
+        GetStats::ok(CodingChallengeStats {
+            submissions,
+            accepted,
+            pass_rate: if submissions > 0 {
+                accepted as f64 / submissions as f64
+            } else {
+                0.0
+            },
+            environments,
+        })
     }
 
     /// Create a new coding challenge.
@@ -239,6 +508,15 @@ impl Api {
         db: Data<&DbTxn>,
         auth: VerifiedUserAuth,
     ) -> CreateCodingChallenge::Response<VerifiedUserAuth> {
+        let limits = self
+            .config
+            .challenges
+            .coding_challenges
+            .content_limits(auth.0.admin);
+        if let Some(field) = data.0.oversized_field(limits) {
+            return CreateCodingChallenge::content_too_large(field);
+        }
+
         let subtask = match create_subtask(
             &db,
             &self.state.services,
@@ -271,17 +549,26 @@ impl Api {
         if data.0.memory_limit > config.memory_limit {
             return CreateCodingChallenge::memory_limit_exceeded(config.memory_limit);
         }
+        if !self
+            .environment_exists(&data.0.evaluator_environment)
+            .await?
+        {
+            return CreateCodingChallenge::evaluator_environment_not_found();
+        }
 
         let cc_id = Uuid::new_v4();
         if let Err(result) = check_challenge(CheckChallenge {
-            judge: self.get_judge(&data.0.evaluator),
+            judge: self.get_judge(&data.0.evaluator, &data.0.evaluator_environment),
             challenge_id: cc_id,
             solution_environment: &data.0.solution_environment,
             solution_code: &data.0.solution_code,
+            additional_solutions: &data.0.additional_solutions,
             time_limit: data.0.time_limit,
             memory_limit: data.0.memory_limit,
             static_tests: data.0.static_tests,
             random_tests: data.0.random_tests,
+            test_seed: data.0.test_seed.unwrap_or_else(random_test_seed),
+            max_testcases: self.config.challenges.coding_challenges.max_testcases,
         })
         .await?
         {
@@ -294,14 +581,42 @@ impl Api {
             memory_limit: Set(data.0.memory_limit as _),
             static_tests: Set(data.0.static_tests as _),
             random_tests: Set(data.0.random_tests as _),
+            test_seed: Set(data.0.test_seed.map(|x| x as _)),
+            reveal_solution_on_solve: Set(data.0.reveal_solution_on_solve),
             evaluator: Set(data.0.evaluator),
+            evaluator_environment: Set(data.0.evaluator_environment),
             description: Set(data.0.description),
             solution_environment: Set(data.0.solution_environment),
             solution_code: Set(data.0.solution_code),
         }
         .insert(&***db)
         .await?;
-        CreateCodingChallenge::ok(CodingChallenge::from(cc, subtask))
+        set_additional_solutions(&db, cc.subtask_id, data.0.additional_solutions).await?;
+        // the creator of a subtask never has to pay hearts
+        CreateCodingChallenge::ok(CodingChallenge::from(cc, subtask, 0))
+    }
+
+    /// Invalidate all cached evaluator results (examples, generated inputs and
+    /// checked examples) of a coding challenge.
+    #[oai(
+        path = "/tasks/:task_id/coding_challenges/:subtask_id/cache/invalidate",
+        method = "post"
+    )]
+    async fn invalidate_cache(
+        &self,
+        task_id: Path<Uuid>,
+        subtask_id: Path<Uuid>,
+        db: Data<&DbTxn>,
+        _auth: AdminAuth,
+    ) -> InvalidateCache::Response<AdminAuth> {
+        let Some((cc, _)) =
+            get_subtask::<challenges_coding_challenges::Entity>(&db, task_id.0, subtask_id.0)
+                .await?
+        else {
+            return InvalidateCache::subtask_not_found();
+        };
+        judge::invalidate_cache(&self.judge_cache, &cc.evaluator).await?;
+        InvalidateCache::ok()
     }
 
     /// Update a coding challenge.
@@ -317,6 +632,15 @@ impl Api {
         db: Data<&DbTxn>,
         auth: AdminAuth,
     ) -> UpdateCodingChallenge::Response<AdminAuth> {
+        let limits = self
+            .config
+            .challenges
+            .coding_challenges
+            .content_limits(auth.0.admin);
+        if let Some(field) = data.0.oversized_field(limits) {
+            return UpdateCodingChallenge::content_too_large(field);
+        }
+
         let (cc, subtask) = match update_subtask::<challenges_coding_challenges::Entity>(
             &db,
             &auth.0,
@@ -342,40 +666,89 @@ impl Api {
         if *data.0.memory_limit.get_new(&(cc.memory_limit as _)) > config.memory_limit {
             return UpdateCodingChallenge::memory_limit_exceeded(config.memory_limit);
         }
+        if let PatchValue::Set(environment) = &data.0.evaluator_environment {
+            if !self.environment_exists(environment).await? {
+                return UpdateCodingChallenge::evaluator_environment_not_found();
+            }
+        }
 
+        let additional_solutions = match &data.0.additional_solutions {
+            PatchValue::Set(solutions) => solutions.clone(),
+            PatchValue::Unchanged => get_additional_solutions(&db, [cc.subtask_id])
+                .await?
+                .remove(&cc.subtask_id)
+                .unwrap_or_default(),
+        };
         if let Err(result) = check_challenge(CheckChallenge {
-            judge: self.get_judge(data.0.evaluator.get_new(&cc.evaluator)),
+            judge: self.get_judge(
+                data.0.evaluator.get_new(&cc.evaluator),
+                data.0
+                    .evaluator_environment
+                    .get_new(&cc.evaluator_environment),
+            ),
             challenge_id: cc.subtask_id,
             solution_environment: data
                 .0
                 .solution_environment
                 .get_new(&cc.solution_environment),
             solution_code: data.0.solution_code.get_new(&cc.solution_code),
+            additional_solutions: &additional_solutions,
             time_limit: *data.0.time_limit.get_new(&(cc.time_limit as _)),
             memory_limit: *data.0.memory_limit.get_new(&(cc.memory_limit as _)),
             static_tests: *data.0.static_tests.get_new(&(cc.static_tests as _)),
             random_tests: *data.0.random_tests.get_new(&(cc.random_tests as _)),
+            test_seed: data
+                .0
+                .test_seed
+                .get_new(&cc.test_seed.map(|x| x as _))
+                .unwrap_or_else(random_test_seed),
+            max_testcases: self.config.challenges.coding_challenges.max_testcases,
         })
         .await?
         {
             return Ok(_CheckError::Response::from(result).into());
         }
 
+        let old_evaluator = match &data.0.evaluator {
+            PatchValue::Set(evaluator) if *evaluator != cc.evaluator => Some(cc.evaluator.clone()),
+            _ => None,
+        };
         let cc = challenges_coding_challenges::ActiveModel {
             subtask_id: Unchanged(cc.subtask_id),
             time_limit: data.0.time_limit.map(|x| x as _).update(cc.time_limit),
             memory_limit: data.0.memory_limit.map(|x| x as _).update(cc.memory_limit),
             static_tests: data.0.static_tests.map(|x| x as _).update(cc.static_tests),
             random_tests: data.0.random_tests.map(|x| x as _).update(cc.random_tests),
+            test_seed: data
+                .0
+                .test_seed
+                .map(|x| x.map(|x| x as _))
+                .update(cc.test_seed),
+            reveal_solution_on_solve: data
+                .0
+                .reveal_solution_on_solve
+                .update(cc.reveal_solution_on_solve),
             evaluator: data.0.evaluator.update(cc.evaluator),
+            evaluator_environment: data
+                .0
+                .evaluator_environment
+                .update(cc.evaluator_environment),
             description: data.0.description.update(cc.description),
             solution_environment: data.0.solution_environment.update(cc.solution_environment),
             solution_code: data.0.solution_code.update(cc.solution_code),
         }
         .update(&***db)
         .await?;
+        if let PatchValue::Set(solutions) = data.0.additional_solutions {
+            set_additional_solutions(&db, cc.subtask_id, solutions).await?;
+        }
+
+        if let Some(evaluator) = old_evaluator {
+            judge::invalidate_cache(&self.judge_cache, &evaluator).await?;
+        }
 
-        UpdateCodingChallenge::ok(CodingChallenge::from(cc, subtask))
+        // only admins can update coding challenges and they never have to pay hearts
+        UpdateCodingChallenge::ok(CodingChallenge::from(cc, subtask, 0))
     }
 }
 
@@ -395,10 +768,38 @@ response!(GetExamples = {
     SubtaskNotFound(404, error),
     /// The evaluator failed to execute.
     EvaluatorFailed(400, error),
+    /// The evaluator exceeded its time limit.
+    EvaluatorTimeout(400, error),
     /// Failed to generate an example.
     ExampleGenerationFailed(400, error),
 });
 
+response!(GetRawExamples = {
+    Ok(200) => Vec<RawExample>,
+    /// Subtask does not exist.
+    SubtaskNotFound(404, error),
+    /// The user is not allowed to request the raw examples of this coding challenge.
+    Forbidden(403, error),
+    /// The evaluator failed to execute.
+    EvaluatorFailed(400, error),
+    /// The evaluator exceeded its time limit.
+    EvaluatorTimeout(400, error),
+});
+
+response!(GetSeedExample = {
+    Ok(200) => SeedExample,
+    /// Subtask does not exist.
+    SubtaskNotFound(404, error),
+    /// The user is not allowed to inspect the test cases of this coding challenge.
+    Forbidden(403, error),
+    /// The evaluator failed to execute.
+    EvaluatorFailed(400, error),
+    /// The evaluator exceeded its time limit.
+    EvaluatorTimeout(400, error),
+    /// The solution of the coding challenge failed on this seed. Its output is truncated to 65536 bytes.
+    SolutionFailed(400, error) => CheckResult<RunResult>,
+});
+
 response!(GetEvaluator = {
     Ok(200) => String,
     /// Subtask does not exist.
@@ -407,11 +808,19 @@ response!(GetEvaluator = {
     Forbidden(403, error),
 });
 
+response!(GetStats = {
+    Ok(200) => CodingChallengeStats,
+    /// Subtask does not exist.
+    SubtaskNotFound(404, error),
+    /// The user is not allowed to request the statistics of this coding challenge.
+    Forbidden(403, error),
+});
+
 response!(GetSolution = {
     Ok(200) => SubmissionContent,
     /// Subtask does not exist.
     SubtaskNotFound(404, error),
-    /// The user is not allowed to request the solution of this coding challenge.
+    /// The user is not allowed to request the solution of this coding challenge (yet).
     Forbidden(403, error),
 });
 
@@ -431,9 +840,19 @@ response!(CreateCodingChallenge = {
     TimeLimitExceeded(403, error) => u64,
     /// Memory limit exceeded
     MemoryLimitExceeded(403, error) => u64,
+    /// A field exceeds its length or size limit. `details` contains the name of the field and its limits.
+    ContentTooLarge(413, error) => OversizedField,
+    /// The evaluator environment does not exist.
+    EvaluatorEnvironmentNotFound(404, error),
     .._CheckError::Response,
 });
 
+response!(InvalidateCache = {
+    Ok(200),
+    /// Subtask does not exist.
+    SubtaskNotFound(404, error),
+});
+
 response!(UpdateCodingChallenge = {
     Ok(200) => CodingChallenge,
     /// Subtask does not exist.
@@ -444,15 +863,48 @@ response!(UpdateCodingChallenge = {
     TimeLimitExceeded(403, error) => u64,
     /// Memory limit exceeded
     MemoryLimitExceeded(403, error) => u64,
+    /// A field exceeds its length or size limit. `details` contains the name of the field and its limits.
+    ContentTooLarge(413, error) => OversizedField,
+    /// The evaluator environment does not exist.
+    EvaluatorEnvironmentNotFound(404, error),
     .._CheckError::Response,
 });
 
 impl Api {
-    fn get_judge<'a>(&'a self, evaluator: &'a str) -> Judge<'a> {
+    fn get_judge<'a>(&'a self, evaluator: &'a str, evaluator_environment: &'a str) -> Judge<'a> {
         Judge {
             sandkasten: &self.sandkasten,
+            sandkasten_lock: &self.sandkasten_lock,
             evaluator,
+            evaluator_environment,
             cache: &self.judge_cache,
+            evaluator_timeout: self.config.challenges.coding_challenges.evaluator_timeout,
+            retry: self.config.challenges.coding_challenges.sandkasten_retry,
+            cache_ttl: self
+                .config
+                .challenges
+                .coding_challenges
+                .evaluator_cache_ttl(),
+        }
+    }
+
+    async fn environment_exists(&self, environment: &str) -> anyhow::Result<bool> {
+        Ok(get_environments(
+            &self.judge_cache,
+            &self.sandkasten,
+            self.config
+                .challenges
+                .coding_challenges
+                .environments_cache_ttl(),
+        )
+        .await?
+        .contains_key(environment))
+    }
+
+    async fn has_premium(&self, user: &User) -> ServiceResult<bool> {
+        if user.admin {
+            return Ok(false);
         }
+        self.state.services.shop.has_premium(user.id).await
     }
 }
@@ -1,7 +1,7 @@
 use std::sync::Arc;
 
 use entity::challenges_coding_challenges;
-use fnct::{format::JsonFormatter, key};
+use fnct::format::JsonFormatter;
 use lib::{auth::VerifiedUserAuth, config::Config, Cache, SharedState};
 use poem::web::Data;
 use poem_ext::{db::DbTxn, response};
@@ -10,15 +10,21 @@ use sandkasten_client::{
     schemas::{environments::ListEnvironmentsResponse, programs::RunResult},
     SandkastenClient,
 };
-use schemas::challenges::coding_challenges::{CheckResult, ExecutorConfig, SubmissionContent};
+use schemas::challenges::coding_challenges::{
+    CheckResult, CodingChallengeEnvironment, CodingChallengeLimits, EvaluatorStageResult,
+    ExampleTestResult, ExecutorConfig, OversizedField, SubmissionContent, ValidateEvaluatorRequest,
+    MAX_DESCRIPTION_LEN, MAX_EVALUATOR_LEN, MAX_SOLUTION_LEN,
+};
+use tokio::sync::Semaphore;
 use tracing::error;
 use uuid::Uuid;
 
 use crate::{
     endpoints::Tags,
     services::{
-        judge::{self, get_executor_config, Judge},
-        subtasks::{check_hearts, get_subtask},
+        judge::{self, get_environments, get_executor_config, has_evaluator_library, Judge},
+        subtasks::{can_create, check_hearts, get_subtask},
+        tasks::get_task_with_specific,
     },
 };
 
@@ -26,6 +32,7 @@ pub struct Api {
     pub state: Arc<SharedState>,
     pub config: Arc<Config>,
     pub sandkasten: SandkastenClient,
+    pub sandkasten_lock: Arc<Semaphore>,
     pub judge_cache: Cache<JsonFormatter>,
 }
 
@@ -45,6 +52,10 @@ impl Api {
         db: Data<&DbTxn>,
         auth: VerifiedUserAuth,
     ) -> TestExample::Response<VerifiedUserAuth> {
+        if let Some(field) = data.0.oversized_field() {
+            return TestExample::content_too_large(field.into());
+        }
+
         let Some((cc, subtask)) =
             get_subtask::<challenges_coding_challenges::Entity>(&db, task_id.0, subtask_id.0)
                 .await?
@@ -59,7 +70,7 @@ impl Api {
             return TestExample::not_enough_hearts();
         }
 
-        let judge = self.get_judge(&cc.evaluator);
+        let judge = self.get_judge(&cc.evaluator, &cc.evaluator_environment);
 
         let examples = match judge.examples().await {
             Err(judge::Error::EvaluatorFailed(err) | judge::Error::InvalidOutput(err)) => {
@@ -69,6 +80,10 @@ impl Api {
                 );
                 return TestExample::evaluator_failed();
             }
+            Err(judge::Error::EvaluatorTimeout(err)) => {
+                error!("evaluator for {} timed out: {:?}", subtask_id.0, err);
+                return TestExample::evaluator_timeout();
+            }
             x => x?,
         };
         if !examples.contains(&example_id.0) {
@@ -84,6 +99,10 @@ impl Api {
                 );
                 return TestExample::evaluator_failed();
             }
+            Err(judge::Error::EvaluatorTimeout(err)) => {
+                error!("evaluator for {} timed out: {:?}", subtask_id.0, err);
+                return TestExample::evaluator_timeout();
+            }
             x => x?,
         };
 
@@ -106,6 +125,10 @@ impl Api {
                 );
                 return TestExample::evaluator_failed();
             }
+            Err(judge::Error::EvaluatorTimeout(err)) => {
+                error!("evaluator for {} timed out: {:?}", subtask_id.0, err);
+                return TestExample::evaluator_timeout();
+            }
             Err(judge::Error::EnvironmentNotFound) => {
                 return TestExample::environment_not_found();
             }
@@ -115,6 +138,136 @@ impl Api {
         TestExample::ok(result)
     }
 
+    /// Test a solution against all examples.
+    #[oai(
+        path = "/tasks/:task_id/coding_challenges/:subtask_id/examples/test_all",
+        method = "post"
+    )]
+    async fn test_all_examples(
+        &self,
+        task_id: Path<Uuid>,
+        subtask_id: Path<Uuid>,
+        data: Json<SubmissionContent>,
+        db: Data<&DbTxn>,
+        auth: VerifiedUserAuth,
+    ) -> TestAllExamples::Response<VerifiedUserAuth> {
+        if let Some(field) = data.0.oversized_field() {
+            return TestAllExamples::content_too_large(field.into());
+        }
+
+        let Some((cc, subtask)) =
+            get_subtask::<challenges_coding_challenges::Entity>(&db, task_id.0, subtask_id.0)
+                .await?
+        else {
+            return TestAllExamples::subtask_not_found();
+        };
+        if !auth.0.admin && auth.0.id != subtask.creator && !subtask.enabled {
+            return TestAllExamples::subtask_not_found();
+        }
+
+        if !check_hearts(&self.state.services, &self.config, &auth.0, &subtask).await? {
+            return TestAllExamples::not_enough_hearts();
+        }
+
+        let judge = self.get_judge(&cc.evaluator, &cc.evaluator_environment);
+
+        let examples = match judge.examples().await {
+            Err(judge::Error::EvaluatorFailed(err) | judge::Error::InvalidOutput(err)) => {
+                error!(
+                    "evaluator for {} failed to execute while listing examples: {:?}",
+                    subtask_id.0, err
+                );
+                return TestAllExamples::evaluator_failed();
+            }
+            Err(judge::Error::EvaluatorTimeout(err)) => {
+                error!("evaluator for {} timed out: {:?}", subtask_id.0, err);
+                return TestAllExamples::evaluator_timeout();
+            }
+            x => x?,
+        };
+
+        let mut results = Vec::with_capacity(examples.len());
+        for example_id in examples {
+            let result = match judge.generate(&example_id).await {
+                Ok(inp) => {
+                    judge
+                        .run_solution(
+                            &example_id,
+                            &inp,
+                            &data.0.environment,
+                            &data.0.code,
+                            Some(cc.time_limit as _),
+                            Some(cc.memory_limit as _),
+                        )
+                        .await
+                }
+                Err(err) => Err(err),
+            };
+            let result = match result {
+                Err(judge::Error::EvaluatorFailed(err) | judge::Error::InvalidOutput(err)) => {
+                    error!(
+                        "evaluator for {} failed to execute while testing submission for \
+                         example {}: {:?}",
+                        subtask_id.0, example_id, err
+                    );
+                    return TestAllExamples::evaluator_failed();
+                }
+                Err(judge::Error::EvaluatorTimeout(err)) => {
+                    error!("evaluator for {} timed out: {:?}", subtask_id.0, err);
+                    return TestAllExamples::evaluator_timeout();
+                }
+                Err(judge::Error::EnvironmentNotFound) => {
+                    return TestAllExamples::environment_not_found();
+                }
+                x => x?,
+            };
+            results.push(ExampleTestResult { example_id, result });
+        }
+
+        TestAllExamples::ok(results)
+    }
+
+    /// Check whether an evaluator produces valid output.
+    ///
+    /// Runs the `examples` stage, the `generate` stage on the first example and
+    /// the `prepare` stage with a trivial solution and returns the result of
+    /// each stage. Only users who are allowed to create coding challenges in
+    /// the given task can validate evaluators.
+    #[oai(path = "/coding_challenges/evaluator/validate", method = "post")]
+    async fn validate_evaluator(
+        &self,
+        data: Json<ValidateEvaluatorRequest>,
+        db: Data<&DbTxn>,
+        auth: VerifiedUserAuth,
+    ) -> ValidateEvaluator::Response<VerifiedUserAuth> {
+        if !auth.0.admin {
+            let Some(task_id) = data.0.task_id else {
+                return ValidateEvaluator::forbidden();
+            };
+            let Some((_, task)) = get_task_with_specific(&db, task_id).await? else {
+                return ValidateEvaluator::task_not_found();
+            };
+            if !can_create(&self.state.services, &self.config, &task, &auth.0).await? {
+                return ValidateEvaluator::forbidden();
+            }
+        }
+
+        let limits = self
+            .config
+            .challenges
+            .coding_challenges
+            .content_limits(auth.0.admin);
+        if let Some(field) = data.0.oversized_field(limits) {
+            return ValidateEvaluator::content_too_large(field);
+        }
+
+        ValidateEvaluator::ok(
+            self.get_judge(&data.0.evaluator, &data.0.evaluator_environment)
+                .validate()
+                .await?,
+        )
+    }
+
     /// Return a map of all environments available on the code execution engine.
     ///
     /// The keys represent the environment ids and the values contain additional
@@ -125,19 +278,71 @@ impl Api {
         _auth: VerifiedUserAuth,
     ) -> ListEnvironments::Response<VerifiedUserAuth> {
         ListEnvironments::ok(ListEnvironmentsResponse(
-            self.judge_cache
-                .cached_result(key!(), &[], None, || async {
-                    self.sandkasten.list_environments().await
-                })
-                .await??,
+            get_environments(
+                &self.judge_cache,
+                &self.sandkasten,
+                self.config
+                    .challenges
+                    .coding_challenges
+                    .environments_cache_ttl(),
+            )
+            .await?,
         ))
     }
 
+    /// Return all environments that can be used for submissions and
+    /// evaluators of coding challenges, sorted by id.
+    #[oai(path = "/coding_challenges/environments", method = "get")]
+    async fn list_coding_challenge_environments(
+        &self,
+        _auth: VerifiedUserAuth,
+    ) -> ListCodingChallengeEnvironments::Response<VerifiedUserAuth> {
+        let mut environments = get_environments(
+            &self.judge_cache,
+            &self.sandkasten,
+            self.config
+                .challenges
+                .coding_challenges
+                .environments_cache_ttl(),
+        )
+        .await?
+        .into_iter()
+        .map(|(id, env)| CodingChallengeEnvironment {
+            evaluator_library: has_evaluator_library(&id),
+            id,
+            name: env.name,
+            version: env.version,
+            example: env.example,
+        })
+        .collect::<Vec<_>>();
+        environments.sort_by(|a, b| a.id.cmp(&b.id));
+        ListCodingChallengeEnvironments::ok(environments)
+    }
+
     /// Return the config of the code execution engine.
     #[oai(path = "/executor/config", method = "get")]
     async fn get_config(&self, _auth: VerifiedUserAuth) -> GetConfig::Response<VerifiedUserAuth> {
         GetConfig::ok(get_executor_config(&self.judge_cache, &self.sandkasten).await?)
     }
+
+    /// Return the size limits of evaluators, solutions and descriptions as
+    /// well as the maximum time and memory limits of coding challenges.
+    #[oai(path = "/coding_challenges/limits", method = "get")]
+    async fn get_limits(&self, auth: VerifiedUserAuth) -> GetLimits::Response<VerifiedUserAuth> {
+        let config = get_executor_config(&self.judge_cache, &self.sandkasten).await?;
+        let limits = self
+            .config
+            .challenges
+            .coding_challenges
+            .content_limits(auth.0.admin);
+        GetLimits::ok(CodingChallengeLimits {
+            max_evaluator_len: limits.evaluator.min(MAX_EVALUATOR_LEN),
+            max_solution_len: MAX_SOLUTION_LEN,
+            max_description_len: limits.description.min(MAX_DESCRIPTION_LEN),
+            max_time_limit: config.time_limit,
+            max_memory_limit: config.memory_limit,
+        })
+    }
 }
 
 response!(TestExample = {
@@ -150,6 +355,36 @@ response!(TestExample = {
     NotEnoughHearts(403, error),
     /// The evaluator failed to execute.
     EvaluatorFailed(400, error),
+    /// The evaluator exceeded its time limit.
+    EvaluatorTimeout(400, error),
+    /// A field exceeds its size limit in bytes. `details` contains the name of the field.
+    ContentTooLarge(413, error) => String,
+});
+
+response!(TestAllExamples = {
+    Ok(200) => Vec<ExampleTestResult>,
+    /// Subtask does not exist.
+    SubtaskNotFound(404, error),
+    /// Environment does not exist.
+    EnvironmentNotFound(404, error),
+    /// The user does not have enough hearts to submit a solution and is neither an admin nor the creator of this subtask.
+    NotEnoughHearts(403, error),
+    /// The evaluator failed to execute.
+    EvaluatorFailed(400, error),
+    /// The evaluator exceeded its time limit.
+    EvaluatorTimeout(400, error),
+    /// A field exceeds its size limit in bytes. `details` contains the name of the field.
+    ContentTooLarge(413, error) => String,
+});
+
+response!(ValidateEvaluator = {
+    Ok(200) => Vec<EvaluatorStageResult>,
+    /// Task does not exist.
+    TaskNotFound(404, error),
+    /// The user is not allowed to create coding challenges in this task.
+    Forbidden(403, error),
+    /// A field exceeds its length or size limit. `details` contains the name of the field and its limits.
+    ContentTooLarge(413, error) => OversizedField,
 });
 
 response!(ListEnvironments = {
@@ -157,17 +392,34 @@ response!(ListEnvironments = {
     Ok(200) => ListEnvironmentsResponse,
 });
 
+response!(ListCodingChallengeEnvironments = {
+    Ok(200) => Vec<CodingChallengeEnvironment>,
+});
+
 response!(GetConfig = {
     /// Configuration of the code execution engine.
     Ok(200) => ExecutorConfig,
 });
 
+response!(GetLimits = {
+    Ok(200) => CodingChallengeLimits,
+});
+
 impl Api {
-    fn get_judge<'a>(&'a self, evaluator: &'a str) -> Judge<'a> {
+    fn get_judge<'a>(&'a self, evaluator: &'a str, evaluator_environment: &'a str) -> Judge<'a> {
         Judge {
             sandkasten: &self.sandkasten,
+            sandkasten_lock: &self.sandkasten_lock,
             evaluator,
+            evaluator_environment,
             cache: &self.judge_cache,
+            evaluator_timeout: self.config.challenges.coding_challenges.evaluator_timeout,
+            retry: self.config.challenges.coding_challenges.sandkasten_retry,
+            cache_ttl: self
+                .config
+                .challenges
+                .coding_challenges
+                .evaluator_cache_ttl(),
         }
     }
 }
@@ -1,6 +1,9 @@
 use std::sync::Arc;
 
+use entity::challenges_coding_challenges;
 use fnct::format::JsonFormatter;
+use futures::future::join_all;
+use key_rwlock::KeyRwLock;
 use lib::{config::Config, Cache, SharedState};
 use poem_ext::response;
 use poem_openapi::{Object, OpenApi};
@@ -8,11 +11,18 @@ use sandkasten_client::{
     schemas::programs::{BuildRunResult, RunResult},
     SandkastenClient,
 };
-use schemas::challenges::coding_challenges::CheckResult;
-use tokio::sync::Semaphore;
+use schemas::challenges::coding_challenges::{CheckResult, SubmissionContent};
+use sea_orm::EntityTrait;
+use tokio::sync::{RwLock, Semaphore};
+use tracing::{error, info, instrument, warn};
 use uuid::Uuid;
 
-use crate::services::judge::{Error as JudgeError, Judge};
+use self::submissions::{JudgeTasks, QueuePositions};
+use crate::services::{
+    judge::{Error as JudgeError, Judge},
+    subtasks::RewardLock,
+    webhook::Webhook,
+};
 
 mod assets;
 mod challenges;
@@ -22,17 +32,46 @@ pub mod submissions;
 pub struct CodingChallenges {
     pub state: Arc<SharedState>,
     pub sandkasten: SandkastenClient,
+    pub sandkasten_lock: Arc<Semaphore>,
     pub judge_cache: Cache<JsonFormatter>,
     pub judge_lock: Arc<Semaphore>,
+    pub judge_tasks: JudgeTasks,
+    pub reward_lock: Arc<RewardLock>,
+    pub queue_positions: Arc<RwLock<QueuePositions>>,
+    pub submission_lock: Arc<KeyRwLock<Uuid>>,
     pub config: Arc<Config>,
 }
 
 impl CodingChallenges {
     pub async fn setup_api(self) -> anyhow::Result<impl OpenApi> {
+        if self
+            .config
+            .challenges
+            .coding_challenges
+            .warm_evaluator_cache
+        {
+            tokio::spawn(warm_evaluator_cache(
+                Arc::clone(&self.state),
+                Arc::clone(&self.config),
+                self.sandkasten.clone(),
+                Arc::clone(&self.sandkasten_lock),
+                self.judge_cache.clone(),
+            ));
+        }
+
+        let webhook = self
+            .config
+            .challenges
+            .coding_challenges
+            .webhook_url
+            .clone()
+            .map(|url| Webhook::new(url, self.state.jwt_secret.clone()));
+
         Ok((
             assets::Api,
             challenges::Api {
                 sandkasten: self.sandkasten.clone(),
+                sandkasten_lock: Arc::clone(&self.sandkasten_lock),
                 judge_cache: self.judge_cache.clone(),
                 config: Arc::clone(&self.config),
                 state: Arc::clone(&self.state),
@@ -41,18 +80,21 @@ impl CodingChallenges {
                 state: Arc::clone(&self.state),
                 config: Arc::clone(&self.config),
                 sandkasten: self.sandkasten.clone(),
+                sandkasten_lock: Arc::clone(&self.sandkasten_lock),
                 judge_cache: self.judge_cache.clone(),
             },
             submissions::Api {
                 config: self.config,
                 state: self.state,
                 sandkasten: self.sandkasten,
+                sandkasten_lock: self.sandkasten_lock,
                 judge_cache: self.judge_cache,
-                reward_lock: Default::default(),
-                queue_positions: Arc::new(
-                    QueuePositions::new(self.judge_lock.available_permits()).into(),
-                ),
+                reward_lock: self.reward_lock,
+                queue_positions: self.queue_positions,
+                submission_lock: self.submission_lock,
                 judge_lock: self.judge_lock,
+                judge_tasks: self.judge_tasks,
+                webhook,
             }
             .setup_api()
             .await?,
@@ -60,22 +102,87 @@ impl CodingChallenges {
     }
 }
 
-async fn check_challenge(
+/// Run the evaluators of all coding challenges once to populate the examples
+/// cache, so the first requests after a restart don't have to wait for them.
+async fn warm_evaluator_cache(
+    state: Arc<SharedState>,
+    config: Arc<Config>,
+    sandkasten: SandkastenClient,
+    sandkasten_lock: Arc<Semaphore>,
+    judge_cache: Cache<JsonFormatter>,
+) {
+    let coding_challenges = match challenges_coding_challenges::Entity::find()
+        .all(&state.db)
+        .await
+    {
+        Ok(coding_challenges) => coding_challenges,
+        Err(err) => {
+            error!("failed to load coding challenges for cache warm-up: {err}");
+            return;
+        }
+    };
+
+    info!(
+        "warming evaluator cache for {} coding challenge(s)",
+        coding_challenges.len()
+    );
+    let lock = Semaphore::new(config.challenges.coding_challenges.max_concurrency);
+    let results = join_all(coding_challenges.iter().map(|cc| async {
+        let _guard = lock.acquire().await;
+        Judge {
+            sandkasten: &sandkasten,
+            sandkasten_lock: &sandkasten_lock,
+            evaluator: &cc.evaluator,
+            evaluator_environment: &cc.evaluator_environment,
+            cache: &judge_cache,
+            evaluator_timeout: config.challenges.coding_challenges.evaluator_timeout,
+            retry: config.challenges.coding_challenges.sandkasten_retry,
+            cache_ttl: config.challenges.coding_challenges.evaluator_cache_ttl(),
+        }
+        .examples()
+        .await
+    }))
+    .await;
+
+    let mut warmed = 0;
+    for (cc, result) in coding_challenges.iter().zip(results) {
+        match result {
+            Ok(_) => warmed += 1,
+            Err(err) => warn!(
+                "failed to warm evaluator cache for coding challenge {}: {err}",
+                cc.subtask_id
+            ),
+        }
+    }
+    info!(
+        "warmed evaluator cache for {warmed}/{} coding challenge(s)",
+        coding_challenges.len()
+    );
+}
+
+#[instrument(skip_all, fields(subtask_id = %challenge_id))]
+pub(crate) async fn check_challenge(
     CheckChallenge {
         judge,
         challenge_id,
         solution_environment,
         solution_code,
+        additional_solutions,
         time_limit,
         memory_limit,
         static_tests,
         random_tests,
+        test_seed,
+        max_testcases,
     }: CheckChallenge<'_>,
 ) -> Result<Result<(), CheckError>, JudgeError> {
     let examples = match judge.examples().await {
         Err(JudgeError::EvaluatorFailed(err)) => {
             return Ok(Err(CheckError::EvaluatorFailed(err)));
         }
+        Err(JudgeError::EvaluatorTimeout(err)) => {
+            return Ok(Err(CheckError::EvaluatorTimeout(err)));
+        }
         Err(JudgeError::InvalidOutput(err)) => {
             return Ok(Err(CheckError::InvalidOutput(err)));
         }
@@ -84,38 +191,54 @@ async fn check_challenge(
     if examples.is_empty() {
         return Ok(Err(CheckError::NoExamples));
     }
+    if exceeds_max_testcases(examples.len(), static_tests, random_tests, max_testcases) {
+        return Ok(Err(CheckError::TooManyTestcases(max_testcases)));
+    }
 
-    for seed in examples
+    let seeds = examples
         .into_iter()
         .chain((0..static_tests).map(|x| format!("_static_{x}_{challenge_id}")))
-        .chain((0..random_tests).map(|_| Uuid::new_v4().to_string()))
-    {
-        let result = match judge
-            .get_example_checked(
-                &seed,
-                solution_environment,
-                solution_code,
-                Some(time_limit),
-                Some(memory_limit),
-            )
-            .await
-        {
-            Err(JudgeError::EnvironmentNotFound) => {
-                return Ok(Err(CheckError::EnvironmentNotFound));
+        .chain(random_test_seeds(test_seed, random_tests))
+        .collect::<Vec<_>>();
+    let solutions = std::iter::once((None, solution_environment, solution_code)).chain(
+        additional_solutions
+            .iter()
+            .enumerate()
+            .map(|(i, s)| (Some(i), s.environment.as_str(), s.code.as_str())),
+    );
+    for (solution, environment, code) in solutions {
+        for seed in &seeds {
+            let result = match judge
+                .get_example_checked(
+                    seed,
+                    environment,
+                    code,
+                    Some(time_limit),
+                    Some(memory_limit),
+                )
+                .await
+            {
+                Err(JudgeError::EnvironmentNotFound) => {
+                    return Ok(Err(CheckError::EnvironmentNotFound));
+                }
+                Err(JudgeError::EvaluatorFailed(err)) => {
+                    return Ok(Err(CheckError::EvaluatorFailed(err)));
+                }
+                Err(JudgeError::EvaluatorTimeout(err)) => {
+                    return Ok(Err(CheckError::EvaluatorTimeout(err)));
+                }
+                Err(JudgeError::InvalidOutput(err)) => {
+                    return Ok(Err(CheckError::InvalidOutput(err)));
+                }
+                x => x?,
+            };
+            if let Err(result) = result {
+                return Ok(Err(CheckError::TestcaseFailed(CheckTestcaseError {
+                    seed: seed.clone(),
+                    solution,
+                    result,
+                })));
             }
-            Err(JudgeError::EvaluatorFailed(err)) => {
-                return Ok(Err(CheckError::EvaluatorFailed(err)));
-            }
-            Err(JudgeError::InvalidOutput(err)) => {
-                return Ok(Err(CheckError::InvalidOutput(err)));
-            }
-            x => x?,
-        };
-        if let Err(result) = result {
-            return Ok(Err(CheckError::TestcaseFailed(CheckTestcaseError {
-                seed: seed.clone(),
-                result,
-            })));
         }
     }
 
@@ -131,25 +254,63 @@ mod _check_error {
         EnvironmentNotFound(404, error),
         /// The evaluator crashed.
         EvaluatorFailed(400, error) => BuildRunResult,
+        /// The evaluator exceeded its time limit.
+        EvaluatorTimeout(400, error) => BuildRunResult,
         /// The evaluator failed to produce valid output.
         InvalidOutput(400, error) => BuildRunResult,
         /// The sample solution failed on a specific test case.
         TestcaseFailed(400, error) => CheckTestcaseError,
+        /// The number of examples, static and random tests exceeds the
+        /// maximum number of test cases.
+        TooManyTestcases(400, error) => usize,
     });
 }
 use _check_error::CheckError::raw as _CheckError;
 
-use self::submissions::QueuePositions;
+pub(crate) struct CheckChallenge<'a> {
+    pub judge: Judge<'a>,
+    pub challenge_id: Uuid,
+    pub solution_environment: &'a str,
+    pub solution_code: &'a str,
+    /// Further solutions that must pass the same test cases.
+    pub additional_solutions: &'a [SubmissionContent],
+    pub time_limit: u64,
+    pub memory_limit: u64,
+    pub static_tests: u8,
+    pub random_tests: u8,
+    /// The value the seeds of the random tests are derived from.
+    pub test_seed: u64,
+    pub max_testcases: usize,
+}
 
-struct CheckChallenge<'a> {
-    judge: Judge<'a>,
-    challenge_id: Uuid,
-    solution_environment: &'a str,
-    solution_code: &'a str,
-    time_limit: u64,
-    memory_limit: u64,
+/// Return whether the examples, static and random tests of a challenge exceed
+/// the maximum number of test cases.
+fn exceeds_max_testcases(
+    examples: usize,
     static_tests: u8,
     random_tests: u8,
+    max_testcases: usize,
+) -> bool {
+    examples + static_tests as usize + random_tests as usize > max_testcases
+}
+
+/// Return a new random value to derive the seeds of random tests from.
+pub(crate) fn random_test_seed() -> u64 {
+    Uuid::new_v4().as_u64_pair().0
+}
+
+/// Derive the seeds of `n` random tests from `test_seed`.
+fn random_test_seeds(test_seed: u64, n: u8) -> impl Iterator<Item = String> {
+    // splitmix64
+    let mut state = test_seed;
+    let mut next = move || {
+        state = state.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    };
+    (0..n).map(move |_| Uuid::from_u64_pair(next(), next()).to_string())
 }
 
 impl From<CheckError> for _CheckError::Response {
@@ -158,28 +319,66 @@ impl From<CheckError> for _CheckError::Response {
             CheckError::NoExamples => _CheckError::no_examples(),
             CheckError::EnvironmentNotFound => _CheckError::environment_not_found(),
             CheckError::EvaluatorFailed(x) => _CheckError::evaluator_failed(x),
+            CheckError::EvaluatorTimeout(x) => _CheckError::evaluator_timeout(x),
             CheckError::InvalidOutput(x) => _CheckError::invalid_output(x),
             CheckError::TestcaseFailed(x) => _CheckError::testcase_failed(x),
+            CheckError::TooManyTestcases(x) => _CheckError::too_many_testcases(x),
         }
     }
 }
 
 #[derive(Debug)]
-enum CheckError {
+pub(crate) enum CheckError {
     /// The list of examples provided by the evaluator is empty.
     NoExamples,
     /// The solution environment does not exist.
     EnvironmentNotFound,
     /// The evaluator crashed.
     EvaluatorFailed(BuildRunResult),
+    /// The evaluator exceeded its time limit.
+    EvaluatorTimeout(BuildRunResult),
     /// The evaluator failed to produce valid output.
     InvalidOutput(BuildRunResult),
     /// The sample solution failed on a specific test case.
     TestcaseFailed(CheckTestcaseError),
+    /// The number of examples, static and random tests exceeds the maximum
+    /// number of test cases.
+    TooManyTestcases(usize),
 }
 
 #[derive(Debug, Object)]
 pub struct CheckTestcaseError {
     pub seed: String,
+    /// The index of the additional solution that failed or `null` if the
+    /// primary solution failed.
+    pub solution: Option<usize>,
     pub result: CheckResult<RunResult>,
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+
+    #[test]
+    fn random_test_seeds_are_deterministic() {
+        let seeds = random_test_seeds(42, 10).collect::<Vec<_>>();
+        assert_eq!(seeds, random_test_seeds(42, 10).collect::<Vec<_>>());
+        assert_eq!(seeds.iter().collect::<HashSet<_>>().len(), 10);
+        assert_eq!(seeds[..3], random_test_seeds(42, 3).collect::<Vec<_>>());
+        assert_ne!(seeds, random_test_seeds(43, 10).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn testcases_at_the_limit() {
+        assert!(!exceeds_max_testcases(3, 20, 27, 50));
+        assert!(!exceeds_max_testcases(50, 0, 0, 50));
+    }
+
+    #[test]
+    fn testcases_over_the_limit() {
+        assert!(exceeds_max_testcases(3, 20, 28, 50));
+        assert!(exceeds_max_testcases(1, u8::MAX, u8::MAX, 510));
+    }
+}
@@ -1,53 +1,83 @@
-use std::{collections::HashMap, sync::Arc};
+use std::{
+    cmp::Reverse,
+    collections::{hash_map::Entry, BTreeMap, HashMap, HashSet},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
 
 use anyhow::{bail, Context};
-use chrono::Utc;
+use chrono::{DateTime, NaiveDateTime, Utc};
 use entity::{
     challenges_coding_challenge_result, challenges_coding_challenge_submissions,
     challenges_coding_challenges, challenges_subtasks, challenges_user_subtasks,
     sea_orm_active_enums::ChallengesVerdict,
 };
-use fnct::{format::JsonFormatter, key};
+use fnct::format::JsonFormatter;
 use key_rwlock::KeyRwLock;
 use lib::{
     auth::{AdminAuth, VerifiedUserAuth},
-    config::Config,
+    config::{Config, SandkastenRetry},
+    metrics::Metrics,
     Cache, SharedState,
 };
 use poem::web::Data;
 use poem_ext::{db::DbTxn, response, responses::ErrorResponse};
-use poem_openapi::{param::Path, payload::Json, OpenApi};
+use poem_openapi::{
+    param::{Header, Path, Query},
+    payload::Json,
+    OpenApi,
+};
 use sandkasten_client::{schemas::environments::Environment, SandkastenClient};
-use schemas::challenges::coding_challenges::{QueueStatus, Submission, SubmissionContent};
+use schemas::challenges::coding_challenges::{
+    QueueStatus, RejudgeStatus, SetVerdictRequest, Submission, SubmissionBundle, SubmissionContent,
+};
 use sea_orm::{
-    ActiveModelTrait, ColumnTrait, DatabaseConnection, DatabaseTransaction, DbErr, EntityTrait,
-    ModelTrait, QueryFilter, QueryOrder, Set, TransactionTrait,
+    sea_query::{Expr, OnConflict},
+    ActiveEnum, ActiveModelTrait, ColumnTrait, DatabaseConnection, DatabaseTransaction, DbErr,
+    EntityTrait, ModelTrait, QueryFilter, QueryOrder, QuerySelect, Set, TransactionTrait,
+    TryInsertResult, Unchanged,
 };
 use thiserror::Error;
-use tokio::sync::{RwLock, Semaphore};
-use tracing::{debug, error, trace};
+use tokio::sync::{watch, Notify, RwLock, Semaphore};
+use tracing::{debug, error, info_span, instrument, trace, Instrument, Span};
 use uuid::Uuid;
 
-use super::{check_challenge, CheckChallenge, CheckError, CheckTestcaseError};
+use super::{check_challenge, random_test_seed, CheckChallenge, CheckError, CheckTestcaseError};
 use crate::{
+    after_commit::AfterCommit,
     endpoints::Tags,
     services::{
         judge::{self, Judge},
         subtasks::{
-            deduct_hearts, get_subtask, get_user_subtask, send_task_rewards, update_user_subtask,
-            SendTaskRewardsError, UserSubtaskExt,
+            attempts_exhausted_with_pending, deduct_hearts, get_subtask, get_user_subtask,
+            publish_subtask_solved, send_task_rewards, submissions_visible_before,
+            update_user_subtask, RewardLock, SendTaskRewardsError, UserSubtaskExt,
         },
+        webhook::{SubmissionJudged, Webhook},
     },
 };
 
+/// Number of seconds after which an idempotency key of a submission expires.
+const IDEMPOTENCY_KEY_TTL: i64 = 24 * 60 * 60;
+
 pub struct Api {
     pub state: Arc<SharedState>,
     pub config: Arc<Config>,
     pub sandkasten: SandkastenClient,
+    pub sandkasten_lock: Arc<Semaphore>,
     pub judge_cache: Cache<JsonFormatter>,
     pub judge_lock: Arc<Semaphore>,
-    pub reward_lock: Arc<KeyRwLock<(Uuid, Uuid)>>,
+    pub reward_lock: Arc<RewardLock>,
     pub queue_positions: Arc<RwLock<QueuePositions>>,
+    /// Held by a user's submission request from the check of
+    /// `max_submissions_per_user` until the submission has been enqueued, and
+    /// while the progress of the user is being reset.
+    pub submission_lock: Arc<KeyRwLock<Uuid>>,
+    pub judge_tasks: JudgeTasks,
+    pub webhook: Option<Webhook>,
 }
 
 #[OpenApi(tag = "Tags::CodingChallenges")]
@@ -63,7 +93,40 @@ impl Api {
         })
     }
 
+    /// List all submissions that have no result, are older than the configured
+    /// threshold and are not in the judge queue.
+    #[oai(path = "/coding_challenges/stuck", method = "get")]
+    async fn list_stuck_submissions(
+        &self,
+        _auth: AdminAuth,
+    ) -> ListStuckSubmissions::Response<AdminAuth> {
+        ListStuckSubmissions::ok(
+            self.find_stuck_submissions()
+                .await?
+                .iter()
+                .map(|submission| Submission::from(submission, None, None))
+                .collect(),
+        )
+    }
+
+    /// Add all stuck submissions (see above) back to the judge queue.
+    #[oai(path = "/coding_challenges/stuck/requeue", method = "post")]
+    async fn requeue_stuck_submissions(
+        &self,
+        _auth: AdminAuth,
+    ) -> RequeueStuckSubmissions::Response<AdminAuth> {
+        let submissions = self.find_stuck_submissions().await?;
+        RequeueStuckSubmissions::ok(
+            self.enqueue_submissions(submissions)
+                .await?
+                .into_iter()
+                .map(|(submission, position)| Submission::from(&submission, None, Some(position)))
+                .collect(),
+        )
+    }
+
     /// List all submissions of a coding challenge.
+    #[allow(clippy::too_many_arguments)]
     #[oai(
         path = "/tasks/:task_id/coding_challenges/:subtask_id/submissions",
         method = "get"
@@ -72,6 +135,16 @@ impl Api {
         &self,
         task_id: Path<Uuid>,
         subtask_id: Path<Uuid>,
+        /// Only include submissions with this verdict.
+        verdict: Query<Option<ChallengesVerdict>>,
+        /// Only include submissions created at or after this time.
+        since: Query<Option<DateTime<Utc>>>,
+        /// Only include submissions created before this time.
+        until: Query<Option<DateTime<Utc>>>,
+        /// Maximum number of submissions to return
+        limit: Query<Option<u64>>,
+        /// Pagination offset
+        offset: Query<Option<u64>>,
         db: Data<&DbTxn>,
         auth: VerifiedUserAuth,
     ) -> ListSubmissions::Response<VerifiedUserAuth> {
@@ -81,21 +154,52 @@ impl Api {
         else {
             return ListSubmissions::subtask_not_found();
         };
-        if !auth.0.admin && auth.0.id != subtask.creator && !subtask.enabled {
+        let Some(visible_before) = submissions_visible_before(&auth.0, &subtask) else {
             return ListSubmissions::subtask_not_found();
+        };
+
+        let mut query = cc
+            .find_related(challenges_coding_challenge_submissions::Entity)
+            .filter(challenges_coding_challenge_submissions::Column::Creator.eq(auth.0.id))
+            .find_also_related(challenges_coding_challenge_result::Entity)
+            .order_by_desc(challenges_coding_challenge_submissions::Column::CreationTimestamp)
+            .limit(limit.0)
+            .offset(offset.0);
+        if let Some(verdict) = verdict.0 {
+            query = query.filter(challenges_coding_challenge_result::Column::Verdict.eq(verdict));
+        }
+        if let Some(since) = since.0 {
+            query = query.filter(
+                challenges_coding_challenge_submissions::Column::CreationTimestamp
+                    .gte(since.naive_utc()),
+            );
+        }
+        if let Some(until) = until.0 {
+            query = query.filter(
+                challenges_coding_challenge_submissions::Column::CreationTimestamp
+                    .lt(until.naive_utc()),
+            );
+        }
+        if let Some(visible_before) = visible_before {
+            query = query.filter(
+                challenges_coding_challenge_submissions::Column::CreationTimestamp
+                    .lt(visible_before),
+            );
         }
 
         let queue_positions = self.queue_positions.read().await;
         ListSubmissions::ok(
-            cc.find_related(challenges_coding_challenge_submissions::Entity)
-                .filter(challenges_coding_challenge_submissions::Column::Creator.eq(auth.0.id))
-                .find_also_related(challenges_coding_challenge_result::Entity)
-                .order_by_desc(challenges_coding_challenge_submissions::Column::CreationTimestamp)
+            query
                 .all(&***db)
                 .await?
                 .into_iter()
                 .map(|(submission, result)| {
-                    let position = queue_positions.position(submission.id);
+                    // the result may already exist while the judge task has not been
+                    // removed from the queue yet
+                    let position = match result {
+                        Some(_) => None,
+                        None => queue_positions.status(submission.id).position(),
+                    };
                     Submission::from(&submission, result.map(Into::into), position)
                 })
                 .collect(),
@@ -121,9 +225,9 @@ impl Api {
         else {
             return GetSubmission::submission_not_found();
         };
-        if !auth.0.admin && auth.0.id != subtask.creator && !subtask.enabled {
+        let Some(visible_before) = submissions_visible_before(&auth.0, &subtask) else {
             return GetSubmission::submission_not_found();
-        }
+        };
 
         let Some(submission) =
             challenges_coding_challenge_submissions::Entity::find_by_id(submission_id.0)
@@ -136,6 +240,9 @@ impl Api {
         else {
             return GetSubmission::submission_not_found();
         };
+        if visible_before.is_some_and(|x| submission.creation_timestamp >= x) {
+            return GetSubmission::submission_not_found();
+        }
 
         GetSubmission::ok(SubmissionContent {
             environment: submission.environment,
@@ -143,7 +250,72 @@ impl Api {
         })
     }
 
+    /// Get a submission of a coding challenge together with its evaluation
+    /// result.
+    ///
+    /// Only the creator of the submission and admins may access the bundle.
+    #[oai(
+        path = "/tasks/:task_id/coding_challenges/:subtask_id/submissions/:submission_id/bundle",
+        method = "get"
+    )]
+    async fn get_submission_bundle(
+        &self,
+        task_id: Path<Uuid>,
+        subtask_id: Path<Uuid>,
+        submission_id: Path<Uuid>,
+        db: Data<&DbTxn>,
+        auth: VerifiedUserAuth,
+    ) -> GetSubmissionBundle::Response<VerifiedUserAuth> {
+        let Some((cc, subtask)) =
+            get_subtask::<challenges_coding_challenges::Entity>(&db, task_id.0, subtask_id.0)
+                .await?
+        else {
+            return GetSubmissionBundle::submission_not_found();
+        };
+        let Some(visible_before) = submissions_visible_before(&auth.0, &subtask) else {
+            return GetSubmissionBundle::submission_not_found();
+        };
+
+        let mut query = challenges_coding_challenge_submissions::Entity::find_by_id(
+            submission_id.0,
+        )
+        .filter(challenges_coding_challenge_submissions::Column::SubtaskId.eq(cc.subtask_id));
+        if !auth.0.admin {
+            query = query
+                .filter(challenges_coding_challenge_submissions::Column::Creator.eq(auth.0.id));
+        }
+        let Some((submission, result)) = query
+            .find_also_related(challenges_coding_challenge_result::Entity)
+            .one(&***db)
+            .await?
+        else {
+            return GetSubmissionBundle::submission_not_found();
+        };
+        if visible_before.is_some_and(|x| submission.creation_timestamp >= x) {
+            return GetSubmissionBundle::submission_not_found();
+        }
+
+        let test_seed = result.as_ref().and_then(|x| x.test_seed).map(|x| x as _);
+        GetSubmissionBundle::ok(SubmissionBundle {
+            id: submission.id,
+            subtask_id: submission.subtask_id,
+            creator: submission.creator,
+            creation_timestamp: submission.creation_timestamp.and_utc(),
+            content: SubmissionContent {
+                environment: submission.environment,
+                code: submission.code,
+            },
+            result: result.map(Into::into),
+            test_seed,
+        })
+    }
+
     /// Create a submission for a coding challenge.
+    ///
+    /// Clients may send an `Idempotency-Key` header to safely retry requests:
+    /// If the user has created a submission for this coding challenge with the
+    /// same key within the last 24 hours, this submission is returned instead
+    /// of creating a new one.
     #[oai(
         path = "/tasks/:task_id/coding_challenges/:subtask_id/submissions",
         method = "post"
@@ -153,9 +325,19 @@ impl Api {
         task_id: Path<Uuid>,
         subtask_id: Path<Uuid>,
         data: Json<SubmissionContent>,
+        /// Key to detect retried requests (see above).
+        #[oai(name = "Idempotency-Key", validator(max_length = 256))]
+        idempotency_key: Header<Option<String>>,
         db: Data<&DbTxn>,
         auth: VerifiedUserAuth,
     ) -> CreateSubmission::Response<VerifiedUserAuth> {
+        if self.judge_tasks.is_closed() {
+            return CreateSubmission::shutting_down();
+        }
+        if let Some(field) = data.0.oversized_field() {
+            return CreateSubmission::content_too_large(field.into());
+        }
+
         let Some((cc, subtask)) =
             get_subtask::<challenges_coding_challenges::Entity>(&db, task_id.0, subtask_id.0)
                 .await?
@@ -166,6 +348,29 @@ impl Api {
             return CreateSubmission::subtask_not_found();
         }
 
+        if let Some(key) = &idempotency_key.0 {
+            if let Some((submission, result)) =
+                find_submission_by_idempotency_key(&db, subtask.id, auth.0.id, key).await?
+            {
+                if (Utc::now().naive_utc() - submission.creation_timestamp).num_seconds()
+                    < IDEMPOTENCY_KEY_TTL
+                {
+                    return CreateSubmission::existing(
+                        self.existing_submission(submission, result).await,
+                    );
+                }
+
+                // the key has expired and may be used for the new submission
+                challenges_coding_challenge_submissions::ActiveModel {
+                    id: Unchanged(submission.id),
+                    idempotency_key: Set(None),
+                    ..Default::default()
+                }
+                .update(&***db)
+                .await?;
+            }
+        }
+
         if !self
             .get_environments()
             .await?
@@ -174,21 +379,72 @@ impl Api {
             return CreateSubmission::environment_not_found();
         }
 
-        let user_subtask = get_user_subtask(&db, auth.0.id, subtask.id).await?;
+        let service_account = self.config.challenges.is_service_account(auth.0.id);
+
+        if !auth.0.admin && auth.0.id != subtask.creator && !service_account {
+            let user_subtask = get_user_subtask(&db, auth.0.id, subtask.id).await?;
 
-        if let Some(last_attempt) = user_subtask.last_attempt() {
-            let time_left = self.config.challenges.coding_challenges.timeout as i64
-                - (Utc::now() - last_attempt).num_seconds();
-            if time_left > 0 {
-                return CreateSubmission::too_many_requests(time_left as u64);
+            // `last_attempt` is only updated after a submission has been judged,
+            // so also take the most recent submission into account to prevent
+            // spamming the judge
+            let last_submission = challenges_coding_challenge_submissions::Entity::find()
+                .filter(challenges_coding_challenge_submissions::Column::SubtaskId.eq(subtask.id))
+                .filter(challenges_coding_challenge_submissions::Column::Creator.eq(auth.0.id))
+                .order_by_desc(challenges_coding_challenge_submissions::Column::CreationTimestamp)
+                .one(&***db)
+                .await?;
+            if let Some(last_attempt) = user_subtask
+                .last_attempt()
+                .into_iter()
+                .chain(last_submission.map(|x| x.creation_timestamp.and_utc()))
+                .max()
+            {
+                let time_left = self.config.challenges.coding_challenges.timeout as i64
+                    - (Utc::now() - last_attempt).num_seconds();
+                if time_left > 0 {
+                    return CreateSubmission::too_many_requests(time_left as u64);
+                }
             }
         }
 
-        if !deduct_hearts(&self.state.services, &self.config, &auth.0, &subtask).await? {
-            return CreateSubmission::not_enough_hearts();
+        // parallel requests of the same user must not all pass the limit before
+        // any of their submissions has been enqueued
+        let _guard = self.submission_lock.write(auth.0.id).await;
+        if !auth.0.admin && auth.0.id != subtask.creator && !service_account {
+            let active = self
+                .queue_positions
+                .read()
+                .await
+                .user_submissions(auth.0.id);
+            if active
+                >= self
+                    .config
+                    .challenges
+                    .coding_challenges
+                    .max_submissions_per_user
+            {
+                return CreateSubmission::too_many_concurrent_submissions(active as _);
+            }
+        }
+
+        // queued submissions have not been counted as attempts yet
+        let user_subtask = get_user_subtask(&db, auth.0.id, subtask.id).await?;
+        let queued = self
+            .queue_positions
+            .read()
+            .await
+            .user_subtask_submissions(auth.0.id, subtask.id);
+        if attempts_exhausted_with_pending(&auth.0, &subtask, &user_subtask, queued) {
+            return CreateSubmission::attempts_exhausted();
         }
 
-        let submission = Arc::new(
+        // A concurrent request with the same idempotency key may have created a
+        // submission since the check above. In this case the insert waits for the
+        // other transaction and the existing submission is returned. Hearts are only
+        // deducted after the submission has been inserted, so retries never pay
+        // twice. If the user does not have enough hearts, the transaction is rolled
+        // back and the submission is discarded.
+        let submission = match challenges_coding_challenge_submissions::Entity::insert(
             challenges_coding_challenge_submissions::ActiveModel {
                 id: Set(Uuid::new_v4()),
                 subtask_id: Set(cc.subtask_id),
@@ -196,30 +452,315 @@ impl Api {
                 creation_timestamp: Set(Utc::now().naive_utc()),
                 environment: Set(data.0.environment),
                 code: Set(data.0.code),
+                idempotency_key: Set(idempotency_key.0.clone()),
+                rejudge: Set(false),
+            },
+        )
+        .on_conflict(
+            OnConflict::columns([
+                challenges_coding_challenge_submissions::Column::Creator,
+                challenges_coding_challenge_submissions::Column::SubtaskId,
+                challenges_coding_challenge_submissions::Column::IdempotencyKey,
+            ])
+            .target_and_where(
+                challenges_coding_challenge_submissions::Column::IdempotencyKey.is_not_null(),
+            )
+            .do_nothing()
+            .to_owned(),
+        )
+        .do_nothing()
+        .exec_with_returning(&***db)
+        .await?
+        {
+            TryInsertResult::Inserted(submission) => Arc::new(submission),
+            TryInsertResult::Empty | TryInsertResult::Conflicted => {
+                let key = idempotency_key.0.as_deref().unwrap_or_default();
+                let Some((submission, result)) =
+                    find_submission_by_idempotency_key(&db, subtask.id, auth.0.id, key).await?
+                else {
+                    return Err(DbErr::RecordNotInserted.into());
+                };
+                return CreateSubmission::existing(
+                    self.existing_submission(submission, result).await,
+                );
             }
-            .insert(&***db)
-            .await?,
-        );
+        };
+
+        let Some(hearts) =
+            deduct_hearts(&self.state.services, &self.config, &auth.0, &subtask).await?
+        else {
+            return CreateSubmission::not_enough_hearts();
+        };
 
+        let priority = if auth.0.admin || auth.0.id == subtask.creator {
+            QueuePositions::PRIORITY_HIGH
+        } else {
+            QueuePositions::PRIORITY_DEFAULT
+        };
         let position = start_judge_submission_task(StartJudgeSubmissionTask {
             submission: Arc::clone(&submission),
             subtask,
             judge_lock: Arc::clone(&self.judge_lock),
             db: self.state.db.clone(),
             sandkasten: self.sandkasten.clone(),
+            sandkasten_lock: Arc::clone(&self.sandkasten_lock),
             cache: self.judge_cache.clone(),
             reward_lock: Arc::clone(&self.reward_lock),
             state: Arc::clone(&self.state),
             challenge: Arc::new(cc),
-            user_subtask,
+            rejudge: false,
+            priority,
+            hearts,
+            evaluator_timeout: self.config.challenges.coding_challenges.evaluator_timeout,
+            max_testcases: self.config.challenges.coding_challenges.max_testcases,
+            sandkasten_retry: self.config.challenges.coding_challenges.sandkasten_retry,
+            evaluator_cache_ttl: self
+                .config
+                .challenges
+                .coding_challenges
+                .evaluator_cache_ttl(),
             queue_positions: Arc::clone(&self.queue_positions),
+            judge_tasks: self.judge_tasks.clone(),
+            webhook: self.webhook.clone(),
         })
         .await;
 
         CreateSubmission::ok(Submission::from(&submission, None, Some(position)))
     }
+
+    /// Judge all submissions of a coding challenge again.
+    ///
+    /// Existing results are deleted and every submission that is not already
+    /// waiting in the judge queue is enqueued again. Rewards are only sent to
+    /// users who have not solved the challenge before.
+    #[oai(
+        path = "/tasks/:task_id/coding_challenges/:subtask_id/rejudge",
+        method = "post"
+    )]
+    async fn rejudge_challenge(
+        &self,
+        task_id: Path<Uuid>,
+        subtask_id: Path<Uuid>,
+        db: Data<&DbTxn>,
+        _auth: AdminAuth,
+    ) -> RejudgeChallenge::Response<AdminAuth> {
+        let Some((cc, subtask)) =
+            get_subtask::<challenges_coding_challenges::Entity>(&db, task_id.0, subtask_id.0)
+                .await?
+        else {
+            return RejudgeChallenge::subtask_not_found();
+        };
+
+        let submissions = challenges_coding_challenge_submissions::Entity::find()
+            .filter(challenges_coding_challenge_submissions::Column::SubtaskId.eq(cc.subtask_id))
+            .order_by_asc(challenges_coding_challenge_submissions::Column::CreationTimestamp)
+            .all(&***db)
+            .await?;
+        let submissions = {
+            let queue_positions = self.queue_positions.read().await;
+            submissions
+                .into_iter()
+                .filter(|submission| queue_positions.position(submission.id).is_none())
+                .collect::<Vec<_>>()
+        };
+
+        // remember that these submissions are rejudged, so their attempts are not
+        // counted again if the judge is interrupted and resumed
+        challenges_coding_challenge_submissions::Entity::update_many()
+            .col_expr(
+                challenges_coding_challenge_submissions::Column::Rejudge,
+                Expr::value(true),
+            )
+            .filter(
+                challenges_coding_challenge_submissions::Column::Id
+                    .is_in(submissions.iter().map(|submission| submission.id)),
+            )
+            .exec(&self.state.db)
+            .await?;
+
+        // the judge tasks use their own transactions, so the old results have to be
+        // deleted before they are started
+        challenges_coding_challenge_result::Entity::delete_many()
+            .filter(
+                challenges_coding_challenge_result::Column::SubmissionId
+                    .is_in(submissions.iter().map(|submission| submission.id)),
+            )
+            .exec(&self.state.db)
+            .await?;
+
+        let challenge = Arc::new(cc);
+        let queued = submissions.len();
+        for submission in submissions {
+            start_judge_submission_task(StartJudgeSubmissionTask {
+                submission: Arc::new(submission),
+                subtask: subtask.clone(),
+                judge_lock: Arc::clone(&self.judge_lock),
+                db: self.state.db.clone(),
+                sandkasten: self.sandkasten.clone(),
+                sandkasten_lock: Arc::clone(&self.sandkasten_lock),
+                cache: self.judge_cache.clone(),
+                reward_lock: Arc::clone(&self.reward_lock),
+                state: Arc::clone(&self.state),
+                challenge: Arc::clone(&challenge),
+                rejudge: true,
+                priority: QueuePositions::PRIORITY_DEFAULT,
+                hearts: 0,
+                evaluator_timeout: self.config.challenges.coding_challenges.evaluator_timeout,
+                max_testcases: self.config.challenges.coding_challenges.max_testcases,
+                sandkasten_retry: self.config.challenges.coding_challenges.sandkasten_retry,
+                evaluator_cache_ttl: self
+                    .config
+                    .challenges
+                    .coding_challenges
+                    .evaluator_cache_ttl(),
+                queue_positions: Arc::clone(&self.queue_positions),
+                judge_tasks: self.judge_tasks.clone(),
+                webhook: self.webhook.clone(),
+            })
+            .await;
+        }
+
+        RejudgeChallenge::ok(RejudgeStatus { queued })
+    }
+
+    /// Override the verdict of a submission.
+    ///
+    /// If the verdict is `OK` and the creator of the submission has not solved
+    /// the coding challenge before, the subtask is marked as solved and the
+    /// rewards are sent. Other verdicts never revoke a solve.
+    #[allow(clippy::too_many_arguments)]
+    #[oai(
+        path = "/tasks/:task_id/coding_challenges/:subtask_id/submissions/:submission_id/verdict",
+        method = "put"
+    )]
+    async fn set_verdict(
+        &self,
+        task_id: Path<Uuid>,
+        subtask_id: Path<Uuid>,
+        submission_id: Path<Uuid>,
+        data: Json<SetVerdictRequest>,
+        db: Data<&DbTxn>,
+        after_commit: Data<&AfterCommit>,
+        _auth: AdminAuth,
+    ) -> SetVerdict::Response<AdminAuth> {
+        let Some((cc, subtask)) =
+            get_subtask::<challenges_coding_challenges::Entity>(&db, task_id.0, subtask_id.0)
+                .await?
+        else {
+            return SetVerdict::submission_not_found();
+        };
+        let Some((submission, result)) =
+            challenges_coding_challenge_submissions::Entity::find_by_id(submission_id.0)
+                .filter(
+                    challenges_coding_challenge_submissions::Column::SubtaskId.eq(cc.subtask_id),
+                )
+                .find_also_related(challenges_coding_challenge_result::Entity)
+                .one(&***db)
+                .await?
+        else {
+            return SetVerdict::submission_not_found();
+        };
+        if self
+            .queue_positions
+            .read()
+            .await
+            .position(submission.id)
+            .is_some()
+        {
+            return SetVerdict::submission_queued();
+        }
+
+        if data.0.verdict == ChallengesVerdict::Ok {
+            let _guard = self
+                .reward_lock
+                .write((submission.subtask_id, submission.creator))
+                .await;
+
+            let user_subtask = get_user_subtask(&db, submission.creator, subtask.id).await?;
+            if !user_subtask.is_solved() {
+                let updated = update_user_subtask(
+                    &db,
+                    user_subtask.as_ref(),
+                    challenges_user_subtasks::ActiveModel {
+                        user_id: Set(submission.creator),
+                        subtask_id: Set(subtask.id),
+                        solved_timestamp: Set(Some(submission.creation_timestamp)),
+                        last_attempt_timestamp: Set(Some(submission.creation_timestamp)),
+                        // submissions without a result have not been counted yet
+                        attempts: Set(user_subtask.attempts() as i32 + result.is_none() as i32),
+                        ..Default::default()
+                    },
+                )
+                .await?;
+
+                // `None` if the subtask has been solved concurrently
+                if updated.is_some() {
+                    if submission.creator != subtask.creator {
+                        send_task_rewards(&self.state.services, &db, submission.creator, &subtask)
+                            .await?;
+                    }
+                    publish_subtask_solved(
+                        &after_commit,
+                        &self.state.services,
+                        submission.creator,
+                        &subtask,
+                        submission.creation_timestamp,
+                    );
+                }
+            }
+        }
+
+        let result = match result {
+            Some(result) => {
+                challenges_coding_challenge_result::ActiveModel {
+                    submission_id: Unchanged(result.submission_id),
+                    verdict: Set(data.0.verdict),
+                    reason: Set(data.0.reason),
+                    reason_code: Set(data.0.reason_code),
+                    ..Default::default()
+                }
+                .update(&***db)
+                .await?
+            }
+            None => {
+                challenges_coding_challenge_result::ActiveModel {
+                    submission_id: Set(submission.id),
+                    verdict: Set(data.0.verdict),
+                    reason: Set(data.0.reason),
+                    reason_code: Set(data.0.reason_code),
+                    build_status: Set(None),
+                    build_stderr: Set(None),
+                    build_time: Set(None),
+                    build_memory: Set(None),
+                    run_status: Set(None),
+                    run_stderr: Set(None),
+                    run_time: Set(None),
+                    run_memory: Set(None),
+                    test_seed: Set(None),
+                }
+                .insert(&***db)
+                .await?
+            }
+        };
+
+        SetVerdict::ok(Submission::from(&submission, Some(result.into()), None))
+    }
 }
 
+response!(RejudgeChallenge = {
+    Ok(200) => RejudgeStatus,
+    /// Subtask does not exist.
+    SubtaskNotFound(404, error),
+});
+
+response!(SetVerdict = {
+    Ok(200) => Submission,
+    /// Submission does not exist.
+    SubmissionNotFound(404, error),
+    /// The submission is currently waiting in the judge queue.
+    SubmissionQueued(409, error),
+});
+
 response!(GetQueueStatus = {
     Ok(200) => QueueStatus,
 });
@@ -236,16 +777,41 @@ response!(GetSubmission = {
     SubmissionNotFound(404, error),
 });
 
+response!(GetSubmissionBundle = {
+    Ok(200) => SubmissionBundle,
+    /// Submission does not exist.
+    SubmissionNotFound(404, error),
+});
+
+response!(ListStuckSubmissions = {
+    Ok(200) => Vec<Submission>,
+});
+
+response!(RequeueStuckSubmissions = {
+    /// The submissions that have been added to the judge queue.
+    Ok(200) => Vec<Submission>,
+});
+
 response!(CreateSubmission = {
     Ok(201) => Submission,
+    /// A submission with the same idempotency key already exists.
+    Existing(200) => Submission,
     /// Try again later. `details` contains the number of seconds to wait.
     TooManyRequests(429, error) => u64,
+    /// The user has too many submissions in the judge queue. `details` contains the number of queued submissions.
+    TooManyConcurrentSubmissions(429, error) => u64,
     /// Subtask does not exist.
     SubtaskNotFound(404, error),
     /// The solution environment does not exist.
     EnvironmentNotFound(404, error),
+    /// A field exceeds its size limit in bytes. `details` contains the name of the field.
+    ContentTooLarge(413, error) => String,
     /// The user does not have enough hearts to submit a solution and is neither an admin nor the creator of this subtask.
     NotEnoughHearts(403, error),
+    /// The user has used up all attempts to solve this subtask.
+    AttemptsExhausted(403, error),
+    /// The service is shutting down and does not accept new submissions.
+    ShuttingDown(503, error),
 });
 
 struct StartJudgeSubmissionTask {
@@ -254,12 +820,22 @@ struct StartJudgeSubmissionTask {
     judge_lock: Arc<Semaphore>,
     db: DatabaseConnection,
     sandkasten: SandkastenClient,
+    sandkasten_lock: Arc<Semaphore>,
     cache: Cache<JsonFormatter>,
-    reward_lock: Arc<KeyRwLock<(Uuid, Uuid)>>,
+    reward_lock: Arc<RewardLock>,
     state: Arc<SharedState>,
     challenge: Arc<challenges_coding_challenges::Model>,
-    user_subtask: Option<challenges_user_subtasks::Model>,
+    rejudge: bool,
+    priority: u8,
+    /// The number of hearts to refund if the submission cannot be judged.
+    hearts: u32,
+    evaluator_timeout: u64,
+    max_testcases: usize,
+    sandkasten_retry: SandkastenRetry,
+    evaluator_cache_ttl: Option<Duration>,
     queue_positions: Arc<RwLock<QueuePositions>>,
+    judge_tasks: JudgeTasks,
+    webhook: Option<Webhook>,
 }
 
 async fn start_judge_submission_task(
@@ -268,32 +844,85 @@ async fn start_judge_submission_task(
         judge_lock,
         db,
         sandkasten,
+        sandkasten_lock,
         cache,
         reward_lock,
         state,
         challenge: cc,
         queue_positions,
         subtask,
-        user_subtask,
+        rejudge,
+        priority,
+        hearts,
+        evaluator_timeout,
+        max_testcases,
+        sandkasten_retry,
+        evaluator_cache_ttl,
+        judge_tasks,
+        webhook,
     }: StartJudgeSubmissionTask,
 ) -> usize {
-    let position = queue_positions.write().await.push(submission.id);
+    let (position, notify) = {
+        let mut qp = queue_positions.write().await;
+        let position = qp.push(
+            submission.id,
+            submission.creator,
+            submission.subtask_id,
+            priority,
+        );
+        qp.update_metrics(&state.metrics);
+        (position, qp.notify())
+    };
     trace!(
         "submission {} enqueued at position {}",
         submission.id,
         position
     );
+    let guard = judge_tasks.track();
+    // the task outlives the request, so it only follows from the request's span
+    let span = info_span!(
+        parent: None,
+        "judge_task",
+        submission_id = %submission.id,
+        subtask_id = %submission.subtask_id,
+    );
+    span.follows_from(Span::current());
     tokio::spawn({
         async move {
+            let _guard = guard;
             let submission_id = submission.id;
+            let creator = submission.creator;
+            let metrics = state.metrics.clone();
+            let services = state.services.clone();
+            // the user should not lose hearts because of an error on our side
+            let refund = || async {
+                if hearts == 0 {
+                    return;
+                }
+                if let Err(err) = services.shop.add_hearts(creator, hearts as _).await {
+                    error!(
+                        "failed to refund {hearts} hearts for submission {submission_id}: {err}"
+                    );
+                }
+            };
             let pop = || async {
-                if !queue_positions.write().await.pop(submission_id) {
+                let mut qp = queue_positions.write().await;
+                if !qp.pop(submission_id) {
                     error!("judge task for {submission_id} failed to pop queue position");
                 }
+                qp.update_metrics(&metrics);
             };
+            // wait until the submission is picked up by a worker
+            loop {
+                let notified = notify.notified();
+                if queue_positions.read().await.position(submission_id) == Some(0) {
+                    break;
+                }
+                notified.await;
+            }
             let Ok(_guard) = judge_lock.acquire().await else {
                 error!("judge task for {submission_id} failed to acquire lock",);
-                // don't pop here since we didn't get the semaphore permit
+                pop().await;
                 return;
             };
             let db = match db.begin().await {
@@ -306,28 +935,59 @@ async fn start_judge_submission_task(
             };
             let judge = Judge {
                 sandkasten: &sandkasten,
+                sandkasten_lock: &sandkasten_lock,
                 evaluator: &cc.evaluator,
+                evaluator_environment: &cc.evaluator_environment,
                 cache: &cache,
+                evaluator_timeout,
+                retry: sandkasten_retry,
+                cache_ttl: evaluator_cache_ttl,
             };
-            if let Err(err) = judge_submission(JudgeSubmission {
+            let after_commit = AfterCommit::default();
+            match judge_submission(JudgeSubmission {
                 db: &db,
+                after_commit: &after_commit,
                 subtask: &subtask,
                 challenge: &cc,
-                submission,
+                submission: Arc::clone(&submission),
                 judge,
                 reward_lock,
                 state,
-                user_subtask,
+                rejudge,
+                max_testcases,
             })
             .await
             {
-                error!("judge task for {submission_id} failed: {err}");
-                db.rollback().await.ok();
-            } else if let Err(err) = db.commit().await {
-                error!("judge task for {submission_id} failed to commit db transaction: {err}");
+                Err(err) => {
+                    error!("judge task for {submission_id} failed: {err}");
+                    db.rollback().await.ok();
+                    if err.is_infrastructure_error() {
+                        refund().await;
+                    }
+                }
+                Ok(verdict) => match db.commit().await {
+                    Ok(()) => {
+                        after_commit.spawn();
+                        if let Some(webhook) = &webhook {
+                            webhook.send(&SubmissionJudged {
+                                submission_id,
+                                subtask_id: submission.subtask_id,
+                                creator,
+                                verdict,
+                            });
+                        }
+                    }
+                    Err(err) => {
+                        error!(
+                            "judge task for {submission_id} failed to commit db transaction: {err}"
+                        );
+                        refund().await;
+                    }
+                },
             }
             pop().await;
         }
+        .instrument(span)
     });
 
     position
@@ -335,49 +995,80 @@ async fn start_judge_submission_task(
 
 struct JudgeSubmission<'a, 'b> {
     db: &'a DatabaseTransaction,
+    after_commit: &'a AfterCommit,
     subtask: &'a challenges_subtasks::Model,
     challenge: &'a challenges_coding_challenges::Model,
     submission: Arc<challenges_coding_challenge_submissions::Model>,
     judge: Judge<'b>,
-    reward_lock: Arc<KeyRwLock<(Uuid, Uuid)>>,
+    reward_lock: Arc<RewardLock>,
     state: Arc<SharedState>,
-    user_subtask: Option<challenges_user_subtasks::Model>,
+    /// Whether this submission has been judged before.
+    rejudge: bool,
+    max_testcases: usize,
 }
 
+#[instrument(skip_all, fields(submission_id = %submission.id, subtask_id = %subtask.id))]
 async fn judge_submission(
     JudgeSubmission {
         db,
+        after_commit,
         subtask,
         challenge,
         submission,
         judge,
         reward_lock,
         state,
-        user_subtask,
+        rejudge,
+        max_testcases,
     }: JudgeSubmission<'_, '_>,
-) -> Result<(), JudgeSubmissionError> {
+) -> Result<ChallengesVerdict, JudgeSubmissionError> {
     debug!("judging submission {}", submission.id);
+    let start = Instant::now();
+    let test_seed = challenge
+        .test_seed
+        .map(|x| x as u64)
+        .unwrap_or_else(random_test_seed);
     let result = check_challenge(CheckChallenge {
         judge,
         challenge_id: challenge.subtask_id,
         solution_environment: &submission.environment,
         solution_code: &submission.code,
+        additional_solutions: &[],
         time_limit: challenge.time_limit as _,
         memory_limit: challenge.memory_limit as _,
         static_tests: challenge.static_tests as _,
         random_tests: challenge.random_tests as _,
+        test_seed,
+        max_testcases,
     })
     .await?;
+    state
+        .metrics
+        .submission_judge_time
+        .observe(start.elapsed().as_secs_f64());
     trace!("judge result for {}: {result:?}", submission.id);
-    match result {
+    // attempts of rejudged submissions have already been counted
+    let attempts = |user_subtask: &Option<challenges_user_subtasks::Model>| {
+        user_subtask.attempts() as i32 + !rejudge as i32
+    };
+    let verdict = match result {
         Ok(()) => {
+            state
+                .metrics
+                .submission_verdicts
+                .with_label_values(&[&ChallengesVerdict::Ok.to_value()])
+                .inc();
+
             let _guard = reward_lock
                 .write((submission.subtask_id, submission.creator))
                 .await;
 
+            // don't rely on a snapshot here, other submissions of the same user may have
+            // been judged in the meantime
+            let user_subtask = get_user_subtask(db, submission.creator, subtask.id).await?;
             let solved_previously = user_subtask.is_solved();
             if !solved_previously {
-                update_user_subtask(
+                let updated = update_user_subtask(
                     db,
                     user_subtask.as_ref(),
                     challenges_user_subtasks::ActiveModel {
@@ -385,20 +1076,31 @@ async fn judge_submission(
                         subtask_id: Set(subtask.id),
                         solved_timestamp: Set(Some(submission.creation_timestamp)),
                         last_attempt_timestamp: Set(Some(submission.creation_timestamp)),
-                        attempts: Set(user_subtask.attempts() as i32 + 1),
+                        attempts: Set(attempts(&user_subtask)),
                         ..Default::default()
                     },
                 )
                 .await?;
 
-                if submission.creator != subtask.creator {
-                    send_task_rewards(&state.services, db, submission.creator, subtask).await?;
+                // `None` if the subtask has been solved concurrently
+                if updated.is_some() {
+                    if submission.creator != subtask.creator {
+                        send_task_rewards(&state.services, db, submission.creator, subtask).await?;
+                    }
+                    publish_subtask_solved(
+                        after_commit,
+                        &state.services,
+                        submission.creator,
+                        subtask,
+                        submission.creation_timestamp,
+                    );
                 }
             }
             challenges_coding_challenge_result::ActiveModel {
                 submission_id: Set(submission.id),
                 verdict: Set(ChallengesVerdict::Ok),
                 reason: Set(None),
+                reason_code: Set(None),
                 build_status: Set(None),
                 build_stderr: Set(None),
                 build_time: Set(None),
@@ -407,11 +1109,19 @@ async fn judge_submission(
                 run_stderr: Set(None),
                 run_time: Set(None),
                 run_memory: Set(None),
+                test_seed: Set(Some(test_seed as _)),
             }
             .insert(db)
             .await?;
+            ChallengesVerdict::Ok
         }
         Err(CheckError::TestcaseFailed(CheckTestcaseError { result, .. })) => {
+            state
+                .metrics
+                .submission_verdicts
+                .with_label_values(&[&result.verdict.to_value()])
+                .inc();
+
             let (build_status, build_stderr, build_time, build_memory) = match result.compile {
                 Some(x) => (
                     Some(x.status),
@@ -430,22 +1140,26 @@ async fn judge_submission(
                 ),
                 None => (None, None, None, None),
             };
-            update_user_subtask(
-                db,
-                user_subtask.as_ref(),
-                challenges_user_subtasks::ActiveModel {
-                    user_id: Set(submission.creator),
-                    subtask_id: Set(subtask.id),
-                    last_attempt_timestamp: Set(Some(submission.creation_timestamp)),
-                    attempts: Set(user_subtask.attempts() as i32 + 1),
-                    ..Default::default()
-                },
-            )
-            .await?;
+            if !rejudge {
+                let user_subtask = get_user_subtask(db, submission.creator, subtask.id).await?;
+                update_user_subtask(
+                    db,
+                    user_subtask.as_ref(),
+                    challenges_user_subtasks::ActiveModel {
+                        user_id: Set(submission.creator),
+                        subtask_id: Set(subtask.id),
+                        last_attempt_timestamp: Set(Some(submission.creation_timestamp)),
+                        attempts: Set(attempts(&user_subtask)),
+                        ..Default::default()
+                    },
+                )
+                .await?;
+            }
             challenges_coding_challenge_result::ActiveModel {
                 submission_id: Set(submission.id),
                 verdict: Set(result.verdict),
                 reason: Set(result.reason),
+                reason_code: Set(result.reason_code),
                 build_status: Set(build_status),
                 build_stderr: Set(build_stderr),
                 build_time: Set(build_time),
@@ -454,20 +1168,43 @@ async fn judge_submission(
                 run_stderr: Set(run_stderr),
                 run_time: Set(run_time),
                 run_memory: Set(run_memory),
+                test_seed: Set(Some(test_seed as _)),
             }
             .insert(db)
             .await?;
+            result.verdict
         }
         Err(err) => return Err(JudgeSubmissionError::Check(Box::new(err))),
-    }
+    };
+
+    Ok(verdict)
+}
 
-    Ok(())
+async fn find_submission_by_idempotency_key(
+    db: &DatabaseTransaction,
+    subtask_id: Uuid,
+    creator: Uuid,
+    key: &str,
+) -> Result<
+    Option<(
+        challenges_coding_challenge_submissions::Model,
+        Option<challenges_coding_challenge_result::Model>,
+    )>,
+    DbErr,
+> {
+    challenges_coding_challenge_submissions::Entity::find()
+        .filter(challenges_coding_challenge_submissions::Column::SubtaskId.eq(subtask_id))
+        .filter(challenges_coding_challenge_submissions::Column::Creator.eq(creator))
+        .filter(challenges_coding_challenge_submissions::Column::IdempotencyKey.eq(key))
+        .find_also_related(challenges_coding_challenge_result::Entity)
+        .one(db)
+        .await
 }
 
 #[derive(Debug, Error)]
 enum JudgeSubmissionError {
     #[error("failed to judge submission: {0}")]
-    Judge(#[from] judge::Error),
+    Judge(Box<judge::Error>),
     #[error("database error: {0}")]
     Db(#[from] DbErr),
     #[error("check error: {0:?}")]
@@ -476,17 +1213,52 @@ enum JudgeSubmissionError {
     TaskRewards(#[from] SendTaskRewardsError),
 }
 
+impl JudgeSubmissionError {
+    /// Whether the submission could not be judged because of an error that is
+    /// not related to the submitted solution.
+    fn is_infrastructure_error(&self) -> bool {
+        matches!(self, Self::Judge(_) | Self::Db(_))
+    }
+}
+
+impl From<judge::Error> for JudgeSubmissionError {
+    fn from(value: judge::Error) -> Self {
+        Self::Judge(Box::new(value))
+    }
+}
+
 impl Api {
+    /// Return the api representation of a submission that has been found by its
+    /// idempotency key.
+    async fn existing_submission(
+        &self,
+        submission: challenges_coding_challenge_submissions::Model,
+        result: Option<challenges_coding_challenge_result::Model>,
+    ) -> Submission {
+        let position = match result {
+            Some(_) => None,
+            None => self.queue_positions.read().await.position(submission.id),
+        };
+        Submission::from(&submission, result.map(Into::into), position)
+    }
+
     async fn get_environments(&self) -> Result<HashMap<String, Environment>, ErrorResponse> {
-        Ok(self
-            .judge_cache
-            .cached_result(key!(), &[], None, || async {
-                self.sandkasten.list_environments().await
-            })
-            .await??)
+        Ok(judge::get_environments(
+            &self.judge_cache,
+            &self.sandkasten,
+            self.config
+                .challenges
+                .coding_challenges
+                .environments_cache_ttl(),
+        )
+        .await?)
     }
 
     pub async fn setup_api(self) -> anyhow::Result<Self> {
+        self.queue_positions
+            .read()
+            .await
+            .update_metrics(&self.state.metrics);
         self.resume_judge()
             .await
             .context("failed to resume judge")?;
@@ -495,6 +1267,55 @@ impl Api {
 
     pub async fn resume_judge(&self) -> anyhow::Result<()> {
         debug!("resuming judge");
+        let submissions = self.find_pending_submissions(None).await?;
+        debug!("found {} submission(s) to judge", submissions.len());
+        self.enqueue_submissions(submissions).await?;
+        Ok(())
+    }
+
+    /// Return all submissions without a result, optionally only those created
+    /// before `created_before`.
+    async fn find_pending_submissions(
+        &self,
+        created_before: Option<NaiveDateTime>,
+    ) -> Result<Vec<challenges_coding_challenge_submissions::Model>, DbErr> {
+        let mut query = challenges_coding_challenge_submissions::Entity::find()
+            .left_join(challenges_coding_challenge_result::Entity)
+            .filter(challenges_coding_challenge_result::Column::SubmissionId.is_null());
+        if let Some(created_before) = created_before {
+            query = query.filter(
+                challenges_coding_challenge_submissions::Column::CreationTimestamp
+                    .lt(created_before),
+            );
+        }
+        query
+            .order_by_asc(challenges_coding_challenge_submissions::Column::CreationTimestamp)
+            .all(&self.state.db)
+            .await
+    }
+
+    /// Return all submissions without a result that are older than the
+    /// configured threshold and not in the judge queue.
+    async fn find_stuck_submissions(
+        &self,
+    ) -> Result<Vec<challenges_coding_challenge_submissions::Model>, DbErr> {
+        let threshold = Utc::now().naive_utc()
+            - Duration::from_secs(self.config.challenges.coding_challenges.stuck_threshold);
+        let submissions = self.find_pending_submissions(Some(threshold)).await?;
+        let qp = self.queue_positions.read().await;
+        Ok(submissions
+            .into_iter()
+            .filter(|submission| qp.status(submission.id) == QueueItemStatus::NotQueued)
+            .collect())
+    }
+
+    /// Start judge tasks for the given submissions and return their queue
+    /// positions. Submissions that are already in the queue (e.g. because of
+    /// a concurrent request) are skipped.
+    async fn enqueue_submissions(
+        &self,
+        submissions: Vec<challenges_coding_challenge_submissions::Model>,
+    ) -> anyhow::Result<Vec<(Arc<challenges_coding_challenge_submissions::Model>, usize)>> {
         let db = &self.state.db;
 
         let subtasks = challenges_subtasks::Entity::find()
@@ -509,20 +1330,8 @@ impl Api {
             .into_iter()
             .map(|x| (x.subtask_id, Arc::new(x)))
             .collect::<HashMap<_, _>>();
-        let user_subtasks = challenges_user_subtasks::Entity::find()
-            .all(db)
-            .await?
-            .into_iter()
-            .map(|x| ((x.user_id, x.subtask_id), x))
-            .collect::<HashMap<_, _>>();
-        let submissions = challenges_coding_challenge_submissions::Entity::find()
-            .left_join(challenges_coding_challenge_result::Entity)
-            .filter(challenges_coding_challenge_result::Column::SubmissionId.is_null())
-            .order_by_asc(challenges_coding_challenge_submissions::Column::CreationTimestamp)
-            .all(db)
-            .await?;
 
-        debug!("found {} submission(s) to judge", submissions.len());
+        let mut enqueued = Vec::with_capacity(submissions.len());
         for submission in submissions {
             let Some(subtask) = subtasks.get(&submission.subtask_id) else {
                 bail!(
@@ -538,41 +1347,163 @@ impl Api {
                     submission.id
                 );
             };
-            let user_subtask = user_subtasks.get(&(submission.creator, submission.subtask_id));
-            start_judge_submission_task(StartJudgeSubmissionTask {
-                submission: Arc::new(submission),
+            let priority = if submission.creator == subtask.creator {
+                QueuePositions::PRIORITY_HIGH
+            } else {
+                QueuePositions::PRIORITY_DEFAULT
+            };
+            // the submission must not be enqueued by another request between
+            // the check and the start of the judge task
+            let _guard = self.submission_lock.write(submission.creator).await;
+            if self.queue_positions.read().await.status(submission.id) != QueueItemStatus::NotQueued
+            {
+                continue;
+            }
+            let submission = Arc::new(submission);
+            let position = start_judge_submission_task(StartJudgeSubmissionTask {
+                submission: Arc::clone(&submission),
                 subtask: subtask.clone(),
                 judge_lock: Arc::clone(&self.judge_lock),
                 db: db.clone(),
                 sandkasten: self.sandkasten.clone(),
+                sandkasten_lock: Arc::clone(&self.sandkasten_lock),
                 cache: self.judge_cache.clone(),
                 reward_lock: Arc::clone(&self.reward_lock),
                 state: Arc::clone(&self.state),
                 challenge: Arc::clone(challenge),
-                user_subtask: user_subtask.cloned(),
+                rejudge: submission.rejudge,
+                priority,
+                hearts: 0,
+                evaluator_timeout: self.config.challenges.coding_challenges.evaluator_timeout,
+                max_testcases: self.config.challenges.coding_challenges.max_testcases,
+                sandkasten_retry: self.config.challenges.coding_challenges.sandkasten_retry,
+                evaluator_cache_ttl: self
+                    .config
+                    .challenges
+                    .coding_challenges
+                    .evaluator_cache_ttl(),
                 queue_positions: Arc::clone(&self.queue_positions),
+                judge_tasks: self.judge_tasks.clone(),
+                webhook: self.webhook.clone(),
             })
             .await;
+            enqueued.push((submission, position));
         }
 
-        Ok(())
+        Ok(enqueued)
+    }
+}
+
+/// The state of a submission in the judge queue.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QueueItemStatus {
+    /// The submission is currently being judged.
+    Running,
+    /// The submission is waiting behind the given number of submissions.
+    Waiting(usize),
+    /// The submission is not in the queue (e.g. because it has already been
+    /// judged).
+    NotQueued,
+}
+
+impl QueueItemStatus {
+    /// Return the queue position as reported to users.
+    pub fn position(self) -> Option<usize> {
+        match self {
+            Self::Running => Some(0),
+            Self::Waiting(position) => Some(position),
+            Self::NotQueued => None,
+        }
+    }
+}
+
+/// Keeps track of the number of spawned judge tasks that have not finished yet.
+#[derive(Debug, Clone)]
+pub struct JudgeTasks {
+    count: Arc<watch::Sender<usize>>,
+    closed: Arc<AtomicBool>,
+}
+
+impl Default for JudgeTasks {
+    fn default() -> Self {
+        Self {
+            count: Arc::new(watch::channel(0).0),
+            closed: Default::default(),
+        }
     }
 }
 
+impl JudgeTasks {
+    /// Stop accepting new submissions.
+    pub fn close(&self) {
+        self.closed.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::SeqCst)
+    }
+
+    fn track(&self) -> JudgeTaskGuard {
+        self.count.send_modify(|count| *count += 1);
+        JudgeTaskGuard(Arc::clone(&self.count))
+    }
+
+    /// Wait until all judge tasks have finished or the timeout has elapsed.
+    ///
+    /// Returns the number of drained and abandoned tasks. Abandoned
+    /// submissions are judged again by `resume_judge` on the next startup.
+    pub async fn drain(&self, timeout: Duration) -> (usize, usize) {
+        self.close();
+        let outstanding = *self.count.borrow();
+        let mut count = self.count.subscribe();
+        let _ = tokio::time::timeout(timeout, count.wait_for(|&count| count == 0)).await;
+        let abandoned = *self.count.borrow();
+        (outstanding.saturating_sub(abandoned), abandoned)
+    }
+}
+
+struct JudgeTaskGuard(Arc<watch::Sender<usize>>);
+
+impl Drop for JudgeTaskGuard {
+    fn drop(&mut self) {
+        self.0.send_modify(|count| *count -= 1);
+    }
+}
+
+/// Tracks the submissions in the judge queue.
+///
+/// Up to `workers` submissions are active (position `0`) at the same time. The
+/// remaining submissions wait ordered by priority (highest first) and then by
+/// the order in which they have been pushed.
 pub struct QueuePositions {
     workers: usize,
     counter: usize,
-    done: usize,
-    ids: HashMap<Uuid, usize>,
+    active: HashSet<Uuid>,
+    waiting: BTreeMap<(Reverse<u8>, usize), Uuid>,
+    ids: HashMap<Uuid, (Reverse<u8>, usize)>,
+    /// The creator and subtask of each queued submission.
+    creators: HashMap<Uuid, (Uuid, Uuid)>,
+    /// The number of queued submissions per creator.
+    creator_counts: HashMap<Uuid, usize>,
+    notify: Arc<Notify>,
 }
 
 impl QueuePositions {
+    /// Priority of regular submissions.
+    pub const PRIORITY_DEFAULT: u8 = 0;
+    /// Priority of submissions by admins or the creator of the challenge.
+    pub const PRIORITY_HIGH: u8 = 1;
+
     pub fn new(workers: usize) -> Self {
         Self {
             workers,
             counter: 0,
-            done: 0,
+            active: HashSet::new(),
+            waiting: BTreeMap::new(),
             ids: HashMap::new(),
+            creators: HashMap::new(),
+            creator_counts: HashMap::new(),
+            notify: Arc::new(Notify::new()),
         }
     }
 
@@ -581,66 +1512,163 @@ impl QueuePositions {
     }
 
     pub fn active(&self) -> usize {
-        self.workers.min(self.counter - self.done)
+        self.active.len()
     }
 
     pub fn waiting(&self) -> usize {
-        self.id_position(self.counter)
+        self.waiting.len()
+    }
+
+    /// Return the number of submissions of a user that are currently active or
+    /// waiting.
+    pub fn user_submissions(&self, user_id: Uuid) -> usize {
+        self.creator_counts
+            .get(&user_id)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Return the number of submissions of a user for a subtask that are
+    /// currently active or waiting.
+    pub fn user_subtask_submissions(&self, user_id: Uuid, subtask_id: Uuid) -> usize {
+        self.creators
+            .values()
+            .filter(|&&x| x == (user_id, subtask_id))
+            .count()
     }
 
-    pub fn push(&mut self, key: Uuid) -> usize {
-        let id = *self.ids.entry(key).or_insert_with(|| {
+    /// Return a handle that is notified whenever submissions become active.
+    pub fn notify(&self) -> Arc<Notify> {
+        Arc::clone(&self.notify)
+    }
+
+    pub fn push(&mut self, key: Uuid, creator: Uuid, subtask_id: Uuid, priority: u8) -> usize {
+        if !self.active.contains(&key) && !self.ids.contains_key(&key) {
             self.counter += 1;
-            self.counter
-        });
-        self.id_position(id)
+            let id = (Reverse(priority), self.counter);
+            self.ids.insert(key, id);
+            self.waiting.insert(id, key);
+            self.creators.insert(key, (creator, subtask_id));
+            *self.creator_counts.entry(creator).or_default() += 1;
+            self.promote();
+        }
+        self.position(key).unwrap_or_default()
+    }
+
+    pub fn update_metrics(&self, metrics: &Metrics) {
+        metrics.judge_workers.set(self.workers() as _);
+        metrics.judge_active.set(self.active() as _);
+        metrics.judge_waiting.set(self.waiting() as _);
     }
 
     pub fn pop(&mut self, key: Uuid) -> bool {
-        if !self
-            .ids
-            .get(&key)
-            .is_some_and(|&x| self.id_position(x) == 0)
-        {
+        if !self.active.remove(&key) {
             return false;
         }
+        if let Some((creator, _)) = self.creators.remove(&key) {
+            if let Entry::Occupied(mut count) = self.creator_counts.entry(creator) {
+                *count.get_mut() -= 1;
+                if *count.get() == 0 {
+                    count.remove();
+                }
+            }
+        }
 
-        self.ids.remove(&key);
-        self.done += 1;
+        self.promote();
         true
     }
 
     pub fn position(&self, key: Uuid) -> Option<usize> {
-        let id = *self.ids.get(&key)?;
-        Some(self.id_position(id))
+        self.status(key).position()
     }
 
-    fn id_position(&self, id: usize) -> usize {
-        id.saturating_sub(self.workers + self.done)
+    pub fn status(&self, key: Uuid) -> QueueItemStatus {
+        if self.active.contains(&key) {
+            return QueueItemStatus::Running;
+        }
+        match self.ids.get(&key) {
+            Some(id) => QueueItemStatus::Waiting(self.waiting.range(..=id).count()),
+            None => QueueItemStatus::NotQueued,
+        }
+    }
+
+    fn promote(&mut self) {
+        let mut promoted = false;
+        while self.active.len() < self.workers {
+            let Some((_, key)) = self.waiting.pop_first() else {
+                break;
+            };
+            self.ids.remove(&key);
+            self.active.insert(key);
+            promoted = true;
+        }
+        if promoted {
+            self.notify.notify_waiters();
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use sandkasten_client::schemas::programs::{BuildRequest, BuildRunRequest, RunRequest};
+
     use super::*;
 
+    #[tokio::test]
+    async fn classify_infrastructure_errors() {
+        // nothing is listening on this port after the listener is dropped
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        drop(listener);
+
+        let sandkasten = SandkastenClient::new(url.parse().unwrap());
+        let err = sandkasten
+            .build_and_run(&BuildRunRequest {
+                build: BuildRequest::default(),
+                run: RunRequest::default(),
+            })
+            .await
+            .unwrap_err();
+        assert!(JudgeSubmissionError::from(judge::Error::from(err)).is_infrastructure_error());
+        assert!(JudgeSubmissionError::Db(DbErr::Custom(String::new())).is_infrastructure_error());
+        assert!(
+            !JudgeSubmissionError::Check(Box::new(CheckError::NoExamples))
+                .is_infrastructure_error()
+        );
+    }
+
+    #[tokio::test]
+    async fn drain_judge_tasks() {
+        let tasks = JudgeTasks::default();
+        let finished = tasks.track();
+        let _abandoned = tasks.track();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            drop(finished);
+        });
+        assert!(!tasks.is_closed());
+        assert_eq!(tasks.drain(Duration::from_millis(500)).await, (1, 1));
+        assert!(tasks.is_closed());
+    }
+
     #[test]
     fn queue_positions() {
         let mut qp = QueuePositions::new(3);
         assert_eq!(qp.workers(), 3);
         let key = Uuid::from_u128;
+        let (user, subtask) = (Uuid::nil(), Uuid::nil());
         assert_eq!((qp.active(), qp.waiting()), (0, 0));
-        qp.push(key(0));
+        qp.push(key(0), user, subtask, QueuePositions::PRIORITY_DEFAULT);
         assert_eq!((qp.active(), qp.waiting()), (1, 0));
-        qp.push(key(1));
+        qp.push(key(1), user, subtask, QueuePositions::PRIORITY_DEFAULT);
         assert_eq!((qp.active(), qp.waiting()), (2, 0));
-        qp.push(key(2));
+        qp.push(key(2), user, subtask, QueuePositions::PRIORITY_DEFAULT);
         assert_eq!((qp.active(), qp.waiting()), (3, 0));
-        qp.push(key(3));
+        qp.push(key(3), user, subtask, QueuePositions::PRIORITY_DEFAULT);
         assert_eq!((qp.active(), qp.waiting()), (3, 1));
-        qp.push(key(4));
+        qp.push(key(4), user, subtask, QueuePositions::PRIORITY_DEFAULT);
         assert_eq!((qp.active(), qp.waiting()), (3, 2));
-        qp.push(key(5));
+        qp.push(key(5), user, subtask, QueuePositions::PRIORITY_DEFAULT);
         assert_eq!((qp.active(), qp.waiting()), (3, 3));
         assert_eq!(qp.position(key(0)), Some(0));
         assert_eq!(qp.position(key(1)), Some(0));
@@ -648,6 +1676,8 @@ mod tests {
         assert_eq!(qp.position(key(3)), Some(1));
         assert_eq!(qp.position(key(4)), Some(2));
         assert_eq!(qp.position(key(5)), Some(3));
+        assert_eq!(qp.status(key(0)), QueueItemStatus::Running);
+        assert_eq!(qp.status(key(3)), QueueItemStatus::Waiting(1));
 
         // cannot pop pending keys
         assert!(!qp.pop(key(3)));
@@ -658,6 +1688,7 @@ mod tests {
         assert!(qp.pop(key(1)));
         assert_eq!(qp.position(key(0)), Some(0));
         assert_eq!(qp.position(key(1)), None);
+        assert_eq!(qp.status(key(1)), QueueItemStatus::NotQueued);
         assert_eq!(qp.position(key(2)), Some(0));
         assert_eq!(qp.position(key(3)), Some(0));
         assert_eq!(qp.position(key(4)), Some(1));
@@ -674,11 +1705,82 @@ mod tests {
         assert_eq!(qp.position(key(5)), Some(1));
         assert_eq!((qp.active(), qp.waiting()), (3, 1));
 
-        assert_eq!(qp.push(key(6)), 2);
+        assert_eq!(
+            qp.push(key(6), user, subtask, QueuePositions::PRIORITY_DEFAULT),
+            2
+        );
         assert_eq!((qp.active(), qp.waiting()), (3, 2));
-        assert_eq!(qp.push(key(6)), 2); // push is idempotent
+        assert_eq!(
+            qp.push(key(6), user, subtask, QueuePositions::PRIORITY_DEFAULT),
+            2
+        ); // push is idempotent
         assert_eq!((qp.active(), qp.waiting()), (3, 2));
-        assert_eq!(qp.push(key(7)), 3);
+        assert_eq!(
+            qp.push(key(7), user, subtask, QueuePositions::PRIORITY_DEFAULT),
+            3
+        );
         assert_eq!((qp.active(), qp.waiting()), (3, 3));
+
+        // high priority submissions skip the queue, but don't preempt active ones
+        assert_eq!(
+            qp.push(key(8), user, subtask, QueuePositions::PRIORITY_HIGH),
+            1
+        );
+        assert_eq!((qp.active(), qp.waiting()), (3, 4));
+        assert_eq!(qp.position(key(5)), Some(2));
+        assert_eq!(qp.position(key(6)), Some(3));
+        assert_eq!(qp.position(key(7)), Some(4));
+        assert_eq!(
+            qp.push(key(9), user, subtask, QueuePositions::PRIORITY_HIGH),
+            2
+        );
+        assert_eq!(qp.position(key(5)), Some(3));
+        assert_eq!(
+            qp.push(key(10), user, subtask, QueuePositions::PRIORITY_DEFAULT),
+            6
+        );
+        assert_eq!(
+            qp.push(key(8), user, subtask, QueuePositions::PRIORITY_DEFAULT),
+            1
+        ); // still idempotent
+        assert_eq!((qp.active(), qp.waiting()), (3, 6));
+        assert!(!qp.pop(key(8)));
+
+        assert!(qp.pop(key(0)));
+        assert_eq!(qp.position(key(8)), Some(0));
+        assert_eq!(qp.position(key(9)), Some(1));
+        assert_eq!(qp.position(key(5)), Some(2));
+        assert_eq!((qp.active(), qp.waiting()), (3, 5));
+
+        assert!(qp.pop(key(3)));
+        assert_eq!(qp.position(key(9)), Some(0));
+        assert_eq!(qp.position(key(5)), Some(1));
+        assert_eq!(qp.position(key(10)), Some(4));
+        assert_eq!((qp.active(), qp.waiting()), (3, 4));
+    }
+
+    #[test]
+    fn queue_positions_per_user() {
+        let mut qp = QueuePositions::new(1);
+        let key = Uuid::from_u128;
+        let (alice, bob) = (Uuid::from_u128(100), Uuid::from_u128(101));
+        let (a, b) = (Uuid::from_u128(200), Uuid::from_u128(201));
+        qp.push(key(0), alice, a, QueuePositions::PRIORITY_DEFAULT);
+        qp.push(key(1), alice, b, QueuePositions::PRIORITY_DEFAULT);
+        qp.push(key(1), alice, b, QueuePositions::PRIORITY_DEFAULT);
+        qp.push(key(2), bob, a, QueuePositions::PRIORITY_DEFAULT);
+        assert_eq!(qp.user_submissions(alice), 2);
+        assert_eq!(qp.user_submissions(bob), 1);
+        assert_eq!(qp.user_subtask_submissions(alice, a), 1);
+        assert_eq!(qp.user_subtask_submissions(alice, b), 1);
+        assert_eq!(qp.user_subtask_submissions(bob, b), 0);
+
+        assert!(qp.pop(key(0)));
+        assert_eq!(qp.user_submissions(alice), 1);
+        assert_eq!(qp.user_subtask_submissions(alice, a), 0);
+        assert!(qp.pop(key(1)));
+        assert!(!qp.pop(key(1)));
+        assert_eq!(qp.user_submissions(alice), 0);
+        assert_eq!(qp.user_submissions(bob), 1);
     }
 }
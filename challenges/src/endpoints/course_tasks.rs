@@ -10,9 +10,10 @@ use poem_openapi::{
     payload::Json,
     OpenApi,
 };
-use schemas::challenges::course_tasks::{CourseTask, CreateCourseTaskRequest};
+use schemas::challenges::course_tasks::{CourseTask, CourseTaskList, CreateCourseTaskRequest};
 use sea_orm::{
-    ActiveModelTrait, ColumnTrait, Condition, DatabaseTransaction, EntityTrait, QueryFilter, Set,
+    ActiveModelTrait, ColumnTrait, DatabaseTransaction, EntityTrait, PaginatorTrait, QueryFilter,
+    QueryOrder, QuerySelect, Set,
 };
 use uuid::Uuid;
 
@@ -31,6 +32,12 @@ impl CourseTasks {
     async fn list_tasks_in_skill(
         &self,
         skill_id: Path<String>,
+        /// Only return tasks of this course of the skill
+        course_id: Query<Option<String>>,
+        /// Maximum number of tasks to return
+        limit: Query<Option<u64>>,
+        /// Pagination offset
+        offset: Query<Option<u64>>,
         db: Data<&DbTxn>,
         _auth: VerifiedUserAuth,
     ) -> ListTasksInSkill::Response<VerifiedUserAuth> {
@@ -46,21 +53,37 @@ impl CourseTasks {
             None => return ListTasksInSkill::not_found(),
         };
 
-        let condition = skill.courses.into_iter().fold(Condition::any(), |acc, e| {
-            acc.add(challenges_course_tasks::Column::CourseId.eq(e))
-        });
+        let courses = match course_id.0 {
+            Some(course_id) => skill
+                .courses
+                .into_iter()
+                .filter(|x| *x == course_id)
+                .collect(),
+            None => skill.courses,
+        };
+        // skills without (matching) courses cannot have any tasks
+        if courses.is_empty() {
+            return ListTasksInSkill::ok(CourseTaskList {
+                tasks: Vec::new(),
+                total: 0,
+            });
+        }
 
         let query = challenges_course_tasks::Entity::find()
+            .filter(challenges_course_tasks::Column::CourseId.is_in(courses));
+        let total = query.clone().count(&***db).await?;
+        let tasks = query
             .find_also_related(challenges_tasks::Entity)
-            .filter(condition);
-        ListTasksInSkill::ok(
-            query
-                .all(&***db)
-                .await?
-                .into_iter()
-                .filter_map(|(challenge, task)| Some(CourseTask::from(challenge, task?)))
-                .collect(),
-        )
+            .order_by_asc(challenges_course_tasks::Column::CourseId)
+            .order_by_asc(challenges_course_tasks::Column::TaskId)
+            .limit(limit.0)
+            .offset(offset.0)
+            .all(&***db)
+            .await?
+            .into_iter()
+            .filter_map(|(challenge, task)| Some(CourseTask::from(challenge, task?)))
+            .collect();
+        ListTasksInSkill::ok(CourseTaskList { tasks, total })
     }
 
     /// List all tasks in a course.
@@ -185,7 +208,7 @@ impl CourseTasks {
 }
 
 response!(ListTasksInSkill = {
-    Ok(200) => Vec<CourseTask>,
+    Ok(200) => CourseTaskList,
     /// Skill does not exist.
     NotFound(404, error),
 });
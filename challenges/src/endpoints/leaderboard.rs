@@ -8,13 +8,13 @@ use poem_openapi::{
     param::{Path, Query},
     OpenApi,
 };
-use schemas::challenges::leaderboard::{Leaderboard, Rank};
+use schemas::challenges::leaderboard::{Leaderboard, LeaderboardLanguage, Rank};
 use uuid::Uuid;
 
 use super::Tags;
 use crate::services::leaderboard::{
     global::{get_global_leaderboard, get_global_leaderboard_user},
-    language::{get_language_leaderboard, get_language_leaderboard_user},
+    language::{get_language_leaderboard, get_language_leaderboard_user, get_languages},
     task::{get_task_leaderboard, get_task_leaderboard_user},
 };
 
@@ -85,6 +85,22 @@ impl LeaderboardEndpoints {
         GetTaskLeaderboardUser::ok(rank)
     }
 
+    /// Return the languages in which coding challenges have been solved.
+    #[oai(path = "/leaderboard/languages", method = "get")]
+    async fn get_leaderboard_languages(
+        &self,
+        db: Data<&DbTxn>,
+        _auth: VerifiedUserAuth,
+    ) -> GetLeaderboardLanguages::Response<VerifiedUserAuth> {
+        let languages = self
+            .cache
+            .cached_result(key!(), &[], Some(Duration::from_secs(10)), || {
+                get_languages(&db)
+            })
+            .await??;
+        GetLeaderboardLanguages::ok(languages)
+    }
+
     #[oai(path = "/leaderboard/by-language/:language", method = "get")]
     async fn get_language_leaderboard(
         &self,
@@ -151,6 +167,10 @@ response!(GetTaskLeaderboardUser = {
     Ok(200) => Rank,
 });
 
+response!(GetLeaderboardLanguages = {
+    Ok(200) => Vec<LeaderboardLanguage>,
+});
+
 response!(GetLanguageLeaderboard = {
     Ok(200) => Leaderboard,
 });
@@ -18,23 +18,31 @@ use poem_openapi::{
     OpenApi,
 };
 use schemas::challenges::matchings::{
-    CreateMatchingRequest, Matching, MatchingSummary, MatchingWithSolution, SolveMatchingFeedback,
-    SolveMatchingRequest, UpdateMatchingRequest,
+    CreateMatchingRequest, DuplicateMatchingEntry, Matching, MatchingAttempt, MatchingSide,
+    MatchingSummary, MatchingWithSolution, SolveMatchingFeedback, SolveMatchingRequest,
+    UpdateMatchingRequest,
+};
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, EntityTrait, QueryFilter, QueryOrder, Set, Unchanged,
 };
-use sea_orm::{ActiveModelTrait, Set, Unchanged};
 use uuid::Uuid;
 
 use super::Tags;
-use crate::services::subtasks::{
-    create_subtask, deduct_hearts, get_subtask, get_user_subtask, query_subtask,
-    query_subtask_admin, query_subtasks, send_task_rewards, update_subtask, update_user_subtask,
-    CreateSubtaskError, QuerySubtaskAdminError, QuerySubtasksFilter, UpdateSubtaskError,
-    UserSubtaskExt,
+use crate::{
+    after_commit::AfterCommit,
+    services::subtasks::{
+        attempts_exhausted, create_subtask, deduct_hearts, get_subtask, get_user_subtask,
+        publish_subtask_solved, query_subtask, query_subtask_admin, query_subtasks,
+        send_task_rewards, update_subtask, update_user_subtask, CreateSubtaskError,
+        QuerySubtaskAdminError, QuerySubtasksFilter, RewardLock, UpdateSubtaskError,
+        UserSubtaskExt,
+    },
 };
 
 pub struct Matchings {
     pub state: Arc<SharedState>,
     pub config: Arc<Config>,
+    pub reward_lock: Arc<RewardLock>,
 }
 
 #[OpenApi(tag = "Tags::Matchings")]
@@ -73,6 +81,8 @@ impl Matchings {
                     retired: retired.0,
                     creator: creator.0,
                     ty: None,
+                    tags: Vec::new(),
+                    include_locked: false,
                 },
                 MatchingSummary::from,
             )
@@ -174,6 +184,9 @@ impl Matchings {
             Err(InvalidMatchingError::RightEntriesNotMatched(x)) => {
                 return CreateMatching::right_entries_not_matched(x)
             }
+            Err(InvalidMatchingError::DuplicateEntries(side, value)) => {
+                return CreateMatching::duplicate_entries(DuplicateMatchingEntry { side, value })
+            }
         }
 
         let matching = challenges_matchings::ActiveModel {
@@ -229,6 +242,9 @@ impl Matchings {
             Err(InvalidMatchingError::RightEntriesNotMatched(x)) => {
                 return UpdateMatching::right_entries_not_matched(x)
             }
+            Err(InvalidMatchingError::DuplicateEntries(side, value)) => {
+                return UpdateMatching::duplicate_entries(DuplicateMatchingEntry { side, value })
+            }
         }
 
         let matching = challenges_matchings::ActiveModel {
@@ -258,6 +274,7 @@ impl Matchings {
         subtask_id: Path<Uuid>,
         data: Json<SolveMatchingRequest>,
         db: Data<&DbTxn>,
+        after_commit: Data<&AfterCommit>,
         auth: VerifiedUserAuth,
     ) -> SolveMatching::Response<VerifiedUserAuth> {
         let Some((matching, subtask)) =
@@ -273,10 +290,15 @@ impl Matchings {
             return SolveMatching::solution_different_length();
         }
 
+        let _guard = self.reward_lock.write((subtask.id, auth.0.id)).await;
         let user_subtask = get_user_subtask(&db, auth.0.id, subtask.id).await?;
+        if attempts_exhausted(&auth.0, &subtask, &user_subtask) {
+            return SolveMatching::attempts_exhausted();
+        }
 
         let solved_previously = user_subtask.is_solved();
-        if let Some(last_attempt) = user_subtask.last_attempt() {
+        let timeout_exempt = self.config.challenges.is_service_account(auth.0.id);
+        if let Some(last_attempt) = user_subtask.last_attempt().filter(|_| !timeout_exempt) {
             let time_left = self.config.challenges.matchings.timeout as i64
                 - (Utc::now() - last_attempt).num_seconds();
             if time_left > 0 {
@@ -284,23 +306,27 @@ impl Matchings {
             }
         }
 
-        if !deduct_hearts(&self.state.services, &self.config, &auth.0, &subtask).await? {
+        if deduct_hearts(&self.state.services, &self.config, &auth.0, &subtask)
+            .await?
+            .is_none()
+        {
             return SolveMatching::not_enough_hearts();
         }
 
-        let correct = data
+        let correct_indices = data
             .0
             .answer
             .iter()
             .zip(matching.solution.iter())
-            .filter(|(&x, &y)| x == y as u8)
-            .count();
+            .map(|(&x, &y)| x == y as u8)
+            .collect::<Vec<_>>();
+        let correct = correct_indices.iter().filter(|&&x| x).count();
         let solved = correct == matching.solution.len();
 
         if !solved_previously {
             let now = Utc::now().naive_utc();
             if solved {
-                update_user_subtask(
+                let updated = update_user_subtask(
                     &db,
                     user_subtask.as_ref(),
                     challenges_user_subtasks::ActiveModel {
@@ -314,8 +340,18 @@ impl Matchings {
                 )
                 .await?;
 
-                if auth.0.id != subtask.creator {
-                    send_task_rewards(&self.state.services, &db, auth.0.id, &subtask).await?;
+                // `None` if a concurrent request has solved the subtask first
+                if updated.is_some() {
+                    if auth.0.id != subtask.creator {
+                        send_task_rewards(&self.state.services, &db, auth.0.id, &subtask).await?;
+                    }
+                    publish_subtask_solved(
+                        &after_commit,
+                        &self.state.services,
+                        auth.0.id,
+                        &subtask,
+                        now,
+                    );
                 }
             } else {
                 update_user_subtask(
@@ -343,7 +379,55 @@ impl Matchings {
             .await?;
         }
 
-        SolveMatching::ok(SolveMatchingFeedback { solved, correct })
+        SolveMatching::ok(SolveMatchingFeedback {
+            solved,
+            correct,
+            correct_indices,
+        })
+    }
+
+    /// List previous attempts to solve a matching.
+    ///
+    /// Only admins can view the attempts of other users.
+    #[oai(
+        path = "/tasks/:task_id/matchings/:subtask_id/attempts",
+        method = "get"
+    )]
+    async fn list_matching_attempts(
+        &self,
+        task_id: Path<Uuid>,
+        subtask_id: Path<Uuid>,
+        /// The user whose attempts should be returned. Defaults to the
+        /// authenticated user.
+        user_id: Query<Option<Uuid>>,
+        db: Data<&DbTxn>,
+        auth: VerifiedUserAuth,
+    ) -> ListMatchingAttempts::Response<VerifiedUserAuth> {
+        let user_id = user_id.0.unwrap_or(auth.0.id);
+        if !auth.0.admin && user_id != auth.0.id {
+            return ListMatchingAttempts::forbidden();
+        }
+
+        let Some((matching, subtask)) =
+            get_subtask::<challenges_matchings::Entity>(&db, task_id.0, subtask_id.0).await?
+        else {
+            return ListMatchingAttempts::subtask_not_found();
+        };
+        if !auth.0.admin && auth.0.id != subtask.creator && !subtask.enabled {
+            return ListMatchingAttempts::subtask_not_found();
+        }
+
+        ListMatchingAttempts::ok(
+            challenges_matching_attempts::Entity::find()
+                .filter(challenges_matching_attempts::Column::MatchingId.eq(matching.subtask_id))
+                .filter(challenges_matching_attempts::Column::UserId.eq(user_id))
+                .order_by_desc(challenges_matching_attempts::Column::Timestamp)
+                .all(&***db)
+                .await?
+                .into_iter()
+                .map(Into::into)
+                .collect(),
+        )
     }
 }
 
@@ -385,6 +469,8 @@ response!(CreateMatching = {
     InvalidIndex(400, error) => u8,
     /// One or more entries in the right list have no match in the left list.
     RightEntriesNotMatched(400, error) => HashSet<u8>,
+    /// The left or right list contains the same entry more than once.
+    DuplicateEntries(400, error) => DuplicateMatchingEntry,
 });
 
 response!(UpdateMatching = {
@@ -401,6 +487,8 @@ response!(UpdateMatching = {
     InvalidIndex(400, error) => u8,
     /// One or more entries in the right list have no match in the left list.
     RightEntriesNotMatched(400, error) => HashSet<u8>,
+    /// The left or right list contains the same entry more than once.
+    DuplicateEntries(400, error) => DuplicateMatchingEntry,
 });
 
 response!(SolveMatching = {
@@ -411,11 +499,21 @@ response!(SolveMatching = {
     SubtaskNotFound(404, error),
     /// The user does not have enough hearts to submit a solution and is neither an admin nor the creator of this subtask.
     NotEnoughHearts(403, error),
+    /// The user has used up all attempts to solve this subtask.
+    AttemptsExhausted(403, error),
     /// The solution list does not contain the same number of entries as the left and right lists.
     SolutionDifferentLength(400, error),
 });
 
-fn check_matching(
+response!(ListMatchingAttempts = {
+    Ok(200) => Vec<MatchingAttempt>,
+    /// Subtask does not exist.
+    SubtaskNotFound(404, error),
+    /// The user is not allowed to view the attempts of other users.
+    Forbidden(403, error),
+});
+
+pub(crate) fn check_matching(
     left: &[String],
     right: &[String],
     solution: &[u8],
@@ -427,6 +525,12 @@ fn check_matching(
     if solution.len() != n {
         return Err(InvalidMatchingError::SolutionDifferentLength);
     }
+    for (side, entries) in [(MatchingSide::Left, left), (MatchingSide::Right, right)] {
+        let mut seen = HashSet::with_capacity(n);
+        if let Some(x) = entries.iter().find(|&x| !seen.insert(x)) {
+            return Err(InvalidMatchingError::DuplicateEntries(side, x.clone()));
+        }
+    }
     if let Some(&x) = solution.iter().find(|&&x| x >= n as _) {
         return Err(InvalidMatchingError::InvalidIndex(x));
     }
@@ -441,11 +545,12 @@ fn check_matching(
 }
 
 #[derive(Debug, PartialEq, Eq)]
-enum InvalidMatchingError {
+pub(crate) enum InvalidMatchingError {
     LeftRightDifferentLength,
     SolutionDifferentLength,
     InvalidIndex(u8),
     RightEntriesNotMatched(HashSet<u8>),
+    DuplicateEntries(MatchingSide, String),
 }
 
 #[cfg(test)]
@@ -478,5 +583,19 @@ mod tests {
             check_matching(&left, &["foo".into()], &solution),
             Err(InvalidMatchingError::LeftRightDifferentLength)
         );
+        assert_eq!(
+            check_matching(&["A".into(), "B".into(), "A".into()], &right, &solution),
+            Err(InvalidMatchingError::DuplicateEntries(
+                MatchingSide::Left,
+                "A".into()
+            ))
+        );
+        assert_eq!(
+            check_matching(&left, &["X".into(), "Z".into(), "Z".into()], &solution),
+            Err(InvalidMatchingError::DuplicateEntries(
+                MatchingSide::Right,
+                "Z".into()
+            ))
+        );
     }
 }
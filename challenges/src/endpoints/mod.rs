@@ -1,16 +1,27 @@
 use std::sync::Arc;
 
 use fnct::format::JsonFormatter;
+use key_rwlock::KeyRwLock;
 use lib::{config::Config, SharedState};
 use poem_openapi::OpenApi;
 use sandkasten_client::SandkastenClient;
-use tokio::sync::Semaphore;
+use tokio::sync::{RwLock, Semaphore};
 
 use self::{
-    challenges::Challenges, coding_challenges::CodingChallenges, course_tasks::CourseTasks,
-    leaderboard::LeaderboardEndpoints, matchings::Matchings, multiple_choice::MultipleChoice,
-    question::Questions, subtasks::Subtasks,
+    challenges::Challenges,
+    coding_challenges::{
+        submissions::{JudgeTasks, QueuePositions},
+        CodingChallenges,
+    },
+    course_tasks::CourseTasks,
+    leaderboard::LeaderboardEndpoints,
+    matchings::Matchings,
+    multiple_choice::MultipleChoice,
+    question::Questions,
+    search::Search,
+    subtasks::Subtasks,
 };
+use crate::services::{moderation::content_moderator, subtasks::RewardLock};
 
 mod challenges;
 pub mod coding_challenges;
@@ -19,6 +30,7 @@ mod leaderboard;
 mod matchings;
 mod multiple_choice;
 mod question;
+mod search;
 mod subtasks;
 
 #[derive(poem_openapi::Tags)]
@@ -39,16 +51,35 @@ pub enum Tags {
     CodingChallenges,
     /// Leaderboard
     Leaderboard,
+    /// Search
+    Search,
 }
 
 pub async fn setup_api(
     state: Arc<SharedState>,
     config: Arc<Config>,
     sandkasten: SandkastenClient,
+    judge_tasks: JudgeTasks,
 ) -> anyhow::Result<impl OpenApi> {
+    let moderator = content_moderator(config.challenges.moderation.as_ref());
+    let sandkasten_lock = Arc::new(Semaphore::new(
+        config
+            .challenges
+            .coding_challenges
+            .max_sandkasten_concurrency,
+    ));
+    let reward_lock = Arc::new(RewardLock::default());
+    let queue_positions = Arc::new(RwLock::new(QueuePositions::new(
+        config.challenges.coding_challenges.max_concurrency,
+    )));
+    let submission_lock = Arc::new(KeyRwLock::default());
     Ok((
         Challenges {
             state: Arc::clone(&state),
+            config: Arc::clone(&config),
+            sandkasten: sandkasten.clone(),
+            sandkasten_lock: Arc::clone(&sandkasten_lock),
+            judge_cache: state.cache.with_formatter(JsonFormatter),
         },
         CourseTasks {
             state: Arc::clone(&state),
@@ -57,27 +88,39 @@ pub async fn setup_api(
         Subtasks {
             state: Arc::clone(&state),
             config: Arc::clone(&config),
+            queue_positions: Arc::clone(&queue_positions),
+            submission_lock: Arc::clone(&submission_lock),
         }
         .get_api(),
         MultipleChoice {
             state: Arc::clone(&state),
             config: Arc::clone(&config),
+            moderator: Arc::clone(&moderator),
+            reward_lock: Arc::clone(&reward_lock),
         },
         Questions {
             state: Arc::clone(&state),
             config: Arc::clone(&config),
+            moderator,
+            reward_lock: Arc::clone(&reward_lock),
         },
         Matchings {
             state: Arc::clone(&state),
             config: Arc::clone(&config),
+            reward_lock: Arc::clone(&reward_lock),
         },
         CodingChallenges {
             judge_cache: state.cache.with_formatter(JsonFormatter),
             state: Arc::clone(&state),
             sandkasten,
+            sandkasten_lock,
             judge_lock: Arc::new(Semaphore::new(
                 config.challenges.coding_challenges.max_concurrency,
             )),
+            judge_tasks,
+            reward_lock,
+            queue_positions,
+            submission_lock,
             config,
         }
         .setup_api()
@@ -86,5 +129,6 @@ pub async fn setup_api(
             cache: state.cache.with_formatter(Default::default()),
             state,
         },
+        Search,
     ))
 }
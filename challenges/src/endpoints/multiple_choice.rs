@@ -18,24 +18,33 @@ use poem_openapi::{
     OpenApi,
 };
 use schemas::challenges::multiple_choice::{
-    check_answers, split_answers, Answer, CreateMultipleChoiceQuestionRequest,
-    MultipleChoiceQuestion, MultipleChoiceQuestionSummary, SolveMCQFeedback, SolveMCQRequest,
-    UpdateMultipleChoiceQuestionRequest,
+    answer_order, check_answers, split_answers, unshuffle_answers, Answer,
+    CreateMultipleChoiceQuestionRequest, MultipleChoiceQuestion, MultipleChoiceQuestionSummary,
+    SolveMCQFeedback, SolveMCQRequest, UpdateMultipleChoiceQuestionRequest,
 };
 use sea_orm::{ActiveModelTrait, Set, Unchanged};
 use uuid::Uuid;
 
 use super::Tags;
-use crate::services::subtasks::{
-    create_subtask, deduct_hearts, get_subtask, get_user_subtask, query_subtask,
-    query_subtask_admin, query_subtasks, send_task_rewards, update_subtask, update_user_subtask,
-    CreateSubtaskError, QuerySubtaskAdminError, QuerySubtasksFilter, UpdateSubtaskError,
-    UserSubtaskExt,
+use crate::{
+    after_commit::AfterCommit,
+    services::{
+        moderation::ContentModerator,
+        subtasks::{
+            attempts_exhausted, create_subtask, deduct_hearts, get_subtask, get_user_subtask,
+            publish_subtask_solved, query_subtask, query_subtask_admin, query_subtasks,
+            send_task_rewards, update_subtask, update_user_subtask, CreateSubtaskError,
+            QuerySubtaskAdminError, QuerySubtasksFilter, RewardLock, UpdateSubtaskError,
+            UserSubtaskExt,
+        },
+    },
 };
 
 pub struct MultipleChoice {
     pub state: Arc<SharedState>,
     pub config: Arc<Config>,
+    pub moderator: Arc<dyn ContentModerator>,
+    pub reward_lock: Arc<RewardLock>,
 }
 
 #[OpenApi(tag = "Tags::MultipleChoice")]
@@ -74,6 +83,8 @@ impl MultipleChoice {
                     retired: retired.0,
                     creator: creator.0,
                     ty: None,
+                    tags: Vec::new(),
+                    include_locked: false,
                 },
                 MultipleChoiceQuestionSummary::from,
             )
@@ -95,7 +106,7 @@ impl MultipleChoice {
             &auth.0,
             task_id.0,
             subtask_id.0,
-            MultipleChoiceQuestion::<String>::from,
+            |mcq, subtask| MultipleChoiceQuestion::<String>::for_user(mcq, subtask, auth.0.id),
         )
         .await?
         {
@@ -169,6 +180,13 @@ impl MultipleChoice {
             return CreateMCQ::invalid_multiple_choice();
         }
 
+        let texts = std::iter::once(data.0.question.as_str())
+            .chain(data.0.answers.iter().map(|x| x.answer.as_str()))
+            .collect::<Vec<_>>();
+        if self.moderator.is_flagged(&texts).await {
+            return CreateMCQ::content_rejected();
+        }
+
         let (answers, correct) = split_answers(data.0.answers);
         let mcq = challenges_multiple_choice_quizes::ActiveModel {
             subtask_id: Set(subtask.id),
@@ -176,6 +194,7 @@ impl MultipleChoice {
             answers: Set(answers),
             correct_answers: Set(correct),
             single_choice: Set(data.0.single_choice),
+            shuffle: Set(data.0.shuffle),
         }
         .insert(&***db)
         .await?;
@@ -206,6 +225,23 @@ impl MultipleChoice {
             Err(UpdateSubtaskError::TaskNotFound) => return UpdateMCQ::task_not_found(),
         };
 
+        if matches!(data.0.question, PatchValue::Set(_))
+            || matches!(data.0.answers, PatchValue::Set(_))
+        {
+            let question = data.0.question.get_new(&mcq.question).as_str();
+            let texts = match &data.0.answers {
+                PatchValue::Set(answers) => std::iter::once(question)
+                    .chain(answers.iter().map(|x| x.answer.as_str()))
+                    .collect::<Vec<_>>(),
+                PatchValue::Unchanged => std::iter::once(question)
+                    .chain(mcq.answers.iter().map(String::as_str))
+                    .collect(),
+            };
+            if self.moderator.is_flagged(&texts).await {
+                return UpdateMCQ::content_rejected();
+            }
+        }
+
         let (answers, correct, cnt) = if let PatchValue::Set(answers) = data.0.answers {
             let cnt = answers.iter().filter(|x| x.correct).count();
             let (a, c) = split_answers(answers);
@@ -228,6 +264,7 @@ impl MultipleChoice {
             answers,
             correct_answers: correct,
             single_choice: data.0.single_choice.update(mcq.single_choice),
+            shuffle: data.0.shuffle.update(mcq.shuffle),
         }
         .update(&***db)
         .await?;
@@ -246,6 +283,7 @@ impl MultipleChoice {
         subtask_id: Path<Uuid>,
         data: Json<SolveMCQRequest>,
         db: Data<&DbTxn>,
+        after_commit: Data<&AfterCommit>,
         auth: VerifiedUserAuth,
     ) -> SolveMCQ::Response<VerifiedUserAuth> {
         let Some((mcq, subtask)) =
@@ -262,10 +300,15 @@ impl MultipleChoice {
             return SolveMCQ::wrong_length();
         }
 
+        let _guard = self.reward_lock.write((subtask.id, auth.0.id)).await;
         let user_subtask = get_user_subtask(&db, auth.0.id, subtask.id).await?;
+        if attempts_exhausted(&auth.0, &subtask, &user_subtask) {
+            return SolveMCQ::attempts_exhausted();
+        }
 
         let solved_previously = user_subtask.is_solved();
-        if let Some(last_attempt) = user_subtask.last_attempt() {
+        let timeout_exempt = self.config.challenges.is_service_account(auth.0.id);
+        if let Some(last_attempt) = user_subtask.last_attempt().filter(|_| !timeout_exempt) {
             let time_left = self.config.challenges.multiple_choice_questions.timeout as i64
                 - (Utc::now() - last_attempt).num_seconds();
             if time_left > 0 {
@@ -273,17 +316,26 @@ impl MultipleChoice {
             }
         }
 
-        if !deduct_hearts(&self.state.services, &self.config, &auth.0, &subtask).await? {
+        if deduct_hearts(&self.state.services, &self.config, &auth.0, &subtask)
+            .await?
+            .is_none()
+        {
             return SolveMCQ::not_enough_hearts();
         }
 
-        let correct_cnt = check_answers(&data.0.answers, mcq.correct_answers);
+        let answers = if mcq.shuffle {
+            let order = answer_order(mcq.answers.len(), auth.0.id, subtask.id);
+            unshuffle_answers(&data.0.answers, &order)
+        } else {
+            data.0.answers
+        };
+        let correct_cnt = check_answers(&answers, mcq.correct_answers);
         let solved = correct_cnt == mcq.answers.len();
 
         if !solved_previously {
             let now = Utc::now().naive_utc();
             if solved {
-                update_user_subtask(
+                let updated = update_user_subtask(
                     &db,
                     user_subtask.as_ref(),
                     challenges_user_subtasks::ActiveModel {
@@ -297,8 +349,18 @@ impl MultipleChoice {
                 )
                 .await?;
 
-                if auth.0.id != subtask.creator {
-                    send_task_rewards(&self.state.services, &db, auth.0.id, &subtask).await?;
+                // `None` if a concurrent request has solved the subtask first
+                if updated.is_some() {
+                    if auth.0.id != subtask.creator {
+                        send_task_rewards(&self.state.services, &db, auth.0.id, &subtask).await?;
+                    }
+                    publish_subtask_solved(
+                        &after_commit,
+                        &self.state.services,
+                        auth.0.id,
+                        &subtask,
+                        now,
+                    );
                 }
             } else {
                 update_user_subtask(
@@ -357,6 +419,8 @@ response!(CreateMCQ = {
     InvalidSingleChoice(400, error),
     /// There is no correct answer.
     InvalidMultipleChoice(400, error),
+    /// The question or one of the answers has been rejected by the content moderation.
+    ContentRejected(400, error),
 });
 
 response!(UpdateMCQ = {
@@ -369,6 +433,8 @@ response!(UpdateMCQ = {
     InvalidSingleChoice(400, error),
     /// There is no correct answer.
     InvalidMultipleChoice(400, error),
+    /// The question or one of the answers has been rejected by the content moderation.
+    ContentRejected(400, error),
 });
 
 response!(SolveMCQ = {
@@ -381,4 +447,6 @@ response!(SolveMCQ = {
     SubtaskNotFound(404, error),
     /// The user does not have enough hearts to submit a solution and is neither an admin nor the creator of this subtask.
     NotEnoughHearts(403, error),
+    /// The user has used up all attempts to solve this subtask.
+    AttemptsExhausted(403, error),
 });
@@ -2,7 +2,8 @@ use std::sync::Arc;
 
 use chrono::{DateTime, Utc};
 use entity::{
-    challenges_questions, challenges_user_subtasks, sea_orm_active_enums::ChallengesSubtaskType,
+    challenges_question_attempts, challenges_questions, challenges_user_subtasks,
+    sea_orm_active_enums::ChallengesSubtaskType,
 };
 use lib::{
     auth::{AdminAuth, VerifiedUserAuth},
@@ -10,30 +11,42 @@ use lib::{
     SharedState,
 };
 use poem::web::Data;
-use poem_ext::{db::DbTxn, response};
+use poem_ext::{db::DbTxn, patch_value::PatchValue, response};
 use poem_openapi::{
     param::{Path, Query},
     payload::Json,
     OpenApi,
 };
+use regex::{Regex, RegexBuilder};
 use schemas::challenges::question::{
-    CreateQuestionRequest, Question, QuestionSummary, QuestionWithSolution, SolveQuestionFeedback,
-    SolveQuestionRequest, UpdateQuestionRequest,
+    CreateQuestionRequest, Question, QuestionAttempt, QuestionSummary, QuestionWithSolution,
+    SolveQuestionFeedback, SolveQuestionRequest, UpdateQuestionRequest,
+};
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, EntityTrait, QueryFilter, QueryOrder, Set, Unchanged,
 };
-use sea_orm::{ActiveModelTrait, Set, Unchanged};
 use uuid::Uuid;
 
 use super::Tags;
-use crate::services::subtasks::{
-    create_subtask, deduct_hearts, get_subtask, get_user_subtask, query_subtask,
-    query_subtask_admin, query_subtasks, send_task_rewards, update_subtask, update_user_subtask,
-    CreateSubtaskError, QuerySubtaskAdminError, QuerySubtasksFilter, UpdateSubtaskError,
-    UserSubtaskExt,
+use crate::{
+    after_commit::AfterCommit,
+    services::{
+        moderation::ContentModerator,
+        subtasks::{
+            attempts_exhausted, create_subtask, deduct_hearts, get_subtask, get_user_subtask,
+            publish_subtask_solved, query_subtask, query_subtask_admin, query_subtasks,
+            send_task_rewards, update_subtask, update_user_subtask, CreateSubtaskError,
+            QuerySubtaskAdminError, QuerySubtasksFilter, RewardLock, UpdateSubtaskError,
+            UserSubtaskExt,
+        },
+    },
 };
 
 pub struct Questions {
     pub state: Arc<SharedState>,
     pub config: Arc<Config>,
+    pub moderator: Arc<dyn ContentModerator>,
+    pub reward_lock: Arc<RewardLock>,
 }
 
 #[OpenApi(tag = "Tags::Questions")]
@@ -72,6 +85,8 @@ impl Questions {
                     retired: retired.0,
                     creator: creator.0,
                     ty: None,
+                    tags: Vec::new(),
+                    include_locked: false,
                 },
                 QuestionSummary::from,
             )
@@ -161,7 +176,11 @@ impl Questions {
             }
         };
 
-        if !check_answers(
+        if data.0.match_regex {
+            if !check_regex(&data.0.answers, data.0.case_sensitive) {
+                return CreateQuestion::invalid_regex();
+            }
+        } else if !check_answers(
             &data.0.answers,
             data.0.ascii_letters,
             data.0.digits,
@@ -170,10 +189,19 @@ impl Questions {
             return CreateQuestion::invalid_char();
         }
 
+        let texts = std::iter::once(&data.0.question)
+            .chain(&data.0.answers)
+            .map(String::as_str)
+            .collect::<Vec<_>>();
+        if self.moderator.is_flagged(&texts).await {
+            return CreateQuestion::content_rejected();
+        }
+
         let question = challenges_questions::ActiveModel {
             subtask_id: Set(subtask.id),
             question: Set(data.0.question),
             answers: Set(data.0.answers),
+            match_regex: Set(data.0.match_regex),
             case_sensitive: Set(data.0.case_sensitive),
             ascii_letters: Set(data.0.ascii_letters),
             digits: Set(data.0.digits),
@@ -209,8 +237,16 @@ impl Questions {
             Err(UpdateSubtaskError::TaskNotFound) => return UpdateQuestion::task_not_found(),
         };
 
-        if !check_answers(
-            data.0.answers.get_new(&question.answers),
+        let answers = data.0.answers.get_new(&question.answers);
+        if *data.0.match_regex.get_new(&question.match_regex) {
+            if !check_regex(
+                answers,
+                *data.0.case_sensitive.get_new(&question.case_sensitive),
+            ) {
+                return UpdateQuestion::invalid_regex();
+            }
+        } else if !check_answers(
+            answers,
             *data.0.ascii_letters.get_new(&question.ascii_letters),
             *data.0.digits.get_new(&question.digits),
             *data.0.punctuation.get_new(&question.punctuation),
@@ -218,10 +254,23 @@ impl Questions {
             return UpdateQuestion::invalid_char();
         }
 
+        if matches!(data.0.question, PatchValue::Set(_))
+            || matches!(data.0.answers, PatchValue::Set(_))
+        {
+            let texts = std::iter::once(data.0.question.get_new(&question.question))
+                .chain(answers)
+                .map(String::as_str)
+                .collect::<Vec<_>>();
+            if self.moderator.is_flagged(&texts).await {
+                return UpdateQuestion::content_rejected();
+            }
+        }
+
         let question = challenges_questions::ActiveModel {
             subtask_id: Unchanged(question.subtask_id),
             question: data.0.question.update(question.question),
             answers: data.0.answers.update(question.answers),
+            match_regex: data.0.match_regex.update(question.match_regex),
             case_sensitive: data.0.case_sensitive.update(question.case_sensitive),
             ascii_letters: data.0.ascii_letters.update(question.ascii_letters),
             digits: data.0.digits.update(question.digits),
@@ -245,6 +294,7 @@ impl Questions {
         subtask_id: Path<Uuid>,
         data: Json<SolveQuestionRequest>,
         db: Data<&DbTxn>,
+        after_commit: Data<&AfterCommit>,
         auth: VerifiedUserAuth,
     ) -> SolveQuestion::Response<VerifiedUserAuth> {
         let Some((question, subtask)) =
@@ -256,10 +306,15 @@ impl Questions {
             return SolveQuestion::subtask_not_found();
         }
 
+        let _guard = self.reward_lock.write((subtask.id, auth.0.id)).await;
         let user_subtask = get_user_subtask(&db, auth.0.id, subtask.id).await?;
+        if attempts_exhausted(&auth.0, &subtask, &user_subtask) {
+            return SolveQuestion::attempts_exhausted();
+        }
 
         let solved_previously = user_subtask.is_solved();
-        if let Some(last_attempt) = user_subtask.last_attempt() {
+        let timeout_exempt = self.config.challenges.is_service_account(auth.0.id);
+        if let Some(last_attempt) = user_subtask.last_attempt().filter(|_| !timeout_exempt) {
             let time_left = self.config.challenges.questions.timeout as i64
                 - (Utc::now() - last_attempt).num_seconds();
             if time_left > 0 {
@@ -267,20 +322,20 @@ impl Questions {
             }
         }
 
-        if !deduct_hearts(&self.state.services, &self.config, &auth.0, &subtask).await? {
+        if deduct_hearts(&self.state.services, &self.config, &auth.0, &subtask)
+            .await?
+            .is_none()
+        {
             return SolveQuestion::not_enough_hearts();
         }
 
         let answer = normalize_answer(&data.0.answer, question.case_sensitive);
-        let solved = question
-            .answers
-            .iter()
-            .any(|ans| normalize_answer(ans, question.case_sensitive) == answer);
+        let solved = check_solution(&question, &answer);
 
         if !solved_previously {
             let now = Utc::now().naive_utc();
             if solved {
-                update_user_subtask(
+                let updated = update_user_subtask(
                     &db,
                     user_subtask.as_ref(),
                     challenges_user_subtasks::ActiveModel {
@@ -294,8 +349,18 @@ impl Questions {
                 )
                 .await?;
 
-                if auth.0.id != subtask.creator {
-                    send_task_rewards(&self.state.services, &db, auth.0.id, &subtask).await?;
+                // `None` if a concurrent request has solved the subtask first
+                if updated.is_some() {
+                    if auth.0.id != subtask.creator {
+                        send_task_rewards(&self.state.services, &db, auth.0.id, &subtask).await?;
+                    }
+                    publish_subtask_solved(
+                        &after_commit,
+                        &self.state.services,
+                        auth.0.id,
+                        &subtask,
+                        now,
+                    );
                 }
             } else {
                 update_user_subtask(
@@ -311,10 +376,65 @@ impl Questions {
                 )
                 .await?;
             }
+
+            challenges_question_attempts::ActiveModel {
+                id: Set(Uuid::new_v4()),
+                question_id: Set(question.subtask_id),
+                user_id: Set(auth.0.id),
+                timestamp: Set(now),
+                solved: Set(solved),
+                given_answer: Set(answer),
+            }
+            .insert(&***db)
+            .await?;
         }
 
         SolveQuestion::ok(SolveQuestionFeedback { solved })
     }
+
+    /// List previous attempts to solve a question.
+    ///
+    /// Only admins can view the attempts of other users.
+    #[oai(
+        path = "/tasks/:task_id/questions/:subtask_id/attempts",
+        method = "get"
+    )]
+    async fn list_question_attempts(
+        &self,
+        task_id: Path<Uuid>,
+        subtask_id: Path<Uuid>,
+        /// The user whose attempts should be returned. Defaults to the
+        /// authenticated user.
+        user_id: Query<Option<Uuid>>,
+        db: Data<&DbTxn>,
+        auth: VerifiedUserAuth,
+    ) -> ListQuestionAttempts::Response<VerifiedUserAuth> {
+        let user_id = user_id.0.unwrap_or(auth.0.id);
+        if !auth.0.admin && user_id != auth.0.id {
+            return ListQuestionAttempts::forbidden();
+        }
+
+        let Some((question, subtask)) =
+            get_subtask::<challenges_questions::Entity>(&db, task_id.0, subtask_id.0).await?
+        else {
+            return ListQuestionAttempts::subtask_not_found();
+        };
+        if !auth.0.admin && auth.0.id != subtask.creator && !subtask.enabled {
+            return ListQuestionAttempts::subtask_not_found();
+        }
+
+        ListQuestionAttempts::ok(
+            challenges_question_attempts::Entity::find()
+                .filter(challenges_question_attempts::Column::QuestionId.eq(question.subtask_id))
+                .filter(challenges_question_attempts::Column::UserId.eq(user_id))
+                .order_by_desc(challenges_question_attempts::Column::Timestamp)
+                .all(&***db)
+                .await?
+                .into_iter()
+                .map(Into::into)
+                .collect(),
+        )
+    }
 }
 
 response!(ListQuestions = {
@@ -349,6 +469,10 @@ response!(CreateQuestion = {
     CoinLimitExceeded(403, error) => u64,
     /// One of `ascii_letters`, `digits` or `punctuation` is set to `false`, but one of the `answers` contains such a character.
     InvalidChar(400, error),
+    /// `match_regex` is set to `true`, but one of the `answers` is not a valid regular expression.
+    InvalidRegex(400, error),
+    /// The question or one of the answers has been rejected by the content moderation.
+    ContentRejected(400, error),
 });
 
 response!(UpdateQuestion = {
@@ -359,6 +483,10 @@ response!(UpdateQuestion = {
     TaskNotFound(404, error),
     /// One of `ascii_letters`, `digits` or `punctuation` is set to `false`, but one of the `answers` contains such a character.
     InvalidChar(400, error),
+    /// `match_regex` is set to `true`, but one of the `answers` is not a valid regular expression.
+    InvalidRegex(400, error),
+    /// The question or one of the answers has been rejected by the content moderation.
+    ContentRejected(400, error),
 });
 
 response!(SolveQuestion = {
@@ -369,9 +497,24 @@ response!(SolveQuestion = {
     SubtaskNotFound(404, error),
     /// The user does not have enough hearts to submit a solution and is neither an admin nor the creator of this subtask.
     NotEnoughHearts(403, error),
+    /// The user has used up all attempts to solve this subtask.
+    AttemptsExhausted(403, error),
 });
 
-fn check_answers(answers: &[String], ascii_letters: bool, digits: bool, punctuation: bool) -> bool {
+response!(ListQuestionAttempts = {
+    Ok(200) => Vec<QuestionAttempt>,
+    /// Subtask does not exist.
+    SubtaskNotFound(404, error),
+    /// The user is not allowed to view the attempts of other users.
+    Forbidden(403, error),
+});
+
+pub(crate) fn check_answers(
+    answers: &[String],
+    ascii_letters: bool,
+    digits: bool,
+    punctuation: bool,
+) -> bool {
     answers.iter().all(|answer| {
         answer.chars().all(|c| {
             (ascii_letters || !c.is_ascii_alphabetic())
@@ -381,6 +524,38 @@ fn check_answers(answers: &[String], ascii_letters: bool, digits: bool, punctuat
     })
 }
 
+/// Maximum size (in bytes) of a compiled answer regex. Keeps creators from
+/// submitting patterns that are excessively expensive to compile or match.
+const REGEX_SIZE_LIMIT: usize = 1 << 16;
+
+pub(crate) fn check_regex(answers: &[String], case_sensitive: bool) -> bool {
+    answers
+        .iter()
+        .all(|answer| build_regex(answer, case_sensitive).is_ok())
+}
+
+fn build_regex(pattern: &str, case_sensitive: bool) -> Result<Regex, regex::Error> {
+    RegexBuilder::new(&format!("^(?:{pattern})$"))
+        .case_insensitive(!case_sensitive)
+        .size_limit(REGEX_SIZE_LIMIT)
+        .dfa_size_limit(REGEX_SIZE_LIMIT)
+        .build()
+}
+
+/// Check a normalized answer against the answers of a question.
+fn check_solution(question: &challenges_questions::Model, answer: &str) -> bool {
+    if question.match_regex {
+        question.answers.iter().any(|pattern| {
+            build_regex(pattern, question.case_sensitive).is_ok_and(|re| re.is_match(answer))
+        })
+    } else {
+        question
+            .answers
+            .iter()
+            .any(|ans| normalize_answer(ans, question.case_sensitive) == answer)
+    }
+}
+
 fn normalize_answer(answer: &str, case_sensitive: bool) -> String {
     let answer = answer.trim();
     let mut out = String::with_capacity(answer.len());
@@ -419,4 +594,29 @@ mod tests {
             "this is my answer! 42"
         );
     }
+
+    #[test]
+    fn test_check_solution_regex() {
+        let question = challenges_questions::Model {
+            subtask_id: Uuid::new_v4(),
+            question: String::new(),
+            answers: vec![r"x ?= ?-?\d+".into(), "foo|bar".into()],
+            case_sensitive: false,
+            ascii_letters: true,
+            digits: true,
+            punctuation: true,
+            blocks: vec![],
+            match_regex: true,
+        };
+        let check = |answer| check_solution(&question, &normalize_answer(answer, false));
+        assert!(check("x = 42"));
+        assert!(check("  X=-7 "));
+        assert!(check("BAR"));
+        assert!(!check("x = 42 y"));
+        assert!(!check("foobar"));
+
+        assert!(check_regex(&question.answers, false));
+        assert!(!check_regex(&["(".into()], false));
+        assert!(!check_regex(&["a{1000}{1000}".into()], false));
+    }
 }
@@ -0,0 +1,166 @@
+use std::collections::HashMap;
+
+use entity::{challenges_challenges, challenges_coding_challenges, challenges_subtasks};
+use lib::auth::VerifiedUserAuth;
+use poem::web::Data;
+use poem_ext::{db::DbTxn, response};
+use poem_openapi::{param::Query, OpenApi};
+use schemas::challenges::search::{
+    ChallengeSearchResult, CodingChallengeSearchResult, SearchResult,
+};
+use sea_orm::{
+    sea_query::{extension::postgres::PgExpr, Condition, Expr},
+    ColumnTrait, EntityTrait, PaginatorTrait, QueryFilter, QueryOrder, QuerySelect,
+};
+
+use super::Tags;
+use crate::services::subtasks::{not_in_archived_challenge, not_in_unlisted_challenge};
+
+pub struct Search;
+
+#[OpenApi(tag = "Tags::Search")]
+impl Search {
+    /// Search challenges and coding challenges by keyword.
+    ///
+    /// Challenges are matched by title and description, coding challenges by
+    /// description. Challenges are returned before coding challenges. Unlisted
+    /// challenges and their coding challenges are only found by admins.
+    #[oai(path = "/search", method = "get")]
+    async fn search(
+        &self,
+        /// The search query.
+        #[oai(validator(min_length = 1, max_length = 256))]
+        q: Query<String>,
+        /// Maximum number of results to return.
+        #[oai(default = "default_limit", validator(maximum(value = "100")))]
+        limit: Query<u64>,
+        /// Pagination offset.
+        #[oai(default, validator(maximum(value = "10000")))]
+        offset: Query<u64>,
+        db: Data<&DbTxn>,
+        auth: VerifiedUserAuth,
+    ) -> SearchAll::Response<VerifiedUserAuth> {
+        let pattern = format!("%{}%", escape_like(&q.0));
+        let mut challenges = challenges_challenges::Entity::find().filter(
+            Condition::any()
+                .add(
+                    Expr::col((
+                        challenges_challenges::Entity,
+                        challenges_challenges::Column::Title,
+                    ))
+                    .ilike(&pattern),
+                )
+                .add(
+                    Expr::col((
+                        challenges_challenges::Entity,
+                        challenges_challenges::Column::Description,
+                    ))
+                    .ilike(&pattern),
+                ),
+        );
+        if !auth.0.admin {
+            challenges = challenges
+                .filter(challenges_challenges::Column::Archived.eq(false))
+                .filter(challenges_challenges::Column::Listed.eq(true));
+        }
+        let matching_challenges = challenges.clone();
+        let challenges = challenges
+            .order_by_asc(challenges_challenges::Column::Title)
+            .offset(offset.0)
+            .limit(limit.0)
+            .all(&***db)
+            .await?;
+
+        // coding challenges are returned after all challenges, so skip the part of
+        // the offset that has not been used up by challenges
+        let coding_limit = limit.0 - challenges.len() as u64;
+        if coding_limit == 0 {
+            return SearchAll::ok(challenges.into_iter().map(challenge_result).collect());
+        }
+        let coding_offset = offset
+            .0
+            .saturating_sub(matching_challenges.count(&***db).await?);
+
+        let mut coding_challenges = challenges_coding_challenges::Entity::find()
+            .find_also_related(challenges_subtasks::Entity)
+            .filter(
+                Expr::col((
+                    challenges_coding_challenges::Entity,
+                    challenges_coding_challenges::Column::Description,
+                ))
+                .ilike(&pattern),
+            );
+        if !auth.0.admin {
+            coding_challenges = coding_challenges
+                .filter(
+                    Condition::any()
+                        .add(challenges_subtasks::Column::Enabled.eq(true))
+                        .add(challenges_subtasks::Column::Creator.eq(auth.0.id)),
+                )
+                .filter(not_in_archived_challenge())
+                .filter(not_in_unlisted_challenge());
+        }
+        let coding_challenges = coding_challenges
+            .order_by_asc(challenges_subtasks::Column::CreationTimestamp)
+            .offset(coding_offset)
+            .limit(coding_limit)
+            .all(&***db)
+            .await?
+            .into_iter()
+            .filter_map(|(_, subtask)| subtask)
+            .collect::<Vec<_>>();
+
+        let categories = challenges_challenges::Entity::find()
+            .filter(
+                challenges_challenges::Column::TaskId
+                    .is_in(coding_challenges.iter().map(|subtask| subtask.task_id)),
+            )
+            .all(&***db)
+            .await?
+            .into_iter()
+            .map(|challenge| (challenge.task_id, challenge.category_id))
+            .collect::<HashMap<_, _>>();
+
+        SearchAll::ok(
+            challenges
+                .into_iter()
+                .map(challenge_result)
+                .chain(coding_challenges.into_iter().map(|subtask| {
+                    SearchResult::CodingChallenge(CodingChallengeSearchResult {
+                        subtask_id: subtask.id,
+                        task_id: subtask.task_id,
+                        category_id: categories.get(&subtask.task_id).copied(),
+                    })
+                }))
+                .collect(),
+        )
+    }
+}
+
+response!(SearchAll = {
+    Ok(200) => Vec<SearchResult>,
+});
+
+fn challenge_result(challenge: challenges_challenges::Model) -> SearchResult {
+    SearchResult::Challenge(ChallengeSearchResult {
+        task_id: challenge.task_id,
+        category_id: challenge.category_id,
+        title: challenge.title,
+    })
+}
+
+fn default_limit() -> u64 {
+    20
+}
+
+/// Escape the wildcard characters of a `LIKE` pattern.
+fn escape_like(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        if matches!(c, '\\' | '%' | '_') {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out
+}
@@ -1,4 +1,4 @@
-use chrono::Utc;
+use chrono::{DateTime, Utc};
 use entity::{challenges_ban, sea_orm_active_enums::ChallengesBanAction};
 use lib::auth::{AdminAuth, VerifiedUserAuth};
 use poem::web::Data;
@@ -8,10 +8,10 @@ use poem_openapi::{
     payload::Json,
     OpenApi,
 };
-use schemas::challenges::subtasks::{Ban, CreateBanRequest, UpdateBanRequest};
+use schemas::challenges::subtasks::{Ban, CreateBanRequest, UnbanResult, UpdateBanRequest};
 use sea_orm::{
-    ActiveModelTrait, ColumnTrait, Condition, DatabaseTransaction, DbErr, EntityTrait, ModelTrait,
-    QueryFilter, Set, Unchanged,
+    sea_query::Expr, ActiveModelTrait, ColumnTrait, Condition, DatabaseTransaction, DbErr,
+    EntityTrait, ModelTrait, QueryFilter, QueryOrder, QuerySelect, Set, Unchanged,
 };
 use uuid::Uuid;
 
@@ -24,7 +24,8 @@ impl Api {
     /// Return a list of all bans.
     ///
     /// Normal users are allowed to query their own bans by setting `user_id` to
-    /// their own user id.
+    /// their own user id. The most recent bans are returned first.
+    #[allow(clippy::too_many_arguments)]
     #[oai(path = "/bans", method = "get")]
     pub async fn list_bans(
         &self,
@@ -32,6 +33,10 @@ impl Api {
         creator: Query<Option<Uuid>>,
         active: Query<Option<bool>>,
         action: Query<Option<ChallengesBanAction>>,
+        /// Maximum number of bans to return
+        limit: Query<Option<u64>>,
+        /// Pagination offset
+        offset: Query<Option<u64>>,
         db: Data<&DbTxn>,
         auth: VerifiedUserAuth,
     ) -> ListBans::Response<VerifiedUserAuth> {
@@ -47,14 +52,7 @@ impl Api {
             query = query.filter(challenges_ban::Column::Creator.eq(creator));
         }
         if let Some(active) = active.0 {
-            let now = Utc::now();
-            let mut cond = Condition::all()
-                .add(challenges_ban::Column::Start.lte(now))
-                .add(
-                    Condition::any()
-                        .add(challenges_ban::Column::End.is_null())
-                        .add(challenges_ban::Column::End.gt(now)),
-                );
+            let mut cond = active_condition(Utc::now());
             if !active {
                 cond = cond.not();
             }
@@ -65,6 +63,10 @@ impl Api {
         }
         ListBans::ok(
             query
+                .order_by_desc(challenges_ban::Column::Start)
+                .order_by_asc(challenges_ban::Column::Id)
+                .limit(limit.0)
+                .offset(offset.0)
                 .all(&***db)
                 .await?
                 .into_iter()
@@ -157,6 +159,28 @@ impl Api {
         ban.delete(&***db).await?;
         DeleteBan::ok()
     }
+
+    /// Lift all active bans of a user for the given action by letting them end
+    /// now.
+    #[oai(path = "/users/:user_id/unban", method = "post")]
+    pub async fn unban_user(
+        &self,
+        user_id: Path<Uuid>,
+        action: Query<ChallengesBanAction>,
+        db: Data<&DbTxn>,
+        _auth: AdminAuth,
+    ) -> UnbanUser::Response<AdminAuth> {
+        let now = Utc::now();
+        let lifted = challenges_ban::Entity::update_many()
+            .col_expr(challenges_ban::Column::End, Expr::value(now.naive_utc()))
+            .filter(challenges_ban::Column::UserId.eq(user_id.0))
+            .filter(challenges_ban::Column::Action.eq(action.0))
+            .filter(active_condition(now))
+            .exec(&***db)
+            .await?
+            .rows_affected;
+        UnbanUser::ok(UnbanResult { lifted })
+    }
 }
 
 response!(ListBans = {
@@ -185,6 +209,21 @@ response!(DeleteBan = {
     BanNotFound(404, error),
 });
 
+response!(UnbanUser = {
+    Ok(200) => UnbanResult,
+});
+
+/// Match bans that are active at `now`.
+fn active_condition(now: DateTime<Utc>) -> Condition {
+    Condition::all()
+        .add(challenges_ban::Column::Start.lte(now))
+        .add(
+            Condition::any()
+                .add(challenges_ban::Column::End.is_null())
+                .add(challenges_ban::Column::End.gt(now)),
+        )
+}
+
 async fn get_ban(
     db: &DatabaseTransaction,
     ban_id: Uuid,
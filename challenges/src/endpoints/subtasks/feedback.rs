@@ -5,7 +5,11 @@ use entity::{
     challenges_user_subtasks,
     sea_orm_active_enums::{ChallengesRating, ChallengesReportReason, ChallengesSubtaskType},
 };
-use lib::{auth::VerifiedUserAuth, config::Config, SharedState};
+use lib::{
+    auth::VerifiedUserAuth,
+    config::{Config, Quizzes},
+    SharedState,
+};
 use poem::web::Data;
 use poem_ext::{db::DbTxn, response};
 use poem_openapi::{param::Path, payload::Json, OpenApi};
@@ -83,7 +87,7 @@ impl Api {
 
         if data.0.rating == ChallengesRating::Negative {
             let ratings = challenges_user_subtasks::Entity::find()
-                .filter(challenges_user_subtasks::Column::UserId.eq(subtask.id))
+                .filter(challenges_user_subtasks::Column::SubtaskId.eq(subtask.id))
                 .filter(challenges_user_subtasks::Column::Rating.is_not_null())
                 .all(&***db)
                 .await?;
@@ -95,7 +99,7 @@ impl Api {
                 .iter()
                 .filter(|x| x.rating == Some(ChallengesRating::Negative))
                 .count();
-            if negative >= 10 && negative > positive {
+            if should_report(&self.config.challenges.quizzes, positive, negative) {
                 create_report(
                     &db,
                     None,
@@ -121,3 +125,38 @@ response!(PostFeedback = {
     /// The user is not allowed to post feeback for this subtask.
     PermissionDenied(403, error),
 });
+
+/// Return whether a subtask should be reported automatically because of its
+/// dislikes.
+fn should_report(config: &Quizzes, positive: usize, negative: usize) -> bool {
+    negative >= config.dislike_report_min as usize
+        && (!config.dislike_report_majority || negative > positive)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(dislike_report_min: u32, dislike_report_majority: bool) -> Quizzes {
+        Quizzes {
+            dislike_report_min,
+            dislike_report_majority,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn report_threshold() {
+        let conf = config(10, true);
+        assert!(!should_report(&conf, 0, 9));
+        assert!(should_report(&conf, 0, 10));
+        assert!(should_report(&conf, 10, 11));
+        assert!(!should_report(&conf, 10, 10));
+        assert!(!should_report(&conf, 20, 15));
+
+        let conf = config(3, false);
+        assert!(!should_report(&conf, 0, 2));
+        assert!(should_report(&conf, 0, 3));
+        assert!(should_report(&conf, 20, 3));
+    }
+}
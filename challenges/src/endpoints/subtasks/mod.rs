@@ -1,32 +1,61 @@
-use std::sync::Arc;
+use std::{collections::HashMap, sync::Arc};
 
-use entity::{challenges_subtasks, challenges_tasks, sea_orm_active_enums::ChallengesSubtaskType};
-use lib::{auth::VerifiedUserAuth, config::Config, SharedState};
+use chrono::{DateTime, NaiveDateTime, Utc};
+use entity::{
+    challenges_coding_challenge_result, challenges_coding_challenge_submissions,
+    challenges_matching_attempts, challenges_multiple_choice_attempts,
+    challenges_question_attempts, challenges_subtasks, challenges_tasks, challenges_user_subtasks,
+    sea_orm_active_enums::ChallengesSubtaskType,
+};
+use key_rwlock::KeyRwLock;
+use lib::{
+    auth::{AdminAuth, VerifiedUserAuth},
+    config::Config,
+    services::shop::AddCoinsError,
+    SharedState,
+};
 use poem::web::Data;
 use poem_ext::{db::DbTxn, response, responses::ErrorResponse};
 use poem_openapi::{
     param::{Path, Query},
+    payload::Json,
     OpenApi,
 };
-use schemas::challenges::subtasks::{Subtask, SubtaskStats};
-use sea_orm::{ColumnTrait, DatabaseTransaction, EntityTrait, ModelTrait, QueryFilter};
+use schemas::challenges::{
+    sort::{SortBy, SortOrder},
+    subtasks::{
+        BulkEnableSubtasksRequest, BulkEnableSubtasksResult, GetSubtaskStatesRequest,
+        MoveSubtaskRequest, RecomputeAttemptsResult, ResetProgressResult, SkillReward, Subtask,
+        SubtaskRewards, SubtaskState, SubtaskStats, SubtaskStatsByType,
+    },
+};
+use sea_orm::{
+    sea_query::Expr, ActiveModelTrait, ColumnTrait, DatabaseTransaction, EntityTrait, ModelTrait,
+    PaginatorTrait, QueryFilter, Set, Unchanged,
+};
+use tokio::sync::RwLock;
 use uuid::Uuid;
 
-use super::Tags;
+use super::{coding_challenges::submissions::QueuePositions, Tags};
 use crate::services::subtasks::{
-    get_user_subtasks, query_subtasks_only, stat_subtasks, stat_subtasks_prepare,
-    QuerySubtasksFilter,
+    get_skill_rewards, get_user_subtasks, move_subtask, query_random_subtask, query_subtasks_only,
+    recompute_attempts, revoke_task_rewards, stat_subtasks, stat_subtasks_prepare,
+    MoveSubtaskError, QuerySubtasksFilter, SendTaskRewardsError, UserSubtaskExt,
 };
 
 mod bans;
 mod config;
 mod feedback;
 mod reports;
+mod streaks;
+mod user_stats;
 
 #[derive(Clone)]
 pub struct Subtasks {
     pub state: Arc<SharedState>,
     pub config: Arc<Config>,
+    pub queue_positions: Arc<RwLock<QueuePositions>>,
+    pub submission_lock: Arc<KeyRwLock<Uuid>>,
 }
 
 impl Subtasks {
@@ -38,12 +67,15 @@ impl Subtasks {
             },
             self.clone(),
             feedback::Api {
-                state: self.state,
+                state: Arc::clone(&self.state),
                 config: Arc::clone(&self.config),
             },
             reports::Api {
+                state: Arc::clone(&self.state),
                 config: self.config,
             },
+            streaks::Api,
+            user_stats::Api { state: self.state },
         )
     }
 }
@@ -51,6 +83,9 @@ impl Subtasks {
 #[OpenApi(tag = "Tags::Subtasks")]
 impl Subtasks {
     /// List all subtasks across all parent tasks.
+    ///
+    /// Subtasks of unlisted challenges are only included if `task_id` is set or
+    /// the user created them.
     #[allow(clippy::too_many_arguments)]
     #[oai(path = "/subtasks", method = "get")]
     pub async fn list_subtasks(
@@ -70,12 +105,20 @@ impl Subtasks {
         retired: Query<Option<bool>>,
         /// Filter by creator.
         creator: Query<Option<Uuid>>,
+        /// Only include subtasks that have all of these tags.
+        #[oai(default)]
+        tag: Query<Vec<String>>,
+        /// Sort subtasks by this property (default: creation timestamp).
+        sort: Query<Option<SortBy>>,
+        /// The sort order (default: ascending).
+        order: Query<Option<SortOrder>>,
         db: Data<&DbTxn>,
         auth: VerifiedUserAuth,
     ) -> ListSubtasks::Response<VerifiedUserAuth> {
         ListSubtasks::ok(
             query_subtasks_only(
                 &db,
+                &self.state.cache,
                 &auth.0,
                 task_id.0,
                 QuerySubtasksFilter {
@@ -86,12 +129,81 @@ impl Subtasks {
                     retired: retired.0,
                     creator: creator.0,
                     ty: subtask_type.0,
+                    tags: tag.0,
+                    include_locked: false,
                 },
+                sort.0,
+                order.0,
+                None,
+                None,
             )
             .await?,
         )
     }
 
+    /// List all subtasks created by the authenticated user across all parent
+    /// tasks.
+    #[oai(path = "/me/subtasks", method = "get")]
+    pub async fn list_own_subtasks(
+        &self,
+        /// Filter by subtask type.
+        subtask_type: Query<Option<ChallengesSubtaskType>>,
+        /// Whether to search for enabled subtasks.
+        enabled: Query<Option<bool>>,
+        /// Maximum number of subtasks to return
+        limit: Query<Option<u64>>,
+        /// Pagination offset
+        offset: Query<Option<u64>>,
+        db: Data<&DbTxn>,
+        auth: VerifiedUserAuth,
+    ) -> ListSubtasks::Response<VerifiedUserAuth> {
+        let subtasks = query_subtasks_only(
+            &db,
+            &self.state.cache,
+            &auth.0,
+            None,
+            QuerySubtasksFilter {
+                enabled: enabled.0,
+                creator: Some(auth.0.id),
+                ty: subtask_type.0,
+                ..Default::default()
+            },
+            None,
+            None,
+            limit.0,
+            offset.0,
+        )
+        .await?;
+        ListSubtasks::ok(subtasks)
+    }
+
+    /// Return a random enabled subtask the user has not solved yet.
+    #[oai(path = "/subtasks/random", method = "get")]
+    pub async fn get_random_subtask(
+        &self,
+        /// Filter by subtask type.
+        #[oai(name = "type")]
+        subtask_type: Query<Option<ChallengesSubtaskType>>,
+        /// Whether to include retired subtasks the user has not started yet.
+        #[oai(default)]
+        include_locked: Query<bool>,
+        db: Data<&DbTxn>,
+        auth: VerifiedUserAuth,
+    ) -> GetRandomSubtask::Response<VerifiedUserAuth> {
+        match query_random_subtask(
+            &db,
+            &self.state.cache,
+            &auth.0,
+            subtask_type.0,
+            include_locked.0,
+        )
+        .await?
+        {
+            Some(subtask) => GetRandomSubtask::ok(subtask),
+            None => GetRandomSubtask::no_subtask_available(),
+        }
+    }
+
     /// Return user specific subtask statistics
     #[oai(path = "/subtasks/stats", method = "get")]
     pub async fn get_subtask_stats(
@@ -118,6 +230,356 @@ impl Subtasks {
         GetSubtaskStats::ok(stat_subtasks(&subtasks, &user_subtasks, filter))
     }
 
+    /// Return user specific statistics of the subtasks of a task grouped by
+    /// subtask type.
+    #[oai(path = "/tasks/:task_id/subtasks/stats_by_type", method = "get")]
+    async fn get_subtask_stats_by_type(
+        &self,
+        task_id: Path<Uuid>,
+        db: Data<&DbTxn>,
+        auth: VerifiedUserAuth,
+    ) -> GetSubtaskStatsByType::Response<VerifiedUserAuth> {
+        let user_subtasks = get_user_subtasks(&db, auth.0.id).await?;
+        let subtasks = stat_subtasks_prepare(
+            &db,
+            &auth.0,
+            Some(vec![task_id.0]),
+            &QuerySubtasksFilter::default(),
+        )
+        .await?;
+
+        let stats = |ty| {
+            stat_subtasks(
+                subtasks.iter().filter(|subtask| subtask.ty == ty),
+                &user_subtasks,
+                QuerySubtasksFilter::default(),
+            )
+        };
+        GetSubtaskStatsByType::ok(SubtaskStatsByType {
+            coding_challenge: stats(ChallengesSubtaskType::CodingChallenge),
+            matching: stats(ChallengesSubtaskType::Matching),
+            multiple_choice_question: stats(ChallengesSubtaskType::MultipleChoiceQuestion),
+            question: stats(ChallengesSubtaskType::Question),
+        })
+    }
+
+    /// Return the state of multiple subtasks for the authenticated user.
+    ///
+    /// Subtasks that do not exist or are not visible to the user are omitted.
+    #[oai(path = "/subtasks/states", method = "post")]
+    async fn get_subtask_states(
+        &self,
+        data: Json<GetSubtaskStatesRequest>,
+        db: Data<&DbTxn>,
+        auth: VerifiedUserAuth,
+    ) -> GetSubtaskStates::Response<VerifiedUserAuth> {
+        let subtasks = challenges_subtasks::Entity::find()
+            .filter(challenges_subtasks::Column::Id.is_in(data.0.subtask_ids.iter().copied()))
+            .all(&***db)
+            .await?;
+        let user_subtasks = challenges_user_subtasks::Entity::find()
+            .filter(challenges_user_subtasks::Column::UserId.eq(auth.0.id))
+            .filter(challenges_user_subtasks::Column::SubtaskId.is_in(data.0.subtask_ids))
+            .all(&***db)
+            .await?
+            .into_iter()
+            .map(|x| (x.subtask_id, x))
+            .collect::<HashMap<_, _>>();
+
+        GetSubtaskStates::ok(
+            subtasks
+                .into_iter()
+                .filter(|subtask| auth.0.admin || auth.0.id == subtask.creator || subtask.enabled)
+                .map(|subtask| {
+                    let user_subtask = user_subtasks.get(&subtask.id);
+                    let state = SubtaskState {
+                        unlocked: auth.0.admin
+                            || auth.0.id == subtask.creator
+                            || !subtask.retired
+                            || user_subtask.attempted(),
+                        solved: user_subtask.is_solved(),
+                        rated: user_subtask.is_rated(),
+                        attempts: user_subtask.attempts() as _,
+                    };
+                    (subtask.id, state)
+                })
+                .collect(),
+        )
+    }
+
+    /// Enable or disable multiple subtasks at once.
+    #[oai(path = "/subtasks/bulk_enable", method = "post")]
+    async fn bulk_enable_subtasks(
+        &self,
+        data: Json<BulkEnableSubtasksRequest>,
+        db: Data<&DbTxn>,
+        _auth: AdminAuth,
+    ) -> BulkEnableSubtasks::Response<AdminAuth> {
+        let requested = data.0.subtask_ids.len() as u64;
+        // only subtasks that are currently enabled get a new disabled timestamp
+        let disabled_timestamp = match data.0.enabled {
+            true => Expr::value(None::<NaiveDateTime>),
+            false => Expr::case(
+                challenges_subtasks::Column::Enabled.eq(true),
+                Utc::now().naive_utc(),
+            )
+            .finally(Expr::col(challenges_subtasks::Column::DisabledTimestamp))
+            .into(),
+        };
+        let updated = challenges_subtasks::Entity::update_many()
+            .col_expr(
+                challenges_subtasks::Column::Enabled,
+                Expr::value(data.0.enabled),
+            )
+            .col_expr(
+                challenges_subtasks::Column::DisabledTimestamp,
+                disabled_timestamp,
+            )
+            .filter(challenges_subtasks::Column::Id.is_in(data.0.subtask_ids))
+            .exec(&***db)
+            .await?
+            .rows_affected;
+
+        BulkEnableSubtasks::ok(BulkEnableSubtasksResult {
+            updated,
+            not_found: requested - updated,
+        })
+    }
+
+    /// Retire a subtask.
+    ///
+    /// Retired subtasks stay enabled and solvable by users who have already
+    /// started them, but are hidden from all other users. Users no longer
+    /// receive rewards for solving retired subtasks.
+    #[oai(path = "/tasks/:task_id/subtasks/:subtask_id/retire", method = "post")]
+    async fn retire_subtask(
+        &self,
+        task_id: Path<Uuid>,
+        subtask_id: Path<Uuid>,
+        db: Data<&DbTxn>,
+        auth: VerifiedUserAuth,
+    ) -> RetireSubtask::Response<VerifiedUserAuth> {
+        set_retired(&db, &auth, task_id.0, subtask_id.0, true).await
+    }
+
+    /// Revert the retirement of a subtask.
+    #[oai(
+        path = "/tasks/:task_id/subtasks/:subtask_id/unretire",
+        method = "post"
+    )]
+    async fn unretire_subtask(
+        &self,
+        task_id: Path<Uuid>,
+        subtask_id: Path<Uuid>,
+        db: Data<&DbTxn>,
+        auth: VerifiedUserAuth,
+    ) -> RetireSubtask::Response<VerifiedUserAuth> {
+        set_retired(&db, &auth, task_id.0, subtask_id.0, false).await
+    }
+
+    /// Move a subtask to another parent task.
+    ///
+    /// The user must be allowed to create subtasks in the target task and must
+    /// not be banned from creating subtasks. If users have already received xp
+    /// for solving the subtask, the target task must have the same skills as
+    /// the current one.
+    #[oai(path = "/tasks/:task_id/subtasks/:subtask_id/move", method = "post")]
+    async fn move_subtask(
+        &self,
+        task_id: Path<Uuid>,
+        subtask_id: Path<Uuid>,
+        data: Json<MoveSubtaskRequest>,
+        db: Data<&DbTxn>,
+        auth: VerifiedUserAuth,
+    ) -> MoveSubtask::Response<VerifiedUserAuth> {
+        match move_subtask(
+            &db,
+            &self.state.services,
+            &self.config,
+            &auth.0,
+            task_id.0,
+            subtask_id.0,
+            data.0.target_task_id,
+        )
+        .await?
+        {
+            Ok(subtask) => MoveSubtask::ok(subtask),
+            Err(MoveSubtaskError::SubtaskNotFound) => MoveSubtask::subtask_not_found(),
+            Err(MoveSubtaskError::TaskNotFound) => MoveSubtask::task_not_found(),
+            Err(MoveSubtaskError::Forbidden) => MoveSubtask::forbidden(),
+            Err(MoveSubtaskError::Banned(until)) => MoveSubtask::banned(until),
+            Err(MoveSubtaskError::SkillsDiffer) => MoveSubtask::skills_differ(),
+        }
+    }
+
+    /// Return the xp per skill and the morphcoins a user receives for solving a
+    /// subtask.
+    #[oai(path = "/tasks/:task_id/subtasks/:subtask_id/rewards", method = "get")]
+    async fn get_subtask_rewards(
+        &self,
+        task_id: Path<Uuid>,
+        subtask_id: Path<Uuid>,
+        db: Data<&DbTxn>,
+        auth: VerifiedUserAuth,
+    ) -> GetSubtaskRewards::Response<VerifiedUserAuth> {
+        let Some(subtask) = challenges_subtasks::Entity::find_by_id(subtask_id.0)
+            .filter(challenges_subtasks::Column::TaskId.eq(task_id.0))
+            .one(&***db)
+            .await?
+        else {
+            return GetSubtaskRewards::subtask_not_found();
+        };
+        if !auth.0.admin && auth.0.id != subtask.creator && !subtask.enabled {
+            return GetSubtaskRewards::subtask_not_found();
+        }
+
+        let skills = get_skill_rewards(&self.state.services, &db, &subtask).await?;
+        GetSubtaskRewards::ok(SubtaskRewards {
+            rewarded: !subtask.retired && auth.0.id != subtask.creator,
+            xp: subtask.xp as _,
+            coins: subtask.coins as _,
+            skills: skills
+                .into_iter()
+                .map(|(skill, xp)| SkillReward { skill, xp: xp as _ })
+                .collect(),
+        })
+    }
+
+    /// Reset the progress of a user on a subtask.
+    ///
+    /// This deletes the user's attempts and submissions. The xp and coins the
+    /// user has received for solving the subtask are kept unless
+    /// `revoke_rewards` is set. The progress on a coding challenge cannot be
+    /// reset while submissions of the user are waiting to be judged.
+    #[oai(
+        path = "/tasks/:task_id/subtasks/:subtask_id/users/:user_id/progress",
+        method = "delete"
+    )]
+    async fn reset_progress(
+        &self,
+        task_id: Path<Uuid>,
+        subtask_id: Path<Uuid>,
+        user_id: Path<Uuid>,
+        /// Whether to take back the xp and coins the user has received for
+        /// solving the subtask.
+        #[oai(default)]
+        revoke_rewards: Query<bool>,
+        db: Data<&DbTxn>,
+        _auth: AdminAuth,
+    ) -> ResetProgress::Response<AdminAuth> {
+        let Some(subtask) = challenges_subtasks::Entity::find_by_id(subtask_id.0)
+            .filter(challenges_subtasks::Column::TaskId.eq(task_id.0))
+            .one(&***db)
+            .await?
+        else {
+            return ResetProgress::subtask_not_found();
+        };
+
+        // the judge would insert results for submissions that no longer exist.
+        // Submissions that have just been enqueued are not committed yet, so the
+        // queue has to be checked as well while no new ones can be enqueued.
+        let _guard = self.submission_lock.write(user_id.0).await;
+        if subtask.ty == ChallengesSubtaskType::CodingChallenge {
+            let queued = self
+                .queue_positions
+                .read()
+                .await
+                .user_subtask_submissions(user_id.0, subtask.id);
+            let pending = challenges_coding_challenge_submissions::Entity::find()
+                .left_join(challenges_coding_challenge_result::Entity)
+                .filter(challenges_coding_challenge_submissions::Column::SubtaskId.eq(subtask.id))
+                .filter(challenges_coding_challenge_submissions::Column::Creator.eq(user_id.0))
+                .filter(challenges_coding_challenge_result::Column::SubmissionId.is_null())
+                .count(&***db)
+                .await?;
+            if queued > 0 || pending > 0 {
+                return ResetProgress::submissions_pending();
+            }
+        }
+
+        let user_subtask = challenges_user_subtasks::Entity::find_by_id((user_id.0, subtask.id))
+            .one(&***db)
+            .await?;
+        let revoke = revoke_rewards.0 && user_subtask.is_solved() && user_id.0 != subtask.creator;
+        let progress = match user_subtask {
+            Some(user_subtask) => user_subtask.delete(&***db).await?.rows_affected,
+            None => 0,
+        };
+
+        let (attempts, submissions) = match subtask.ty {
+            ChallengesSubtaskType::CodingChallenge => (
+                0,
+                challenges_coding_challenge_submissions::Entity::delete_many()
+                    .filter(
+                        challenges_coding_challenge_submissions::Column::SubtaskId.eq(subtask.id),
+                    )
+                    .filter(challenges_coding_challenge_submissions::Column::Creator.eq(user_id.0))
+                    .exec(&***db)
+                    .await?
+                    .rows_affected,
+            ),
+            ChallengesSubtaskType::Matching => (
+                challenges_matching_attempts::Entity::delete_many()
+                    .filter(challenges_matching_attempts::Column::MatchingId.eq(subtask.id))
+                    .filter(challenges_matching_attempts::Column::UserId.eq(user_id.0))
+                    .exec(&***db)
+                    .await?
+                    .rows_affected,
+                0,
+            ),
+            ChallengesSubtaskType::MultipleChoiceQuestion => (
+                challenges_multiple_choice_attempts::Entity::delete_many()
+                    .filter(challenges_multiple_choice_attempts::Column::QuestionId.eq(subtask.id))
+                    .filter(challenges_multiple_choice_attempts::Column::UserId.eq(user_id.0))
+                    .exec(&***db)
+                    .await?
+                    .rows_affected,
+                0,
+            ),
+            ChallengesSubtaskType::Question => (
+                challenges_question_attempts::Entity::delete_many()
+                    .filter(challenges_question_attempts::Column::QuestionId.eq(subtask.id))
+                    .filter(challenges_question_attempts::Column::UserId.eq(user_id.0))
+                    .exec(&***db)
+                    .await?
+                    .rows_affected,
+                0,
+            ),
+        };
+
+        if revoke {
+            match revoke_task_rewards(&self.state.services, &db, user_id.0, &subtask).await {
+                Err(SendTaskRewardsError::AddCoins(AddCoinsError::NotEnoughCoins)) => {
+                    return ResetProgress::not_enough_coins();
+                }
+                x => x?,
+            }
+        }
+
+        ResetProgress::ok(ResetProgressResult {
+            progress,
+            attempts,
+            submissions,
+            rewards_revoked: revoke,
+        })
+    }
+
+    /// Recompute the attempt counters of all users from the attempt history.
+    ///
+    /// Counters are only raised, never lowered, because attempts made before
+    /// the history has been recorded are missing from it. The counters of
+    /// multiple choice questions are not changed because their attempts are
+    /// not recorded.
+    #[oai(path = "/admin/recompute_attempts", method = "post")]
+    async fn recompute_attempts(
+        &self,
+        db: Data<&DbTxn>,
+        _auth: AdminAuth,
+    ) -> RecomputeAttempts::Response<AdminAuth> {
+        let (checked, corrected) = recompute_attempts(&db).await?;
+        RecomputeAttempts::ok(RecomputeAttemptsResult { checked, corrected })
+    }
+
     /// Delete a subtask.
     #[oai(path = "/tasks/:task_id/subtasks/:subtask_id", method = "delete")]
     async fn delete_question(
@@ -148,10 +610,71 @@ response!(ListSubtasks = {
     Ok(200) => Vec<Subtask>,
 });
 
+response!(GetRandomSubtask = {
+    Ok(200) => Subtask,
+    /// There is no unsolved subtask available.
+    NoSubtaskAvailable(404, error),
+});
+
 response!(GetSubtaskStats = {
     Ok(200) => SubtaskStats,
 });
 
+response!(GetSubtaskStatsByType = {
+    Ok(200) => SubtaskStatsByType,
+});
+
+response!(GetSubtaskStates = {
+    Ok(200) => HashMap<Uuid, SubtaskState>,
+});
+
+response!(BulkEnableSubtasks = {
+    Ok(200) => BulkEnableSubtasksResult,
+});
+
+response!(RetireSubtask = {
+    Ok(200),
+    /// Subtask does not exist.
+    SubtaskNotFound(404, error),
+    /// The user is not allowed to modify this subtask.
+    Forbidden(403, error),
+});
+
+response!(MoveSubtask = {
+    Ok(200) => Subtask,
+    /// Subtask does not exist.
+    SubtaskNotFound(404, error),
+    /// Target task does not exist.
+    TaskNotFound(404, error),
+    /// The user is not allowed to move this subtask to the target task.
+    Forbidden(403, error),
+    /// The user is currently banned from creating subtasks.
+    Banned(403, error) => Option<DateTime<Utc>>,
+    /// The subtask has already awarded xp and the target task has different
+    /// skills.
+    SkillsDiffer(409, error),
+});
+
+response!(GetSubtaskRewards = {
+    Ok(200) => SubtaskRewards,
+    /// Subtask does not exist.
+    SubtaskNotFound(404, error),
+});
+
+response!(ResetProgress = {
+    Ok(200) => ResetProgressResult,
+    /// Subtask does not exist.
+    SubtaskNotFound(404, error),
+    /// The user has already spent the coins that should be revoked.
+    NotEnoughCoins(412, error),
+    /// Some of the user's submissions have not been judged yet.
+    SubmissionsPending(409, error),
+});
+
+response!(RecomputeAttempts = {
+    Ok(200) => RecomputeAttemptsResult,
+});
+
 response!(DeleteSubtask = {
     Ok(200),
     /// Subtask does not exist.
@@ -177,3 +700,27 @@ async fn get_subtask(
         },
     )
 }
+
+async fn set_retired(
+    db: &DatabaseTransaction,
+    auth: &VerifiedUserAuth,
+    task_id: Uuid,
+    subtask_id: Uuid,
+    retired: bool,
+) -> RetireSubtask::Response<VerifiedUserAuth> {
+    let Some((subtask, _)) = get_subtask(db, task_id, subtask_id).await? else {
+        return RetireSubtask::subtask_not_found();
+    };
+    if !(auth.0.admin || auth.0.id == subtask.creator) {
+        return RetireSubtask::forbidden();
+    }
+
+    challenges_subtasks::ActiveModel {
+        id: Unchanged(subtask.id),
+        retired: Set(retired),
+        ..Default::default()
+    }
+    .update(db)
+    .await?;
+    RetireSubtask::ok()
+}
@@ -1,13 +1,15 @@
 use std::sync::Arc;
 
-use chrono::{DateTime, Duration, Utc};
+use chrono::{DateTime, Duration, NaiveDateTime, Utc};
 use entity::{
     challenges_ban, challenges_subtask_reports, challenges_subtasks, challenges_user_subtasks,
     sea_orm_active_enums::{ChallengesBanAction, ChallengesReportReason},
 };
 use lib::{
     auth::{AdminAuth, VerifiedUserAuth},
-    config::Config,
+    config::{Config, Quizzes},
+    services::events::{ReportResolution, ReportResolved},
+    SharedState,
 };
 use poem::web::Data;
 use poem_ext::{db::DbTxn, response, responses::ErrorResponse};
@@ -17,23 +19,28 @@ use poem_openapi::{
     OpenApi,
 };
 use schemas::challenges::subtasks::{
-    CreateReportRequest, Report, ResolveReportAction, ResolveReportRequest,
+    CreateReportRequest, Report, ReportList, ReportReasonCounts, ReportStats, ResolveReportAction,
+    ResolveReportRequest,
 };
 use sea_orm::{
-    ActiveModelTrait, ColumnTrait, DatabaseTransaction, EntityTrait, ModelTrait, PaginatorTrait,
-    QueryFilter, QueryOrder, QuerySelect, Set,
+    sea_query, ActiveModelTrait, ColumnTrait, DatabaseTransaction, EntityTrait, ModelTrait,
+    PaginatorTrait, QueryFilter, QueryOrder, QuerySelect, Set,
 };
+use tracing::warn;
 use uuid::Uuid;
 
 use super::get_subtask;
 use crate::{
+    after_commit::AfterCommit,
     endpoints::Tags,
     services::subtasks::{
-        get_active_ban, get_user_subtask, update_user_subtask, ActiveBan, UserSubtaskExt,
+        disabled_timestamp, get_active_ban, get_user_subtask, update_user_subtask, ActiveBan,
+        UserSubtaskExt,
     },
 };
 
 pub struct Api {
+    pub state: Arc<SharedState>,
     pub config: Arc<Config>,
 }
 
@@ -47,22 +54,79 @@ impl Api {
         limit: Query<Option<u64>>,
         /// Pagination offset
         offset: Query<Option<u64>>,
+        /// Only return reports with this reason
+        reason: Query<Option<ChallengesReportReason>>,
+        /// Only return reports created at or after this time
+        since: Query<Option<DateTime<Utc>>>,
         db: Data<&DbTxn>,
         _auth: AdminAuth,
     ) -> ListReports::Response<AdminAuth> {
-        let query = challenges_subtask_reports::Entity::find()
+        let mut query = challenges_subtask_reports::Entity::find();
+        if let Some(reason) = reason.0 {
+            query = query.filter(challenges_subtask_reports::Column::Reason.eq(reason));
+        }
+        if let Some(since) = since.0 {
+            query =
+                query.filter(challenges_subtask_reports::Column::Timestamp.gte(since.naive_utc()));
+        }
+        let total = query.clone().count(&***db).await?;
+        let reports = query
             .find_also_related(challenges_subtasks::Entity)
             .order_by_desc(challenges_subtask_reports::Column::Timestamp)
             .limit(limit.0)
-            .offset(offset.0);
-        ListReports::ok(
-            query
-                .all(&***db)
-                .await?
-                .into_iter()
-                .filter_map(|(report, subtask)| Some(Report::from(report, &subtask?)))
-                .collect(),
-        )
+            .offset(offset.0)
+            .all(&***db)
+            .await?
+            .into_iter()
+            .filter_map(|(report, subtask)| Some(Report::from(report, &subtask?)))
+            .collect();
+        ListReports::ok(ReportList { reports, total })
+    }
+
+    /// Return statistics about the open subtask reports.
+    #[oai(path = "/subtask_reports/stats", method = "get")]
+    pub async fn get_report_stats(
+        &self,
+        db: Data<&DbTxn>,
+        _auth: AdminAuth,
+    ) -> GetReportStats::Response<AdminAuth> {
+        let reasons = challenges_subtask_reports::Entity::find()
+            .select_only()
+            .column(challenges_subtask_reports::Column::Reason)
+            .column_as(challenges_subtask_reports::Column::Id.count(), "count")
+            .group_by(challenges_subtask_reports::Column::Reason)
+            .into_tuple::<(ChallengesReportReason, i64)>()
+            .all(&***db)
+            .await?;
+        let disabled_subtasks = challenges_subtasks::Entity::find()
+            .filter(challenges_subtasks::Column::Enabled.eq(false))
+            .filter(
+                challenges_subtasks::Column::Id.in_subquery(
+                    sea_query::Query::select()
+                        .column(challenges_subtask_reports::Column::SubtaskId)
+                        .from(challenges_subtask_reports::Entity)
+                        .to_owned(),
+                ),
+            )
+            .count(&***db)
+            .await?;
+
+        let mut stats = ReportStats {
+            open: 0,
+            reasons: ReportReasonCounts::default(),
+            disabled_subtasks,
+        };
+        for (reason, count) in reasons {
+            stats.open += count as u64;
+            *match reason {
+                ChallengesReportReason::Abuse => &mut stats.reasons.abuse,
+                ChallengesReportReason::Dislike => &mut stats.reasons.dislike,
+                ChallengesReportReason::Other => &mut stats.reasons.other,
+                ChallengesReportReason::UnrelatedSkill => &mut stats.reasons.unrelated_skill,
+                ChallengesReportReason::Wrong => &mut stats.reasons.wrong,
+            } += count as u64;
+        }
+        GetReportStats::ok(stats)
     }
 
     /// Report a subtask.
@@ -85,7 +149,7 @@ impl Api {
             return CreateReport::permission_denied();
         }
 
-        match get_active_ban(&db, &auth.0, ChallengesBanAction::Report).await? {
+        match get_active_ban(&db, &self.config, &auth.0, ChallengesBanAction::Report).await? {
             ActiveBan::NotBanned => {}
             ActiveBan::Temporary(end) => return CreateReport::banned(Some(end)),
             ActiveBan::Permanent => return CreateReport::banned(None),
@@ -111,6 +175,7 @@ impl Api {
         report_id: Path<Uuid>,
         data: Json<ResolveReportRequest>,
         db: Data<&DbTxn>,
+        after_commit: Data<&AfterCommit>,
         auth: AdminAuth,
     ) -> ResolveReport::Response<AdminAuth> {
         let Some((report, Some(subtask))) =
@@ -121,6 +186,17 @@ impl Api {
         else {
             return ResolveReport::report_not_found();
         };
+        let reporter = report.user_id;
+        let resolved = ReportResolved {
+            report_id: report.id,
+            task_id: subtask.task_id,
+            subtask_id: subtask.id,
+            resolution: match data.0.action {
+                ResolveReportAction::Revise => ReportResolution::Revise,
+                ResolveReportAction::BlockReporter => ReportResolution::BlockReporter,
+                ResolveReportAction::BlockCreator => ReportResolution::BlockCreator,
+            },
+        };
 
         let subtask_deleted = match data.0.action {
             ResolveReportAction::Revise => false,
@@ -132,13 +208,14 @@ impl Api {
                     &db,
                     reporter,
                     ChallengesBanAction::Report,
-                    &self.config.challenges.quizzes.ban_days,
+                    &self.config.challenges.quizzes,
                     auth.0.id,
                     format!("Bad report ({}): {}", report.id, report.comment),
                 )
                 .await?;
                 challenges_subtasks::ActiveModel {
                     enabled: Set(true),
+                    disabled_timestamp: Set(None),
                     ..subtask.into()
                 }
                 .update(&***db)
@@ -150,7 +227,7 @@ impl Api {
                     &db,
                     subtask.creator,
                     ChallengesBanAction::Create,
-                    &self.config.challenges.quizzes.ban_days,
+                    &self.config.challenges.quizzes,
                     auth.0.id,
                     format!("Bad subtask: {}", report.comment),
                 )
@@ -164,12 +241,27 @@ impl Api {
             report.delete(&***db).await?;
         }
 
+        // automatically generated reports don't have a reporter
+        if let Some(reporter) = reporter {
+            let events = self.state.services.events.clone();
+            after_commit.defer(async move {
+                let report_id = resolved.report_id;
+                if let Err(err) = events.notify_report_resolved(reporter, resolved).await {
+                    warn!("failed to notify reporter of resolved report {report_id}: {err}");
+                }
+            });
+        }
+
         ResolveReport::ok()
     }
 }
 
 response!(ListReports = {
-    Ok(200) => Vec<Report>,
+    Ok(200) => ReportList,
+});
+
+response!(GetReportStats = {
+    Ok(200) => ReportStats,
 });
 
 response!(CreateReport = {
@@ -229,6 +321,7 @@ pub(super) async fn create_report(
 
     let subtask = challenges_subtasks::ActiveModel {
         enabled: Set(false),
+        disabled_timestamp: Set(disabled_timestamp(&subtask, false)),
         ..subtask.into()
     }
     .update(db)
@@ -241,7 +334,7 @@ async fn ban_user(
     db: &DatabaseTransaction,
     user_id: Uuid,
     action: ChallengesBanAction,
-    ban_days: &[u32],
+    config: &Quizzes,
     creator: Uuid,
     reason: String,
 ) -> Result<challenges_ban::Model, ErrorResponse> {
@@ -253,15 +346,18 @@ async fn ban_user(
         .count(db)
         .await?;
 
-    let duration = ban_days
-        .get(bans as usize)
-        .map(|&days| Duration::days(days as _));
+    let duration = ban_duration(config, bans);
 
     Ok(challenges_ban::ActiveModel {
         id: Set(Uuid::new_v4()),
         user_id: Set(user_id),
         start: Set(now),
-        end: Set(duration.map(|duration| now + duration)),
+        end: Set(duration.map(|duration| {
+            now.checked_add_signed(duration).unwrap_or_else(|| {
+                warn!("ban duration of {duration} for user {user_id} is out of range, clamping");
+                NaiveDateTime::MAX
+            })
+        })),
         action: Set(action),
         creator: Set(creator),
         reason: Set(reason),
@@ -269,3 +365,50 @@ async fn ban_user(
     .insert(db)
     .await?)
 }
+
+/// Return the duration of a ban for a user who has already been banned `bans`
+/// times for the same action, or `None` if the ban is permanent.
+fn ban_duration(config: &Quizzes, bans: u64) -> Option<Duration> {
+    let days = match config.ban_days.get(bans as usize) {
+        Some(days) => days,
+        None if config.permanent_after_last => return None,
+        None => config.ban_days.last()?,
+    };
+    Some(Duration::try_days(*days as _).unwrap_or_else(Duration::max_value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(ban_days: Vec<u32>, permanent_after_last: bool) -> Quizzes {
+        Quizzes {
+            ban_days,
+            permanent_after_last,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn escalating_ban_durations() {
+        let days = Duration::try_days;
+
+        let conf = config(vec![3, 7, 30], true);
+        assert_eq!(ban_duration(&conf, 0), days(3));
+        assert_eq!(ban_duration(&conf, 1), days(7));
+        assert_eq!(ban_duration(&conf, 2), days(30));
+        assert_eq!(ban_duration(&conf, 3), None);
+        assert_eq!(ban_duration(&conf, 100), None);
+
+        let conf = config(vec![3, 7, 30], false);
+        assert_eq!(ban_duration(&conf, 0), days(3));
+        assert_eq!(ban_duration(&conf, 1), days(7));
+        assert_eq!(ban_duration(&conf, 3), days(30));
+        assert_eq!(ban_duration(&conf, 100), days(30));
+
+        assert!(ban_duration(&config(vec![u32::MAX], false), 0).is_some());
+
+        // without any durations all bans are permanent
+        assert_eq!(ban_duration(&config(vec![], false), 0), None);
+    }
+}
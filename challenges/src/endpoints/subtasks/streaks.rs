@@ -0,0 +1,43 @@
+use chrono::Utc;
+use entity::challenges_user_streaks;
+use lib::auth::VerifiedUserAuth;
+use poem::web::Data;
+use poem_ext::{db::DbTxn, response};
+use poem_openapi::OpenApi;
+use schemas::challenges::subtasks::UserStreak;
+use sea_orm::EntityTrait;
+
+use crate::{endpoints::Tags, services::streaks::current_streak};
+
+pub struct Api;
+
+#[OpenApi(tag = "Tags::Subtasks")]
+impl Api {
+    /// Return the solve streak of the authenticated user.
+    #[oai(path = "/streak", method = "get")]
+    async fn get_streak(
+        &self,
+        db: Data<&DbTxn>,
+        auth: VerifiedUserAuth,
+    ) -> GetStreak::Response<VerifiedUserAuth> {
+        let streak = challenges_user_streaks::Entity::find_by_id(auth.0.id)
+            .one(&***db)
+            .await?;
+        GetStreak::ok(match streak {
+            Some(streak) => UserStreak {
+                current_streak: current_streak(&streak, Utc::now().date_naive()) as _,
+                longest_streak: streak.longest_streak as _,
+                last_solved_date: streak.last_solved_date,
+            },
+            None => UserStreak {
+                current_streak: 0,
+                longest_streak: 0,
+                last_solved_date: None,
+            },
+        })
+    }
+}
+
+response!(GetStreak = {
+    Ok(200) => UserStreak,
+});
@@ -0,0 +1,103 @@
+use std::{sync::Arc, time::Duration};
+
+use entity::{
+    challenges_coding_challenge_submissions, challenges_subtasks, challenges_user_subtasks,
+    sea_orm_active_enums::ChallengesSubtaskType,
+};
+use fnct::key;
+use lib::{auth::VerifiedUserAuth, SharedState};
+use poem::web::Data;
+use poem_ext::{db::DbTxn, response};
+use poem_openapi::OpenApi;
+use schemas::challenges::subtasks::{SolvedSubtasks, UserStats};
+use sea_orm::{
+    sea_query::{Alias, Expr},
+    ColumnTrait, DatabaseTransaction, DbErr, EntityTrait, PaginatorTrait, QueryFilter, QuerySelect,
+};
+use uuid::Uuid;
+
+use crate::endpoints::Tags;
+
+/// Time for which the statistics of a user are cached.
+const USER_STATS_CACHE_TTL: Duration = Duration::from_secs(10);
+
+pub struct Api {
+    pub state: Arc<SharedState>,
+}
+
+#[OpenApi(tag = "Tags::Subtasks")]
+impl Api {
+    /// Return the rewards and the number of solved subtasks of the authenticated
+    /// user.
+    ///
+    /// Subtasks created by the user do not count towards the rewards.
+    #[oai(path = "/me/stats", method = "get")]
+    async fn get_user_stats(
+        &self,
+        db: Data<&DbTxn>,
+        auth: VerifiedUserAuth,
+    ) -> GetUserStats::Response<VerifiedUserAuth> {
+        let stats = self
+            .state
+            .cache
+            .cached_result(key!(auth.0.id), &[], Some(USER_STATS_CACHE_TTL), || {
+                get_user_stats(&db, auth.0.id)
+            })
+            .await??;
+        GetUserStats::ok(stats)
+    }
+}
+
+response!(GetUserStats = {
+    Ok(200) => UserStats,
+});
+
+async fn get_user_stats(db: &DatabaseTransaction, user_id: Uuid) -> Result<UserStats, DbErr> {
+    // creators don't receive any rewards for their own subtasks
+    let reward = |col: challenges_subtasks::Column| {
+        Expr::expr(
+            Expr::case(challenges_subtasks::Column::Creator.eq(user_id), 0)
+                .finally(Expr::col((challenges_subtasks::Entity, col))),
+        )
+        .sum()
+        .cast_as(Alias::new("int8"))
+    };
+    let solved = challenges_subtasks::Entity::find()
+        .select_only()
+        .column(challenges_subtasks::Column::Ty)
+        .column_as(challenges_subtasks::Column::Id.count(), "solved")
+        .column_as(reward(challenges_subtasks::Column::Xp), "xp")
+        .column_as(reward(challenges_subtasks::Column::Coins), "coins")
+        .inner_join(challenges_user_subtasks::Entity)
+        .filter(challenges_user_subtasks::Column::UserId.eq(user_id))
+        .filter(challenges_user_subtasks::Column::SolvedTimestamp.is_not_null())
+        .group_by(challenges_subtasks::Column::Ty)
+        .into_tuple::<(ChallengesSubtaskType, i64, i64, i64)>()
+        .all(db)
+        .await?;
+    let submissions = challenges_coding_challenge_submissions::Entity::find()
+        .filter(challenges_coding_challenge_submissions::Column::Creator.eq(user_id))
+        .count(db)
+        .await?;
+
+    let mut stats = UserStats {
+        xp: 0,
+        coins: 0,
+        solved: SolvedSubtasks::default(),
+        submissions,
+    };
+    for (ty, solved, xp, coins) in solved {
+        stats.xp += xp.max(0) as u64;
+        stats.coins += coins.max(0) as u64;
+        stats.solved.total += solved as u64;
+        *match ty {
+            ChallengesSubtaskType::CodingChallenge => &mut stats.solved.coding_challenges,
+            ChallengesSubtaskType::Matching => &mut stats.solved.matchings,
+            ChallengesSubtaskType::MultipleChoiceQuestion => {
+                &mut stats.solved.multiple_choice_questions
+            }
+            ChallengesSubtaskType::Question => &mut stats.solved.questions,
+        } += solved as u64;
+    }
+    Ok(stats)
+}
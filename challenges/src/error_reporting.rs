@@ -0,0 +1,104 @@
+use std::sync::Arc;
+
+use poem::{http::StatusCode, Endpoint, Middleware, Request, Response, Result};
+use sentry::{Hub, Level, SentryFutureExt};
+
+/// Report internal server errors to Sentry.
+///
+/// Other server errors (e.g. the health check reporting that a dependency is
+/// unavailable) are expected and not reported. Each request is handled with
+/// its own hub, so errors that have already been reported while handling it
+/// (e.g. by logging them) are not reported again.
+pub struct ReportServerErrors;
+
+impl<E: Endpoint> Middleware<E> for ReportServerErrors {
+    type Output = ReportServerErrorsEndpoint<E>;
+
+    fn transform(&self, ep: E) -> Self::Output {
+        ReportServerErrorsEndpoint { inner: ep }
+    }
+}
+
+pub struct ReportServerErrorsEndpoint<E> {
+    inner: E,
+}
+
+#[poem::async_trait]
+impl<E: Endpoint> Endpoint for ReportServerErrorsEndpoint<E> {
+    type Output = Response;
+
+    async fn call(&self, req: Request) -> Result<Self::Output> {
+        let hub = Arc::new(Hub::new_from_top(Hub::current()));
+        let request = format!("{} {}", req.method(), req.uri().path());
+        let resp = self
+            .inner
+            .get_response(req)
+            .bind_hub(Arc::clone(&hub))
+            .await;
+        if resp.status() == StatusCode::INTERNAL_SERVER_ERROR && hub.last_event_id().is_none() {
+            hub.capture_message(
+                &format!("{request} responded with {}", resp.status()),
+                Level::Error,
+            );
+        }
+        Ok(resp)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use poem::{handler, EndpointExt};
+    use sentry::{test::TestTransport, ClientOptions};
+
+    use super::*;
+
+    #[handler]
+    fn endpoint(req: &Request) -> StatusCode {
+        match req.uri().path() {
+            "/fail" => StatusCode::INTERNAL_SERVER_ERROR,
+            "/unavailable" => StatusCode::SERVICE_UNAVAILABLE,
+            "/reported" => {
+                sentry::capture_message("already reported", Level::Error);
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+            _ => StatusCode::OK,
+        }
+    }
+
+    #[tokio::test]
+    async fn server_errors_are_reported_once() {
+        let transport = TestTransport::new();
+        let options = ClientOptions {
+            dsn: Some("https://public@sentry.invalid/1".parse().unwrap()),
+            transport: Some(Arc::new(transport.clone())),
+            ..Default::default()
+        };
+        let hub = Arc::new(Hub::new(
+            Some(Arc::new(options.into())),
+            Arc::new(Default::default()),
+        ));
+        let app = endpoint.with(ReportServerErrors);
+        let messages = |path| {
+            let req = Request::builder().uri_str(path).finish();
+            let app = &app;
+            let hub = Arc::clone(&hub);
+            let transport = Arc::clone(&transport);
+            async move {
+                app.call(req).bind_hub(hub).await.unwrap();
+                transport
+                    .fetch_and_clear_events()
+                    .into_iter()
+                    .map(|event| event.message.unwrap())
+                    .collect::<Vec<_>>()
+            }
+        };
+
+        assert!(messages("/ok").await.is_empty());
+        assert!(messages("/unavailable").await.is_empty());
+        assert_eq!(
+            messages("/fail").await,
+            ["GET /fail responded with 500 Internal Server Error"]
+        );
+        assert_eq!(messages("/reported").await, ["already reported"]);
+    }
+}
@@ -0,0 +1,108 @@
+use poem::{
+    http::{header, Method, StatusCode},
+    Body, Endpoint, IntoResponse, Middleware, Request, Response, Result,
+};
+use sha2::{Digest, Sha256};
+
+/// Add an `ETag` header to successful reads of challenges and categories and
+/// answer with `304 Not Modified` if the client already has the current
+/// representation (`If-None-Match`).
+///
+/// The tag is derived from the response body, which includes the `updated_at`
+/// timestamp of the challenge or category, so it is independent of the replica
+/// that handled the request.
+pub struct ConditionalGet;
+
+impl<E: Endpoint> Middleware<E> for ConditionalGet {
+    type Output = ConditionalGetEndpoint<E>;
+
+    fn transform(&self, ep: E) -> Self::Output {
+        ConditionalGetEndpoint { inner: ep }
+    }
+}
+
+pub struct ConditionalGetEndpoint<E> {
+    inner: E,
+}
+
+#[poem::async_trait]
+impl<E: Endpoint> Endpoint for ConditionalGetEndpoint<E> {
+    type Output = Response;
+
+    async fn call(&self, req: Request) -> Result<Self::Output> {
+        if req.method() != Method::GET || !is_conditional_route(req.uri().path()) {
+            return self.inner.call(req).await.map(IntoResponse::into_response);
+        }
+
+        let if_none_match = req.header(header::IF_NONE_MATCH).map(str::to_owned);
+        let mut resp = self.inner.call(req).await?.into_response();
+        if resp.status() != StatusCode::OK {
+            return Ok(resp);
+        }
+
+        let body = resp.take_body().into_bytes().await?;
+        let etag = format!("\"{:x}\"", Sha256::digest(&body));
+        if if_none_match.is_some_and(|value| etag_matches(&value, &etag)) {
+            return Ok(Response::builder()
+                .status(StatusCode::NOT_MODIFIED)
+                .header(header::ETAG, etag)
+                .finish());
+        }
+        resp.set_body(Body::from(body));
+        resp.headers_mut().insert(
+            header::ETAG,
+            etag.parse().expect("etag is a valid header value"),
+        );
+        Ok(resp)
+    }
+}
+
+/// Return whether `path` is one of the challenge or category read endpoints:
+/// `/categories`, `/categories/:category_id`,
+/// `/categories/:category_id/challenges` or
+/// `/categories/:category_id/challenges/:challenge_id`.
+fn is_conditional_route(path: &str) -> bool {
+    let segments = path.trim_matches('/').split('/').collect::<Vec<_>>();
+    matches!(
+        segments.as_slice(),
+        ["categories"]
+            | ["categories", _]
+            | ["categories", _, "challenges"]
+            | ["categories", _, "challenges", _]
+    )
+}
+
+/// Return whether the value of an `If-None-Match` header matches `etag`.
+///
+/// Weak comparison is used, so `W/"x"` matches `"x"`.
+fn etag_matches(if_none_match: &str, etag: &str) -> bool {
+    if_none_match
+        .split(',')
+        .map(str::trim)
+        .any(|tag| tag == "*" || tag.trim_start_matches("W/") == etag)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn conditional_routes() {
+        assert!(is_conditional_route("/categories"));
+        assert!(is_conditional_route("/categories/x"));
+        assert!(is_conditional_route("/categories/x/challenges/"));
+        assert!(is_conditional_route("/categories/x/challenges/y"));
+        assert!(!is_conditional_route("/categories/x/stats"));
+        assert!(!is_conditional_route("/categories/x/challenges/y/export"));
+        assert!(!is_conditional_route("/subtasks"));
+    }
+
+    #[test]
+    fn if_none_match() {
+        assert!(etag_matches("\"a\"", "\"a\""));
+        assert!(etag_matches("\"b\", W/\"a\"", "\"a\""));
+        assert!(etag_matches("*", "\"a\""));
+        assert!(!etag_matches("\"b\"", "\"a\""));
+        assert!(!etag_matches("a", "\"a\""));
+    }
+}
@@ -0,0 +1,75 @@
+use std::{collections::BTreeMap, fmt::Display, future::Future, sync::Arc, time::Duration};
+
+use lib::redis::RedisConnection;
+use poem::{handler, http::StatusCode, web::Data, web::Json, IntoResponse, Response};
+use sandkasten_client::SandkastenClient;
+use sea_orm::DatabaseConnection;
+use tracing::warn;
+
+/// Time after which a dependency is considered unavailable.
+const CHECK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The dependencies that must be reachable for the service to be ready.
+pub struct HealthChecks {
+    pub db: DatabaseConnection,
+    pub auth_redis: RedisConnection,
+    pub challenges_redis: RedisConnection,
+    pub sandkasten: SandkastenClient,
+}
+
+/// Liveness probe: succeeds as long as the process is able to handle requests.
+#[handler]
+pub fn live() -> StatusCode {
+    StatusCode::OK
+}
+
+/// Readiness probe: checks all dependencies and returns `503` if any of them
+/// is unavailable.
+///
+/// The response only contains the status of each dependency, as the endpoint
+/// is not authenticated. Errors are logged.
+#[handler]
+pub async fn ready(checks: Data<&Arc<HealthChecks>>) -> Response {
+    let (db, auth_redis, challenges_redis, sandkasten) = tokio::join!(
+        check(checks.db.ping()),
+        check(ping_redis(checks.auth_redis.clone())),
+        check(ping_redis(checks.challenges_redis.clone())),
+        check(checks.sandkasten.version()),
+    );
+    let status = BTreeMap::from([
+        ("database", db),
+        ("auth_redis", auth_redis),
+        ("challenges_redis", challenges_redis),
+        ("sandkasten", sandkasten),
+    ]);
+
+    let mut code = StatusCode::OK;
+    for (dependency, result) in &status {
+        if let Err(err) = result {
+            warn!("readiness check for {dependency} failed: {err}");
+            code = StatusCode::SERVICE_UNAVAILABLE;
+        }
+    }
+    let status = status
+        .into_iter()
+        .map(|(dependency, result)| {
+            (
+                dependency,
+                if result.is_ok() { "ok" } else { "unavailable" },
+            )
+        })
+        .collect::<BTreeMap<_, _>>();
+    Json(status).with_status(code).into_response()
+}
+
+async fn check<T, E: Display>(future: impl Future<Output = Result<T, E>>) -> Result<(), String> {
+    match tokio::time::timeout(CHECK_TIMEOUT, future).await {
+        Ok(Ok(_)) => Ok(()),
+        Ok(Err(err)) => Err(err.to_string()),
+        Err(_) => Err("timed out".into()),
+    }
+}
+
+async fn ping_redis(mut conn: RedisConnection) -> redis::RedisResult<String> {
+    redis::cmd("PING").query_async(&mut conn).await
+}
@@ -4,35 +4,66 @@
 use std::{sync::Arc, time::Duration};
 
 use fnct::{backend::AsyncRedisBackend, format::PostcardFormatter};
-use lib::{config, jwt::JwtSecret, redis::RedisConnection, services::Services, Cache, SharedState};
-use poem::{listener::TcpListener, middleware::Tracing, EndpointExt, Route, Server};
+use lib::{
+    config::{self, Otlp},
+    jwt::JwtSecret,
+    metrics::{metrics_endpoint, Metrics},
+    redis::RedisConnection,
+    services::Services,
+    Cache, SharedState,
+};
+use opentelemetry::trace::TracerProvider;
+use opentelemetry_otlp::{SpanExporter, WithExportConfig};
+use opentelemetry_sdk::{trace::SdkTracerProvider, Resource};
+use poem::{get, listener::TcpListener, middleware::Tracing, EndpointExt, Route, Server};
 use poem_ext::{db::DbTransactionMiddleware, panic_handler::PanicHandler};
 use poem_openapi::OpenApiService;
 use sandkasten_client::SandkastenClient;
 use sea_orm::{ConnectOptions, Database};
 use sentry::integrations::tracing::EventFilter;
 use tracing::{info, warn, Level};
-use tracing_subscriber::{prelude::*, EnvFilter};
+use tracing_subscriber::{filter::LevelFilter, prelude::*, EnvFilter};
 
-use crate::endpoints::setup_api;
+use crate::{
+    after_commit::AfterCommitMiddleware,
+    endpoints::{coding_challenges::submissions::JudgeTasks, setup_api},
+    error_reporting::ReportServerErrors,
+    etag::ConditionalGet,
+    health::HealthChecks,
+    rate_limit::RateLimiter,
+};
 
+mod after_commit;
 mod endpoints;
+mod error_reporting;
+mod etag;
+mod health;
+mod rate_limit;
 mod services;
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     let config = Arc::new(config::load()?);
 
-    let _sentry_guard = config.challenges.sentry.as_ref().map(|sentry_config| {
-        sentry::init((
-            sentry_config.dsn.as_str(),
-            sentry::ClientOptions {
-                release: Some(env!("CARGO_PKG_VERSION").into()),
-                attach_stacktrace: true,
-                ..Default::default()
-            },
-        ))
-    });
+    // keep the guard alive until the server has shut down so that all pending
+    // events are flushed
+    let _sentry_guard = config
+        .challenges
+        .sentry
+        .as_ref()
+        .or(config.sentry.as_ref())
+        .map(|sentry_config| {
+            sentry::init((
+                sentry_config.dsn.as_str(),
+                sentry::ClientOptions {
+                    release: Some(env!("CARGO_PKG_VERSION").into()),
+                    attach_stacktrace: true,
+                    ..Default::default()
+                },
+            ))
+        });
+
+    let otlp_tracer_provider = config.otlp.as_ref().map(otlp_tracer_provider).transpose()?;
 
     tracing_subscriber::registry()
         .with(tracing_subscriber::fmt::layer().with_filter(EnvFilter::from_default_env()))
@@ -44,6 +75,11 @@ async fn main() -> anyhow::Result<()> {
                 &Level::TRACE => EventFilter::Ignore,
             }),
         )
+        .with(otlp_tracer_provider.as_ref().map(|provider| {
+            tracing_opentelemetry::layer()
+                .with_tracer(provider.tracer("challenges"))
+                .with_filter(LevelFilter::INFO)
+        }))
         .init();
 
     info!("Connecting to database");
@@ -52,11 +88,9 @@ async fn main() -> anyhow::Result<()> {
     let db = Database::connect(db_options).await?;
 
     info!("Connecting to redis");
+    let challenges_redis = RedisConnection::new(config.redis.challenges.as_str()).await?;
     let cache = Cache::new(
-        AsyncRedisBackend::new(
-            RedisConnection::new(config.redis.challenges.as_str()).await?,
-            "challenges".into(),
-        ),
+        AsyncRedisBackend::new(challenges_redis.clone(), "challenges".into()),
         PostcardFormatter,
         Duration::from_secs(config.cache_ttl),
     );
@@ -77,6 +111,7 @@ async fn main() -> anyhow::Result<()> {
 
     let jwt_secret = JwtSecret::try_from(config.jwt_secret.as_str())?;
     let services = Services::from_config(
+        "challenges",
         jwt_secret.clone(),
         Duration::from_secs(config.internal_jwt_ttl),
         &config.services,
@@ -84,14 +119,29 @@ async fn main() -> anyhow::Result<()> {
     );
     let shared_state = Arc::new(SharedState {
         jwt_secret,
-        auth_redis,
+        auth_redis: auth_redis.clone(),
         services,
         cache,
         db: db.clone(),
+        metrics: Metrics::new()?,
+    });
+
+    let judge_tasks = JudgeTasks::default();
+    let health_checks = Arc::new(HealthChecks {
+        db: db.clone(),
+        auth_redis,
+        challenges_redis: challenges_redis.clone(),
+        sandkasten: sandkasten.clone(),
     });
 
     let api_service = OpenApiService::new(
-        setup_api(shared_state.clone(), Arc::clone(&config), sandkasten).await?,
+        setup_api(
+            shared_state.clone(),
+            Arc::clone(&config),
+            sandkasten,
+            judge_tasks.clone(),
+        )
+        .await?,
         "Bootstrap Academy Backend: Challenges Microservice",
         env!("CARGO_PKG_VERSION"),
     )
@@ -101,11 +151,32 @@ async fn main() -> anyhow::Result<()> {
         .nest("/openapi.json", api_service.spec_endpoint())
         .nest("/docs", api_service.swagger_ui())
         .nest("/redoc", api_service.redoc())
+        .at("/metrics", get(metrics_endpoint))
         .nest("/", api_service)
-        .with(Tracing)
-        .with(PanicHandler::middleware())
         .with(DbTransactionMiddleware::new(db))
-        .data(shared_state);
+        .with(AfterCommitMiddleware)
+        .with(ConditionalGet);
+    // rejected requests should not start a db transaction
+    let app = match config.challenges.rate_limit.clone() {
+        Some(rate_limit) => app
+            .with(RateLimiter {
+                redis: challenges_redis,
+                jwt_secret: shared_state.jwt_secret.clone(),
+                config: rate_limit,
+            })
+            .boxed(),
+        None => app.map_to_response().boxed(),
+    }
+    .data(shared_state);
+    // the health endpoints must not depend on the db transaction middleware,
+    // which fails every request while the database is unavailable
+    let app = Route::new()
+        .at("/health/live", get(health::live))
+        .at("/health/ready", get(health::ready).data(health_checks))
+        .nest("/", app)
+        .with(ReportServerErrors)
+        .with(Tracing)
+        .with(PanicHandler::middleware());
 
     info!(
         "Listening on {}:{}",
@@ -115,8 +186,70 @@ async fn main() -> anyhow::Result<()> {
         config.challenges.host.as_str(),
         config.challenges.port,
     )))
-    .run(app)
+    .run_with_graceful_shutdown(
+        app,
+        async {
+            shutdown_signal().await;
+            judge_tasks.close();
+        },
+        None,
+    )
     .await?;
 
+    info!("Waiting for judge tasks to finish");
+    let (drained, abandoned) = judge_tasks
+        .drain(Duration::from_secs(
+            config.challenges.coding_challenges.shutdown_timeout,
+        ))
+        .await;
+    info!("Drained {drained} judge task(s), abandoned {abandoned}");
+
+    info!("Shutting down");
+    if let Some(provider) = otlp_tracer_provider {
+        if let Err(err) = provider.shutdown() {
+            warn!("Failed to flush pending traces: {err}");
+        }
+    }
     Ok(())
 }
+
+/// Export spans to an OpenTelemetry collector.
+fn otlp_tracer_provider(config: &Otlp) -> anyhow::Result<SdkTracerProvider> {
+    let exporter = SpanExporter::builder()
+        .with_http()
+        .with_endpoint(config.endpoint.as_str())
+        .build()?;
+    Ok(SdkTracerProvider::builder()
+        .with_batch_exporter(exporter)
+        .with_resource(Resource::builder().with_service_name("challenges").build())
+        .build())
+}
+
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        if let Err(err) = tokio::signal::ctrl_c().await {
+            warn!("Failed to listen for ctrl-c: {err}");
+            std::future::pending::<()>().await;
+        }
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            Err(err) => {
+                warn!("Failed to listen for SIGTERM: {err}");
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {}
+        _ = terminate => {}
+    }
+}
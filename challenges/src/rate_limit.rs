@@ -0,0 +1,237 @@
+use std::net::IpAddr;
+
+use lib::{
+    config::{RateLimit, RateLimitBucket},
+    jwt::{verify_jwt, JwtSecret, UserAccessToken},
+    redis::RedisConnection,
+};
+use poem::{
+    http::{header, Method, StatusCode},
+    web::Json,
+    Endpoint, IntoResponse, Middleware, Request, Response, Result,
+};
+use serde_json::json;
+use tracing::warn;
+
+/// Atomically take a token from the bucket `KEYS[1]`.
+///
+/// `ARGV[1]` is the capacity of the bucket and `ARGV[2]` the number of
+/// milliseconds it takes to refill it completely. Returns the number of
+/// milliseconds to wait until the next token is available, or `0` if a token
+/// has been taken. The time of the redis server is used so that all replicas
+/// agree on the current time.
+const TAKE_TOKEN: &str = r"
+local capacity = tonumber(ARGV[1])
+local period = tonumber(ARGV[2])
+local time = redis.call('TIME')
+local now = tonumber(time[1]) * 1000 + math.floor(tonumber(time[2]) / 1000)
+local bucket = redis.call('HMGET', KEYS[1], 'tokens', 'ts')
+local tokens = tonumber(bucket[1]) or capacity
+local ts = tonumber(bucket[2]) or now
+tokens = math.min(capacity, tokens + math.max(0, now - ts) * capacity / period)
+local wait = 0
+if tokens >= 1 then
+    tokens = tokens - 1
+else
+    wait = math.ceil((1 - tokens) * period / capacity)
+end
+redis.call('HSET', KEYS[1], 'tokens', tostring(tokens), 'ts', now)
+redis.call('PEXPIRE', KEYS[1], period)
+return wait
+";
+
+/// Limit the number of requests per user (or IP address for unauthenticated
+/// requests) using token buckets that are shared between all replicas.
+pub struct RateLimiter {
+    pub redis: RedisConnection,
+    pub jwt_secret: JwtSecret,
+    pub config: RateLimit,
+}
+
+impl<E: Endpoint> Middleware<E> for RateLimiter {
+    type Output = RateLimiterEndpoint<E>;
+
+    fn transform(&self, ep: E) -> Self::Output {
+        RateLimiterEndpoint {
+            inner: ep,
+            redis: self.redis.clone(),
+            jwt_secret: self.jwt_secret.clone(),
+            config: self.config.clone(),
+        }
+    }
+}
+
+pub struct RateLimiterEndpoint<E> {
+    inner: E,
+    redis: RedisConnection,
+    jwt_secret: JwtSecret,
+    config: RateLimit,
+}
+
+#[poem::async_trait]
+impl<E: Endpoint> Endpoint for RateLimiterEndpoint<E> {
+    type Output = Response;
+
+    async fn call(&self, req: Request) -> Result<Self::Output> {
+        let (route, bucket) = route_bucket(&self.config, req.method(), req.uri().path());
+        let key = format!("challenges:rate_limit:{route}:{}", self.client_key(&req));
+        match self.take_token(&key, bucket).await {
+            Ok(0) => {}
+            Ok(wait) => {
+                let retry_after = wait.div_ceil(1000);
+                return Ok(
+                    Json(json!({"error": "too_many_requests", "details": retry_after}))
+                        .with_status(StatusCode::TOO_MANY_REQUESTS)
+                        .with_header(header::RETRY_AFTER, retry_after)
+                        .into_response(),
+                );
+            }
+            // don't block all requests just because redis is unavailable
+            Err(err) => warn!("rate limiter failed to access redis: {err}"),
+        }
+        self.inner.call(req).await.map(IntoResponse::into_response)
+    }
+}
+
+impl<E> RateLimiterEndpoint<E> {
+    /// Identify the client by its user id if it has sent a valid access token
+    /// and by its IP address otherwise.
+    fn client_key(&self, req: &Request) -> String {
+        let user = req
+            .header(header::AUTHORIZATION)
+            .and_then(|auth| auth.strip_prefix("Bearer "))
+            .and_then(|token| verify_jwt::<UserAccessToken>(token, &self.jwt_secret).ok());
+        if let Some(user) = user {
+            return format!("user:{}", user.uid);
+        }
+        let forwarded_for = req
+            .headers()
+            .get_all("x-forwarded-for")
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .collect::<Vec<_>>()
+            .join(",");
+        let ip = client_ip(
+            req.remote_addr().as_socket_addr().map(|addr| addr.ip()),
+            &forwarded_for,
+            &self.config.trusted_proxies,
+        );
+        match ip {
+            Some(ip) => format!("ip:{ip}"),
+            None => "ip:unknown".into(),
+        }
+    }
+
+    async fn take_token(&self, key: &str, bucket: RateLimitBucket) -> redis::RedisResult<u64> {
+        redis::cmd("EVAL")
+            .arg(TAKE_TOKEN)
+            .arg(1)
+            .arg(key)
+            .arg(bucket.capacity.get())
+            .arg(bucket.period.get() * 1000)
+            .query_async(&mut self.redis.clone())
+            .await
+    }
+}
+
+/// Return the IP address of the client that has sent a request from `remote`.
+///
+/// If `remote` is a trusted proxy, the `X-Forwarded-For` header is searched
+/// from right to left for the first address that is not a trusted proxy.
+/// Addresses to the left of it may have been forged by the client.
+fn client_ip(
+    remote: Option<IpAddr>,
+    forwarded_for: &str,
+    trusted_proxies: &[IpAddr],
+) -> Option<IpAddr> {
+    let mut ip = remote?;
+    for hop in forwarded_for.rsplit(',') {
+        if !trusted_proxies.contains(&ip) {
+            break;
+        }
+        match hop.trim().parse() {
+            Ok(hop) => ip = hop,
+            Err(_) => break,
+        }
+    }
+    Some(ip)
+}
+
+/// Return the name and the token bucket of the route class of a request with
+/// the given `method` and `path`.
+fn route_bucket(config: &RateLimit, method: &Method, path: &str) -> (String, RateLimitBucket) {
+    let segment = path.trim_end_matches('/').rsplit('/').next().unwrap_or("");
+    let route = format!("{method} {segment}");
+    match config.routes.get(&route) {
+        Some(&bucket) => (route.replace(' ', ":"), bucket),
+        None => ("default".into(), config.default),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::HashMap,
+        num::{NonZeroU32, NonZeroU64},
+    };
+
+    use super::*;
+
+    #[test]
+    fn route_buckets() {
+        let bucket = |capacity| RateLimitBucket {
+            capacity: NonZeroU32::new(capacity).unwrap(),
+            period: NonZeroU64::new(60).unwrap(),
+        };
+        let config = RateLimit {
+            default: bucket(120),
+            routes: HashMap::from([
+                ("POST attempts".into(), bucket(10)),
+                ("POST submissions".into(), bucket(5)),
+            ]),
+            trusted_proxies: vec![],
+        };
+
+        let capacity = |method, path| {
+            let (route, bucket) = route_bucket(&config, &method, path);
+            (route, bucket.capacity.get())
+        };
+        assert_eq!(
+            capacity(Method::POST, "/tasks/x/multiple_choice/y/attempts"),
+            ("POST:attempts".into(), 10)
+        );
+        assert_eq!(
+            capacity(Method::POST, "/tasks/x/coding_challenges/y/submissions/"),
+            ("POST:submissions".into(), 5)
+        );
+        assert_eq!(
+            capacity(Method::GET, "/tasks/x/coding_challenges/y/submissions"),
+            ("default".into(), 120)
+        );
+        assert_eq!(
+            capacity(Method::GET, "/tasks/x/coding_challenges/y/submissions/z"),
+            ("default".into(), 120)
+        );
+        assert_eq!(capacity(Method::GET, "/"), ("default".into(), 120));
+    }
+
+    #[test]
+    fn client_ips() {
+        let ip = |x: &str| x.parse::<IpAddr>().unwrap();
+        let proxies = [ip("10.0.0.1"), ip("10.0.0.2")];
+        let client_ip =
+            |remote, forwarded_for| client_ip(Some(ip(remote)), forwarded_for, &proxies).unwrap();
+
+        // the header is ignored unless the request comes from a trusted proxy
+        assert_eq!(client_ip("1.2.3.4", "5.6.7.8"), ip("1.2.3.4"));
+        assert_eq!(client_ip("10.0.0.1", ""), ip("10.0.0.1"));
+        assert_eq!(client_ip("10.0.0.1", "1.2.3.4"), ip("1.2.3.4"));
+        // addresses added by the client itself are not trusted
+        assert_eq!(
+            client_ip("10.0.0.1", "5.6.7.8, 1.2.3.4, 10.0.0.2"),
+            ip("1.2.3.4")
+        );
+        assert_eq!(client_ip("10.0.0.1", "garbage"), ip("10.0.0.1"));
+        assert_eq!(client_ip("10.0.0.1", "10.0.0.2"), ip("10.0.0.2"));
+    }
+}
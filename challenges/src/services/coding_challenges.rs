@@ -0,0 +1,56 @@
+use std::collections::HashMap;
+
+use entity::challenges_coding_challenge_solutions;
+use schemas::challenges::coding_challenges::SubmissionContent;
+use sea_orm::{ColumnTrait, DatabaseTransaction, DbErr, EntityTrait, QueryFilter, QueryOrder, Set};
+use uuid::Uuid;
+
+/// Return the additional reference solutions of the given coding challenges.
+pub async fn get_additional_solutions(
+    db: &DatabaseTransaction,
+    subtask_ids: impl IntoIterator<Item = Uuid>,
+) -> Result<HashMap<Uuid, Vec<SubmissionContent>>, DbErr> {
+    let mut out = HashMap::<_, Vec<_>>::new();
+    for solution in challenges_coding_challenge_solutions::Entity::find()
+        .filter(challenges_coding_challenge_solutions::Column::SubtaskId.is_in(subtask_ids))
+        .order_by_asc(challenges_coding_challenge_solutions::Column::Position)
+        .all(db)
+        .await?
+    {
+        out.entry(solution.subtask_id)
+            .or_default()
+            .push(solution.into());
+    }
+    Ok(out)
+}
+
+/// Replace the additional reference solutions of a coding challenge.
+pub async fn set_additional_solutions(
+    db: &DatabaseTransaction,
+    subtask_id: Uuid,
+    solutions: Vec<SubmissionContent>,
+) -> Result<(), DbErr> {
+    challenges_coding_challenge_solutions::Entity::delete_many()
+        .filter(challenges_coding_challenge_solutions::Column::SubtaskId.eq(subtask_id))
+        .exec(db)
+        .await?;
+    if solutions.is_empty() {
+        return Ok(());
+    }
+    challenges_coding_challenge_solutions::Entity::insert_many(
+        solutions
+            .into_iter()
+            .enumerate()
+            .map(
+                |(position, solution)| challenges_coding_challenge_solutions::ActiveModel {
+                    subtask_id: Set(subtask_id),
+                    position: Set(position as _),
+                    environment: Set(solution.environment),
+                    code: Set(solution.code),
+                },
+            ),
+    )
+    .exec(db)
+    .await?;
+    Ok(())
+}
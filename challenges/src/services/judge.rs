@@ -1,28 +1,61 @@
-use entity::sea_orm_active_enums::ChallengesVerdict;
+use std::{collections::HashMap, time::Duration};
+
+use entity::sea_orm_active_enums::{ChallengesVerdict, ChallengesVerdictReason};
 use fnct::{format::JsonFormatter, key};
-use lib::{Cache, CacheError};
+use lib::{config::SandkastenRetry, Cache, CacheError};
 use sandkasten_client::{
     schemas::{
+        environments::Environment,
         programs::{
             BuildRequest, BuildRunError, BuildRunRequest, BuildRunResult, File, LimitsOpt,
             MainFile, RunRequest, RunResult,
         },
-        ErrorResponse,
+        ErrorResponse, GeneralError,
     },
     Error as SandkastenError, SandkastenClient,
 };
-use schemas::challenges::coding_challenges::{CheckResult, Example, ExecutorConfig};
+use schemas::challenges::coding_challenges::{
+    CheckResult, EvaluatorStage, EvaluatorStageResult, Example, ExecutorConfig,
+};
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use serde_json::Value;
+use sha2::{Digest, Sha256};
 use thiserror::Error;
+use tokio::{sync::Semaphore, time::sleep};
+use tracing::{info_span, instrument, warn, Instrument};
 
 pub const EVALUATOR_TEMPLATE: &str = include_str!("../../assets/evaluator/template.py");
 pub const EVALUATOR_LIBRARY: &str = include_str!("../../assets/evaluator/lib.py");
+// bump these whenever the corresponding file changes
+pub const EVALUATOR_TEMPLATE_VERSION: u32 = 2;
+pub const EVALUATOR_LIBRARY_VERSION: u32 = 2;
 
 pub struct Judge<'a> {
     pub sandkasten: &'a SandkastenClient,
+    /// Limits the number of concurrent requests to sandkasten.
+    pub sandkasten_lock: &'a Semaphore,
     pub evaluator: &'a str,
+    /// The environment to run the evaluator in.
+    pub evaluator_environment: &'a str,
     pub cache: &'a Cache<JsonFormatter>,
+    /// Time limit (in seconds) for a single run of the evaluator.
+    pub evaluator_timeout: u64,
+    pub retry: SandkastenRetry,
+    /// Time to live of cached evaluator results (`None` to use the default).
+    pub cache_ttl: Option<Duration>,
+}
+
+/// Return the cache tag of all cached results that depend on `evaluator`.
+pub fn cache_tag(evaluator: &str) -> String {
+    format!("evaluator_{:x}", Sha256::digest(evaluator))
+}
+
+/// Invalidate all cached results of `evaluator`.
+pub async fn invalidate_cache(
+    cache: &Cache<JsonFormatter>,
+    evaluator: &str,
+) -> Result<(), CacheError<JsonFormatter>> {
+    cache.pop_tag(&cache_tag(evaluator)).await
 }
 
 impl Judge<'_> {
@@ -38,14 +71,15 @@ impl Judge<'_> {
             .cached_result(
                 key!(
                     self.evaluator,
+                    self.evaluator_environment,
                     seed,
                     solution_environment,
                     solution_code,
                     time_limit,
                     memory_limit
                 ),
-                &[],
-                None,
+                &[&cache_tag(self.evaluator)],
+                self.cache_ttl,
                 || async {
                     let input = self.generate(seed).await?;
                     let result = self
@@ -78,19 +112,29 @@ impl Judge<'_> {
 
     pub async fn examples(&self) -> Result<Vec<String>, Error> {
         self.cache
-            .cached_result(key!(self.evaluator), &[], None, || async {
-                self.run_evaluator(vec!["examples".into()], None::<()>)
-                    .await
-            })
+            .cached_result(
+                key!(self.evaluator, self.evaluator_environment),
+                &[&cache_tag(self.evaluator)],
+                self.cache_ttl,
+                || async {
+                    self.run_evaluator(vec!["examples".into()], None::<()>)
+                        .await
+                },
+            )
             .await?
     }
 
     pub async fn generate(&self, seed: &str) -> Result<Input, Error> {
         self.cache
-            .cached_result(key!(self.evaluator, seed), &[], None, || async {
-                self.run_evaluator(vec!["generate".into(), seed.into()], None::<()>)
-                    .await
-            })
+            .cached_result(
+                key!(self.evaluator, self.evaluator_environment, seed),
+                &[&cache_tag(self.evaluator)],
+                self.cache_ttl,
+                || async {
+                    self.run_evaluator(vec!["generate".into(), seed.into()], None::<()>)
+                        .await
+                },
+            )
             .await?
     }
 
@@ -104,39 +148,174 @@ impl Judge<'_> {
             .await
     }
 
+    /// Run the `examples`, `generate` and `prepare` stages of the evaluator
+    /// and report which of them failed to produce valid output.
+    ///
+    /// Stages that depend on the output of a failed stage are skipped.
+    pub async fn validate(&self) -> Result<Vec<EvaluatorStageResult>, Error> {
+        let mut stages = Vec::new();
+
+        let Some(examples) = self
+            .validate_stage::<_, Vec<String>>(
+                &mut stages,
+                EvaluatorStage::Examples,
+                vec!["examples".into()],
+                None::<()>,
+            )
+            .await?
+        else {
+            return Ok(stages);
+        };
+        let Some(seed) = examples.into_iter().next() else {
+            if let Some(stage) = stages.last_mut() {
+                stage.ok = false;
+                stage.error = Some("the list of examples is empty".into());
+            }
+            return Ok(stages);
+        };
+
+        let Some(input) = self
+            .validate_stage::<_, Input>(
+                &mut stages,
+                EvaluatorStage::Generate,
+                vec!["generate".into(), seed.clone()],
+                None::<()>,
+            )
+            .await?
+        else {
+            return Ok(stages);
+        };
+
+        self.validate_stage::<_, PrepareResult>(
+            &mut stages,
+            EvaluatorStage::Prepare,
+            vec!["prepare".into(), seed],
+            Some(PrepareRequest {
+                environment: "python",
+                code: "",
+                data: &input.data,
+            }),
+        )
+        .await?;
+
+        Ok(stages)
+    }
+
+    async fn validate_stage<I: Serialize, O: DeserializeOwned>(
+        &self,
+        stages: &mut Vec<EvaluatorStageResult>,
+        stage: EvaluatorStage,
+        args: Vec<String>,
+        stdin: Option<I>,
+    ) -> Result<Option<O>, Error> {
+        let out = match self.build_and_run_evaluator(args, stdin).await {
+            Err(Error::Sandkasten(SandkastenError::ErrorResponse(err))) => {
+                let error = match *err {
+                    ErrorResponse::Inner(BuildRunError::EnvironmentNotFound) => {
+                        "the evaluator environment does not exist".into()
+                    }
+                    ErrorResponse::Inner(BuildRunError::CompileError(result)) => {
+                        format!("the evaluator could not be compiled:\n{}", result.stderr)
+                    }
+                    err => {
+                        return Err(Error::Sandkasten(SandkastenError::ErrorResponse(Box::new(
+                            err,
+                        ))))
+                    }
+                };
+                stages.push(EvaluatorStageResult {
+                    stage,
+                    ok: false,
+                    error: Some(error),
+                    result: None,
+                });
+                return Ok(None);
+            }
+            x => x?,
+        };
+        let result = if out.run.status != 0 {
+            if out.run.resource_usage.time >= self.evaluator_timeout * 1000 {
+                Err("the evaluator exceeded its time limit".into())
+            } else {
+                Err(format!(
+                    "the evaluator exited with status {}",
+                    out.run.status
+                ))
+            }
+        } else {
+            serde_json::from_str(&out.run.stdout).map_err(|err| err.to_string())
+        };
+        Ok(match result {
+            Ok(value) => {
+                stages.push(EvaluatorStageResult {
+                    stage,
+                    ok: true,
+                    error: None,
+                    result: None,
+                });
+                Some(value)
+            }
+            Err(error) => {
+                stages.push(EvaluatorStageResult {
+                    stage,
+                    ok: false,
+                    error: Some(error),
+                    result: Some(out),
+                });
+                None
+            }
+        })
+    }
+
     async fn run_evaluator<I: Serialize, O: DeserializeOwned>(
         &self,
         args: Vec<String>,
         stdin: Option<I>,
     ) -> Result<O, Error> {
-        let out = self
-            .sandkasten
-            .build_and_run(&BuildRunRequest {
+        let out = self.build_and_run_evaluator(args, stdin).await?;
+        if out.run.status != 0 {
+            if out.run.resource_usage.time >= self.evaluator_timeout * 1000 {
+                return Err(Error::EvaluatorTimeout(out));
+            }
+            return Err(Error::EvaluatorFailed(out));
+        }
+        serde_json::from_str(&out.run.stdout).map_err(|_| Error::InvalidOutput(out))
+    }
+
+    async fn build_and_run_evaluator<I: Serialize>(
+        &self,
+        args: Vec<String>,
+        stdin: Option<I>,
+    ) -> Result<BuildRunResult, Error> {
+        Ok(build_and_run(
+            self.sandkasten,
+            self.sandkasten_lock,
+            self.retry,
+            &BuildRunRequest {
                 build: BuildRequest {
-                    environment: "python".into(),
+                    environment: self.evaluator_environment.into(),
                     main_file: MainFile {
                         content: self.evaluator.to_owned(),
                         ..Default::default()
                     },
-                    files: vec![File {
-                        name: "lib.py".into(),
-                        content: EVALUATOR_LIBRARY.into(),
-                    }],
+                    files: evaluator_library(self.evaluator_environment),
                     ..Default::default()
                 },
                 run: RunRequest {
                     args,
                     stdin: stdin.map(|s| serde_json::to_string(&s)).transpose()?,
+                    run_limits: LimitsOpt {
+                        time: Some(self.evaluator_timeout),
+                        ..Default::default()
+                    },
                     ..Default::default()
                 },
-            })
-            .await?;
-        if out.run.status != 0 {
-            return Err(Error::EvaluatorFailed(out));
-        }
-        serde_json::from_str(&out.run.stdout).map_err(|_| Error::InvalidOutput(out))
+            },
+        )
+        .await?)
     }
 
+    #[instrument(skip_all, fields(seed = %seed, environment = %environment))]
     pub async fn run_solution(
         &self,
         seed: &str,
@@ -162,15 +341,18 @@ impl Judge<'_> {
                 return Ok(CheckResult {
                     verdict: ChallengesVerdict::PreCheckFailed,
                     reason: Some(prepare_result.reason),
+                    reason_code: None,
                     compile: None,
                     run: None,
                 })
             }
         };
 
-        let output = match self
-            .sandkasten
-            .build_and_run(&BuildRunRequest {
+        let output = match build_and_run(
+            self.sandkasten,
+            self.sandkasten_lock,
+            self.retry,
+            &BuildRunRequest {
                 build: BuildRequest {
                     environment: environment.into(),
                     main_file: MainFile {
@@ -188,8 +370,9 @@ impl Judge<'_> {
                     },
                     ..Default::default()
                 },
-            })
-            .await
+            },
+        )
+        .await
         {
             Err(SandkastenError::ErrorResponse(err)) => {
                 return match *err {
@@ -199,6 +382,7 @@ impl Judge<'_> {
                     ErrorResponse::Inner(BuildRunError::CompileError(result)) => Ok(CheckResult {
                         verdict: ChallengesVerdict::CompilationError,
                         reason: None,
+                        reason_code: None,
                         compile: Some(result),
                         run: None,
                     }),
@@ -223,6 +407,7 @@ impl Judge<'_> {
             return Ok(CheckResult {
                 verdict,
                 reason: None,
+                reason_code: None,
                 compile: output.build,
                 run: Some(output.run),
             });
@@ -239,12 +424,44 @@ impl Judge<'_> {
         Ok(CheckResult {
             verdict: result.verdict,
             reason: result.reason,
+            reason_code: result.reason_code,
             compile: output.build,
             run: Some(output.run),
         })
     }
 }
 
+/// Return whether the evaluator library is available to evaluators running in
+/// `environment`.
+pub fn has_evaluator_library(environment: &str) -> bool {
+    !evaluator_library(environment).is_empty()
+}
+
+/// Return the library files that are available to evaluators running in
+/// `environment`.
+fn evaluator_library(environment: &str) -> Vec<File> {
+    match environment {
+        "python" => vec![File {
+            name: "lib.py".into(),
+            content: EVALUATOR_LIBRARY.into(),
+        }],
+        _ => Vec::new(),
+    }
+}
+
+/// Return all environments available on sandkasten.
+pub async fn get_environments(
+    cache: &Cache<JsonFormatter>,
+    sandkasten: &SandkastenClient,
+    ttl: Option<Duration>,
+) -> anyhow::Result<HashMap<String, Environment>> {
+    Ok(cache
+        .cached_result(key!(), &[], ttl, || async {
+            sandkasten.list_environments().await
+        })
+        .await??)
+}
+
 pub async fn get_executor_config(
     cache: &Cache<JsonFormatter>,
     sandkasten: &SandkastenClient,
@@ -257,6 +474,55 @@ pub async fn get_executor_config(
         .into())
 }
 
+/// Build and run a program, retrying transient failures (connection errors
+/// and internal server errors) with exponential backoff.
+#[instrument(skip_all, fields(environment = %request.build.environment))]
+async fn build_and_run(
+    sandkasten: &SandkastenClient,
+    lock: &Semaphore,
+    retry: SandkastenRetry,
+    request: &BuildRunRequest,
+) -> Result<BuildRunResult, SandkastenError<BuildRunError>> {
+    let mut backoff = Duration::from_millis(retry.backoff);
+    let mut attempt = 1;
+    loop {
+        // don't hold the permit while waiting for the next attempt
+        let result = {
+            let _permit = lock
+                .acquire()
+                .await
+                .expect("sandkasten lock is never closed");
+            sandkasten
+                .build_and_run(request)
+                .instrument(info_span!("sandkasten_build_and_run", attempt))
+                .await
+        };
+        match result {
+            Err(err) if attempt < retry.attempts && is_retryable(&err) => {
+                warn!(
+                    "sandkasten request failed (attempt {attempt}/{}), retrying in {backoff:?}: {err}",
+                    retry.attempts
+                );
+                sleep(backoff).await;
+                backoff *= 2;
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+fn is_retryable<E>(err: &SandkastenError<E>) -> bool {
+    match err {
+        SandkastenError::ReqwestError(_) => true,
+        SandkastenError::ErrorResponse(err) => matches!(
+            **err,
+            ErrorResponse::GeneralError(GeneralError::InternalServerError)
+        ),
+        SandkastenError::UrlParseError(_) => false,
+    }
+}
+
 #[derive(Debug, Error)]
 pub enum Error {
     #[error("cache error: {0}")]
@@ -269,6 +535,8 @@ pub enum Error {
     EnvironmentNotFound,
     #[error("failed to execute evaluator: {0:?}")]
     EvaluatorFailed(BuildRunResult),
+    #[error("evaluator exceeded the time limit: {0:?}")]
+    EvaluatorTimeout(BuildRunResult),
     #[error("evaluator failed to produce valid output: {0:?}")]
     InvalidOutput(BuildRunResult),
 }
@@ -302,4 +570,248 @@ struct PrepareResult {
 struct EvaluatorCheckOutput {
     verdict: ChallengesVerdict,
     reason: Option<String>,
+    #[serde(default, deserialize_with = "deserialize_reason_code")]
+    reason_code: Option<ChallengesVerdictReason>,
+}
+
+/// Ignore reason codes this version does not know about instead of failing the
+/// whole check.
+fn deserialize_reason_code<'de, D>(
+    deserializer: D,
+) -> Result<Option<ChallengesVerdictReason>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    Ok(
+        Option::<Value>::deserialize(deserializer)?.and_then(|code| {
+            match serde_json::from_value(code.clone()) {
+                Ok(code) => Some(code),
+                Err(_) => {
+                    warn!("evaluator returned unknown reason code {code}");
+                    None
+                }
+            }
+        }),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc,
+    };
+
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::{TcpListener, TcpStream},
+    };
+
+    use super::*;
+
+    fn run_result(stdout: &str) -> String {
+        format!(
+            r#"{{"program_id":"00000000-0000-0000-0000-000000000000","ttl":60,"cached":false,"build":null,"run":{{"status":0,"stdout":{},"stderr":"","resource_usage":{{"time":1,"memory":1}},"limits":{{"cpus":1,"time":1,"memory":1,"tmpfs":1,"filesize":1,"file_descriptors":1,"processes":1,"stdout_max_size":1,"stderr_max_size":1,"network":false}}}}}}"#,
+            serde_json::to_string(stdout).unwrap()
+        )
+    }
+
+    /// Start a fake sandkasten that fails the first `failures` requests with
+    /// an internal server error and runs every other program with `stdout`.
+    async fn mock_sandkasten(failures: u32, stdout: &str) -> (SandkastenClient, Arc<AtomicU32>) {
+        let result = run_result(stdout);
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        let requests = Arc::new(AtomicU32::new(0));
+        tokio::spawn({
+            let requests = Arc::clone(&requests);
+            async move {
+                loop {
+                    let (stream, _) = listener.accept().await.unwrap();
+                    let n = requests.fetch_add(1, Ordering::SeqCst);
+                    let (status, body) = if n < failures {
+                        (
+                            "500 Internal Server Error",
+                            r#"{"error":"internal_server_error"}"#,
+                        )
+                    } else {
+                        ("200 OK", result.as_str())
+                    };
+                    respond(stream, status, body).await;
+                }
+            }
+        });
+        (SandkastenClient::new(url.parse().unwrap()), requests)
+    }
+
+    async fn respond(mut stream: TcpStream, status: &str, body: &str) {
+        let mut request = Vec::new();
+        let mut buf = [0; 4096];
+        loop {
+            let n = stream.read(&mut buf).await.unwrap();
+            request.extend_from_slice(&buf[..n]);
+            let request = String::from_utf8_lossy(&request);
+            if let Some((head, body)) = request.split_once("\r\n\r\n") {
+                let length = head
+                    .lines()
+                    .find_map(|line| {
+                        let (name, value) = line.split_once(':')?;
+                        name.eq_ignore_ascii_case("content-length")
+                            .then(|| value.trim().parse::<usize>().unwrap())
+                    })
+                    .unwrap_or(0);
+                if body.len() >= length {
+                    break;
+                }
+            }
+        }
+        let response = format!(
+            "HTTP/1.1 {status}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+            body.len()
+        );
+        stream.write_all(response.as_bytes()).await.unwrap();
+    }
+
+    async fn test_cache() -> Option<Cache<JsonFormatter>> {
+        let Ok(url) = std::env::var("TEST_REDIS_URL") else {
+            eprintln!("TEST_REDIS_URL is not set, skipping test");
+            return None;
+        };
+        let redis = lib::redis::RedisConnection::new(&url).await.unwrap();
+        Some(Cache::new(
+            fnct::backend::AsyncRedisBackend::new(redis, format!("test-{}", uuid::Uuid::new_v4())),
+            JsonFormatter,
+            Duration::from_secs(60),
+        ))
+    }
+
+    fn judge<'a>(
+        sandkasten: &'a SandkastenClient,
+        lock: &'a Semaphore,
+        evaluator: &'a str,
+        cache: &'a Cache<JsonFormatter>,
+    ) -> Judge<'a> {
+        Judge {
+            sandkasten,
+            sandkasten_lock: lock,
+            evaluator,
+            evaluator_environment: "python",
+            cache,
+            evaluator_timeout: 1,
+            retry: SandkastenRetry {
+                attempts: 1,
+                backoff: 1,
+            },
+            cache_ttl: None,
+        }
+    }
+
+    fn request() -> BuildRunRequest {
+        BuildRunRequest {
+            build: BuildRequest::default(),
+            run: RunRequest::default(),
+        }
+    }
+
+    #[test]
+    fn evaluator_cache_tag() {
+        assert_eq!(cache_tag("print(1)"), cache_tag("print(1)"));
+        assert_ne!(cache_tag("print(1)"), cache_tag("print(2)"));
+        assert!(cache_tag("").starts_with("evaluator_"));
+    }
+
+    #[test]
+    fn evaluator_library_environments() {
+        assert!(has_evaluator_library("python"));
+        assert!(!has_evaluator_library("rust"));
+    }
+
+    #[test]
+    fn evaluator_reason_codes() {
+        let parse = |json| serde_json::from_str::<EvaluatorCheckOutput>(json).unwrap();
+        let output =
+            parse(r#"{"verdict":"WRONG_ANSWER","reason":"","reason_code":"WRONG_OUTPUT"}"#);
+        assert_eq!(output.verdict, ChallengesVerdict::WrongAnswer);
+        assert_eq!(
+            output.reason_code,
+            Some(ChallengesVerdictReason::WrongOutput)
+        );
+        let output = parse(r#"{"verdict":"WRONG_ANSWER","reason":"","reason_code":"TOO_SLOW"}"#);
+        assert_eq!(output.reason_code, None);
+        let output = parse(r#"{"verdict":"OK","reason":"","reason_code":null}"#);
+        assert_eq!(output.reason_code, None);
+        let output = parse(r#"{"verdict":"INVALID_OUTPUT_FORMAT","reason":""}"#);
+        assert_eq!(output.reason_code, None);
+    }
+
+    #[tokio::test]
+    async fn invalidate_evaluator_cache() {
+        let Some(cache) = test_cache().await else {
+            return;
+        };
+        let lock = Semaphore::new(1);
+        let (examples, example_requests) = mock_sandkasten(0, r#"["a","b"]"#).await;
+        let (generate, generate_requests) =
+            mock_sandkasten(0, r#"{"input":"1 2","data":null}"#).await;
+
+        let (lock, cache) = (&lock, &cache);
+        let get_examples = |evaluator| {
+            let examples = &examples;
+            async move {
+                judge(examples, lock, evaluator, cache)
+                    .examples()
+                    .await
+                    .unwrap()
+            }
+        };
+        let get_input = |evaluator| {
+            let generate = &generate;
+            async move {
+                judge(generate, lock, evaluator, cache)
+                    .generate("seed")
+                    .await
+                    .unwrap()
+            }
+        };
+
+        for _ in 0..2 {
+            assert_eq!(get_examples("print(1)").await, ["a", "b"]);
+            assert_eq!(get_input("print(1)").await.input, "1 2");
+            get_examples("print(2)").await;
+        }
+        assert_eq!(example_requests.load(Ordering::SeqCst), 2);
+        assert_eq!(generate_requests.load(Ordering::SeqCst), 1);
+
+        invalidate_cache(cache, "print(1)").await.unwrap();
+        get_examples("print(1)").await;
+        get_input("print(1)").await;
+        get_examples("print(2)").await;
+        assert_eq!(example_requests.load(Ordering::SeqCst), 3);
+        assert_eq!(generate_requests.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn retry_transient_sandkasten_errors() {
+        let retry = SandkastenRetry {
+            attempts: 3,
+            backoff: 1,
+        };
+
+        let lock = Semaphore::new(1);
+
+        let (sandkasten, requests) = mock_sandkasten(2, "ok").await;
+        let result = build_and_run(&sandkasten, &lock, retry, &request())
+            .await
+            .unwrap();
+        assert_eq!(result.run.stdout, "ok");
+        assert_eq!(requests.load(Ordering::SeqCst), 3);
+
+        let (sandkasten, requests) = mock_sandkasten(3, "ok").await;
+        let err = build_and_run(&sandkasten, &lock, retry, &request())
+            .await
+            .unwrap_err();
+        assert!(is_retryable(&err));
+        assert_eq!(requests.load(Ordering::SeqCst), 3);
+        assert_eq!(lock.available_permits(), 1);
+    }
 }
@@ -3,10 +3,10 @@ use entity::{
     challenges_subtasks, sea_orm_active_enums::ChallengesVerdictVariant,
 };
 use lib::services::Services;
-use schemas::challenges::leaderboard::{Leaderboard, Rank};
+use schemas::challenges::leaderboard::{Leaderboard, LeaderboardLanguage, Rank};
 use sea_orm::{
     sea_query::{Alias, Expr, Query, SelectStatement, SimpleExpr},
-    ColumnTrait, DatabaseTransaction, Iden, Value,
+    ColumnTrait, ConnectionTrait, DatabaseTransaction, Iden, Order, Value,
 };
 use uuid::Uuid;
 
@@ -98,6 +98,80 @@ fn get_base_query(language: &str) -> SelectStatement {
         .to_owned()
 }
 
+/// Return all languages in which at least one coding challenge has been solved.
+pub async fn get_languages(db: &DatabaseTransaction) -> anyhow::Result<Vec<LeaderboardLanguage>> {
+    let rows: Vec<(String, i64, i64)> = db
+        .query_all(
+            db.get_database_backend().build(
+                Query::select()
+                    .column(Alias::new("environment"))
+                    .expr_as(
+                        Expr::col(Alias::new("user_id")).count_distinct(),
+                        Alias::new("users"),
+                    )
+                    .expr_as(
+                        Expr::col(Alias::new("user_id")).count(),
+                        Alias::new("solved"),
+                    )
+                    .from_subquery(
+                        Query::select()
+                            .expr_as(
+                                Expr::col(
+                                    challenges_coding_challenge_submissions::Column::Environment,
+                                ),
+                                Alias::new("environment"),
+                            )
+                            .expr_as(
+                                Expr::col(challenges_coding_challenge_submissions::Column::Creator),
+                                Alias::new("user_id"),
+                            )
+                            .from(challenges_coding_challenge_result::Entity)
+                            .inner_join(
+                                challenges_coding_challenge_submissions::Entity,
+                                Expr::col((
+                                    challenges_coding_challenge_result::Entity,
+                                    challenges_coding_challenge_result::Column::SubmissionId,
+                                ))
+                                .equals((
+                                    challenges_coding_challenge_submissions::Entity,
+                                    challenges_coding_challenge_submissions::Column::Id,
+                                )),
+                            )
+                            .and_where(
+                                Expr::col(challenges_coding_challenge_result::Column::Verdict).eq(
+                                    SimpleExpr::Constant(Value::String(Some(
+                                        ChallengesVerdictVariant::Ok.to_string().into(),
+                                    ))),
+                                ),
+                            )
+                            .group_by_columns([
+                                challenges_coding_challenge_submissions::Column::Environment,
+                                challenges_coding_challenge_submissions::Column::Creator,
+                                challenges_coding_challenge_submissions::Column::SubtaskId,
+                            ])
+                            .to_owned(),
+                        Alias::new("x"),
+                    )
+                    .group_by_col(Alias::new("environment"))
+                    .order_by(Alias::new("users"), Order::Desc)
+                    .order_by(Alias::new("environment"), Order::Asc),
+            ),
+        )
+        .await?
+        .into_iter()
+        .map(|row| row.try_get_many_by_index())
+        .collect::<Result<_, _>>()?;
+
+    Ok(rows
+        .into_iter()
+        .map(|(environment, users, solved)| LeaderboardLanguage {
+            environment,
+            users: users as _,
+            solved: solved as _,
+        })
+        .collect())
+}
+
 pub async fn get_language_leaderboard(
     db: &DatabaseTransaction,
     services: &Services,
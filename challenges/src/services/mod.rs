@@ -1,5 +1,9 @@
+pub mod coding_challenges;
 pub mod course_tasks;
 pub mod judge;
 pub mod leaderboard;
+pub mod moderation;
+pub mod streaks;
 pub mod subtasks;
 pub mod tasks;
+pub mod webhook;
@@ -0,0 +1,112 @@
+use std::{sync::Arc, time::Duration};
+
+use lib::config::Moderation;
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+/// Decides whether user submitted content may be published.
+#[poem::async_trait]
+pub trait ContentModerator: Send + Sync {
+    /// Return whether any of `texts` has been flagged and must be rejected.
+    async fn is_flagged(&self, texts: &[&str]) -> bool;
+}
+
+/// Accepts all content.
+pub struct NoModeration;
+
+#[poem::async_trait]
+impl ContentModerator for NoModeration {
+    async fn is_flagged(&self, _texts: &[&str]) -> bool {
+        false
+    }
+}
+
+/// Sends content to an external moderation endpoint.
+///
+/// The endpoint receives `{"texts": [...]}` and must respond with
+/// `{"flagged": true}` if the content is not acceptable.
+pub struct HttpModerator {
+    client: reqwest::Client,
+    config: Moderation,
+}
+
+#[derive(Serialize)]
+struct ModerationRequest<'a> {
+    texts: &'a [&'a str],
+}
+
+#[derive(Deserialize)]
+struct ModerationResponse {
+    flagged: bool,
+}
+
+impl HttpModerator {
+    pub fn new(config: Moderation) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            config,
+        }
+    }
+
+    async fn check(&self, texts: &[&str]) -> reqwest::Result<bool> {
+        let response = self
+            .client
+            .post(self.config.url.clone())
+            .timeout(Duration::from_secs(self.config.timeout))
+            .json(&ModerationRequest { texts })
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<ModerationResponse>()
+            .await?;
+        Ok(response.flagged)
+    }
+}
+
+#[poem::async_trait]
+impl ContentModerator for HttpModerator {
+    async fn is_flagged(&self, texts: &[&str]) -> bool {
+        match self.check(texts).await {
+            Ok(flagged) => flagged,
+            Err(err) => {
+                warn!("moderation request failed: {err}");
+                !self.config.fail_open
+            }
+        }
+    }
+}
+
+/// Return the content moderator for `config` or one that accepts everything
+/// if moderation is disabled.
+pub fn content_moderator(config: Option<&Moderation>) -> Arc<dyn ContentModerator> {
+    match config {
+        Some(config) => Arc::new(HttpModerator::new(config.clone())),
+        None => Arc::new(NoModeration),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::net::TcpListener;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn unavailable_endpoint() {
+        // nothing is listening on this port after the listener is dropped
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        drop(listener);
+
+        let moderator = |fail_open| {
+            HttpModerator::new(Moderation {
+                url: url.parse().unwrap(),
+                timeout: 1,
+                fail_open,
+            })
+        };
+        assert!(!moderator(true).is_flagged(&["hello"]).await);
+        assert!(moderator(false).is_flagged(&["hello"]).await);
+        assert!(!NoModeration.is_flagged(&["hello"]).await);
+    }
+}
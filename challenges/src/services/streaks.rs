@@ -0,0 +1,155 @@
+use chrono::{NaiveDate, NaiveDateTime};
+use entity::challenges_user_streaks;
+use sea_orm::{
+    sea_query::OnConflict, ActiveModelTrait, DatabaseTransaction, DbErr, EntityTrait, QuerySelect,
+    Set, Unchanged,
+};
+use uuid::Uuid;
+
+/// Update the solve streak of a user who has solved a subtask at `timestamp`
+/// (UTC).
+pub async fn record_solve(
+    db: &DatabaseTransaction,
+    user_id: Uuid,
+    timestamp: NaiveDateTime,
+) -> Result<(), DbErr> {
+    // make sure the row exists so it can be locked below
+    challenges_user_streaks::Entity::insert(challenges_user_streaks::ActiveModel {
+        user_id: Set(user_id),
+        current_streak: Set(0),
+        longest_streak: Set(0),
+        last_solved_date: Set(None),
+    })
+    .on_conflict(
+        OnConflict::column(challenges_user_streaks::Column::UserId)
+            .do_nothing()
+            .to_owned(),
+    )
+    .exec_without_returning(db)
+    .await?;
+
+    let Some(streak) = challenges_user_streaks::Entity::find_by_id(user_id)
+        .lock_exclusive()
+        .one(db)
+        .await?
+    else {
+        return Ok(());
+    };
+    let next = advance(&streak, timestamp.date());
+    if next != streak {
+        challenges_user_streaks::ActiveModel {
+            user_id: Unchanged(user_id),
+            current_streak: Set(next.current_streak),
+            longest_streak: Set(next.longest_streak),
+            last_solved_date: Set(next.last_solved_date),
+        }
+        .update(db)
+        .await?;
+    }
+    Ok(())
+}
+
+/// Return the current streak of a user on `today`, which is zero if the user
+/// has solved nothing since yesterday.
+pub fn current_streak(streak: &challenges_user_streaks::Model, today: NaiveDate) -> i32 {
+    match streak.last_solved_date {
+        Some(last) if last >= today.pred_opt().unwrap_or(today) => streak.current_streak,
+        _ => 0,
+    }
+}
+
+/// Return the streak after solving a subtask on `today`.
+///
+/// The streak is only incremented once per day and starts over if at least
+/// one day has been skipped.
+fn advance(
+    streak: &challenges_user_streaks::Model,
+    today: NaiveDate,
+) -> challenges_user_streaks::Model {
+    let current_streak = match streak.last_solved_date {
+        // already solved something today (or the clock went backwards)
+        Some(last) if last >= today => return streak.clone(),
+        Some(last) if last.succ_opt() == Some(today) => streak.current_streak + 1,
+        _ => 1,
+    };
+    challenges_user_streaks::Model {
+        user_id: streak.user_id,
+        current_streak,
+        longest_streak: streak.longest_streak.max(current_streak),
+        last_solved_date: Some(today),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    fn streak(
+        current: i32,
+        longest: i32,
+        last: Option<NaiveDate>,
+    ) -> challenges_user_streaks::Model {
+        challenges_user_streaks::Model {
+            user_id: Uuid::nil(),
+            current_streak: current,
+            longest_streak: longest,
+            last_solved_date: last,
+        }
+    }
+
+    #[test]
+    fn advance_streak() {
+        let today = date(2024, 4, 24);
+        assert_eq!(
+            advance(&streak(0, 0, None), today),
+            streak(1, 1, Some(today))
+        );
+        // only once per day
+        assert_eq!(
+            advance(&streak(3, 5, Some(today)), today),
+            streak(3, 5, Some(today))
+        );
+        assert_eq!(
+            advance(&streak(3, 5, Some(date(2024, 4, 23))), today),
+            streak(4, 5, Some(today))
+        );
+        assert_eq!(
+            advance(&streak(5, 5, Some(date(2024, 4, 23))), today),
+            streak(6, 6, Some(today))
+        );
+        // a day has been skipped
+        assert_eq!(
+            advance(&streak(5, 5, Some(date(2024, 4, 22))), today),
+            streak(1, 5, Some(today))
+        );
+    }
+
+    #[test]
+    fn advance_streak_across_month_and_year_boundaries() {
+        assert_eq!(
+            advance(&streak(1, 1, Some(date(2024, 2, 29))), date(2024, 3, 1)),
+            streak(2, 2, Some(date(2024, 3, 1)))
+        );
+        assert_eq!(
+            advance(&streak(1, 1, Some(date(2023, 12, 31))), date(2024, 1, 1)),
+            streak(2, 2, Some(date(2024, 1, 1)))
+        );
+        assert_eq!(
+            advance(&streak(1, 1, Some(date(2023, 2, 28))), date(2023, 3, 2)),
+            streak(1, 1, Some(date(2023, 3, 2)))
+        );
+    }
+
+    #[test]
+    fn current_streak_expires() {
+        let s = streak(3, 5, Some(date(2024, 4, 23)));
+        assert_eq!(current_streak(&s, date(2024, 4, 23)), 3);
+        assert_eq!(current_streak(&s, date(2024, 4, 24)), 3);
+        assert_eq!(current_streak(&s, date(2024, 4, 25)), 0);
+        assert_eq!(current_streak(&streak(0, 0, None), date(2024, 4, 25)), 0);
+    }
+}
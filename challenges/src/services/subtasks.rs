@@ -1,33 +1,52 @@
-use std::collections::HashMap;
+use std::{
+    collections::{HashMap, HashSet},
+    time::Duration,
+};
 
 use anyhow::Context;
 use chrono::{DateTime, NaiveDateTime, Utc};
 use entity::{
-    challenges_ban, challenges_subtasks, challenges_tasks, challenges_user_subtasks,
-    sea_orm_active_enums::{ChallengesBanAction, ChallengesSubtaskType},
+    challenges_ban, challenges_challenges, challenges_coding_challenge_result,
+    challenges_coding_challenge_submissions, challenges_matching_attempts,
+    challenges_question_attempts, challenges_subtask_tags, challenges_subtasks, challenges_tasks,
+    challenges_user_subtasks,
+    sea_orm_active_enums::{ChallengesBanAction, ChallengesRating, ChallengesSubtaskType},
 };
+use fnct::key;
+use key_rwlock::KeyRwLock;
 use lib::{
     auth::User,
     config::Config,
     services::{
-        shop::AddCoinsError, skills::AddSkillProgressError, ServiceError, ServiceResult, Services,
+        events::{Event, SubtaskSolved},
+        shop::AddCoinsError,
+        skills::AddSkillProgressError,
+        ServiceError, ServiceResult, Services,
     },
+    Cache,
 };
-use poem_ext::responses::ErrorResponse;
-use schemas::challenges::subtasks::{
-    CreateSubtaskRequest, Subtask, SubtaskStats, UpdateSubtaskRequest,
+use poem_ext::{patch_value::PatchValue, responses::ErrorResponse};
+use schemas::challenges::{
+    sort::{SortBy, SortOrder},
+    subtasks::{CreateSubtaskRequest, Subtask, SubtaskRatings, SubtaskStats, UpdateSubtaskRequest},
 };
 use sea_orm::{
-    ActiveModelTrait, ColumnTrait, Condition, DatabaseTransaction, DbErr, EntityTrait, ModelTrait,
-    QueryFilter, QueryOrder, Related, Set, Unchanged,
+    sea_query::{Expr, OnConflict, PostgresQueryBuilder, Query, SimpleExpr},
+    ActiveModelTrait, ActiveValue, ColumnTrait, Condition, ConnectionTrait, DatabaseTransaction,
+    DbBackend, DbErr, EntityTrait, Insert, Iterable, ModelTrait, Order, PaginatorTrait,
+    QueryFilter, QueryOrder, QuerySelect, QueryTrait, Related, Select, Set, Statement, Unchanged,
+    UpdateOne,
 };
 use thiserror::Error;
+use tracing::{error, info, warn};
 use uuid::Uuid;
 
 use super::{
     course_tasks::get_skills_of_course,
+    streaks::record_solve,
     tasks::{get_specific_task, get_task, get_task_with_specific, Task},
 };
+use crate::after_commit::AfterCommit;
 
 pub async fn check_hearts(
     services: &Services,
@@ -38,6 +57,7 @@ pub async fn check_hearts(
     if subtask.retired
         || user.admin
         || user.id == subtask.creator
+        || config.challenges.is_service_account(user.id)
         || services.shop.has_premium(user.id).await?
     {
         return Ok(true);
@@ -51,26 +71,72 @@ pub async fn check_hearts(
     Ok(hearts >= subtask_hearts(config, subtask.ty))
 }
 
+/// Check whether a user has used up all attempts to solve a subtask. Admins
+/// and the creator of the subtask have an unlimited number of attempts.
+pub fn attempts_exhausted(
+    user: &User,
+    subtask: &challenges_subtasks::Model,
+    user_subtask: impl UserSubtaskExt,
+) -> bool {
+    attempts_exhausted_with_pending(user, subtask, user_subtask, 0)
+}
+
+/// Like [`attempts_exhausted`], but also count `pending` attempts that have not
+/// been recorded yet (e.g. submissions in the judge queue).
+pub fn attempts_exhausted_with_pending(
+    user: &User,
+    subtask: &challenges_subtasks::Model,
+    user_subtask: impl UserSubtaskExt,
+    pending: usize,
+) -> bool {
+    !(user.admin || user.id == subtask.creator || user_subtask.is_solved())
+        && subtask
+            .max_attempts
+            .is_some_and(|max| user_subtask.attempts() + pending >= max.max(0) as usize)
+}
+
+/// Deduct the hearts required to attempt a subtask.
+///
+/// Returns the number of hearts that have been deducted or `None` if the user
+/// does not have enough hearts.
 pub async fn deduct_hearts(
     services: &Services,
     config: &Config,
     user: &User,
     subtask: &challenges_subtasks::Model,
-) -> anyhow::Result<bool> {
+) -> anyhow::Result<Option<u32>> {
     if subtask.retired
         || user.admin
         || user.id == subtask.creator
+        || config.challenges.is_service_account(user.id)
         || services.shop.has_premium(user.id).await?
     {
-        return Ok(true);
+        return Ok(Some(0));
     }
 
     let hearts = subtask_hearts(config, subtask.ty);
-    services
+    let success = services
         .shop
         .add_hearts(user.id, -(hearts as i32))
         .await
-        .with_context(|| format!("failed to deduct {hearts} hearts for user {}", user.id))
+        .with_context(|| format!("failed to deduct {hearts} hearts for user {}", user.id))?;
+    Ok(success.then_some(hearts))
+}
+
+/// Return the number of hearts `user` has to pay to attempt `subtask`.
+///
+/// `premium` indicates whether the user has a premium subscription.
+pub fn hearts_cost(config: &Config, user: &User, premium: bool, subtask: &Subtask) -> u32 {
+    if subtask.retired
+        || user.admin
+        || user.id == subtask.creator
+        || config.challenges.is_service_account(user.id)
+        || premium
+    {
+        0
+    } else {
+        subtask_hearts(config, subtask.ty)
+    }
 }
 
 fn subtask_hearts(config: &Config, ty: ChallengesSubtaskType) -> u32 {
@@ -94,18 +160,13 @@ pub async fn send_task_rewards(
     }
 
     if subtask.xp != 0 {
-        let skills = get_skills(
-            services,
-            get_parent_task(db, subtask)
-                .await?
-                .ok_or(SendTaskRewardsError::NoParentTask)?
-                .1,
-        )
-        .await?;
-        for skill in &skills {
+        for (skill, xp) in get_skill_rewards(services, db, subtask).await? {
+            if xp == 0 {
+                continue;
+            }
             services
                 .skills
-                .add_skill_progress(user_id, skill, subtask.xp / skills.len() as i64)
+                .add_skill_progress(user_id, &skill, xp)
                 .await??;
         }
     }
@@ -118,6 +179,147 @@ pub async fn send_task_rewards(
     Ok(())
 }
 
+/// Take back the xp and coins `user_id` has received for solving `subtask`.
+///
+/// If this fails partway through, the rewards that have already been taken
+/// back are restored, so the user either keeps or loses all of them.
+pub async fn revoke_task_rewards(
+    services: &Services,
+    db: &DatabaseTransaction,
+    user_id: Uuid,
+    subtask: &challenges_subtasks::Model,
+) -> Result<(), SendTaskRewardsError> {
+    if subtask.retired {
+        return Ok(());
+    }
+
+    // look up the xp of all skills before anything is taken back
+    let skills = if subtask.xp != 0 {
+        get_skill_rewards(services, db, subtask).await?
+    } else {
+        Vec::new()
+    };
+    let skills = skills
+        .into_iter()
+        .filter(|&(_, xp)| xp != 0)
+        .collect::<Vec<_>>();
+
+    // coins come first, as this fails if the user has already spent them
+    if subtask.coins != 0 {
+        services
+            .shop
+            .add_coins(user_id, -subtask.coins, "Challenges / Aufgaben", false)
+            .await??;
+    }
+    for (i, (skill, xp)) in skills.iter().enumerate() {
+        if let Err(err) = add_skill_progress(services, user_id, skill, -xp).await {
+            error!(
+                "failed to revoke {xp} xp in {skill} from user {user_id} for subtask {}, \
+                 restoring the rewards revoked so far: {err}",
+                subtask.id
+            );
+            restore_task_rewards(services, user_id, subtask, &skills[..i]).await;
+            return Err(err);
+        }
+    }
+    // the rewards are not restored if the transaction fails to commit afterwards
+    info!(
+        "revoked {} coins and {skills:?} xp from user {user_id} for subtask {}",
+        subtask.coins, subtask.id
+    );
+    Ok(())
+}
+
+/// Give back the coins of `subtask` and the xp in `skills` after revoking the
+/// rewards of `user_id` has failed partway through. Failures are logged, as
+/// nothing else can be done about them.
+async fn restore_task_rewards(
+    services: &Services,
+    user_id: Uuid,
+    subtask: &challenges_subtasks::Model,
+    skills: &[(String, i64)],
+) {
+    if subtask.coins != 0 {
+        if let Err(err) = services
+            .shop
+            .add_coins(user_id, subtask.coins, "Challenges / Aufgaben", true)
+            .await
+            .map_err(SendTaskRewardsError::from)
+            .and_then(|x| x.map(|_| ()).map_err(Into::into))
+        {
+            error!(
+                "failed to restore {} coins of user {user_id} for subtask {}: {err}",
+                subtask.coins, subtask.id
+            );
+        }
+    }
+    for (skill, xp) in skills {
+        if let Err(err) = add_skill_progress(services, user_id, skill, *xp).await {
+            error!(
+                "failed to restore {xp} xp in {skill} of user {user_id} for subtask {}: {err}",
+                subtask.id
+            );
+        }
+    }
+}
+
+async fn add_skill_progress(
+    services: &Services,
+    user_id: Uuid,
+    skill: &str,
+    xp: i64,
+) -> Result<(), SendTaskRewardsError> {
+    Ok(services
+        .skills
+        .add_skill_progress(user_id, skill, xp)
+        .await??)
+}
+
+/// Return the xp each skill of the parent task of `subtask` receives when the
+/// subtask is solved.
+pub async fn get_skill_rewards(
+    services: &Services,
+    db: &DatabaseTransaction,
+    subtask: &challenges_subtasks::Model,
+) -> Result<Vec<(String, i64)>, SendTaskRewardsError> {
+    let skills = get_skill_weights(
+        services,
+        get_parent_task(db, subtask)
+            .await?
+            .ok_or(SendTaskRewardsError::NoParentTask)?
+            .1,
+    )
+    .await?;
+    Ok(distribute_xp(subtask.xp, &skills)
+        .into_iter()
+        .map(|(skill, xp)| (skill.into(), xp))
+        .collect())
+}
+
+/// Publish a `subtask_solved` event once the transaction has been committed.
+/// Delivery is best-effort, so failures are only logged.
+pub fn publish_subtask_solved(
+    after_commit: &AfterCommit,
+    services: &Services,
+    user_id: Uuid,
+    subtask: &challenges_subtasks::Model,
+    solved_at: NaiveDateTime,
+) {
+    let event = Event::SubtaskSolved(SubtaskSolved {
+        user_id,
+        task_id: subtask.task_id,
+        subtask_id: subtask.id,
+        solved_at: solved_at.and_utc(),
+    });
+    let events = services.events.clone();
+    let subtask_id = subtask.id;
+    after_commit.defer(async move {
+        if let Err(err) = events.publish(event).await {
+            warn!("failed to publish subtask_solved event for {subtask_id}: {err}");
+        }
+    });
+}
+
 pub async fn get_user_subtasks(
     db: &DatabaseTransaction,
     user_id: Uuid,
@@ -143,32 +345,106 @@ pub async fn get_user_subtask(
         .await
 }
 
+/// Serializes the processing of solves of the same subtask by the same user,
+/// so that concurrent solves don't send the rewards more than once. Keyed by
+/// `(subtask_id, user_id)`.
+pub type RewardLock = KeyRwLock<(Uuid, Uuid)>;
+
+/// Insert or update the progress of a user on a subtask.
+///
+/// If `values` marks a subtask as solved that has not been solved before, the
+/// row is only written if the subtask has not been solved concurrently in the
+/// meantime. Otherwise nothing is changed and `None` is returned, so the
+/// caller must not send the rewards again.
 pub async fn update_user_subtask(
     db: &DatabaseTransaction,
     user_subtask: Option<&challenges_user_subtasks::Model>,
     values: challenges_user_subtasks::ActiveModel,
-) -> Result<challenges_user_subtasks::Model, DbErr> {
-    if let Some(user_subtask) = user_subtask {
-        challenges_user_subtasks::ActiveModel {
-            user_id: Unchanged(user_subtask.user_id),
-            subtask_id: Unchanged(user_subtask.subtask_id),
-            ..values
-        }
-        .update(db)
-        .await
+) -> Result<Option<challenges_user_subtasks::Model>, DbErr> {
+    let newly_solved = match &values.solved_timestamp {
+        ActiveValue::Set(Some(timestamp)) if !user_subtask.is_solved() => Some(*timestamp),
+        _ => None,
+    };
+    let result = if let Some(user_subtask) = user_subtask {
+        update_user_subtask_query(user_subtask, values, newly_solved.is_some())
+            .exec(db)
+            .await
     } else {
-        challenges_user_subtasks::ActiveModel { ..values }
-            .insert(db)
+        insert_user_subtask_query(values, newly_solved.is_some())
+            .exec_with_returning(db)
             .await
+    };
+    let user_subtask = match result {
+        Ok(user_subtask) => user_subtask,
+        // the subtask has been solved concurrently, so the guarded update or upsert has not
+        // returned any row
+        Err(DbErr::RecordNotUpdated | DbErr::RecordNotFound(_)) if newly_solved.is_some() => {
+            return Ok(None)
+        }
+        Err(err) => return Err(err),
+    };
+    if let Some(timestamp) = newly_solved {
+        record_solve(db, user_subtask.user_id, timestamp).await?;
+    }
+    Ok(Some(user_subtask))
+}
+
+/// Update an existing user subtask. A newly solved subtask is only updated if it has not been
+/// solved in the meantime.
+fn update_user_subtask_query(
+    user_subtask: &challenges_user_subtasks::Model,
+    values: challenges_user_subtasks::ActiveModel,
+    newly_solved: bool,
+) -> UpdateOne<challenges_user_subtasks::ActiveModel> {
+    let update = challenges_user_subtasks::Entity::update(challenges_user_subtasks::ActiveModel {
+        user_id: Unchanged(user_subtask.user_id),
+        subtask_id: Unchanged(user_subtask.subtask_id),
+        ..values
+    });
+    if newly_solved {
+        update.filter(challenges_user_subtasks::Column::SolvedTimestamp.is_null())
+    } else {
+        update
     }
 }
 
+/// Insert a user subtask, or update it if another request has created it since it has been
+/// read. A newly solved subtask is only updated if it has not been solved in the meantime.
+fn insert_user_subtask_query(
+    values: challenges_user_subtasks::ActiveModel,
+    newly_solved: bool,
+) -> Insert<challenges_user_subtasks::ActiveModel> {
+    let mut on_conflict = OnConflict::columns([
+        challenges_user_subtasks::Column::UserId,
+        challenges_user_subtasks::Column::SubtaskId,
+    ]);
+    on_conflict.update_columns(challenges_user_subtasks::Column::iter().filter(|&column| {
+        values.get(column).is_set()
+            && !matches!(
+                column,
+                challenges_user_subtasks::Column::UserId
+                    | challenges_user_subtasks::Column::SubtaskId
+            )
+    }));
+    if newly_solved {
+        on_conflict.action_and_where(
+            Expr::col((
+                challenges_user_subtasks::Entity,
+                challenges_user_subtasks::Column::SolvedTimestamp,
+            ))
+            .is_null(),
+        );
+    }
+    challenges_user_subtasks::Entity::insert(values).on_conflict(on_conflict)
+}
+
 pub async fn get_active_ban(
     db: &DatabaseTransaction,
+    config: &Config,
     user: &User,
     action: ChallengesBanAction,
 ) -> Result<ActiveBan, DbErr> {
-    if user.admin {
+    if user.admin || config.challenges.is_service_account(user.id) {
         return Ok(ActiveBan::NotBanned);
     }
     let bans = challenges_ban::Entity::find()
@@ -242,13 +518,53 @@ pub async fn get_parent_task(
     )
 }
 
-pub async fn get_skills(services: &Services, task: Task) -> ServiceResult<Vec<String>> {
+/// Return the skills of a task together with their relative weights.
+///
+/// All skills have the same weight unless the task is a challenge with custom
+/// skill weights.
+pub async fn get_skill_weights(
+    services: &Services,
+    task: Task,
+) -> ServiceResult<Vec<(String, u32)>> {
     Ok(match task {
-        Task::Challenge(challenge) => challenge.skill_ids,
-        Task::CourseTask(task) => get_skills_of_course(services, &task.course_id).await?,
+        Task::Challenge(challenge) => match challenge.skill_weights {
+            Some(weights) if weights.len() == challenge.skill_ids.len() => challenge
+                .skill_ids
+                .into_iter()
+                .zip(weights.into_iter().map(|weight| weight.max(0) as _))
+                .collect(),
+            _ => challenge.skill_ids.into_iter().map(|x| (x, 1)).collect(),
+        },
+        Task::CourseTask(task) => get_skills_of_course(services, &task.course_id)
+            .await?
+            .into_iter()
+            .map(|x| (x, 1))
+            .collect(),
     })
 }
 
+/// Split `xp` between the skills proportionally to their weights.
+///
+/// The remainder that is lost due to rounding is assigned to the first skill,
+/// so the sum always equals `xp`. If all weights are zero, `xp` is split
+/// equally.
+fn distribute_xp(xp: i64, skills: &[(String, u32)]) -> Vec<(&str, i64)> {
+    let total = skills.iter().map(|&(_, weight)| weight as i64).sum::<i64>();
+    let (weight, total): (fn(u32) -> i64, _) = match total {
+        0 => (|_| 1, skills.len() as i64),
+        total => (|weight| weight as i64, total),
+    };
+    let mut out = skills
+        .iter()
+        .map(|(skill, w)| (skill.as_str(), xp * weight(*w) / total))
+        .collect::<Vec<_>>();
+    let remainder = xp - out.iter().map(|&(_, xp)| xp).sum::<i64>();
+    if let Some((_, first)) = out.first_mut() {
+        *first += remainder;
+    }
+    out
+}
+
 pub trait UserSubtaskExt {
     fn is_solved(&self) -> bool;
     fn is_rated(&self) -> bool;
@@ -343,27 +659,160 @@ pub struct QuerySubtasksFilter {
     pub retired: Option<bool>,
     pub creator: Option<Uuid>,
     pub ty: Option<ChallengesSubtaskType>,
+    /// Only include subtasks that have all of these tags.
+    pub tags: Vec<String>,
+    /// Also include retired subtasks the user has not started yet.
+    pub include_locked: bool,
 }
 
+/// Return all subtasks matching the filter.
+///
+/// `limit` and `offset` are applied in the database query, i.e. before the
+/// `attempted`, `solved` and `rated` filters.
+#[allow(clippy::too_many_arguments)]
 pub async fn query_subtasks_only(
     db: &DatabaseTransaction,
+    cache: &Cache,
     user: &User,
     task_id: Option<Uuid>,
     filter: QuerySubtasksFilter,
-) -> Result<Vec<Subtask>, DbErr> {
+    sort: Option<SortBy>,
+    order: Option<SortOrder>,
+    limit: Option<u64>,
+    offset: Option<u64>,
+) -> Result<Vec<Subtask>, ErrorResponse> {
     let user_subtasks = get_user_subtasks(db, user.id).await?;
     let mut query = challenges_subtasks::Entity::find();
     if let Some(task_id) = task_id {
         query = query.filter(challenges_subtasks::Column::TaskId.eq(task_id));
+    } else if !user.admin {
+        // creators still see their own subtasks, e.g. in `/me/subtasks`
+        query = query.filter(
+            Condition::any()
+                .add(not_in_unlisted_challenge())
+                .add(challenges_subtasks::Column::Creator.eq(user.id)),
+        );
     }
-    Ok(prepare_query(query, &filter, user)
+    let order = sort_order(order);
+    query = match sort.unwrap_or(SortBy::Created) {
+        SortBy::Position | SortBy::Title | SortBy::Created => {
+            query.order_by(challenges_subtasks::Column::CreationTimestamp, order)
+        }
+        SortBy::Xp => query.order_by(challenges_subtasks::Column::Xp, order),
+        SortBy::Solved => query.order_by(
+            solved_count(
+                Expr::col((
+                    challenges_user_subtasks::Entity,
+                    challenges_user_subtasks::Column::SubtaskId,
+                ))
+                .equals((challenges_subtasks::Entity, challenges_subtasks::Column::Id)),
+            ),
+            order,
+        ),
+    };
+    let subtasks = prepare_query(query, &filter, user)
+        .limit(limit)
+        .offset(offset)
         .all(db)
-        .await?
+        .await?;
+    let subtask_ids = subtasks
+        .iter()
+        .map(|subtask| subtask.id)
+        .collect::<Vec<_>>();
+    let mut tags = get_subtask_tags(db, subtask_ids.iter().copied()).await?;
+    let ratings = get_subtask_ratings(db, subtask_ids).await?;
+    let difficulties = cache
+        .cached_result(key!(), &[], Some(DIFFICULTY_CACHE_TTL), || {
+            get_subtask_difficulties(db)
+        })
+        .await??;
+    Ok(subtasks
         .into_iter()
-        .filter_map(|subtask| subtasks_filter_map(subtask, &filter, &user_subtasks))
+        .filter_map(|subtask| {
+            let mut subtask =
+                subtasks_filter_map(subtask, &filter, &user_subtasks, &mut tags, &ratings)?;
+            subtask.difficulty = difficulties.get(&subtask.id).copied();
+            Some(subtask)
+        })
         .collect())
 }
 
+/// Return a random enabled subtask the user has not solved yet.
+///
+/// Retired subtasks the user has not started are locked and only included if
+/// `include_locked` is set. Subtasks of unlisted challenges are only included
+/// for admins.
+pub async fn query_random_subtask(
+    db: &DatabaseTransaction,
+    cache: &Cache,
+    user: &User,
+    ty: Option<ChallengesSubtaskType>,
+    include_locked: bool,
+) -> Result<Option<Subtask>, ErrorResponse> {
+    let user_subtasks = |condition: SimpleExpr| {
+        Query::select()
+            .column(challenges_user_subtasks::Column::SubtaskId)
+            .from(challenges_user_subtasks::Entity)
+            .and_where(challenges_user_subtasks::Column::UserId.eq(user.id))
+            .and_where(condition)
+            .to_owned()
+    };
+    let mut query = challenges_subtasks::Entity::find()
+        .filter(
+            challenges_subtasks::Column::Id.not_in_subquery(user_subtasks(
+                challenges_user_subtasks::Column::SolvedTimestamp.is_not_null(),
+            )),
+        )
+        .order_by(Expr::cust("random()"), Order::Asc);
+    if !user.admin {
+        query = query.filter(not_in_unlisted_challenge());
+    }
+    if !include_locked {
+        query = query.filter(
+            Condition::any()
+                .add(challenges_subtasks::Column::Retired.eq(false))
+                .add(challenges_subtasks::Column::Id.in_subquery(user_subtasks(
+                    challenges_user_subtasks::Column::LastAttemptTimestamp.is_not_null(),
+                ))),
+        );
+    }
+    let filter = QuerySubtasksFilter {
+        enabled: Some(true),
+        ty,
+        include_locked,
+        ..Default::default()
+    };
+    let Some(subtask) = prepare_query(query, &filter, user).one(db).await? else {
+        return Ok(None);
+    };
+
+    let user_subtask = get_user_subtask(db, user.id, subtask.id).await?;
+    let tags = get_subtask_tags(db, [subtask.id])
+        .await?
+        .remove(&subtask.id)
+        .unwrap_or_default();
+    let ratings = get_subtask_ratings(db, [subtask.id])
+        .await?
+        .remove(&subtask.id)
+        .unwrap_or_default();
+    let difficulties = cache
+        .cached_result(key!(), &[], Some(DIFFICULTY_CACHE_TTL), || {
+            get_subtask_difficulties(db)
+        })
+        .await??;
+    let difficulty = difficulties.get(&subtask.id).copied();
+    let mut subtask = Subtask::from(
+        subtask,
+        false,
+        user_subtask.is_rated(),
+        user_subtask.attempts(),
+        tags,
+        ratings,
+    );
+    subtask.difficulty = difficulty;
+    Ok(Some(subtask))
+}
+
 pub async fn stat_subtasks_prepare(
     db: &DatabaseTransaction,
     user: &User,
@@ -373,12 +822,36 @@ pub async fn stat_subtasks_prepare(
     let mut query = challenges_subtasks::Entity::find();
     if let Some(task_ids) = task_ids {
         query = query.filter(challenges_subtasks::Column::TaskId.is_in(task_ids));
+    } else if !user.admin {
+        query = query.filter(not_in_unlisted_challenge());
     }
     prepare_query(query, filter, user).all(db).await
 }
 
-pub fn stat_subtasks(
-    subtasks: &[challenges_subtasks::Model],
+/// Exclude subtasks of archived challenges, which are hidden from normal users.
+pub fn not_in_archived_challenge() -> SimpleExpr {
+    challenges_subtasks::Column::TaskId.not_in_subquery(
+        Query::select()
+            .column(challenges_challenges::Column::TaskId)
+            .from(challenges_challenges::Entity)
+            .and_where(challenges_challenges::Column::Archived.eq(true))
+            .to_owned(),
+    )
+}
+
+/// Exclude subtasks of unlisted challenges, which are only accessible by id.
+pub fn not_in_unlisted_challenge() -> SimpleExpr {
+    challenges_subtasks::Column::TaskId.not_in_subquery(
+        Query::select()
+            .column(challenges_challenges::Column::TaskId)
+            .from(challenges_challenges::Entity)
+            .and_where(challenges_challenges::Column::Listed.eq(false))
+            .to_owned(),
+    )
+}
+
+pub fn stat_subtasks<'a>(
+    subtasks: impl IntoIterator<Item = &'a challenges_subtasks::Model>,
     user_subtasks: &HashMap<Uuid, challenges_user_subtasks::Model>,
     filter: QuerySubtasksFilter,
 ) -> SubtaskStats {
@@ -418,7 +891,7 @@ where
     E: EntityTrait + Related<challenges_subtasks::Entity>,
 {
     let user_subtasks = get_user_subtasks(db, user.id).await?;
-    Ok(prepare_query(
+    let subtasks = prepare_query(
         E::find()
             .find_also_related(challenges_subtasks::Entity)
             .filter(challenges_subtasks::Column::TaskId.eq(task_id)),
@@ -426,13 +899,21 @@ where
         user,
     )
     .all(db)
-    .await?
-    .into_iter()
-    .filter_map(|(specific, subtask)| {
-        let subtask = subtasks_filter_map(subtask?, &filter, &user_subtasks)?;
-        Some(map(specific, subtask))
-    })
-    .collect())
+    .await?;
+    let subtask_ids = subtasks
+        .iter()
+        .filter_map(|(_, subtask)| Some(subtask.as_ref()?.id))
+        .collect::<Vec<_>>();
+    let mut tags = get_subtask_tags(db, subtask_ids.iter().copied()).await?;
+    let ratings = get_subtask_ratings(db, subtask_ids).await?;
+    Ok(subtasks
+        .into_iter()
+        .filter_map(|(specific, subtask)| {
+            let subtask =
+                subtasks_filter_map(subtask?, &filter, &user_subtasks, &mut tags, &ratings)?;
+            Some(map(specific, subtask))
+        })
+        .collect())
 }
 
 fn prepare_query<Q>(mut query: Q, filter: &QuerySubtasksFilter, user: &User) -> Q
@@ -440,12 +921,33 @@ where
     Q: QueryFilter + QueryOrder,
 {
     if !user.admin {
-        query = query.filter(
+        query = query.filter(not_in_archived_challenge()).filter(
             Condition::any()
                 .add(challenges_subtasks::Column::Creator.eq(user.id))
                 .add(challenges_subtasks::Column::Enabled.eq(true)),
         );
     }
+    if !user.admin && !filter.include_locked {
+        // retired subtasks are only visible to users who have already started them
+        query = query.filter(
+            Condition::any()
+                .add(challenges_subtasks::Column::Creator.eq(user.id))
+                .add(challenges_subtasks::Column::Retired.eq(false))
+                .add(
+                    challenges_subtasks::Column::Id.in_subquery(
+                        Query::select()
+                            .column(challenges_user_subtasks::Column::SubtaskId)
+                            .from(challenges_user_subtasks::Entity)
+                            .and_where(challenges_user_subtasks::Column::UserId.eq(user.id))
+                            .and_where(
+                                challenges_user_subtasks::Column::LastAttemptTimestamp
+                                    .is_not_null(),
+                            )
+                            .to_owned(),
+                    ),
+                ),
+        );
+    }
     if let Some(enabled) = filter.enabled {
         query = query.filter(challenges_subtasks::Column::Enabled.eq(enabled));
     }
@@ -458,9 +960,63 @@ where
     if let Some(ty) = filter.ty {
         query = query.filter(challenges_subtasks::Column::Ty.eq(ty));
     }
+    if !filter.tags.is_empty() {
+        let tags = filter.tags.iter().collect::<HashSet<_>>();
+        query = query.filter(
+            challenges_subtasks::Column::Id.in_subquery(
+                Query::select()
+                    .column(challenges_subtask_tags::Column::SubtaskId)
+                    .from(challenges_subtask_tags::Entity)
+                    .and_where(challenges_subtask_tags::Column::Tag.is_in(tags.iter().copied()))
+                    .group_by_col(challenges_subtask_tags::Column::SubtaskId)
+                    .and_having(
+                        Expr::col(challenges_subtask_tags::Column::Tag)
+                            .count()
+                            .eq(tags.len() as i64),
+                    )
+                    .to_owned(),
+            ),
+        );
+    }
     query.order_by_asc(challenges_subtasks::Column::CreationTimestamp)
 }
 
+pub fn sort_order(order: Option<SortOrder>) -> Order {
+    match order.unwrap_or_default() {
+        SortOrder::Asc => Order::Asc,
+        SortOrder::Desc => Order::Desc,
+    }
+}
+
+/// Return a subquery that counts how often the subtasks matching `condition`
+/// have been solved.
+pub fn solved_count(condition: SimpleExpr) -> SimpleExpr {
+    SimpleExpr::SubQuery(
+        None,
+        Box::new(
+            Query::select()
+                .expr(
+                    Expr::col((
+                        challenges_user_subtasks::Entity,
+                        challenges_user_subtasks::Column::UserId,
+                    ))
+                    .count(),
+                )
+                .from(challenges_user_subtasks::Entity)
+                .and_where(condition)
+                .and_where(
+                    Expr::col((
+                        challenges_user_subtasks::Entity,
+                        challenges_user_subtasks::Column::SolvedTimestamp,
+                    ))
+                    .is_not_null(),
+                )
+                .to_owned()
+                .into_sub_query_statement(),
+        ),
+    )
+}
+
 fn subtasks_filter(
     subtask: &challenges_subtasks::Model,
     filter: &QuerySubtasksFilter,
@@ -479,6 +1035,8 @@ fn subtasks_filter_map(
     subtask: challenges_subtasks::Model,
     filter: &QuerySubtasksFilter,
     user_subtasks: &HashMap<Uuid, challenges_user_subtasks::Model>,
+    tags: &mut HashMap<Uuid, Vec<String>>,
+    ratings: &HashMap<Uuid, SubtaskRatings>,
 ) -> Option<Subtask> {
     let user_subtask = user_subtasks.get(&subtask.id);
     let attempted = user_subtask.attempted();
@@ -487,7 +1045,159 @@ fn subtasks_filter_map(
     (filter.attempted.unwrap_or(attempted) == attempted
         && filter.solved.unwrap_or(solved) == solved
         && filter.rated.unwrap_or(rated) == rated)
-        .then_some(Subtask::from(subtask, solved, rated))
+        .then(|| {
+            let tags = tags.remove(&subtask.id).unwrap_or_default();
+            let ratings = ratings.get(&subtask.id).copied().unwrap_or_default();
+            Subtask::from(
+                subtask,
+                solved,
+                rated,
+                user_subtask.attempts(),
+                tags,
+                ratings,
+            )
+        })
+}
+
+/// Return the number of positive and negative ratings of the given subtasks.
+pub async fn get_subtask_ratings(
+    db: &DatabaseTransaction,
+    subtask_ids: impl IntoIterator<Item = Uuid>,
+) -> Result<HashMap<Uuid, SubtaskRatings>, DbErr> {
+    let mut out = HashMap::<_, SubtaskRatings>::new();
+    for (subtask_id, rating, count) in challenges_user_subtasks::Entity::find()
+        .select_only()
+        .column(challenges_user_subtasks::Column::SubtaskId)
+        .column(challenges_user_subtasks::Column::Rating)
+        .column_as(challenges_user_subtasks::Column::UserId.count(), "count")
+        .filter(challenges_user_subtasks::Column::SubtaskId.is_in(subtask_ids))
+        .filter(challenges_user_subtasks::Column::Rating.is_not_null())
+        .group_by(challenges_user_subtasks::Column::SubtaskId)
+        .group_by(challenges_user_subtasks::Column::Rating)
+        .into_tuple::<(Uuid, ChallengesRating, i64)>()
+        .all(db)
+        .await?
+    {
+        let ratings = out.entry(subtask_id).or_default();
+        match rating {
+            ChallengesRating::Positive => ratings.positive = count as _,
+            ChallengesRating::Negative => ratings.negative = count as _,
+            ChallengesRating::Neutral => {}
+        }
+    }
+    Ok(out)
+}
+
+/// Minimum number of users who must have attempted a subtask before its
+/// difficulty is computed.
+const MIN_DIFFICULTY_ATTEMPTS: i64 = 10;
+/// Time after which the cached difficulties of all subtasks are recomputed.
+const DIFFICULTY_CACHE_TTL: Duration = Duration::from_secs(300);
+
+/// Return the difficulties of all subtasks that have been attempted by enough
+/// users.
+async fn get_subtask_difficulties(db: &DatabaseTransaction) -> Result<HashMap<Uuid, f64>, DbErr> {
+    Ok(challenges_user_subtasks::Entity::find()
+        .select_only()
+        .column(challenges_user_subtasks::Column::SubtaskId)
+        .column_as(
+            challenges_user_subtasks::Column::UserId.count(),
+            "attempted",
+        )
+        .column_as(
+            challenges_user_subtasks::Column::SolvedTimestamp.count(),
+            "solved",
+        )
+        .filter(
+            Condition::any()
+                .add(challenges_user_subtasks::Column::LastAttemptTimestamp.is_not_null())
+                .add(challenges_user_subtasks::Column::SolvedTimestamp.is_not_null()),
+        )
+        .group_by(challenges_user_subtasks::Column::SubtaskId)
+        .having(
+            Expr::expr(challenges_user_subtasks::Column::UserId.count())
+                .gte(MIN_DIFFICULTY_ATTEMPTS),
+        )
+        .into_tuple::<(Uuid, i64, i64)>()
+        .all(db)
+        .await?
+        .into_iter()
+        .filter_map(|(subtask_id, attempted, solved)| {
+            Some((subtask_id, difficulty(attempted, solved)?))
+        })
+        .collect())
+}
+
+/// Compute the difficulty of a subtask from the number of users who attempted
+/// and solved it.
+fn difficulty(attempted: i64, solved: i64) -> Option<f64> {
+    (attempted >= MIN_DIFFICULTY_ATTEMPTS)
+        .then(|| 1.0 - solved.clamp(0, attempted) as f64 / attempted as f64)
+}
+
+/// Return the tags of the given subtasks.
+pub async fn get_subtask_tags(
+    db: &DatabaseTransaction,
+    subtask_ids: impl IntoIterator<Item = Uuid>,
+) -> Result<HashMap<Uuid, Vec<String>>, DbErr> {
+    let mut out = HashMap::<_, Vec<_>>::new();
+    for tag in challenges_subtask_tags::Entity::find()
+        .filter(challenges_subtask_tags::Column::SubtaskId.is_in(subtask_ids))
+        .order_by_asc(challenges_subtask_tags::Column::Tag)
+        .all(db)
+        .await?
+    {
+        out.entry(tag.subtask_id).or_default().push(tag.tag);
+    }
+    Ok(out)
+}
+
+/// Replace the tags of a subtask.
+pub async fn set_subtask_tags(
+    db: &DatabaseTransaction,
+    subtask_id: Uuid,
+    tags: Vec<String>,
+) -> Result<(), DbErr> {
+    challenges_subtask_tags::Entity::delete_many()
+        .filter(challenges_subtask_tags::Column::SubtaskId.eq(subtask_id))
+        .exec(db)
+        .await?;
+    if tags.is_empty() {
+        return Ok(());
+    }
+    challenges_subtask_tags::Entity::insert_many(tags.into_iter().map(|tag| {
+        challenges_subtask_tags::ActiveModel {
+            subtask_id: Set(subtask_id),
+            tag: Set(tag),
+        }
+    }))
+    .exec(db)
+    .await?;
+    Ok(())
+}
+
+async fn query_single_subtask(
+    db: &DatabaseTransaction,
+    user: &User,
+    subtask: challenges_subtasks::Model,
+) -> Result<Subtask, DbErr> {
+    let user_subtask = get_user_subtask(db, user.id, subtask.id).await?;
+    let tags = get_subtask_tags(db, [subtask.id])
+        .await?
+        .remove(&subtask.id)
+        .unwrap_or_default();
+    let ratings = get_subtask_ratings(db, [subtask.id])
+        .await?
+        .remove(&subtask.id)
+        .unwrap_or_default();
+    Ok(Subtask::from(
+        subtask,
+        user_subtask.is_solved(),
+        user_subtask.is_rated(),
+        user_subtask.attempts(),
+        tags,
+        ratings,
+    ))
 }
 
 pub async fn query_subtask<E, T>(
@@ -508,11 +1218,9 @@ where
         return Ok(None);
     }
 
-    let user_subtask = get_user_subtask(db, user.id, subtask.id).await?;
-
     Ok(Some(map(
         specific,
-        Subtask::from(subtask, user_subtask.is_solved(), user_subtask.is_rated()),
+        query_single_subtask(db, user, subtask).await?,
     )))
 }
 
@@ -535,10 +1243,9 @@ where
         return Ok(Err(QuerySubtaskAdminError::NoAccess));
     }
 
-    let user_subtask = get_user_subtask(db, user.id, subtask.id).await?;
     Ok(Ok(map(
         specific,
-        Subtask::from(subtask, user_subtask.is_solved(), user_subtask.is_rated()),
+        query_single_subtask(db, user, subtask).await?,
     )))
 }
 
@@ -569,6 +1276,39 @@ where
     )
 }
 
+/// Return the new `disabled_timestamp` of `subtask` if its `enabled` flag is
+/// set to `enabled`.
+///
+/// The timestamp is only set when an enabled subtask gets disabled and is
+/// cleared whenever the subtask is enabled again.
+pub fn disabled_timestamp(
+    subtask: &challenges_subtasks::Model,
+    enabled: bool,
+) -> Option<NaiveDateTime> {
+    match (subtask.enabled, enabled) {
+        (_, true) => None,
+        (true, false) => Some(Utc::now().naive_utc()),
+        (false, false) => subtask.disabled_timestamp,
+    }
+}
+
+/// Return the time before which submissions must have been created to remain
+/// accessible to `user` (`Some(None)` if all submissions are accessible) or
+/// `None` if no submission of the subtask is accessible.
+///
+/// Submissions of disabled subtasks that have been created before the subtask
+/// got disabled can still be viewed by their creators, e.g. to fetch the
+/// results of submissions that were still in the judge queue.
+pub fn submissions_visible_before(
+    user: &User,
+    subtask: &challenges_subtasks::Model,
+) -> Option<Option<NaiveDateTime>> {
+    if user.admin || user.id == subtask.creator || subtask.enabled {
+        return Some(None);
+    }
+    subtask.disabled_timestamp.map(Some)
+}
+
 pub async fn create_subtask(
     db: &DatabaseTransaction,
     services: &Services,
@@ -601,7 +1341,7 @@ pub async fn create_subtask(
         }
     }
 
-    match get_active_ban(db, user, ChallengesBanAction::Create).await? {
+    match get_active_ban(db, config, user, ChallengesBanAction::Create).await? {
         ActiveBan::NotBanned => {}
         ActiveBan::Temporary(end) => return Ok(Err(CreateSubtaskError::Banned(Some(end)))),
         ActiveBan::Permanent => return Ok(Err(CreateSubtaskError::Banned(None))),
@@ -617,11 +1357,21 @@ pub async fn create_subtask(
         coins: Set(coins as _),
         enabled: Set(true),
         retired: Set(false),
+        max_attempts: Set(data.max_attempts.map(|x| x as _)),
+        disabled_timestamp: Set(None),
     }
     .insert(db)
     .await?;
+    set_subtask_tags(db, subtask.id, data.tags.clone()).await?;
 
-    Ok(Ok(Subtask::from(subtask, false, false)))
+    Ok(Ok(Subtask::from(
+        subtask,
+        false,
+        false,
+        0,
+        data.tags,
+        Default::default(),
+    )))
 }
 
 pub enum CreateSubtaskError {
@@ -664,14 +1414,24 @@ where
         coins: data.coins.map(|x| x as _).update(subtask.coins),
         enabled: data.enabled.update(subtask.enabled),
         retired: data.retired.update(subtask.retired),
+        max_attempts: data
+            .max_attempts
+            .map(|x| x.map(|x| x as _))
+            .update(subtask.max_attempts),
+        disabled_timestamp: data
+            .enabled
+            .map(|enabled| disabled_timestamp(&subtask, enabled))
+            .update(subtask.disabled_timestamp),
     }
     .update(db)
     .await?;
+    if let PatchValue::Set(tags) = data.tags {
+        set_subtask_tags(db, subtask.id, tags).await?;
+    }
 
-    let user_subtask = get_user_subtask(db, user.id, subtask.id).await?;
     Ok(Ok((
         specific,
-        Subtask::from(subtask, user_subtask.is_solved(), user_subtask.is_rated()),
+        query_single_subtask(db, user, subtask).await?,
     )))
 }
 
@@ -679,3 +1439,549 @@ pub enum UpdateSubtaskError {
     SubtaskNotFound,
     TaskNotFound,
 }
+
+/// Recompute the `attempts` counters of all user subtasks from the attempt
+/// history and return the number of checked and corrected counters.
+///
+/// The attempts of coding challenges are their judged submissions. Multiple
+/// choice questions are skipped because their attempts are not recorded.
+///
+/// Counters are only ever raised: the history is incomplete for attempts made
+/// before it has been recorded (e.g. question attempts), so a counter that is
+/// higher than the recorded attempts is kept.
+pub async fn recompute_attempts(db: &DatabaseTransaction) -> Result<(u64, u64), DbErr> {
+    let checked = challenges_user_subtasks::Entity::find()
+        .inner_join(challenges_subtasks::Entity)
+        .filter(challenges_subtasks::Column::Ty.ne(ChallengesSubtaskType::MultipleChoiceQuestion))
+        .count(db)
+        .await?;
+
+    let mut corrected = 0;
+    corrected += raise_attempts(
+        db,
+        challenges_matching_attempts::Entity::find()
+            .select_only()
+            .column_as(challenges_matching_attempts::Column::UserId, "user_id")
+            .column_as(
+                challenges_matching_attempts::Column::MatchingId,
+                "subtask_id",
+            )
+            .column_as(challenges_matching_attempts::Column::Id.count(), "count")
+            .group_by(challenges_matching_attempts::Column::UserId)
+            .group_by(challenges_matching_attempts::Column::MatchingId),
+    )
+    .await?;
+    corrected += raise_attempts(
+        db,
+        challenges_question_attempts::Entity::find()
+            .select_only()
+            .column_as(challenges_question_attempts::Column::UserId, "user_id")
+            .column_as(
+                challenges_question_attempts::Column::QuestionId,
+                "subtask_id",
+            )
+            .column_as(challenges_question_attempts::Column::Id.count(), "count")
+            .group_by(challenges_question_attempts::Column::UserId)
+            .group_by(challenges_question_attempts::Column::QuestionId),
+    )
+    .await?;
+    corrected += raise_attempts(
+        db,
+        challenges_coding_challenge_submissions::Entity::find()
+            .inner_join(challenges_coding_challenge_result::Entity)
+            .select_only()
+            .column_as(
+                challenges_coding_challenge_submissions::Column::Creator,
+                "user_id",
+            )
+            .column_as(
+                challenges_coding_challenge_submissions::Column::SubtaskId,
+                "subtask_id",
+            )
+            .column_as(
+                challenges_coding_challenge_submissions::Column::Id.count(),
+                "count",
+            )
+            .group_by(challenges_coding_challenge_submissions::Column::Creator)
+            .group_by(challenges_coding_challenge_submissions::Column::SubtaskId),
+    )
+    .await?;
+
+    Ok((checked, corrected))
+}
+
+/// Raise the `attempts` counters of all user subtasks to the `count` that
+/// `counts` returns for their `user_id` and `subtask_id`, if it is higher, and
+/// return the number of raised counters.
+async fn raise_attempts<E: EntityTrait>(
+    db: &DatabaseTransaction,
+    counts: Select<E>,
+) -> Result<u64, DbErr> {
+    let (counts, values) = counts.into_query().build(PostgresQueryBuilder);
+    let sql = format!(
+        r#"UPDATE "challenges_user_subtasks" SET "attempts" = "counts"."count"
+        FROM ({counts}) AS "counts"
+        WHERE "challenges_user_subtasks"."user_id" = "counts"."user_id"
+            AND "challenges_user_subtasks"."subtask_id" = "counts"."subtask_id"
+            AND "challenges_user_subtasks"."attempts" < "counts"."count""#
+    );
+    Ok(db
+        .execute(Statement::from_sql_and_values(
+            DbBackend::Postgres,
+            sql,
+            values,
+        ))
+        .await?
+        .rows_affected())
+}
+
+/// Move a subtask to another parent task.
+///
+/// Once users have received xp for solving the subtask, it can only be moved
+/// to tasks with the same skills, so that the xp stay attributed to the skills
+/// they have been awarded for.
+pub async fn move_subtask(
+    db: &DatabaseTransaction,
+    services: &Services,
+    config: &Config,
+    user: &User,
+    task_id: Uuid,
+    subtask_id: Uuid,
+    target_task_id: Uuid,
+) -> Result<Result<Subtask, MoveSubtaskError>, ErrorResponse> {
+    let Some(subtask) = challenges_subtasks::Entity::find_by_id(subtask_id)
+        .filter(challenges_subtasks::Column::TaskId.eq(task_id))
+        .one(db)
+        .await?
+    else {
+        return Ok(Err(MoveSubtaskError::SubtaskNotFound));
+    };
+    if !(user.admin || user.id == subtask.creator) {
+        return Ok(Err(MoveSubtaskError::Forbidden));
+    }
+
+    let Some((target, target_specific)) = get_task_with_specific(db, target_task_id).await? else {
+        return Ok(Err(MoveSubtaskError::TaskNotFound));
+    };
+    if !can_create(services, config, &target_specific, user).await? {
+        return Ok(Err(MoveSubtaskError::Forbidden));
+    }
+
+    match get_active_ban(db, config, user, ChallengesBanAction::Create).await? {
+        ActiveBan::NotBanned => {}
+        ActiveBan::Temporary(end) => return Ok(Err(MoveSubtaskError::Banned(Some(end)))),
+        ActiveBan::Permanent => return Ok(Err(MoveSubtaskError::Banned(None))),
+    }
+
+    let xp_awarded = subtask.xp > 0
+        && challenges_user_subtasks::Entity::find()
+            .filter(challenges_user_subtasks::Column::SubtaskId.eq(subtask.id))
+            .filter(challenges_user_subtasks::Column::SolvedTimestamp.is_not_null())
+            .one(db)
+            .await?
+            .is_some();
+    if xp_awarded && target.id != subtask.task_id {
+        let skills = |weights: Vec<(String, u32)>| {
+            weights
+                .into_iter()
+                .map(|(skill, _)| skill)
+                .collect::<HashSet<_>>()
+        };
+        let source = match get_parent_task(db, &subtask).await? {
+            Some((_, specific)) => skills(get_skill_weights(services, specific).await?),
+            None => HashSet::new(),
+        };
+        let target = skills(get_skill_weights(services, target_specific).await?);
+        if source != target {
+            return Ok(Err(MoveSubtaskError::SkillsDiffer));
+        }
+    }
+
+    let subtask = challenges_subtasks::ActiveModel {
+        id: Unchanged(subtask.id),
+        task_id: Set(target.id),
+        ..Default::default()
+    }
+    .update(db)
+    .await?;
+
+    Ok(Ok(query_single_subtask(db, user, subtask).await?))
+}
+
+pub enum MoveSubtaskError {
+    SubtaskNotFound,
+    Forbidden,
+    Banned(Option<DateTime<Utc>>),
+    TaskNotFound,
+    SkillsDiffer,
+}
+
+#[cfg(test)]
+mod tests {
+    use entity::{challenges_questions, challenges_user_streaks};
+    use migration::{Migrator, MigratorTrait};
+    use sea_orm::{Database, DatabaseConnection, TransactionTrait};
+
+    use super::*;
+
+    fn skills(weights: &[u32]) -> Vec<(String, u32)> {
+        weights
+            .iter()
+            .enumerate()
+            .map(|(i, &weight)| (format!("skill{i}"), weight))
+            .collect()
+    }
+
+    fn xp(xp: i64, weights: &[u32]) -> Vec<i64> {
+        distribute_xp(xp, &skills(weights))
+            .into_iter()
+            .map(|(_, xp)| xp)
+            .collect()
+    }
+
+    #[test]
+    fn distribute_xp_equally() {
+        assert_eq!(xp(9, &[1, 1, 1]), [3, 3, 3]);
+        assert_eq!(xp(10, &[1, 1, 1]), [4, 3, 3]);
+        assert_eq!(xp(2, &[1, 1, 1]), [2, 0, 0]);
+        assert_eq!(xp(10, &[0, 0]), [5, 5]);
+        assert_eq!(xp(10, &[]), Vec::<i64>::new());
+    }
+
+    #[test]
+    fn distribute_xp_weighted() {
+        assert_eq!(xp(100, &[3, 1]), [75, 25]);
+        assert_eq!(xp(10, &[1, 2]), [4, 6]);
+        assert_eq!(xp(10, &[2, 1, 0]), [7, 3, 0]);
+        assert_eq!(xp(7, &[1, 1, 5]), [1, 1, 5]);
+        assert_eq!(xp(8, &[1, 1, 5]), [2, 1, 5]);
+        for weights in [&[1, 2, 3][..], &[5, 7], &[1, 1, 1, 1, 1, 1, 1, 1]] {
+            assert_eq!(xp(101, weights).iter().sum::<i64>(), 101);
+        }
+    }
+
+    /// Return a normal user and an enabled subtask of type `ty` created by
+    /// someone else.
+    fn user_and_subtask(ty: ChallengesSubtaskType) -> (User, challenges_subtasks::Model) {
+        let user = User {
+            id: Uuid::from_u128(1),
+            email_verified: true,
+            admin: false,
+        };
+        let subtask = challenges_subtasks::Model {
+            id: Uuid::from_u128(2),
+            task_id: Uuid::from_u128(3),
+            creator: Uuid::from_u128(4),
+            creation_timestamp: Default::default(),
+            xp: 0,
+            coins: 0,
+            enabled: true,
+            ty,
+            retired: false,
+            max_attempts: None,
+            disabled_timestamp: None,
+        };
+        (user, subtask)
+    }
+
+    #[test]
+    fn attempts_exhausted_at_limit() {
+        let (user, subtask) = user_and_subtask(ChallengesSubtaskType::Question);
+        let subtask = challenges_subtasks::Model {
+            max_attempts: Some(3),
+            ..subtask
+        };
+        let user_subtask = |attempts, solved: bool| challenges_user_subtasks::Model {
+            user_id: user.id,
+            subtask_id: subtask.id,
+            solved_timestamp: solved.then(Default::default),
+            rating: None,
+            rating_timestamp: None,
+            last_attempt_timestamp: Some(Default::default()),
+            attempts,
+        };
+
+        assert!(!attempts_exhausted(
+            &user,
+            &subtask,
+            None::<challenges_user_subtasks::Model>
+        ));
+        assert!(!attempts_exhausted(&user, &subtask, user_subtask(2, false)));
+        assert!(attempts_exhausted(&user, &subtask, user_subtask(3, false)));
+        assert!(attempts_exhausted(&user, &subtask, user_subtask(4, false)));
+        // pending attempts count towards the limit
+        assert!(!attempts_exhausted_with_pending(
+            &user,
+            &subtask,
+            user_subtask(1, false),
+            1
+        ));
+        assert!(attempts_exhausted_with_pending(
+            &user,
+            &subtask,
+            user_subtask(2, false),
+            1
+        ));
+        // solved subtasks can still be attempted
+        assert!(!attempts_exhausted(&user, &subtask, user_subtask(3, true)));
+        // admins and creators are exempt
+        let admin = User {
+            admin: true,
+            ..user
+        };
+        assert!(!attempts_exhausted(
+            &admin,
+            &subtask,
+            user_subtask(3, false)
+        ));
+        let creator = User {
+            id: subtask.creator,
+            ..user
+        };
+        assert!(!attempts_exhausted(
+            &creator,
+            &subtask,
+            user_subtask(3, false)
+        ));
+        // unlimited attempts
+        let unlimited = challenges_subtasks::Model {
+            max_attempts: None,
+            ..subtask.clone()
+        };
+        assert!(!attempts_exhausted(
+            &user,
+            &unlimited,
+            user_subtask(100, false)
+        ));
+    }
+
+    fn solve(user_id: Uuid, subtask_id: Uuid) -> challenges_user_subtasks::ActiveModel {
+        let now = Utc::now().naive_utc();
+        challenges_user_subtasks::ActiveModel {
+            user_id: Set(user_id),
+            subtask_id: Set(subtask_id),
+            solved_timestamp: Set(Some(now)),
+            last_attempt_timestamp: Set(Some(now)),
+            attempts: Set(1),
+            ..Default::default()
+        }
+    }
+
+    /// Connect to the database in `TEST_DATABASE_URL` and run the migrations,
+    /// or return `None` to skip the test if the variable is not set.
+    async fn test_db() -> Option<DatabaseConnection> {
+        let Ok(url) = std::env::var("TEST_DATABASE_URL") else {
+            eprintln!("TEST_DATABASE_URL is not set, skipping test");
+            return None;
+        };
+        let db = Database::connect(url).await.unwrap();
+        Migrator::up(&db, None).await.unwrap();
+        Some(db)
+    }
+
+    async fn create_subtask(db: &DatabaseConnection) -> challenges_subtasks::Model {
+        let now = Utc::now().naive_utc();
+        let task = challenges_tasks::ActiveModel {
+            id: Set(Uuid::new_v4()),
+            creator: Set(Uuid::new_v4()),
+            creation_timestamp: Set(now),
+        }
+        .insert(db)
+        .await
+        .unwrap();
+        challenges_subtasks::ActiveModel {
+            id: Set(Uuid::new_v4()),
+            task_id: Set(task.id),
+            creator: Set(task.creator),
+            creation_timestamp: Set(now),
+            xp: Set(0),
+            coins: Set(0),
+            enabled: Set(true),
+            ty: Set(ChallengesSubtaskType::Question),
+            retired: Set(false),
+            max_attempts: Set(None),
+            disabled_timestamp: Set(None),
+        }
+        .insert(db)
+        .await
+        .unwrap()
+    }
+
+    /// Solve a subtask in two transactions at the same time and return the
+    /// results of both.
+    async fn solve_concurrently(
+        db: &DatabaseConnection,
+        user_subtask: Option<challenges_user_subtasks::Model>,
+        user_id: Uuid,
+        subtask_id: Uuid,
+    ) -> [Option<challenges_user_subtasks::Model>; 2] {
+        let first = db.begin().await.unwrap();
+        let second = db.begin().await.unwrap();
+        let solved_first =
+            update_user_subtask(&first, user_subtask.as_ref(), solve(user_id, subtask_id))
+                .await
+                .unwrap();
+        // blocks on the row written by the first transaction until it commits
+        let solve_second = tokio::spawn(async move {
+            let solved =
+                update_user_subtask(&second, user_subtask.as_ref(), solve(user_id, subtask_id))
+                    .await
+                    .unwrap();
+            second.commit().await.unwrap();
+            solved
+        });
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        first.commit().await.unwrap();
+        [solved_first, solve_second.await.unwrap()]
+    }
+
+    #[tokio::test]
+    async fn concurrent_solves_are_recorded_once() {
+        let Some(db) = test_db().await else {
+            return;
+        };
+        let subtask = create_subtask(&db).await;
+        let user_id = Uuid::new_v4();
+
+        // no user subtask yet: both transactions insert
+        let [first, second] = solve_concurrently(&db, None, user_id, subtask.id).await;
+        assert!(first.is_some());
+        assert_eq!(second, None);
+
+        // unsolved user subtask: both transactions update
+        let unsolved = challenges_user_subtasks::ActiveModel {
+            user_id: Set(user_id),
+            subtask_id: Set(subtask.id),
+            solved_timestamp: Set(None),
+            ..Default::default()
+        }
+        .update(&db)
+        .await
+        .unwrap();
+        let [first, second] = solve_concurrently(&db, Some(unsolved), user_id, subtask.id).await;
+        assert!(first.is_some());
+        assert_eq!(second, None);
+
+        challenges_tasks::Entity::delete_by_id(subtask.task_id)
+            .exec(&db)
+            .await
+            .unwrap();
+        challenges_user_streaks::Entity::delete_by_id(user_id)
+            .exec(&db)
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn recompute_attempts_only_raises() {
+        let Some(db) = test_db().await else {
+            return;
+        };
+        let subtask = create_subtask(&db).await;
+        challenges_questions::ActiveModel {
+            subtask_id: Set(subtask.id),
+            question: Set("question".into()),
+            answers: Set(vec!["answer".into()]),
+            case_sensitive: Set(false),
+            ascii_letters: Set(true),
+            digits: Set(true),
+            punctuation: Set(true),
+            blocks: Set(vec![]),
+            match_regex: Set(false),
+        }
+        .insert(&db)
+        .await
+        .unwrap();
+
+        let users = [(Uuid::new_v4(), 1), (Uuid::new_v4(), 5)];
+        for (user_id, attempts) in users {
+            challenges_user_subtasks::ActiveModel {
+                user_id: Set(user_id),
+                subtask_id: Set(subtask.id),
+                attempts: Set(attempts),
+                ..Default::default()
+            }
+            .insert(&db)
+            .await
+            .unwrap();
+            for _ in 0..3 {
+                challenges_question_attempts::ActiveModel {
+                    id: Set(Uuid::new_v4()),
+                    question_id: Set(subtask.id),
+                    user_id: Set(user_id),
+                    timestamp: Set(Utc::now().naive_utc()),
+                    solved: Set(false),
+                    given_answer: Set("wrong".into()),
+                }
+                .insert(&db)
+                .await
+                .unwrap();
+            }
+        }
+
+        let txn = db.begin().await.unwrap();
+        let (checked, corrected) = recompute_attempts(&txn).await.unwrap();
+        txn.commit().await.unwrap();
+        assert!(checked >= 2);
+        assert!(corrected >= 1);
+
+        let attempts = |user_id| {
+            let db = &db;
+            async move {
+                challenges_user_subtasks::Entity::find_by_id((user_id, subtask.id))
+                    .one(db)
+                    .await
+                    .unwrap()
+                    .unwrap()
+                    .attempts
+            }
+        };
+        // the missing attempt is counted, but recorded attempts are never lost
+        assert_eq!(attempts(users[0].0).await, 3);
+        assert_eq!(attempts(users[1].0).await, 5);
+
+        challenges_tasks::Entity::delete_by_id(subtask.task_id)
+            .exec(&db)
+            .await
+            .unwrap();
+    }
+
+    #[test]
+    fn submissions_of_disabled_subtasks() {
+        let (user, subtask) = user_and_subtask(ChallengesSubtaskType::CodingChallenge);
+        assert_eq!(submissions_visible_before(&user, &subtask), Some(None));
+
+        let disabled_at = disabled_timestamp(&subtask, false).unwrap();
+        let disabled = challenges_subtasks::Model {
+            enabled: false,
+            disabled_timestamp: Some(disabled_at),
+            ..subtask.clone()
+        };
+        assert_eq!(
+            submissions_visible_before(&user, &disabled),
+            Some(Some(disabled_at))
+        );
+        // disabling a subtask again keeps the original timestamp
+        assert_eq!(disabled_timestamp(&disabled, false), Some(disabled_at));
+        assert_eq!(disabled_timestamp(&disabled, true), None);
+
+        let legacy = challenges_subtasks::Model {
+            disabled_timestamp: None,
+            ..disabled.clone()
+        };
+        assert_eq!(submissions_visible_before(&user, &legacy), None);
+        let admin = User {
+            admin: true,
+            ..user
+        };
+        assert_eq!(submissions_visible_before(&admin, &legacy), Some(None));
+    }
+
+    #[test]
+    fn subtask_difficulty() {
+        assert_eq!(difficulty(9, 0), None);
+        assert_eq!(difficulty(10, 10), Some(0.0));
+        assert_eq!(difficulty(10, 0), Some(1.0));
+        assert_eq!(difficulty(20, 15), Some(0.25));
+    }
+}
@@ -0,0 +1,108 @@
+use std::time::Duration;
+
+use entity::sea_orm_active_enums::ChallengesVerdict;
+use hmac::Mac;
+use lib::jwt::JwtSecret;
+use serde::Serialize;
+use tracing::{debug, warn};
+use url::Url;
+use uuid::Uuid;
+
+/// Name of the header that contains the signature of the request body.
+pub const SIGNATURE_HEADER: &str = "X-Signature-256";
+/// Maximum number of times a notification is sent.
+const ATTEMPTS: u32 = 3;
+/// Time to wait before the first retry. Doubled after every failed attempt.
+const BACKOFF: Duration = Duration::from_secs(1);
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Sends signed notifications to an external URL.
+#[derive(Debug, Clone)]
+pub struct Webhook {
+    client: reqwest::Client,
+    url: Url,
+    secret: JwtSecret,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SubmissionJudged {
+    pub submission_id: Uuid,
+    pub subtask_id: Uuid,
+    pub creator: Uuid,
+    pub verdict: ChallengesVerdict,
+}
+
+impl Webhook {
+    pub fn new(url: Url, secret: JwtSecret) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            url,
+            secret,
+        }
+    }
+
+    /// Send a notification in the background. Failed requests are retried a
+    /// few times before the notification is dropped.
+    pub fn send(&self, payload: &impl Serialize) {
+        let body = match serde_json::to_vec(payload) {
+            Ok(body) => body,
+            Err(err) => {
+                warn!("failed to serialize webhook payload: {err}");
+                return;
+            }
+        };
+        let webhook = self.clone();
+        tokio::spawn(async move {
+            let mut backoff = BACKOFF;
+            for attempt in 1..=ATTEMPTS {
+                match webhook.post(&body).await {
+                    Ok(()) => return,
+                    Err(err) if attempt < ATTEMPTS => {
+                        debug!("webhook request failed (attempt {attempt}/{ATTEMPTS}): {err}");
+                        tokio::time::sleep(backoff).await;
+                        backoff *= 2;
+                    }
+                    Err(err) => warn!("webhook request failed after {ATTEMPTS} attempts: {err}"),
+                }
+            }
+        });
+    }
+
+    async fn post(&self, body: &[u8]) -> reqwest::Result<()> {
+        self.client
+            .post(self.url.clone())
+            .timeout(TIMEOUT)
+            .header("Content-Type", "application/json")
+            .header(
+                SIGNATURE_HEADER,
+                format!("sha256={}", sign(&self.secret, body)),
+            )
+            .body(body.to_vec())
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+}
+
+/// Return the hex encoded HMAC-SHA256 of `body`.
+fn sign(secret: &JwtSecret, body: &[u8]) -> String {
+    let mut mac = secret.0.clone();
+    mac.update(body);
+    format!("{:x}", mac.finalize().into_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sign_body() {
+        // RFC 4231, test case 2
+        let secret = JwtSecret::try_from("Jefe").unwrap();
+        assert_eq!(
+            sign(&secret, b"what do ya want for nothing?"),
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+    }
+}
@@ -9,10 +9,15 @@ pub struct Model {
     pub task_id: Uuid,
     pub category_id: Uuid,
     pub skill_ids: Vec<String>,
+    pub skill_weights: Option<Vec<i32>>,
     #[sea_orm(column_type = "Text")]
     pub title: String,
     #[sea_orm(column_type = "Text")]
     pub description: String,
+    pub archived: bool,
+    pub position: i32,
+    pub updated_at: DateTime,
+    pub listed: bool,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
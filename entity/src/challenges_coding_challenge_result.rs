@@ -2,7 +2,7 @@
 
 use sea_orm::entity::prelude::*;
 
-use super::sea_orm_active_enums::ChallengesVerdict;
+use super::sea_orm_active_enums::{ChallengesVerdict, ChallengesVerdictReason};
 
 #[derive(Clone, Debug, PartialEq, DeriveEntityModel, Eq)]
 #[sea_orm(table_name = "challenges_coding_challenge_result")]
@@ -12,6 +12,7 @@ pub struct Model {
     pub verdict: ChallengesVerdict,
     #[sea_orm(column_type = "Text", nullable)]
     pub reason: Option<String>,
+    pub reason_code: Option<ChallengesVerdictReason>,
     pub build_status: Option<i32>,
     #[sea_orm(column_type = "Text", nullable)]
     pub build_stderr: Option<String>,
@@ -22,6 +23,7 @@ pub struct Model {
     pub run_stderr: Option<String>,
     pub run_time: Option<i32>,
     pub run_memory: Option<i32>,
+    pub test_seed: Option<i64>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
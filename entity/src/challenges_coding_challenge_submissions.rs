@@ -14,6 +14,9 @@ pub struct Model {
     pub environment: String,
     #[sea_orm(column_type = "Text")]
     pub code: String,
+    #[sea_orm(column_type = "Text", nullable)]
+    pub idempotency_key: Option<String>,
+    pub rejudge: bool,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
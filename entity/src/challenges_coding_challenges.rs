@@ -19,10 +19,16 @@ pub struct Model {
     pub solution_code: String,
     pub static_tests: i32,
     pub random_tests: i32,
+    pub test_seed: Option<i64>,
+    pub reveal_solution_on_solve: bool,
+    #[sea_orm(column_type = "Text")]
+    pub evaluator_environment: String,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
 pub enum Relation {
+    #[sea_orm(has_many = "super::challenges_coding_challenge_solutions::Entity")]
+    ChallengesCodingChallengeSolutions,
     #[sea_orm(has_many = "super::challenges_coding_challenge_submissions::Entity")]
     ChallengesCodingChallengeSubmissions,
     #[sea_orm(
@@ -35,6 +41,12 @@ pub enum Relation {
     ChallengesSubtasks,
 }
 
+impl Related<super::challenges_coding_challenge_solutions::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::ChallengesCodingChallengeSolutions.def()
+    }
+}
+
 impl Related<super::challenges_coding_challenge_submissions::Entity> for Entity {
     fn to() -> RelationDef {
         Relation::ChallengesCodingChallengeSubmissions.def()
@@ -12,6 +12,7 @@ pub struct Model {
     pub answers: Vec<String>,
     pub correct_answers: i64,
     pub single_choice: bool,
+    pub shuffle: bool,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
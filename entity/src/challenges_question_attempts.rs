@@ -11,6 +11,8 @@ pub struct Model {
     pub user_id: Uuid,
     pub timestamp: DateTime,
     pub solved: bool,
+    #[sea_orm(column_type = "Text")]
+    pub given_answer: String,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
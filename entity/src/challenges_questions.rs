@@ -15,6 +15,7 @@ pub struct Model {
     pub digits: bool,
     pub punctuation: bool,
     pub blocks: Vec<String>,
+    pub match_regex: bool,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
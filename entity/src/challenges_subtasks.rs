@@ -17,6 +17,8 @@ pub struct Model {
     pub enabled: bool,
     pub ty: ChallengesSubtaskType,
     pub retired: bool,
+    pub max_attempts: Option<i32>,
+    pub disabled_timestamp: Option<DateTime>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
@@ -31,6 +33,8 @@ pub enum Relation {
     ChallengesQuestions,
     #[sea_orm(has_many = "super::challenges_subtask_reports::Entity")]
     ChallengesSubtaskReports,
+    #[sea_orm(has_many = "super::challenges_subtask_tags::Entity")]
+    ChallengesSubtaskTags,
     #[sea_orm(
         belongs_to = "super::challenges_tasks::Entity",
         from = "Column::TaskId",
@@ -73,6 +77,12 @@ impl Related<super::challenges_subtask_reports::Entity> for Entity {
     }
 }
 
+impl Related<super::challenges_subtask_tags::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::ChallengesSubtaskTags.def()
+    }
+}
+
 impl Related<super::challenges_tasks::Entity> for Entity {
     fn to() -> RelationDef {
         Relation::ChallengesTasks.def()
@@ -6,6 +6,7 @@ pub mod challenges_ban;
 pub mod challenges_challenge_categories;
 pub mod challenges_challenges;
 pub mod challenges_coding_challenge_result;
+pub mod challenges_coding_challenge_solutions;
 pub mod challenges_coding_challenge_submissions;
 pub mod challenges_coding_challenges;
 pub mod challenges_course_tasks;
@@ -16,7 +17,9 @@ pub mod challenges_multiple_choice_quizes;
 pub mod challenges_question_attempts;
 pub mod challenges_questions;
 pub mod challenges_subtask_reports;
+pub mod challenges_subtask_tags;
 pub mod challenges_subtasks;
 pub mod challenges_tasks;
+pub mod challenges_user_streaks;
 pub mod challenges_user_subtasks;
 pub mod sea_orm_active_enums;
@@ -5,6 +5,7 @@ pub use super::{
     challenges_challenge_categories::Entity as ChallengesChallengeCategories,
     challenges_challenges::Entity as ChallengesChallenges,
     challenges_coding_challenge_result::Entity as ChallengesCodingChallengeResult,
+    challenges_coding_challenge_solutions::Entity as ChallengesCodingChallengeSolutions,
     challenges_coding_challenge_submissions::Entity as ChallengesCodingChallengeSubmissions,
     challenges_coding_challenges::Entity as ChallengesCodingChallenges,
     challenges_course_tasks::Entity as ChallengesCourseTasks,
@@ -15,6 +16,8 @@ pub use super::{
     challenges_question_attempts::Entity as ChallengesQuestionAttempts,
     challenges_questions::Entity as ChallengesQuestions,
     challenges_subtask_reports::Entity as ChallengesSubtaskReports,
+    challenges_subtask_tags::Entity as ChallengesSubtaskTags,
     challenges_subtasks::Entity as ChallengesSubtasks, challenges_tasks::Entity as ChallengesTasks,
+    challenges_user_streaks::Entity as ChallengesUserStreaks,
     challenges_user_subtasks::Entity as ChallengesUserSubtasks,
 };
@@ -145,3 +145,30 @@ pub enum ChallengesVerdict {
     #[sea_orm(string_value = "wrong_answer")]
     WrongAnswer,
 }
+#[derive(
+    Debug,
+    Clone,
+    PartialEq,
+    Eq,
+    EnumIter,
+    DeriveActiveEnum,
+    Copy,
+    poem_openapi::Enum,
+    serde::Serialize,
+    serde::Deserialize,
+)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+#[oai(rename_all = "SCREAMING_SNAKE_CASE")]
+#[sea_orm(
+    rs_type = "String",
+    db_type = "Enum",
+    enum_name = "challenges_verdict_reason"
+)]
+pub enum ChallengesVerdictReason {
+    #[sea_orm(string_value = "crashed")]
+    Crashed,
+    #[sea_orm(string_value = "partial_output")]
+    PartialOutput,
+    #[sea_orm(string_value = "wrong_output")]
+    WrongOutput,
+}
@@ -1,5 +1,13 @@
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    num::{NonZeroU32, NonZeroU64},
+    time::Duration,
+};
+
 use serde::Deserialize;
 use url::Url;
+use uuid::Uuid;
 
 use super::Sentry;
 
@@ -8,7 +16,18 @@ pub struct ChallengesConfig {
     pub host: String,
     pub port: u16,
     pub server: String,
+    /// Overrides the global sentry config for this microservice.
     pub sentry: Option<Sentry>,
+    /// Requests are not rate limited if this section is omitted.
+    pub rate_limit: Option<RateLimit>,
+    /// Users (e.g. CI pipelines) that are exempt from bans, attempt timeouts,
+    /// the judge queue limit and heart deductions. These accounts can solve
+    /// subtasks without any limits and create subtasks even if they are
+    /// banned, so their credentials must be protected like those of an admin.
+    #[serde(default)]
+    pub service_accounts: Vec<Uuid>,
+    /// User submitted questions are not moderated if this section is omitted.
+    pub moderation: Option<Moderation>,
     pub quizzes: Quizzes, // course tasks
     pub multiple_choice_questions: MultipleChoiceQuestions,
     pub questions: Questions,
@@ -16,12 +35,69 @@ pub struct ChallengesConfig {
     pub coding_challenges: CodingChallenges,
 }
 
+#[derive(Debug, Clone, Deserialize)]
+pub struct RateLimit {
+    /// Limit for all requests that do not belong to one of the `routes`.
+    pub default: RateLimitBucket,
+    /// Limits for specific routes, keyed by the request method and the last
+    /// segment of the path (e.g. `POST attempts` or `POST submissions`).
+    #[serde(default)]
+    pub routes: HashMap<String, RateLimitBucket>,
+    /// Addresses of reverse proxies whose `X-Forwarded-For` header is used to
+    /// identify unauthenticated clients.
+    #[serde(default)]
+    pub trusted_proxies: Vec<IpAddr>,
+}
+
+/// A token bucket that holds up to `capacity` requests and is refilled
+/// completely within `period` seconds.
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub struct RateLimitBucket {
+    pub capacity: NonZeroU32,
+    pub period: NonZeroU64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Moderation {
+    /// Endpoint that decides whether user submitted content is acceptable.
+    pub url: Url,
+    /// Number of seconds to wait for a response of the moderation endpoint.
+    pub timeout: u64,
+    /// Whether to accept content if the moderation endpoint cannot be
+    /// reached or fails.
+    pub fail_open: bool,
+}
+
 #[derive(Debug, Deserialize)]
 pub struct Quizzes {
     pub min_level: u32,
     pub max_xp: u64,
     pub max_coins: u64,
+    /// Durations (in days) of consecutive bans of a user for the same action.
     pub ban_days: Vec<u32>,
+    /// Whether bans after the last entry of `ban_days` are permanent. If
+    /// `false`, the last entry is used for all further bans instead.
+    #[serde(default = "default_true")]
+    pub permanent_after_last: bool,
+    /// The minimum number of dislikes after which a subtask is reported
+    /// automatically.
+    pub dislike_report_min: u32,
+    /// Only report a subtask automatically if it has more dislikes than likes.
+    pub dislike_report_majority: bool,
+}
+
+impl Default for Quizzes {
+    fn default() -> Self {
+        Self {
+            min_level: 0,
+            max_xp: 0,
+            max_coins: 0,
+            ban_days: Vec::new(),
+            permanent_after_last: default_true(),
+            dislike_report_min: 0,
+            dislike_report_majority: false,
+        }
+    }
 }
 
 #[derive(Debug, Deserialize)]
@@ -49,7 +125,84 @@ pub struct Matchings {
 pub struct CodingChallenges {
     pub sandkasten_url: Url,
     pub max_concurrency: usize,
+    /// Maximum number of programs (evaluators and solutions) that are run in
+    /// the sandbox at the same time.
+    pub max_sandkasten_concurrency: usize,
+    /// Minimum number of seconds between two submissions of a user for the
+    /// same coding challenge, counted from the later of the previous
+    /// submission and its judgement. Admins and the creator of the challenge
+    /// are exempt.
     pub timeout: u64,
+    /// Maximum number of submissions of a user that may be in the judge queue
+    /// at the same time.
+    pub max_submissions_per_user: usize,
+    pub evaluator_timeout: u64,
+    /// Maximum number of test cases (examples, static and random tests) that
+    /// are run to evaluate a single solution.
+    pub max_testcases: usize,
     pub hearts: u32,
     pub creator_coins: u32,
+    pub warm_evaluator_cache: bool,
+    /// Maximum number of seconds to wait for pending submissions on shutdown.
+    pub shutdown_timeout: u64,
+    /// Number of seconds after which a submission without a result that is not
+    /// in the judge queue is considered stuck.
+    pub stuck_threshold: u64,
+    pub sandkasten_retry: SandkastenRetry,
+    /// Number of seconds to cache evaluator results. Defaults to `cache_ttl`.
+    pub evaluator_cache_ttl: Option<u64>,
+    /// Number of seconds to cache the list of environments. Defaults to
+    /// `cache_ttl`.
+    pub environments_cache_ttl: Option<u64>,
+    /// URL to notify whenever a submission has been judged.
+    pub webhook_url: Option<Url>,
+    /// Length limits for coding challenges created by normal users.
+    pub user_content_limits: ContentLimits,
+    /// Length limits for coding challenges created by admins.
+    pub admin_content_limits: ContentLimits,
+}
+
+impl ChallengesConfig {
+    pub fn is_service_account(&self, user_id: Uuid) -> bool {
+        self.service_accounts.contains(&user_id)
+    }
+}
+
+impl CodingChallenges {
+    pub fn evaluator_cache_ttl(&self) -> Option<Duration> {
+        self.evaluator_cache_ttl.map(Duration::from_secs)
+    }
+
+    pub fn environments_cache_ttl(&self) -> Option<Duration> {
+        self.environments_cache_ttl.map(Duration::from_secs)
+    }
+
+    pub fn content_limits(&self, admin: bool) -> ContentLimits {
+        match admin {
+            true => self.admin_content_limits,
+            false => self.user_content_limits,
+        }
+    }
+}
+
+/// Maximum number of characters of evaluators and descriptions of coding
+/// challenges. The `max_length` validators of the request schemas are an upper
+/// bound for these limits.
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub struct ContentLimits {
+    pub evaluator: usize,
+    pub description: usize,
+}
+
+/// Retry policy for transient sandkasten errors (connection failures, 5xx).
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub struct SandkastenRetry {
+    /// Maximum number of attempts (including the first one).
+    pub attempts: u32,
+    /// Delay (in milliseconds) before the first retry.
+    pub backoff: u64,
+}
+
+fn default_true() -> bool {
+    true
 }
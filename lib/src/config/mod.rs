@@ -1,10 +1,13 @@
-use std::env;
+use std::{collections::HashMap, env};
 
 use config::{ConfigError, Environment, File};
 use serde::{de::DeserializeOwned, Deserialize};
 use url::Url;
 
 use self::challenges::ChallengesConfig;
+pub use self::challenges::{
+    ContentLimits, Moderation, Quizzes, RateLimit, RateLimitBucket, SandkastenRetry,
+};
 
 mod challenges;
 
@@ -33,6 +36,12 @@ pub struct Config {
     pub database: Database,
     pub redis: Redis,
     pub services: Services,
+    /// Error reporting for all microservices. Reporting is disabled if this
+    /// section is omitted.
+    pub sentry: Option<Sentry>,
+    /// Export of traces to an OpenTelemetry collector. Exporting is disabled
+    /// if this section is omitted.
+    pub otlp: Option<Otlp>,
     pub challenges: ChallengesConfig,
 }
 
@@ -65,9 +74,32 @@ pub struct Services {
     pub jobs: Url,
     pub events: Url,
     pub challenges: Url,
+    pub circuit_breaker: CircuitBreakerConfig,
+    /// Audiences of the internal auth tokens sent to other services, keyed by
+    /// service name. Defaults to the name of the service.
+    #[serde(default)]
+    pub audiences: HashMap<String, String>,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub struct CircuitBreakerConfig {
+    /// Number of consecutive failed requests after which no more requests are
+    /// sent to a service.
+    pub failure_threshold: u32,
+    /// Time window (in seconds) in which the failures have to occur.
+    pub window: u64,
+    /// Time (in seconds) to wait before trying to send a request again.
+    pub cooldown: u64,
 }
 
 #[derive(Debug, Deserialize)]
 pub struct Sentry {
     pub dsn: Url,
 }
+
+#[derive(Debug, Deserialize)]
+pub struct Otlp {
+    /// URL of the OTLP/HTTP traces endpoint of the collector, e.g.
+    /// `http://localhost:4318/v1/traces`.
+    pub endpoint: Url,
+}
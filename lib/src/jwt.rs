@@ -44,9 +44,14 @@ pub struct UserAccessTokenData {
     pub admin: bool,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct InternalAuthToken {
+    /// The service the token is intended for.
     pub aud: Cow<'static, str>,
+    /// The service that has issued the token.
+    pub iss: Cow<'static, str>,
+    /// Unix timestamp of when the token has been issued.
+    pub iat: u64,
 }
 
 pub fn sign_jwt(
@@ -2,6 +2,7 @@
 #![warn(clippy::dbg_macro, clippy::use_debug, clippy::todo)]
 
 use fnct::{backend::AsyncRedisBackend, format::PostcardFormatter, AsyncCache};
+use metrics::Metrics;
 use sea_orm::DatabaseConnection;
 use services::Services;
 
@@ -10,6 +11,7 @@ use crate::{jwt::JwtSecret, redis::RedisConnection};
 pub mod auth;
 pub mod config;
 pub mod jwt;
+pub mod metrics;
 pub mod redis;
 pub mod services;
 
@@ -23,4 +25,5 @@ pub struct SharedState {
     pub services: Services,
     pub cache: Cache,
     pub db: DatabaseConnection,
+    pub metrics: Metrics,
 }
@@ -0,0 +1,85 @@
+use std::sync::Arc;
+
+use poem::{handler, http::StatusCode, web::Data, IntoResponse, Response};
+use prometheus::{
+    Encoder, Histogram, HistogramOpts, IntCounterVec, IntGauge, Opts, Registry, TextEncoder,
+};
+use tracing::error;
+
+use crate::SharedState;
+
+/// Prometheus metrics exposed on `/metrics`.
+#[derive(Debug, Clone)]
+pub struct Metrics {
+    registry: Registry,
+    /// Number of submissions that can be judged concurrently.
+    pub judge_workers: IntGauge,
+    /// Number of submissions that are currently being judged.
+    pub judge_active: IntGauge,
+    /// Number of submissions waiting in the judge queue.
+    pub judge_waiting: IntGauge,
+    /// Number of judged submissions by verdict.
+    pub submission_verdicts: IntCounterVec,
+    /// Time (in seconds) it took to judge a submission.
+    pub submission_judge_time: Histogram,
+}
+
+impl Metrics {
+    pub fn new() -> Result<Self, prometheus::Error> {
+        let registry = Registry::new_custom(Some("challenges".into()), None)?;
+
+        let judge_workers = IntGauge::new("judge_workers", "Number of judge workers")?;
+        let judge_active = IntGauge::new(
+            "judge_active",
+            "Number of submissions currently being judged",
+        )?;
+        let judge_waiting = IntGauge::new(
+            "judge_waiting",
+            "Number of submissions waiting in the judge queue",
+        )?;
+        let submission_verdicts = IntCounterVec::new(
+            Opts::new("submission_verdicts_total", "Number of judged submissions"),
+            &["verdict"],
+        )?;
+        let submission_judge_time = Histogram::with_opts(
+            HistogramOpts::new(
+                "submission_judge_seconds",
+                "Time it took to judge a submission",
+            )
+            .buckets(vec![0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0]),
+        )?;
+
+        registry.register(Box::new(judge_workers.clone()))?;
+        registry.register(Box::new(judge_active.clone()))?;
+        registry.register(Box::new(judge_waiting.clone()))?;
+        registry.register(Box::new(submission_verdicts.clone()))?;
+        registry.register(Box::new(submission_judge_time.clone()))?;
+
+        Ok(Self {
+            registry,
+            judge_workers,
+            judge_active,
+            judge_waiting,
+            submission_verdicts,
+            submission_judge_time,
+        })
+    }
+
+    /// Encode all metrics in the prometheus text format.
+    pub fn encode(&self) -> Result<String, prometheus::Error> {
+        let mut buffer = Vec::new();
+        TextEncoder::new().encode(&self.registry.gather(), &mut buffer)?;
+        Ok(String::from_utf8_lossy(&buffer).into_owned())
+    }
+}
+
+#[handler]
+pub fn metrics_endpoint(state: Data<&Arc<SharedState>>) -> Response {
+    match state.metrics.encode() {
+        Ok(metrics) => metrics.into_response(),
+        Err(err) => {
+            error!("failed to encode metrics: {err}");
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
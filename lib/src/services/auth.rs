@@ -3,7 +3,7 @@ use reqwest::StatusCode;
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
-use super::{Service, ServiceResult};
+use super::{Service, ServiceError, ServiceResult};
 
 #[derive(Debug, Clone)]
 pub struct AuthService(Service);
@@ -14,10 +14,9 @@ impl AuthService {
     }
 
     pub async fn get_user_by_id(&self, id: Uuid) -> ServiceResult<Option<User>> {
-        Ok(self
-            .0
+        self.0
             .cache
-            .cached_result(key!(id), &[], None, || async {
+            .cached_result::<_, ServiceError, _, _>(key!(id), &[], None, || async {
                 match self
                     .0
                     .get(&format!("/users/{id}"))
@@ -27,10 +26,10 @@ impl AuthService {
                 {
                     Ok(resp) => Ok(Some(resp.json().await?)),
                     Err(err) if err.status() == Some(StatusCode::NOT_FOUND) => Ok(None),
-                    Err(err) => Err(err),
+                    Err(err) => Err(err.into()),
                 }
             })
-            .await??)
+            .await?
     }
 }
 
@@ -0,0 +1,155 @@
+use std::time::{Duration, Instant};
+
+use crate::config::CircuitBreakerConfig;
+
+/// Stops sending requests to a service after repeated failures.
+///
+/// The circuit is opened after `failure_threshold` consecutive failures within
+/// `window`. While it is open, requests fail immediately. After `cooldown`, a
+/// single request is let through to probe the service (half-open): if it
+/// succeeds, the circuit is closed again, otherwise it is reopened.
+#[derive(Debug)]
+pub(super) struct CircuitBreaker {
+    failure_threshold: u32,
+    window: Duration,
+    cooldown: Duration,
+    state: State,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    Closed {
+        failures: u32,
+        first_failure: Option<Instant>,
+    },
+    Open {
+        since: Instant,
+    },
+    HalfOpen {
+        since: Instant,
+    },
+}
+
+const CLOSED: State = State::Closed {
+    failures: 0,
+    first_failure: None,
+};
+
+impl CircuitBreaker {
+    pub(super) fn new(config: &CircuitBreakerConfig) -> Self {
+        Self {
+            failure_threshold: config.failure_threshold,
+            window: Duration::from_secs(config.window),
+            cooldown: Duration::from_secs(config.cooldown),
+            state: CLOSED,
+        }
+    }
+
+    /// Return whether a request may be sent at `now`.
+    pub(super) fn allow(&mut self, now: Instant) -> bool {
+        match self.state {
+            State::Closed { .. } => true,
+            // the probe may have been cancelled without reporting its result,
+            // so allow another one after the cooldown
+            State::Open { since } | State::HalfOpen { since } if now - since >= self.cooldown => {
+                self.state = State::HalfOpen { since: now };
+                true
+            }
+            State::Open { .. } | State::HalfOpen { .. } => false,
+        }
+    }
+
+    /// Record the outcome of a request that has been sent at `now`.
+    pub(super) fn record(&mut self, success: bool, now: Instant) {
+        self.state = match (self.state, success) {
+            (_, true) => CLOSED,
+            (State::HalfOpen { .. }, false) => State::Open { since: now },
+            (State::Open { since }, false) => State::Open { since },
+            (
+                State::Closed {
+                    failures,
+                    first_failure,
+                },
+                false,
+            ) => {
+                let (failures, first_failure) = match first_failure {
+                    Some(first) if now - first <= self.window => (failures + 1, first),
+                    _ => (1, now),
+                };
+                if failures >= self.failure_threshold {
+                    State::Open { since: now }
+                } else {
+                    State::Closed {
+                        failures,
+                        first_failure: Some(first_failure),
+                    }
+                }
+            }
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn breaker() -> CircuitBreaker {
+        CircuitBreaker::new(&CircuitBreakerConfig {
+            failure_threshold: 3,
+            window: 60,
+            cooldown: 30,
+        })
+    }
+
+    #[test]
+    fn open_after_consecutive_failures() {
+        let mut cb = breaker();
+        let start = Instant::now();
+        cb.record(false, start);
+        cb.record(false, start + Duration::from_secs(1));
+        assert!(cb.allow(start + Duration::from_secs(2)));
+        cb.record(false, start + Duration::from_secs(2));
+        assert!(!cb.allow(start + Duration::from_secs(3)));
+
+        // a success resets the counter
+        let mut cb = breaker();
+        cb.record(false, start);
+        cb.record(false, start);
+        cb.record(true, start);
+        cb.record(false, start);
+        assert!(cb.allow(start));
+
+        // failures outside of the window are not counted
+        let mut cb = breaker();
+        cb.record(false, start);
+        cb.record(false, start);
+        cb.record(false, start + Duration::from_secs(61));
+        assert!(cb.allow(start + Duration::from_secs(61)));
+    }
+
+    #[test]
+    fn half_open_after_cooldown() {
+        let mut cb = breaker();
+        let start = Instant::now();
+        for _ in 0..3 {
+            cb.record(false, start);
+        }
+        assert!(!cb.allow(start + Duration::from_secs(29)));
+
+        // only a single probe is allowed
+        let probe = start + Duration::from_secs(30);
+        assert!(cb.allow(probe));
+        assert!(!cb.allow(probe));
+
+        // failed probe reopens the circuit
+        cb.record(false, probe);
+        assert!(!cb.allow(probe + Duration::from_secs(29)));
+
+        // successful probe closes it
+        let probe = probe + Duration::from_secs(30);
+        assert!(cb.allow(probe));
+        cb.record(true, probe);
+        assert!(cb.allow(probe));
+        assert!(cb.allow(probe));
+    }
+}
@@ -0,0 +1,89 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use uuid::Uuid;
+
+use super::{Service, ServiceResult};
+
+#[derive(Debug, Clone)]
+pub struct EventsService(Service);
+
+impl EventsService {
+    pub(super) fn new(service: Service) -> Self {
+        Self(service)
+    }
+
+    /// Notify a user that their report has been resolved.
+    pub async fn notify_report_resolved(
+        &self,
+        user_id: Uuid,
+        report: ReportResolved,
+    ) -> ServiceResult<()> {
+        self.0
+            .post(&format!("/notifications/{user_id}"))
+            .json(&Notification::ReportResolved(report))
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+
+    /// Publish a domain event to the events service.
+    pub async fn publish(&self, event: Event) -> ServiceResult<()> {
+        self.0
+            .post("/events")
+            .json(&event)
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", content = "data", rename_all = "snake_case")]
+pub enum Event {
+    SubtaskSolved(SubtaskSolved),
+    ChallengeCreated(ChallengeCreated),
+}
+
+#[derive(Debug, Serialize)]
+pub struct SubtaskSolved {
+    pub user_id: Uuid,
+    pub task_id: Uuid,
+    pub subtask_id: Uuid,
+    pub solved_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ChallengeCreated {
+    pub challenge_id: Uuid,
+    pub category_id: Uuid,
+    pub creator: Uuid,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", content = "data", rename_all = "snake_case")]
+enum Notification {
+    ReportResolved(ReportResolved),
+}
+
+#[derive(Debug, Serialize)]
+pub struct ReportResolved {
+    pub report_id: Uuid,
+    pub task_id: Uuid,
+    pub subtask_id: Uuid,
+    pub resolution: ReportResolution,
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReportResolution {
+    /// The subtask will be revised.
+    Revise,
+    /// The report has been rejected and the reporter has been banned from
+    /// reporting subtasks.
+    BlockReporter,
+    /// The subtask has been deleted and its creator has been banned from
+    /// creating subtasks.
+    BlockCreator,
+}
@@ -1,17 +1,28 @@
-use std::{sync::Arc, time::Duration};
+use std::{
+    borrow::Cow,
+    sync::{Arc, Mutex, MutexGuard},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
 
 use fnct::format::JsonFormatter;
-use reqwest::{Client, Method, RequestBuilder, StatusCode};
+use reqwest::{Client, Method, RequestBuilder, Response, StatusCode};
+use serde::Serialize;
 use thiserror::Error;
 use url::Url;
 
-use self::{auth::AuthService, shop::ShopService, skills::SkillsService};
+use self::{
+    auth::AuthService, circuit_breaker::CircuitBreaker, events::EventsService, shop::ShopService,
+    skills::SkillsService,
+};
 use crate::{
-    jwt::{sign_jwt, InternalAuthToken, JwtSecret},
+    config::CircuitBreakerConfig,
+    jwt::{sign_jwt, InternalAuthToken, JwtError, JwtSecret},
     Cache, CacheError,
 };
 
 pub mod auth;
+mod circuit_breaker;
+pub mod events;
 pub mod shop;
 pub mod skills;
 
@@ -20,10 +31,14 @@ pub struct Services {
     pub auth: AuthService,
     pub skills: SkillsService,
     pub shop: ShopService,
+    pub events: EventsService,
 }
 
 impl Services {
+    /// Create the clients for all services. `issuer` is the name of the
+    /// service that sends the requests.
     pub fn from_config(
+        issuer: &'static str,
         jwt_secret: JwtSecret,
         jwt_ttl: Duration,
         conf: &crate::config::Services,
@@ -32,21 +47,41 @@ impl Services {
         let jwt_config = Arc::new(JwtConfig {
             secret: jwt_secret,
             ttl: jwt_ttl,
+            issuer,
         });
+        let audience = |name: &'static str| match conf.audiences.get(name) {
+            Some(aud) => Cow::Owned(aud.clone()),
+            None => Cow::Borrowed(name),
+        };
         Self {
             auth: AuthService::new(Service::new(
-                "auth",
+                audience("auth"),
                 conf.auth.clone(),
                 Arc::clone(&jwt_config),
                 cache.clone(),
+                &conf.circuit_breaker,
             )),
             skills: SkillsService::new(Service::new(
-                "skills",
+                audience("skills"),
                 conf.skills.clone(),
                 Arc::clone(&jwt_config),
                 cache.clone(),
+                &conf.circuit_breaker,
+            )),
+            shop: ShopService::new(Service::new(
+                audience("shop"),
+                conf.shop.clone(),
+                Arc::clone(&jwt_config),
+                cache.clone(),
+                &conf.circuit_breaker,
+            )),
+            events: EventsService::new(Service::new(
+                audience("events"),
+                conf.events.clone(),
+                jwt_config,
+                cache,
+                &conf.circuit_breaker,
             )),
-            shop: ShopService::new(Service::new("shop", conf.shop.clone(), jwt_config, cache)),
         }
     }
 }
@@ -55,45 +90,112 @@ impl Services {
 struct JwtConfig {
     secret: JwtSecret,
     ttl: Duration,
+    issuer: &'static str,
+}
+
+impl JwtConfig {
+    /// Sign an internal auth token for the service `audience`.
+    fn sign(&self, audience: Cow<'static, str>) -> Result<String, JwtError> {
+        sign_jwt(
+            InternalAuthToken {
+                aud: audience,
+                iss: self.issuer.into(),
+                iat: SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap()
+                    .as_secs(),
+            },
+            &self.secret,
+            self.ttl,
+        )
+    }
 }
 
 #[derive(Debug, Clone)]
 struct Service {
-    name: &'static str,
+    audience: Cow<'static, str>,
     base_url: Url,
     jwt_config: Arc<JwtConfig>,
     cache: Cache,
     json_cache: Cache<JsonFormatter>,
+    circuit_breaker: Arc<Mutex<CircuitBreaker>>,
 }
 
 impl Service {
-    fn new(name: &'static str, base_url: Url, jwt_config: Arc<JwtConfig>, cache: Cache) -> Self {
+    fn new(
+        audience: Cow<'static, str>,
+        base_url: Url,
+        jwt_config: Arc<JwtConfig>,
+        cache: Cache,
+        circuit_breaker: &CircuitBreakerConfig,
+    ) -> Self {
         Self {
-            name,
+            audience,
             base_url,
             jwt_config,
             json_cache: cache.with_formatter(JsonFormatter),
             cache,
+            circuit_breaker: Arc::new(Mutex::new(CircuitBreaker::new(circuit_breaker))),
         }
     }
 
-    fn request(&self, method: Method, path: &str) -> RequestBuilder {
-        let token = sign_jwt(
-            InternalAuthToken {
-                aud: self.name.into(),
-            },
-            &self.jwt_config.secret,
-            self.jwt_config.ttl,
-        )
-        .expect("could not sign internal auth token");
-        Client::new()
+    fn request(&self, method: Method, path: &str) -> ServiceRequest<'_> {
+        let token = self
+            .jwt_config
+            .sign(self.audience.clone())
+            .expect("could not sign internal auth token");
+        let builder = Client::new()
             .request(
                 method,
                 self.base_url
                     .join(&format!("_internal/{}", path.trim_start_matches('/')))
                     .expect("could not build url"),
             )
-            .bearer_auth(token)
+            .bearer_auth(token);
+        ServiceRequest {
+            service: self,
+            builder,
+        }
+    }
+
+    fn circuit_breaker(&self) -> MutexGuard<'_, CircuitBreaker> {
+        // the breaker state is always consistent, so a poisoned lock can be ignored
+        self.circuit_breaker
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+/// A request to another service that is guarded by the service's circuit
+/// breaker.
+struct ServiceRequest<'a> {
+    service: &'a Service,
+    builder: RequestBuilder,
+}
+
+impl ServiceRequest<'_> {
+    fn json<T: Serialize + ?Sized>(mut self, json: &T) -> Self {
+        self.builder = self.builder.json(json);
+        self
+    }
+
+    fn query<T: Serialize + ?Sized>(mut self, query: &T) -> Self {
+        self.builder = self.builder.query(query);
+        self
+    }
+
+    async fn send(self) -> ServiceResult<Response> {
+        if !self.service.circuit_breaker().allow(Instant::now()) {
+            return Err(ServiceError::CircuitOpen);
+        }
+        let response = self.builder.send().await;
+        let success = response
+            .as_ref()
+            .is_ok_and(|response| !response.status().is_server_error());
+        self.service
+            .circuit_breaker()
+            .record(success, Instant::now());
+        Ok(response?)
     }
 }
 
@@ -102,7 +204,7 @@ macro_rules! methods {
         paste::paste! {
             $(
                 #[allow(dead_code)]
-                fn $method(&self, path: &str) -> RequestBuilder {
+                fn $method(&self, path: &str) -> ServiceRequest<'_> {
                     self.request(Method::[< $method:upper >], path)
                 }
             )*
@@ -124,6 +226,34 @@ pub enum ServiceError {
     JsonCacheError(#[from] CacheError<JsonFormatter>),
     #[error("unexpected response status code: {0}")]
     UnexpectedStatusCode(StatusCode),
+    #[error("circuit breaker is open")]
+    CircuitOpen,
 }
 
 pub type ServiceResult<T> = Result<T, ServiceError>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::jwt::verify_jwt;
+
+    #[test]
+    fn internal_auth_token_claims() {
+        let secret = JwtSecret::try_from("secret").unwrap();
+        let config = JwtConfig {
+            secret: secret.clone(),
+            ttl: Duration::from_secs(10),
+            issuer: "challenges",
+        };
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+
+        let token = config.sign("skills-internal".into()).unwrap();
+        let claims = verify_jwt::<InternalAuthToken>(&token, &secret).unwrap();
+        assert_eq!(claims.aud, "skills-internal");
+        assert_eq!(claims.iss, "challenges");
+        assert!((now..=now + 1).contains(&claims.iat));
+    }
+}
@@ -6,7 +6,7 @@ use serde::{Deserialize, Serialize};
 use thiserror::Error;
 use uuid::Uuid;
 
-use super::{Service, ServiceResult};
+use super::{Service, ServiceError, ServiceResult};
 
 #[derive(Debug, Clone)]
 pub struct ShopService(Service);
@@ -41,36 +41,35 @@ impl ShopService {
     }
 
     pub async fn has_premium(&self, user_id: Uuid) -> ServiceResult<bool> {
-        Ok(self
-            .0
+        self.0
             .cache
-            .cached_result(
+            .cached_result::<_, ServiceError, _, _>(
                 key!(user_id),
                 &[],
                 Some(Duration::from_secs(10)),
                 || async {
-                    self.0
+                    Ok(self
+                        .0
                         .get(&format!("/premium/{user_id}"))
                         .send()
                         .await?
                         .error_for_status()?
                         .json()
-                        .await
+                        .await?)
                 },
             )
-            .await??)
+            .await?
     }
 
     pub async fn get_hearts(&self, user_id: Uuid) -> ServiceResult<u32> {
-        Ok(self
-            .0
+        self.0
             .cache
             .cached_result(
                 key!(user_id),
                 &["hearts", &format!("{user_id}")],
                 Some(Duration::from_secs(10)),
                 || async {
-                    Ok::<_, reqwest::Error>(
+                    Ok::<_, ServiceError>(
                         self.0
                             .get(&format!("/hearts/{user_id}"))
                             .send()
@@ -82,7 +81,7 @@ impl ShopService {
                     )
                 },
             )
-            .await??)
+            .await?
     }
 
     pub async fn add_hearts(&self, user_id: Uuid, hearts: i32) -> ServiceResult<bool> {
@@ -6,7 +6,7 @@ use serde::{Deserialize, Serialize};
 use thiserror::Error;
 use uuid::Uuid;
 
-use super::{Service, ServiceResult};
+use super::{Service, ServiceError, ServiceResult};
 
 #[derive(Debug, Clone)]
 pub struct SkillsService(Service);
@@ -17,10 +17,9 @@ impl SkillsService {
     }
 
     pub async fn get_skills(&self) -> ServiceResult<HashMap<String, Skill>> {
-        Ok(self
-            .0
+        self.0
             .cache
-            .cached_result::<_, reqwest::Error, _, _>(key!(), &["skills"], None, || async {
+            .cached_result::<_, ServiceError, _, _>(key!(), &["skills"], None, || async {
                 let skills: Vec<Skill> = self
                     .0
                     .get("/skills")
@@ -34,23 +33,23 @@ impl SkillsService {
                     .map(|skill| (skill.id.clone(), skill))
                     .collect())
             })
-            .await??)
+            .await?
     }
 
     pub async fn get_courses(&self) -> ServiceResult<HashMap<String, Course>> {
-        Ok(self
-            .0
+        self.0
             .cache
-            .cached_result::<_, reqwest::Error, _, _>(key!(), &["courses"], None, || async {
-                self.0
+            .cached_result::<_, ServiceError, _, _>(key!(), &["courses"], None, || async {
+                Ok(self
+                    .0
                     .get("/courses")
                     .send()
                     .await?
                     .error_for_status()?
                     .json()
-                    .await
+                    .await?)
             })
-            .await??)
+            .await?
     }
 
     pub async fn add_skill_progress(
@@ -73,19 +72,19 @@ impl SkillsService {
     }
 
     pub async fn get_skill_levels(&self, user_id: Uuid) -> ServiceResult<HashMap<String, u32>> {
-        Ok(self
-            .0
+        self.0
             .cache
-            .cached_result(key!(user_id), &[], None, || async {
-                self.0
+            .cached_result::<_, ServiceError, _, _>(key!(user_id), &[], None, || async {
+                Ok(self
+                    .0
                     .get(&format!("/skills/{user_id}"))
                     .send()
                     .await?
                     .error_for_status()?
                     .json()
-                    .await
+                    .await?)
             })
-            .await??)
+            .await?
     }
 
     pub async fn get_leaderboard(
@@ -93,46 +92,46 @@ impl SkillsService {
         limit: u64,
         offset: u64,
     ) -> ServiceResult<GlobalLeaderboard> {
-        Ok(self
-            .0
+        self.0
             .json_cache
-            .cached_result(
+            .cached_result::<_, ServiceError, _, _>(
                 key!(limit, offset),
                 &[],
                 Some(Duration::from_secs(10)),
                 || async {
-                    self.0
+                    Ok(self
+                        .0
                         .get("/leaderboard")
                         .query(&[("limit", limit), ("offset", offset)])
                         .send()
                         .await?
                         .error_for_status()?
                         .json()
-                        .await
+                        .await?)
                 },
             )
-            .await??)
+            .await?
     }
 
     pub async fn get_leaderboard_user(&self, user_id: Uuid) -> ServiceResult<Rank> {
-        Ok(self
-            .0
+        self.0
             .cache
-            .cached_result(
+            .cached_result::<_, ServiceError, _, _>(
                 key!(user_id),
                 &[],
                 Some(Duration::from_secs(10)),
                 || async {
-                    self.0
+                    Ok(self
+                        .0
                         .get(&format!("/leaderboard/{user_id}"))
                         .send()
                         .await?
                         .error_for_status()?
                         .json()
-                        .await
+                        .await?)
                 },
             )
-            .await??)
+            .await?
     }
 }
 
@@ -35,6 +35,25 @@ mod m20230815_162457_remove_subtask_fee;
 mod m20230815_203544_remove_subtask_unlocked;
 mod m20230816_173651_retire_subtasks;
 mod m20231014_142202_category_creation_timestamp;
+mod m20240402_101530_question_match_regex;
+mod m20240405_083012_multiple_choice_shuffle;
+mod m20240408_140455_question_attempt_answer;
+mod m20240412_091204_challenge_archived;
+mod m20240415_102317_challenge_position;
+mod m20240417_143822_subtask_tags;
+mod m20240419_081530_submission_idempotency_key;
+mod m20240422_103045_challenge_skill_weights;
+mod m20240424_152210_user_streaks;
+mod m20240426_094512_subtask_max_attempts;
+mod m20240429_110342_verdict_reason_code;
+mod m20240502_084917_coding_challenge_test_seed;
+mod m20240506_093126_challenge_updated_at;
+mod m20240508_141203_reveal_solution_on_solve;
+mod m20240510_102448_coding_challenge_solutions;
+mod m20240513_094211_evaluator_environment;
+mod m20240515_083512_subtask_disabled_timestamp;
+mod m20240517_131045_challenge_listed;
+mod m20240520_091734_submission_rejudge;
 
 #[async_trait::async_trait]
 impl MigratorTrait for Migrator {
@@ -70,6 +89,25 @@ impl MigratorTrait for Migrator {
             Box::new(m20230815_203544_remove_subtask_unlocked::Migration),
             Box::new(m20230816_173651_retire_subtasks::Migration),
             Box::new(m20231014_142202_category_creation_timestamp::Migration),
+            Box::new(m20240402_101530_question_match_regex::Migration),
+            Box::new(m20240405_083012_multiple_choice_shuffle::Migration),
+            Box::new(m20240408_140455_question_attempt_answer::Migration),
+            Box::new(m20240412_091204_challenge_archived::Migration),
+            Box::new(m20240415_102317_challenge_position::Migration),
+            Box::new(m20240417_143822_subtask_tags::Migration),
+            Box::new(m20240419_081530_submission_idempotency_key::Migration),
+            Box::new(m20240422_103045_challenge_skill_weights::Migration),
+            Box::new(m20240424_152210_user_streaks::Migration),
+            Box::new(m20240426_094512_subtask_max_attempts::Migration),
+            Box::new(m20240429_110342_verdict_reason_code::Migration),
+            Box::new(m20240502_084917_coding_challenge_test_seed::Migration),
+            Box::new(m20240506_093126_challenge_updated_at::Migration),
+            Box::new(m20240508_141203_reveal_solution_on_solve::Migration),
+            Box::new(m20240510_102448_coding_challenge_solutions::Migration),
+            Box::new(m20240513_094211_evaluator_environment::Migration),
+            Box::new(m20240515_083512_subtask_disabled_timestamp::Migration),
+            Box::new(m20240517_131045_challenge_listed::Migration),
+            Box::new(m20240520_091734_submission_rejudge::Migration),
         ]
     }
 }
@@ -245,6 +245,7 @@ pub enum ChallengeCategory {
     Title,
     Description,
     CreationTimestamp,
+    UpdatedAt,
 }
 
 #[derive(Iden)]
@@ -254,8 +255,13 @@ pub enum Challenge {
     CategoryId,
     TaskId,
     SkillIds,
+    SkillWeights,
     Title,
     Description,
+    Archived,
+    Position,
+    UpdatedAt,
+    Listed,
 }
 
 #[derive(Iden)]
@@ -294,6 +300,8 @@ pub enum Subtask {
     Fee,
     Enabled,
     Retired,
+    MaxAttempts,
+    DisabledTimestamp,
 }
 
 #[derive(Iden)]
@@ -305,6 +313,7 @@ pub enum MultipleChoice {
     Answers,
     CorrectAnswers,
     SingleChoice,
+    Shuffle,
 }
 
 #[derive(Iden)]
@@ -320,6 +329,9 @@ pub enum CodingChallenge {
     SolutionCode,
     StaticTests,
     RandomTests,
+    TestSeed,
+    RevealSolutionOnSolve,
+    EvaluatorEnvironment,
 }
 
 #[derive(Iden)]
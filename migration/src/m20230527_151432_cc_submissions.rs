@@ -113,6 +113,8 @@ pub enum Submission {
     CreationTimestamp,
     Environment,
     Code,
+    IdempotencyKey,
+    Rejudge,
 }
 
 #[derive(Iden)]
@@ -89,6 +89,7 @@ pub enum Question {
     Digits,
     Punctuation,
     Blocks,
+    MatchRegex,
 }
 
 #[derive(Iden, Clone, Copy)]
@@ -100,4 +101,5 @@ pub enum QuestionAttempt {
     UserId,
     Timestamp,
     Solved,
+    GivenAnswer,
 }
@@ -0,0 +1,36 @@
+use sea_orm_migration::prelude::*;
+
+use crate::m20230621_074711_questions::QuestionAttempt;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(QuestionAttempt::Table)
+                    .add_column(
+                        ColumnDef::new(QuestionAttempt::GivenAnswer)
+                            .text()
+                            .not_null()
+                            .default(""),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(QuestionAttempt::Table)
+                    .drop_column(QuestionAttempt::GivenAnswer)
+                    .to_owned(),
+            )
+            .await
+    }
+}
@@ -0,0 +1,56 @@
+use sea_orm_migration::prelude::*;
+
+use crate::m20230322_163425_challenges_init::Challenge;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Challenge::Table)
+                    .add_column(
+                        ColumnDef::new(Challenge::Position)
+                            .integer()
+                            .not_null()
+                            .default(0),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        // order existing challenges by creation timestamp
+        manager
+            .get_connection()
+            .execute_unprepared(
+                r#"
+                UPDATE challenges_challenges AS c SET position = o.position
+                FROM (
+                    SELECT c.task_id, ROW_NUMBER() OVER (
+                        PARTITION BY c.category_id ORDER BY t.creation_timestamp, c.task_id
+                    ) - 1 AS position
+                    FROM challenges_challenges AS c
+                    JOIN challenges_tasks AS t ON t.id = c.task_id
+                ) AS o
+                WHERE c.task_id = o.task_id
+                "#,
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Challenge::Table)
+                    .drop_column(Challenge::Position)
+                    .to_owned(),
+            )
+            .await
+    }
+}
@@ -0,0 +1,58 @@
+use sea_orm_migration::prelude::*;
+
+use crate::m20230322_163425_challenges_init::Subtask;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(SubtaskTag::Table)
+                    .col(ColumnDef::new(SubtaskTag::SubtaskId).uuid().not_null())
+                    .col(ColumnDef::new(SubtaskTag::Tag).text().not_null())
+                    .primary_key(
+                        Index::create()
+                            .col(SubtaskTag::SubtaskId)
+                            .col(SubtaskTag::Tag),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .from(SubtaskTag::Table, SubtaskTag::SubtaskId)
+                            .to(Subtask::Table, Subtask::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("challenges_subtask_tags_tag_idx")
+                    .table(SubtaskTag::Table)
+                    .col(SubtaskTag::Tag)
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(SubtaskTag::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(Iden)]
+pub enum SubtaskTag {
+    #[iden = "challenges_subtask_tags"]
+    Table,
+    SubtaskId,
+    Tag,
+}
@@ -0,0 +1,45 @@
+use sea_orm_migration::prelude::*;
+
+use crate::m20230527_151432_cc_submissions::Submission;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Submission::Table)
+                    .add_column(ColumnDef::new(Submission::IdempotencyKey).text().null())
+                    .to_owned(),
+            )
+            .await?;
+
+        // sea-query does not support partial indices
+        manager
+            .get_connection()
+            .execute_unprepared(
+                r#"
+                CREATE UNIQUE INDEX challenges_coding_challenge_submissions_idempotency_key_idx
+                ON challenges_coding_challenge_submissions (creator, subtask_id, idempotency_key)
+                WHERE idempotency_key IS NOT NULL
+                "#,
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Submission::Table)
+                    .drop_column(Submission::IdempotencyKey)
+                    .to_owned(),
+            )
+            .await
+    }
+}
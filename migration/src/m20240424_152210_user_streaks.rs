@@ -0,0 +1,50 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(UserStreak::Table)
+                    .col(
+                        ColumnDef::new(UserStreak::UserId)
+                            .uuid()
+                            .not_null()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(UserStreak::CurrentStreak)
+                            .integer()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(UserStreak::LongestStreak)
+                            .integer()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(UserStreak::LastSolvedDate).date().null())
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(UserStreak::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(Iden)]
+pub enum UserStreak {
+    #[iden = "challenges_user_streaks"]
+    Table,
+    UserId,
+    CurrentStreak,
+    LongestStreak,
+    LastSolvedDate,
+}
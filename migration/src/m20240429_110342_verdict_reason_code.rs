@@ -0,0 +1,66 @@
+use sea_orm_migration::{prelude::*, sea_query::extension::postgres::Type};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_type(
+                Type::create()
+                    .as_enum(VerdictReason::Type)
+                    .values([
+                        VerdictReason::WrongOutput,
+                        VerdictReason::PartialOutput,
+                        VerdictReason::Crashed,
+                    ])
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(SubmissionResult::Table)
+                    .add_column(
+                        ColumnDef::new(SubmissionResult::ReasonCode)
+                            .custom(VerdictReason::Type)
+                            .null(),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(SubmissionResult::Table)
+                    .drop_column(SubmissionResult::ReasonCode)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .drop_type(Type::drop().name(VerdictReason::Type).to_owned())
+            .await
+    }
+}
+
+#[derive(Iden)]
+enum SubmissionResult {
+    #[iden = "challenges_coding_challenge_result"]
+    Table,
+    ReasonCode,
+}
+
+#[derive(Iden)]
+enum VerdictReason {
+    #[iden = "challenges_verdict_reason"]
+    Type,
+    WrongOutput,
+    PartialOutput,
+    Crashed,
+}
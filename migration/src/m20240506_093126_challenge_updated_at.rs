@@ -0,0 +1,74 @@
+use sea_orm_migration::prelude::*;
+
+use crate::m20230322_163425_challenges_init::{Challenge, ChallengeCategory};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(ChallengeCategory::Table)
+                    .add_column(
+                        ColumnDef::new(ChallengeCategory::UpdatedAt)
+                            .timestamp()
+                            .not_null()
+                            .default(Expr::current_timestamp()),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Challenge::Table)
+                    .add_column(
+                        ColumnDef::new(Challenge::UpdatedAt)
+                            .timestamp()
+                            .not_null()
+                            .default(Expr::current_timestamp()),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        // existing rows have not been updated since they were created
+        manager
+            .get_connection()
+            .execute_unprepared(
+                r#"
+                UPDATE challenges_challenge_categories SET updated_at = creation_timestamp;
+                UPDATE challenges_challenges AS c SET updated_at = t.creation_timestamp
+                FROM challenges_tasks AS t
+                WHERE t.id = c.task_id;
+                "#,
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Challenge::Table)
+                    .drop_column(Challenge::UpdatedAt)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(ChallengeCategory::Table)
+                    .drop_column(ChallengeCategory::UpdatedAt)
+                    .to_owned(),
+            )
+            .await
+    }
+}
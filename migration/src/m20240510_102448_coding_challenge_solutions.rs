@@ -0,0 +1,50 @@
+use sea_orm_migration::prelude::*;
+
+use crate::m20230322_163425_challenges_init::CodingChallenge;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(Solution::Table)
+                    .col(ColumnDef::new(Solution::SubtaskId).uuid().not_null())
+                    .col(ColumnDef::new(Solution::Position).integer().not_null())
+                    .col(ColumnDef::new(Solution::Environment).text().not_null())
+                    .col(ColumnDef::new(Solution::Code).text().not_null())
+                    .primary_key(
+                        Index::create()
+                            .col(Solution::SubtaskId)
+                            .col(Solution::Position),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .from(Solution::Table, Solution::SubtaskId)
+                            .to(CodingChallenge::Table, CodingChallenge::SubtaskId)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(Solution::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(Iden)]
+enum Solution {
+    #[iden = "challenges_coding_challenge_solutions"]
+    Table,
+    SubtaskId,
+    Position,
+    Environment,
+    Code,
+}
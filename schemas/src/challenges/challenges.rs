@@ -15,6 +15,8 @@ pub struct Category {
     pub description: String,
     /// The creation timestamp of the category
     pub creation_timestamp: DateTime<Utc>,
+    /// The timestamp of the last update of the category
+    pub updated_at: DateTime<Utc>,
 }
 
 #[derive(Debug, Clone, Deserialize)]
@@ -71,6 +73,19 @@ pub struct Challenge {
     pub creation_timestamp: DateTime<Utc>,
     /// The skills of the challenge
     pub skills: Vec<String>,
+    /// The relative weights of the skills (in the same order as `skills`). The
+    /// xp of a subtask are split equally between all skills if this is `null`.
+    pub skill_weights: Option<Vec<u16>>,
+    /// Whether the challenge has been archived and is hidden from normal users
+    pub archived: bool,
+    /// The position of the challenge within its category
+    pub position: i32,
+    /// The timestamp of the last update of the challenge
+    pub updated_at: DateTime<Utc>,
+    /// Whether the challenge is included in the list of challenges of its
+    /// category. Unlisted challenges can still be accessed by their id, unlike
+    /// disabled subtasks or archived challenges.
+    pub listed: bool,
 }
 
 #[derive(Debug, Clone, Deserialize)]
@@ -90,6 +105,17 @@ pub struct CreateChallengeRequest {
     /// The skills of the challenge
     #[oai(validator(max_items = 8, unique_items = true))]
     pub skills: Vec<String>,
+    /// The relative weights of the skills (in the same order as `skills`)
+    #[oai(default, validator(max_items = 8))]
+    pub skill_weights: Option<Vec<u16>>,
+    /// Whether the challenge is included in the list of challenges of its
+    /// category. Unlisted challenges can still be accessed by their id.
+    #[oai(default = "listed_default")]
+    pub listed: bool,
+}
+
+pub(super) fn listed_default() -> bool {
+    true
 }
 
 #[derive(Debug, Clone, Deserialize)]
@@ -97,6 +123,7 @@ pub struct CreateChallengeRequest {
 pub enum CreateChallengeError {
     CategoryNotFound,
     SkillsNotFound(Vec<String>),
+    InvalidSkillWeights,
 }
 
 #[derive(Debug, Clone, Object, Serialize)]
@@ -112,6 +139,14 @@ pub struct UpdateChallengeRequest {
     /// The skills of the challenge
     #[oai(validator(max_items = 8, unique_items = true))]
     pub skills: PatchValue<Vec<String>>,
+    /// The relative weights of the skills (in the same order as `skills`)
+    #[oai(validator(max_items = 8))]
+    pub skill_weights: PatchValue<Option<Vec<u16>>>,
+    /// Whether the challenge has been archived and is hidden from normal users
+    pub archived: PatchValue<bool>,
+    /// Whether the challenge is included in the list of challenges of its
+    /// category. Unlisted challenges can still be accessed by their id.
+    pub listed: PatchValue<bool>,
 }
 
 #[derive(Debug, Clone, Deserialize)]
@@ -120,6 +155,7 @@ pub enum UpdateChallengeError {
     ChallengeNotFound,
     CategoryNotFound,
     SkillsNotFound(Vec<String>),
+    InvalidSkillWeights,
 }
 
 #[derive(Debug, Clone, Deserialize)]
@@ -135,6 +171,7 @@ impl From<challenges_challenge_categories::Model> for Category {
             title: value.title,
             description: value.description,
             creation_timestamp: value.creation_timestamp.and_utc(),
+            updated_at: value.updated_at.and_utc(),
         }
     }
 }
@@ -149,6 +186,13 @@ impl Challenge {
             creator: task.creator,
             creation_timestamp: task.creation_timestamp.and_utc(),
             skills: challenge.skill_ids,
+            skill_weights: challenge
+                .skill_weights
+                .map(|weights| weights.into_iter().map(|x| x as _).collect()),
+            archived: challenge.archived,
+            position: challenge.position,
+            updated_at: challenge.updated_at.and_utc(),
+            listed: challenge.listed,
         }
     }
 }
@@ -1,22 +1,29 @@
 use chrono::{DateTime, Utc};
 use entity::{
-    challenges_coding_challenge_result, challenges_coding_challenge_submissions,
-    challenges_coding_challenges, sea_orm_active_enums::ChallengesVerdict,
+    challenges_coding_challenge_result, challenges_coding_challenge_solutions,
+    challenges_coding_challenge_submissions, challenges_coding_challenges,
+    sea_orm_active_enums::{ChallengesVerdict, ChallengesVerdictReason},
 };
+use lib::config::ContentLimits;
 use poem_ext::patch_value::PatchValue;
 use poem_openapi::{
     types::{ParseFromJSON, ToJSON, Type},
-    Object,
+    Enum, Object,
 };
 use sandkasten_client::schemas::{
     configuration::PublicConfig,
-    programs::{ResourceUsage, RunResult},
+    programs::{BuildRunResult, ResourceUsage, RunResult},
 };
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
 use super::subtasks::{CreateSubtaskRequest, Subtask, UpdateSubtaskRequest};
 
+// keep in sync with the `max_length` validators below
+pub const MAX_EVALUATOR_LEN: usize = 131072;
+pub const MAX_SOLUTION_LEN: usize = 65536;
+pub const MAX_DESCRIPTION_LEN: usize = 32768;
+
 #[derive(Debug, Clone, Object)]
 pub struct QueueStatus {
     /// The number of workers used to process submissions.
@@ -27,6 +34,62 @@ pub struct QueueStatus {
     pub waiting: usize,
 }
 
+#[derive(Debug, Clone, Object)]
+pub struct ValidateEvaluatorRequest {
+    /// The program used to generate test cases and evaluate solutions (at
+    /// most `max_evaluator_len` characters and 131072 bytes).
+    #[oai(validator(max_length = 131072))]
+    pub evaluator: String,
+    /// The environment to run the evaluator in.
+    #[oai(default = "evaluator_environment_default")]
+    pub evaluator_environment: String,
+    /// The task the evaluator is written for. Required unless the user is an
+    /// admin.
+    pub task_id: Option<Uuid>,
+}
+
+#[derive(Debug, Clone, Object)]
+pub struct EvaluatorInfo {
+    /// The version of the evaluator library (`lib.py`).
+    pub lib_version: u32,
+    /// The version of the evaluator template (`template.py`).
+    pub template_version: u32,
+    /// The SHA-256 hash of the evaluator library.
+    pub lib_sha256: String,
+    /// The SHA-256 hash of the evaluator template.
+    pub template_sha256: String,
+}
+
+#[derive(Debug, Clone, Object)]
+pub struct EvaluatorStageResult {
+    /// The stage of the evaluator that has been executed.
+    pub stage: EvaluatorStage,
+    /// Whether the evaluator succeeded and produced valid output.
+    pub ok: bool,
+    /// A description of what went wrong (e.g. the error message of the json
+    /// parser).
+    pub error: Option<String>,
+    /// The raw result of the evaluator run if it failed.
+    pub result: Option<BuildRunResult>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Enum)]
+#[oai(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum EvaluatorStage {
+    /// Listing the examples (`examples`).
+    Examples,
+    /// Generating the input for the first example (`generate`).
+    Generate,
+    /// Preparing a trivial solution (`prepare`).
+    Prepare,
+}
+
+#[derive(Debug, Clone, Object)]
+pub struct RejudgeStatus {
+    /// The number of submissions that have been enqueued.
+    pub queued: usize,
+}
+
 #[derive(Debug, Clone, Object)]
 pub struct CodingChallengeSummary {
     #[oai(flatten)]
@@ -41,6 +104,11 @@ pub struct CodingChallengeSummary {
     pub static_tests: u8,
     /// The number of random tests to run for submission evaluation.
     pub random_tests: u8,
+    /// Whether users can see the solution after they have solved the
+    /// challenge.
+    pub reveal_solution_on_solve: bool,
+    /// The number of hearts the user has to pay for a submission.
+    pub hearts_cost: u32,
 }
 
 #[derive(Debug, Clone, Object)]
@@ -57,6 +125,11 @@ pub struct CodingChallenge {
     pub static_tests: u8,
     /// The number of random tests to run for submission evaluation.
     pub random_tests: u8,
+    /// Whether users can see the solution after they have solved the
+    /// challenge.
+    pub reveal_solution_on_solve: bool,
+    /// The number of hearts the user has to pay for a submission.
+    pub hearts_cost: u32,
 }
 
 #[derive(Debug, Clone, Object, Serialize, Deserialize)]
@@ -71,12 +144,60 @@ pub struct Example {
     pub explanation: Option<String>,
 }
 
+#[derive(Debug, Clone, Object)]
+pub struct RawExample {
+    /// The seed of the example.
+    pub id: String,
+    /// The input generated by the evaluator for this seed.
+    pub input: String,
+}
+
+#[derive(Debug, Clone, Object)]
+pub struct SeedExample {
+    /// The seed.
+    pub seed: String,
+    /// The input generated by the evaluator for this seed.
+    pub input: String,
+    /// The output of the reference solution.
+    pub output: String,
+    /// An optional explanation for the output.
+    pub explanation: Option<String>,
+    /// Whether `input`, `output` or `explanation` have been truncated to
+    /// 65536 bytes.
+    pub truncated: bool,
+}
+
+/// Maximum size of the input, output and explanation of a [`SeedExample`] in
+/// bytes.
+pub const MAX_SEED_EXAMPLE_BYTES: usize = 65536;
+
+// keep in sync with the byte limits in the field docs, which are part of the
+// api docs and therefore cannot link to these constants
+
+/// Maximum size of the description of a coding challenge in bytes.
+pub const MAX_DESCRIPTION_BYTES: usize = 32768;
+/// Maximum size of evaluators and solution code in bytes.
+pub const MAX_CODE_BYTES: usize = 131072;
+/// The environment evaluators are run in unless specified otherwise.
+pub const DEFAULT_EVALUATOR_ENVIRONMENT: &str = "python";
+
+#[derive(Debug, Clone, Object)]
+pub struct OversizedField {
+    /// The name of the field that exceeds its limits.
+    pub field: String,
+    /// The maximum number of characters of the field.
+    pub max_length: usize,
+    /// The maximum size of the field in bytes.
+    pub max_bytes: usize,
+}
+
 #[derive(Debug, Clone, Object)]
 pub struct CreateCodingChallengeRequest {
     #[oai(flatten)]
     pub subtask: CreateSubtaskRequest,
-    /// The challenge description.
-    #[oai(validator(max_length = 16384))]
+    /// The challenge description (at most `max_description_len` characters
+    /// and 32768 bytes).
+    #[oai(validator(max_length = 32768))]
     pub description: String,
     /// The number of milliseconds the solution may run.
     #[oai(validator(minimum(value = "1")))]
@@ -93,25 +214,44 @@ pub struct CreateCodingChallengeRequest {
         validator(minimum(value = "1"), maximum(value = "20"))
     )]
     pub random_tests: u8,
-    /// The program used to generate test cases and evaluate solutions
-    #[oai(validator(max_length = 65536))]
+    /// If set, the seeds of the random tests are derived from this value
+    /// instead of being generated randomly.
+    pub test_seed: Option<u64>,
+    /// Whether users can see the solution after they have solved the
+    /// challenge.
+    #[oai(default)]
+    pub reveal_solution_on_solve: bool,
+    /// The program used to generate test cases and evaluate solutions (at
+    /// most `max_evaluator_len` characters and 131072 bytes).
+    #[oai(validator(max_length = 131072))]
     pub evaluator: String,
+    /// The environment to run the evaluator in.
+    #[oai(default = "evaluator_environment_default")]
+    pub evaluator_environment: String,
     /// The environment to run the solution in.
     pub solution_environment: String,
-    /// The solution code
+    /// The solution code (at most 65536 characters and 131072 bytes).
     #[oai(validator(max_length = 65536))]
     pub solution_code: String,
+    /// Additional reference solutions (e.g. in other environments). The
+    /// challenge is only accepted if all reference solutions pass.
+    #[oai(default, validator(max_items = 8))]
+    pub additional_solutions: Vec<SubmissionContent>,
 }
 fn tests_default() -> u8 {
     10
 }
+fn evaluator_environment_default() -> String {
+    DEFAULT_EVALUATOR_ENVIRONMENT.into()
+}
 
 #[derive(Debug, Clone, Object)]
 pub struct UpdateCodingChallengeRequest {
     #[oai(flatten)]
     pub subtask: UpdateSubtaskRequest,
-    /// The challenge description.
-    #[oai(validator(max_length = 16384))]
+    /// The challenge description (at most `max_description_len` characters
+    /// and 32768 bytes).
+    #[oai(validator(max_length = 32768))]
     pub description: PatchValue<String>,
     /// The number of milliseconds the solution may run.
     #[oai(validator(minimum(value = "1")))]
@@ -125,14 +265,27 @@ pub struct UpdateCodingChallengeRequest {
     /// The number of random tests to run for submission evaluation.
     #[oai(validator(minimum(value = "1"), maximum(value = "20")))]
     pub random_tests: PatchValue<u8>,
-    /// The program used to generate test cases and evaluate solutions
-    #[oai(validator(max_length = 65536))]
+    /// If set, the seeds of the random tests are derived from this value
+    /// instead of being generated randomly.
+    pub test_seed: PatchValue<Option<u64>>,
+    /// Whether users can see the solution after they have solved the
+    /// challenge.
+    pub reveal_solution_on_solve: PatchValue<bool>,
+    /// The program used to generate test cases and evaluate solutions (at
+    /// most `max_evaluator_len` characters and 131072 bytes).
+    #[oai(validator(max_length = 131072))]
     pub evaluator: PatchValue<String>,
+    /// The environment to run the evaluator in.
+    pub evaluator_environment: PatchValue<String>,
     /// The environment to run the solution in.
     pub solution_environment: PatchValue<String>,
-    /// The solution code
+    /// The solution code (at most 65536 characters and 131072 bytes).
     #[oai(validator(max_length = 65536))]
     pub solution_code: PatchValue<String>,
+    /// Additional reference solutions (e.g. in other environments). The
+    /// challenge is only accepted if all reference solutions pass.
+    #[oai(validator(max_items = 8))]
+    pub additional_solutions: PatchValue<Vec<SubmissionContent>>,
 }
 
 #[derive(Debug, Clone, Object)]
@@ -153,15 +306,44 @@ pub struct Submission {
     pub queue_position: Option<usize>,
 }
 
+#[derive(Debug, Clone, Object)]
+pub struct SubmissionBundle {
+    /// The unique identifier of the submission.
+    pub id: Uuid,
+    /// The challenge of the submission.
+    pub subtask_id: Uuid,
+    /// The creator of the submission.
+    pub creator: Uuid,
+    /// The creation timestamp of the submission.
+    pub creation_timestamp: DateTime<Utc>,
+    #[oai(flatten)]
+    pub content: SubmissionContent,
+    /// The evaluation result of the submission.
+    pub result: Option<CheckResult<RunSummary>>,
+    /// The value the seeds of the random tests have been derived from.
+    pub test_seed: Option<u64>,
+}
+
 #[derive(Debug, Clone, Object)]
 pub struct SubmissionContent {
     /// The environment to run the solution in.
     pub environment: String,
-    /// The solution code.
+    /// The solution code (at most 65536 characters and 131072 bytes).
     #[oai(validator(max_length = 65536))]
     pub code: String,
 }
 
+#[derive(Debug, Clone, Object)]
+pub struct SetVerdictRequest {
+    /// The new verdict of the submission.
+    pub verdict: ChallengesVerdict,
+    /// The reason for the verdict.
+    #[oai(validator(max_length = 4096))]
+    pub reason: Option<String>,
+    /// Machine readable reason for the verdict.
+    pub reason_code: Option<ChallengesVerdictReason>,
+}
+
 #[derive(Debug, Clone, Object)]
 pub struct EvaluatorError {
     /// The exit code of the evaluator.
@@ -183,11 +365,22 @@ pub struct RunSummary {
 #[derive(Debug, Clone, Object, Serialize, Deserialize)]
 pub struct CheckResult<T: Send + Sync + Type + ParseFromJSON + ToJSON> {
     pub verdict: ChallengesVerdict,
+    /// Human readable reason for the verdict.
     pub reason: Option<String>,
+    /// Machine readable reason for the verdict.
+    pub reason_code: Option<ChallengesVerdictReason>,
     pub compile: Option<T>,
     pub run: Option<T>,
 }
 
+#[derive(Debug, Clone, Object)]
+pub struct ExampleTestResult {
+    /// The unique identifier of the example.
+    pub example_id: String,
+    /// The result of the solution on this example.
+    pub result: CheckResult<RunResult>,
+}
+
 #[derive(Debug, Clone, Object)]
 pub struct ExecutorConfig {
     /// The maximum `time_limit` in milliseconds.
@@ -196,32 +389,145 @@ pub struct ExecutorConfig {
     pub memory_limit: u64,
 }
 
+#[derive(Debug, Clone, Object)]
+pub struct CodingChallengeStats {
+    /// The total number of judged submissions.
+    pub submissions: u64,
+    /// The number of accepted submissions.
+    pub accepted: u64,
+    /// The fraction of judged submissions that have been accepted (`0` if
+    /// there are no judged submissions).
+    pub pass_rate: f64,
+    /// The statistics per solution environment.
+    pub environments: Vec<EnvironmentStats>,
+}
+
+#[derive(Debug, Clone, Object)]
+pub struct EnvironmentStats {
+    /// The solution environment.
+    pub environment: String,
+    /// The number of judged submissions in this environment.
+    pub submissions: u64,
+    /// The number of accepted submissions in this environment.
+    pub accepted: u64,
+    /// The median number of milliseconds the submissions ran.
+    pub median_run_time: Option<u64>,
+}
+
+#[derive(Debug, Clone, Object)]
+pub struct CodingChallengeLimits {
+    /// The maximum length of an evaluator created by the user.
+    pub max_evaluator_len: usize,
+    /// The maximum length of a solution.
+    pub max_solution_len: usize,
+    /// The maximum length of a challenge description created by the user.
+    pub max_description_len: usize,
+    /// The maximum `time_limit` in milliseconds.
+    pub max_time_limit: u64,
+    /// The maximum `memory_limit` in megabytes.
+    pub max_memory_limit: u64,
+}
+
+#[derive(Debug, Clone, Object)]
+pub struct CodingChallengeEnvironment {
+    /// The id of the environment, which is used as `environment` in
+    /// submissions and as `evaluator_environment` of coding challenges.
+    pub id: String,
+    /// The display name of the environment (e.g. `Rust` or `C++`).
+    pub name: String,
+    /// The version of the environment.
+    pub version: String,
+    /// An example program for this environment.
+    pub example: Option<String>,
+    /// Whether the evaluator library (`lib.py`) is available to evaluators
+    /// running in this environment.
+    pub evaluator_library: bool,
+}
+
 impl CodingChallengeSummary {
-    pub fn from(cc: challenges_coding_challenges::Model, subtask: Subtask) -> Self {
+    pub fn from(
+        cc: challenges_coding_challenges::Model,
+        subtask: Subtask,
+        hearts_cost: u32,
+    ) -> Self {
         Self {
             description: cc.description,
             time_limit: cc.time_limit as _,
             memory_limit: cc.memory_limit as _,
             static_tests: cc.static_tests as _,
             random_tests: cc.random_tests as _,
+            reveal_solution_on_solve: cc.reveal_solution_on_solve,
+            hearts_cost,
             subtask,
         }
     }
 }
 
 impl CodingChallenge {
-    pub fn from(cc: challenges_coding_challenges::Model, subtask: Subtask) -> Self {
+    pub fn from(
+        cc: challenges_coding_challenges::Model,
+        subtask: Subtask,
+        hearts_cost: u32,
+    ) -> Self {
         Self {
             description: cc.description,
             time_limit: cc.time_limit as _,
             memory_limit: cc.memory_limit as _,
             static_tests: cc.static_tests as _,
             random_tests: cc.random_tests as _,
+            reveal_solution_on_solve: cc.reveal_solution_on_solve,
+            hearts_cost,
             subtask,
         }
     }
 }
 
+impl From<Example> for SeedExample {
+    fn from(example: Example) -> Self {
+        let mut input = example.input;
+        let mut output = example.output;
+        let mut explanation = example.explanation;
+        let truncated = truncate(&mut input, MAX_SEED_EXAMPLE_BYTES)
+            | truncate(&mut output, MAX_SEED_EXAMPLE_BYTES)
+            | explanation
+                .as_mut()
+                .is_some_and(|x| truncate(x, MAX_SEED_EXAMPLE_BYTES));
+        Self {
+            seed: example.id,
+            input,
+            output,
+            explanation,
+            truncated,
+        }
+    }
+}
+
+/// Truncate `s` to at most `max_bytes` bytes without splitting a character and
+/// return whether anything has been removed.
+fn truncate(s: &mut String, max_bytes: usize) -> bool {
+    if s.len() <= max_bytes {
+        return false;
+    }
+    let end = (0..=max_bytes)
+        .rev()
+        .find(|&i| s.is_char_boundary(i))
+        .unwrap_or(0);
+    s.truncate(end);
+    true
+}
+
+impl CheckResult<RunResult> {
+    /// Truncate the output of the compile and run steps to at most
+    /// `max_bytes` bytes each.
+    pub fn truncate_output(mut self, max_bytes: usize) -> Self {
+        for result in self.compile.iter_mut().chain(self.run.iter_mut()) {
+            truncate(&mut result.stdout, max_bytes);
+            truncate(&mut result.stderr, max_bytes);
+        }
+        self
+    }
+}
+
 impl From<RunResult> for RunSummary {
     fn from(value: RunResult) -> Self {
         Self {
@@ -237,6 +543,7 @@ impl From<CheckResult<RunResult>> for CheckResult<RunSummary> {
         Self {
             verdict: value.verdict,
             reason: value.reason,
+            reason_code: value.reason_code,
             compile: value.compile.map(Into::into),
             run: value.run.map(Into::into),
         }
@@ -276,6 +583,7 @@ impl From<challenges_coding_challenge_result::Model> for CheckResult<RunSummary>
         Self {
             verdict: value.verdict,
             reason: value.reason,
+            reason_code: value.reason_code,
             compile: summary(
                 value.build_status,
                 value.build_stderr,
@@ -292,6 +600,147 @@ impl From<challenges_coding_challenge_result::Model> for CheckResult<RunSummary>
     }
 }
 
+impl CreateCodingChallengeRequest {
+    /// Return the first field that exceeds its length limit in `limits` or its
+    /// size limit in bytes.
+    pub fn oversized_field(&self, limits: ContentLimits) -> Option<OversizedField> {
+        oversized_field(
+            [
+                (
+                    "description",
+                    Some(&self.description),
+                    limits.description.min(MAX_DESCRIPTION_LEN),
+                    MAX_DESCRIPTION_BYTES,
+                ),
+                (
+                    "evaluator",
+                    Some(&self.evaluator),
+                    limits.evaluator.min(MAX_EVALUATOR_LEN),
+                    MAX_CODE_BYTES,
+                ),
+                (
+                    "solution_code",
+                    Some(&self.solution_code),
+                    MAX_SOLUTION_LEN,
+                    MAX_CODE_BYTES,
+                ),
+            ]
+            .into_iter()
+            .chain(additional_solutions(&self.additional_solutions)),
+        )
+        .map(Into::into)
+    }
+}
+
+impl UpdateCodingChallengeRequest {
+    /// Return the first updated field that exceeds its length limit in
+    /// `limits` or its size limit in bytes.
+    pub fn oversized_field(&self, limits: ContentLimits) -> Option<OversizedField> {
+        fn new(value: &PatchValue<String>) -> Option<&String> {
+            match value {
+                PatchValue::Set(value) => Some(value),
+                PatchValue::Unchanged => None,
+            }
+        }
+        oversized_field(
+            [
+                (
+                    "description",
+                    new(&self.description),
+                    limits.description.min(MAX_DESCRIPTION_LEN),
+                    MAX_DESCRIPTION_BYTES,
+                ),
+                (
+                    "evaluator",
+                    new(&self.evaluator),
+                    limits.evaluator.min(MAX_EVALUATOR_LEN),
+                    MAX_CODE_BYTES,
+                ),
+                (
+                    "solution_code",
+                    new(&self.solution_code),
+                    MAX_SOLUTION_LEN,
+                    MAX_CODE_BYTES,
+                ),
+            ]
+            .into_iter()
+            .chain(match &self.additional_solutions {
+                PatchValue::Set(solutions) => additional_solutions(solutions),
+                PatchValue::Unchanged => additional_solutions(&[]),
+            }),
+        )
+        .map(Into::into)
+    }
+}
+
+impl ValidateEvaluatorRequest {
+    /// Return the first field that exceeds its length or size limit.
+    pub fn oversized_field(&self, limits: ContentLimits) -> Option<OversizedField> {
+        oversized_field([(
+            "evaluator",
+            Some(&self.evaluator),
+            limits.evaluator.min(MAX_EVALUATOR_LEN),
+            MAX_CODE_BYTES,
+        )])
+        .map(Into::into)
+    }
+}
+
+impl From<challenges_coding_challenge_solutions::Model> for SubmissionContent {
+    fn from(value: challenges_coding_challenge_solutions::Model) -> Self {
+        Self {
+            environment: value.environment,
+            code: value.code,
+        }
+    }
+}
+
+impl SubmissionContent {
+    /// Return the name of the first field that exceeds its size limit in bytes.
+    pub fn oversized_field(&self) -> Option<&'static str> {
+        oversized_field([("code", Some(&self.code), MAX_SOLUTION_LEN, MAX_CODE_BYTES)])
+            .map(|(name, _, _)| name)
+    }
+}
+
+impl From<(&'static str, usize, usize)> for OversizedField {
+    fn from((field, max_length, max_bytes): (&'static str, usize, usize)) -> Self {
+        Self {
+            field: field.into(),
+            max_length,
+            max_bytes,
+        }
+    }
+}
+
+fn additional_solutions(
+    solutions: &[SubmissionContent],
+) -> impl Iterator<Item = (&'static str, Option<&String>, usize, usize)> {
+    solutions.iter().map(|solution| {
+        (
+            "additional_solutions",
+            Some(&solution.code),
+            MAX_SOLUTION_LEN,
+            MAX_CODE_BYTES,
+        )
+    })
+}
+
+/// Return the name, the maximum length and the maximum size in bytes of the
+/// first field that exceeds one of its limits.
+fn oversized_field<'a>(
+    fields: impl IntoIterator<Item = (&'static str, Option<&'a String>, usize, usize)>,
+) -> Option<(&'static str, usize, usize)> {
+    fields
+        .into_iter()
+        .find(|(_, value, max_length, max_bytes)| {
+            value.is_some_and(|value| {
+                value.len() > *max_bytes || value.chars().count() > *max_length
+            })
+        })
+        .map(|(name, _, max_length, max_bytes)| (name, max_length, max_bytes))
+}
+
 impl From<PublicConfig> for ExecutorConfig {
     fn from(value: PublicConfig) -> Self {
         Self {
@@ -300,3 +749,32 @@ impl From<PublicConfig> for ExecutorConfig {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn oversized_fields() {
+        let s = |len| "x".repeat(len);
+        let check = |value: String| oversized_field([("field", Some(&value), 4, 8)]);
+        assert_eq!(check(s(4)), None);
+        assert_eq!(check(s(5)), Some(("field", 4, 8)));
+        assert_eq!(check("ü".repeat(4)), None);
+        assert_eq!(check("€".repeat(3)), Some(("field", 4, 8)));
+        assert_eq!(oversized_field([("field", None, 0, 0)]), None);
+    }
+
+    #[test]
+    fn truncate_on_char_boundary() {
+        let mut s = "abc".to_owned();
+        assert!(!truncate(&mut s, 3));
+        assert_eq!(s, "abc");
+        assert!(truncate(&mut s, 2));
+        assert_eq!(s, "ab");
+
+        let mut s = "aü".to_owned();
+        assert!(truncate(&mut s, 2));
+        assert_eq!(s, "a");
+    }
+}
@@ -15,6 +15,14 @@ pub struct CourseTask {
     pub lecture_id: Option<String>,
 }
 
+#[derive(Debug, Clone, Object)]
+pub struct CourseTaskList {
+    /// The requested page of tasks ordered by course id.
+    pub tasks: Vec<CourseTask>,
+    /// The total number of tasks matching the filters.
+    pub total: u64,
+}
+
 #[derive(Debug, Clone, Object)]
 pub struct CreateCourseTaskRequest {
     /// The section this task is associated with
@@ -0,0 +1,266 @@
+use entity::{
+    challenges_challenges, challenges_coding_challenges, challenges_matchings,
+    challenges_multiple_choice_quizes, challenges_questions, challenges_subtasks,
+};
+use poem_openapi::{Enum, Object, Union};
+
+use super::{
+    challenges::listed_default,
+    coding_challenges::{CreateCodingChallengeRequest, SubmissionContent},
+    matchings::CreateMatchingRequest,
+    multiple_choice::{combine_answers, CreateMultipleChoiceQuestionRequest},
+    question::CreateQuestionRequest,
+    subtasks::CreateSubtaskRequest,
+};
+
+/// The current version of the challenge export format. Increment this whenever
+/// the format changes in an incompatible way.
+pub const CHALLENGE_EXPORT_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Object)]
+pub struct ChallengeExport {
+    /// The version of the export format.
+    pub version: u32,
+    /// The title of the challenge
+    #[oai(validator(max_length = 256))]
+    pub title: String,
+    /// The description of the challenge
+    #[oai(validator(max_length = 4096))]
+    pub description: String,
+    /// The skills of the challenge
+    #[oai(validator(max_items = 8, unique_items = true))]
+    pub skills: Vec<String>,
+    /// The relative weights of the skills (in the same order as `skills`)
+    #[oai(default, validator(max_items = 8))]
+    pub skill_weights: Option<Vec<u16>>,
+    /// Whether the challenge is included in the list of challenges of its
+    /// category
+    #[oai(default = "listed_default")]
+    pub listed: bool,
+    /// The subtasks of the challenge
+    pub subtasks: Vec<SubtaskExport>,
+}
+
+#[derive(Debug, Clone, Union)]
+#[oai(discriminator_name = "type", one_of)]
+pub enum SubtaskExport {
+    #[oai(mapping = "coding_challenge")]
+    CodingChallenge(CodingChallengeExport),
+    #[oai(mapping = "matching")]
+    Matching(MatchingExport),
+    #[oai(mapping = "multiple_choice_question")]
+    MultipleChoiceQuestion(MultipleChoiceQuestionExport),
+    #[oai(mapping = "question")]
+    Question(QuestionExport),
+}
+
+#[derive(Debug, Clone, Object)]
+pub struct CodingChallengeExport {
+    #[oai(flatten)]
+    pub challenge: CreateCodingChallengeRequest,
+    /// Whether the subtask is enabled
+    pub enabled: bool,
+    /// Whether the subtask is retired
+    pub retired: bool,
+}
+
+#[derive(Debug, Clone, Object)]
+pub struct MatchingExport {
+    #[oai(flatten)]
+    pub matching: CreateMatchingRequest,
+    /// Whether the subtask is enabled
+    pub enabled: bool,
+    /// Whether the subtask is retired
+    pub retired: bool,
+}
+
+#[derive(Debug, Clone, Object)]
+pub struct MultipleChoiceQuestionExport {
+    #[oai(flatten)]
+    pub mcq: CreateMultipleChoiceQuestionRequest,
+    /// Whether the subtask is enabled
+    pub enabled: bool,
+    /// Whether the subtask is retired
+    pub retired: bool,
+}
+
+#[derive(Debug, Clone, Object)]
+pub struct QuestionExport {
+    #[oai(flatten)]
+    pub question: CreateQuestionRequest,
+    /// Whether the subtask is enabled
+    pub enabled: bool,
+    /// Whether the subtask is retired
+    pub retired: bool,
+}
+
+#[derive(Debug, Clone, Object)]
+pub struct InvalidSubtask {
+    /// The index of the invalid subtask in the list of subtasks.
+    pub index: usize,
+    /// The reason why the subtask is invalid.
+    pub reason: InvalidSubtaskReason,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Enum)]
+#[oai(rename_all = "snake_case")]
+pub enum InvalidSubtaskReason {
+    /// The time limit of the coding challenge is too high.
+    TimeLimitExceeded,
+    /// The memory limit of the coding challenge is too high.
+    MemoryLimitExceeded,
+    /// A field of the coding challenge exceeds its length or size limit.
+    ContentTooLarge,
+    /// The list of examples provided by the evaluator is empty.
+    NoExamples,
+    /// The solution or evaluator environment does not exist.
+    EnvironmentNotFound,
+    /// The evaluator crashed.
+    EvaluatorFailed,
+    /// The evaluator exceeded its time limit.
+    EvaluatorTimeout,
+    /// The evaluator failed to produce valid output.
+    InvalidOutput,
+    /// The sample solution failed on a test case.
+    TestcaseFailed,
+    /// The coding challenge has more test cases than allowed.
+    TooManyTestcases,
+    /// `left` and `right` of the matching do not have the same length.
+    LeftRightDifferentLength,
+    /// The solution of the matching does not have the same length as `left`
+    /// and `right`.
+    SolutionDifferentLength,
+    /// The solution of the matching contains an invalid index.
+    InvalidIndex,
+    /// Not every entry on the right of the matching is matched.
+    RightEntriesNotMatched,
+    /// `left` or `right` of the matching contains the same entry more than
+    /// once.
+    DuplicateEntries,
+    /// The multiple choice question is a single choice question but does not
+    /// have exactly one correct answer.
+    InvalidSingleChoice,
+    /// The multiple choice question does not have any correct answer.
+    InvalidMultipleChoice,
+    /// One of the answers of the question is not a valid regular expression.
+    InvalidRegex,
+    /// One of the answers of the question contains a disallowed character.
+    InvalidChar,
+}
+
+impl ChallengeExport {
+    pub fn from(challenge: challenges_challenges::Model, subtasks: Vec<SubtaskExport>) -> Self {
+        Self {
+            version: CHALLENGE_EXPORT_VERSION,
+            title: challenge.title,
+            description: challenge.description,
+            skills: challenge.skill_ids,
+            skill_weights: challenge
+                .skill_weights
+                .map(|weights| weights.into_iter().map(|x| x as _).collect()),
+            listed: challenge.listed,
+            subtasks,
+        }
+    }
+}
+
+impl CodingChallengeExport {
+    pub fn from(
+        cc: challenges_coding_challenges::Model,
+        additional_solutions: Vec<SubmissionContent>,
+        subtask: challenges_subtasks::Model,
+        tags: Vec<String>,
+    ) -> Self {
+        Self {
+            enabled: subtask.enabled,
+            retired: subtask.retired,
+            challenge: CreateCodingChallengeRequest {
+                subtask: export_subtask(subtask, tags),
+                description: cc.description,
+                time_limit: cc.time_limit as _,
+                memory_limit: cc.memory_limit as _,
+                static_tests: cc.static_tests as _,
+                random_tests: cc.random_tests as _,
+                test_seed: cc.test_seed.map(|x| x as _),
+                reveal_solution_on_solve: cc.reveal_solution_on_solve,
+                evaluator: cc.evaluator,
+                evaluator_environment: cc.evaluator_environment,
+                solution_environment: cc.solution_environment,
+                solution_code: cc.solution_code,
+                additional_solutions,
+            },
+        }
+    }
+}
+
+impl MatchingExport {
+    pub fn from(
+        matching: challenges_matchings::Model,
+        subtask: challenges_subtasks::Model,
+        tags: Vec<String>,
+    ) -> Self {
+        Self {
+            enabled: subtask.enabled,
+            retired: subtask.retired,
+            matching: CreateMatchingRequest {
+                subtask: export_subtask(subtask, tags),
+                left: matching.left,
+                right: matching.right,
+                solution: matching.solution.into_iter().map(|x| x as _).collect(),
+            },
+        }
+    }
+}
+
+impl MultipleChoiceQuestionExport {
+    pub fn from(
+        mcq: challenges_multiple_choice_quizes::Model,
+        subtask: challenges_subtasks::Model,
+        tags: Vec<String>,
+    ) -> Self {
+        Self {
+            enabled: subtask.enabled,
+            retired: subtask.retired,
+            mcq: CreateMultipleChoiceQuestionRequest {
+                subtask: export_subtask(subtask, tags),
+                question: mcq.question,
+                answers: combine_answers(mcq.answers, mcq.correct_answers),
+                single_choice: mcq.single_choice,
+                shuffle: mcq.shuffle,
+            },
+        }
+    }
+}
+
+impl QuestionExport {
+    pub fn from(
+        question: challenges_questions::Model,
+        subtask: challenges_subtasks::Model,
+        tags: Vec<String>,
+    ) -> Self {
+        Self {
+            enabled: subtask.enabled,
+            retired: subtask.retired,
+            question: CreateQuestionRequest {
+                subtask: export_subtask(subtask, tags),
+                question: question.question,
+                answers: question.answers,
+                match_regex: question.match_regex,
+                case_sensitive: question.case_sensitive,
+                ascii_letters: question.ascii_letters,
+                digits: question.digits,
+                punctuation: question.punctuation,
+                blocks: question.blocks,
+            },
+        }
+    }
+}
+
+fn export_subtask(subtask: challenges_subtasks::Model, tags: Vec<String>) -> CreateSubtaskRequest {
+    CreateSubtaskRequest {
+        xp: Some(subtask.xp as _),
+        coins: Some(subtask.coins as _),
+        max_attempts: subtask.max_attempts.map(|x| x as _),
+        tags,
+    }
+}
@@ -35,6 +35,17 @@ pub struct Rank {
     pub rank: u64,
 }
 
+#[derive(Debug, Clone, Object, Serialize, Deserialize)]
+pub struct LeaderboardLanguage {
+    /// The sandkasten environment id of the language.
+    pub environment: String,
+    /// The number of users who have solved at least one coding challenge in
+    /// this language.
+    pub users: u64,
+    /// The total number of coding challenges solved in this language.
+    pub solved: u64,
+}
+
 impl From<services::auth::User> for User {
     fn from(value: services::auth::User) -> Self {
         Self {
@@ -1,6 +1,8 @@
-use entity::challenges_matchings;
+use chrono::{DateTime, Utc};
+use entity::{challenges_matching_attempts, challenges_matchings};
 use poem_ext::patch_value::PatchValue;
-use poem_openapi::Object;
+use poem_openapi::{Enum, Object};
+use uuid::Uuid;
 
 use super::subtasks::{CreateSubtaskRequest, Subtask, UpdateSubtaskRequest};
 
@@ -68,6 +70,21 @@ pub struct UpdateMatchingRequest {
     pub solution: PatchValue<Vec<u8>>,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Enum)]
+#[oai(rename_all = "snake_case")]
+pub enum MatchingSide {
+    Left,
+    Right,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Object)]
+pub struct DuplicateMatchingEntry {
+    /// The list that contains the duplicate entry.
+    pub side: MatchingSide,
+    /// The duplicate entry.
+    pub value: String,
+}
+
 #[derive(Debug, Clone, Object)]
 pub struct SolveMatchingRequest {
     /// For each entry on the left the index of its match on the right.
@@ -81,6 +98,21 @@ pub struct SolveMatchingFeedback {
     pub solved: bool,
     /// The number of correct matches.
     pub correct: usize,
+    /// For each entry in the submitted answer whether it was matched
+    /// correctly.
+    pub correct_indices: Vec<bool>,
+}
+
+#[derive(Debug, Clone, Object)]
+pub struct MatchingAttempt {
+    /// The unique identifier of the attempt.
+    pub id: Uuid,
+    /// The user who submitted the attempt.
+    pub user_id: Uuid,
+    /// The timestamp of the attempt.
+    pub timestamp: DateTime<Utc>,
+    /// Whether the attempt was successful.
+    pub solved: bool,
 }
 
 impl MatchingSummary {
@@ -103,6 +135,17 @@ impl Matching {
     }
 }
 
+impl From<challenges_matching_attempts::Model> for MatchingAttempt {
+    fn from(value: challenges_matching_attempts::Model) -> Self {
+        Self {
+            id: value.id,
+            user_id: value.user_id,
+            timestamp: value.timestamp.and_utc(),
+            solved: value.solved,
+        }
+    }
+}
+
 impl MatchingWithSolution {
     pub fn from(matching: challenges_matchings::Model, subtask: Subtask) -> Self {
         Self {
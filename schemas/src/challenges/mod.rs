@@ -2,8 +2,11 @@
 pub mod challenges;
 pub mod coding_challenges;
 pub mod course_tasks;
+pub mod export;
 pub mod leaderboard;
 pub mod matchings;
 pub mod multiple_choice;
 pub mod question;
+pub mod search;
+pub mod sort;
 pub mod subtasks;
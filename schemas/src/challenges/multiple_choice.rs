@@ -4,6 +4,7 @@ use poem_openapi::{
     types::{ParseFromJSON, ToJSON, Type},
     Object,
 };
+use uuid::Uuid;
 
 use super::subtasks::{CreateSubtaskRequest, Subtask, UpdateSubtaskRequest};
 
@@ -32,6 +33,8 @@ where
     /// Whether this question is a single choice question (exactly one answer is
     /// correct).
     pub single_choice: bool,
+    /// Whether the answers are shuffled for each user.
+    pub shuffle: bool,
 }
 
 #[derive(Debug, Clone, Object)]
@@ -47,6 +50,9 @@ pub struct CreateMultipleChoiceQuestionRequest {
     /// Whether this question is a single choice question (exactly one answer is
     /// correct).
     pub single_choice: bool,
+    /// Whether the answers should be shuffled for each user.
+    #[oai(default)]
+    pub shuffle: bool,
 }
 
 #[derive(Debug, Clone, Object)]
@@ -62,6 +68,8 @@ pub struct UpdateMultipleChoiceQuestionRequest {
     /// Whether this question is a single choice question (exactly one answer is
     /// correct).
     pub single_choice: PatchValue<bool>,
+    /// Whether the answers should be shuffled for each user.
+    pub shuffle: PatchValue<bool>,
 }
 
 #[derive(Debug, Clone, Object)]
@@ -76,7 +84,8 @@ pub struct Answer {
 #[derive(Debug, Clone, Object)]
 pub struct SolveMCQRequest {
     /// For each possible answer exactly one boolean (`true` for "answer is
-    /// correct" or `false` for "answer is incorrect").
+    /// correct" or `false` for "answer is incorrect"), in the order in which
+    /// the answers have been returned to the user.
     pub answers: Vec<bool>,
 }
 
@@ -104,6 +113,7 @@ impl MultipleChoiceQuestion<Answer> {
             question: mcq.question,
             answers: combine_answers(mcq.answers, mcq.correct_answers),
             single_choice: mcq.single_choice,
+            shuffle: mcq.shuffle,
             subtask,
         }
     }
@@ -115,9 +125,30 @@ impl MultipleChoiceQuestion<String> {
             question: mcq.question,
             answers: mcq.answers,
             single_choice: mcq.single_choice,
+            shuffle: mcq.shuffle,
             subtask,
         }
     }
+
+    /// Like [`Self::from`], but presents the answers in the order the given
+    /// user sees them if the question is shuffled.
+    pub fn for_user(
+        mut mcq: challenges_multiple_choice_quizes::Model,
+        subtask: Subtask,
+        user_id: Uuid,
+    ) -> Self {
+        if mcq.shuffle {
+            let mut answers = std::mem::take(&mut mcq.answers)
+                .into_iter()
+                .map(Some)
+                .collect::<Vec<_>>();
+            mcq.answers = answer_order(answers.len(), user_id, subtask.id)
+                .into_iter()
+                .filter_map(|i| answers[i].take())
+                .collect();
+        }
+        Self::from(mcq, subtask)
+    }
 }
 
 pub fn combine_answers(answers: Vec<String>, correct: i64) -> Vec<Answer> {
@@ -148,6 +179,40 @@ pub fn check_answers(answers: &[bool], correct: i64) -> usize {
         .count()
 }
 
+/// Return the order in which the answers of a shuffled question are presented
+/// to a user: `order[i]` is the (storage) index of the answer shown at position
+/// `i`. The order only depends on the user and the subtask, so it is stable
+/// across requests.
+pub fn answer_order(len: usize, user_id: Uuid, subtask_id: Uuid) -> Vec<usize> {
+    let (a, b) = user_id.as_u64_pair();
+    let (c, d) = subtask_id.as_u64_pair();
+    let mut state = a ^ b.rotate_left(16) ^ c.rotate_left(32) ^ d.rotate_left(48);
+    let mut next = move || {
+        // splitmix64
+        state = state.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    };
+
+    let mut order = (0..len).collect::<Vec<_>>();
+    for i in (1..len).rev() {
+        order.swap(i, (next() % (i as u64 + 1)) as usize);
+    }
+    order
+}
+
+/// Map answers submitted in the order returned by [`answer_order`] back to
+/// storage order.
+pub fn unshuffle_answers(answers: &[bool], order: &[usize]) -> Vec<bool> {
+    let mut out = vec![false; answers.len()];
+    for (&answer, &i) in answers.iter().zip(order) {
+        out[i] = answer;
+    }
+    out
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -194,4 +259,27 @@ mod tests {
         assert_eq!(check_answers(&[true, true, true, false], 0b1001), 1);
         assert_eq!(check_answers(&[false, true, true, false], 0b1001), 0);
     }
+
+    #[test]
+    fn test_answer_order() {
+        let user_id = Uuid::new_v4();
+        let subtask_id = Uuid::new_v4();
+        for len in 0..32 {
+            let order = answer_order(len, user_id, subtask_id);
+            assert_eq!(order, answer_order(len, user_id, subtask_id));
+            let mut sorted = order.clone();
+            sorted.sort_unstable();
+            assert_eq!(sorted, (0..len).collect::<Vec<_>>());
+        }
+    }
+
+    #[test]
+    fn test_unshuffle_answers() {
+        let user_id = Uuid::new_v4();
+        let subtask_id = Uuid::new_v4();
+        let answers = [true, false, false, true, true, false, true, false];
+        let order = answer_order(answers.len(), user_id, subtask_id);
+        let shuffled = order.iter().map(|&i| answers[i]).collect::<Vec<_>>();
+        assert_eq!(unshuffle_answers(&shuffled, &order), answers);
+    }
 }
@@ -1,6 +1,8 @@
-use entity::challenges_questions;
+use chrono::{DateTime, Utc};
+use entity::{challenges_question_attempts, challenges_questions};
 use poem_ext::patch_value::PatchValue;
 use poem_openapi::Object;
+use uuid::Uuid;
 
 use super::subtasks::{CreateSubtaskRequest, Subtask, UpdateSubtaskRequest};
 
@@ -50,6 +52,8 @@ pub struct QuestionWithSolution {
     pub question: String,
     // The possible answers to the question.
     pub answers: Vec<String>,
+    // Whether the answers are regular expressions.
+    pub match_regex: bool,
     // Whether the answer is case sensitive.
     pub case_sensitive: bool,
     // Whether the answer can contain letters.
@@ -73,6 +77,10 @@ pub struct CreateQuestionRequest {
     /// The possible answers to the question.
     #[oai(validator(min_items = 1, max_items = 32, max_length = 256))]
     pub answers: Vec<String>,
+    /// Whether the answers are regular expressions which have to match the
+    /// whole (normalized) answer of the user.
+    #[oai(default)]
+    pub match_regex: bool,
     /// Whether the answer is case sensitive.
     pub case_sensitive: bool,
     /// Whether the answer can contain letters.
@@ -97,6 +105,9 @@ pub struct UpdateQuestionRequest {
     /// The possible answers to the question.
     #[oai(validator(min_items = 1, max_items = 32, max_length = 256))]
     pub answers: PatchValue<Vec<String>>,
+    /// Whether the answers are regular expressions which have to match the
+    /// whole (normalized) answer of the user.
+    pub match_regex: PatchValue<bool>,
     /// Whether the answer is case sensitive.
     pub case_sensitive: PatchValue<bool>,
     /// Whether the answer can contain letters.
@@ -122,6 +133,20 @@ pub struct SolveQuestionFeedback {
     pub solved: bool,
 }
 
+#[derive(Debug, Clone, Object)]
+pub struct QuestionAttempt {
+    /// The unique identifier of the attempt.
+    pub id: Uuid,
+    /// The user who submitted the attempt.
+    pub user_id: Uuid,
+    /// The timestamp of the attempt.
+    pub timestamp: DateTime<Utc>,
+    /// Whether the attempt was successful.
+    pub solved: bool,
+    /// The (normalized) answer the user submitted.
+    pub given_answer: String,
+}
+
 impl QuestionSummary {
     pub fn from(question: challenges_questions::Model, subtask: Subtask) -> Self {
         Self {
@@ -155,6 +180,7 @@ impl QuestionWithSolution {
         Self {
             question: question.question,
             answers: question.answers,
+            match_regex: question.match_regex,
             case_sensitive: question.case_sensitive,
             ascii_letters: question.ascii_letters,
             digits: question.digits,
@@ -164,3 +190,15 @@ impl QuestionWithSolution {
         }
     }
 }
+
+impl From<challenges_question_attempts::Model> for QuestionAttempt {
+    fn from(value: challenges_question_attempts::Model) -> Self {
+        Self {
+            id: value.id,
+            user_id: value.user_id,
+            timestamp: value.timestamp.and_utc(),
+            solved: value.solved,
+            given_answer: value.given_answer,
+        }
+    }
+}
@@ -0,0 +1,32 @@
+use poem_openapi::{Object, Union};
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Union)]
+#[oai(discriminator_name = "type", one_of)]
+pub enum SearchResult {
+    /// A global challenge whose title or description matches the query.
+    Challenge(ChallengeSearchResult),
+    /// A coding challenge whose description matches the query.
+    CodingChallenge(CodingChallengeSearchResult),
+}
+
+#[derive(Debug, Clone, Object)]
+pub struct ChallengeSearchResult {
+    /// The id of the challenge (task).
+    pub task_id: Uuid,
+    /// The category of the challenge.
+    pub category_id: Uuid,
+    /// The title of the challenge.
+    pub title: String,
+}
+
+#[derive(Debug, Clone, Object)]
+pub struct CodingChallengeSearchResult {
+    /// The id of the coding challenge (subtask).
+    pub subtask_id: Uuid,
+    /// The id of the parent task.
+    pub task_id: Uuid,
+    /// The category of the parent challenge, if the parent task is a global
+    /// challenge.
+    pub category_id: Option<Uuid>,
+}
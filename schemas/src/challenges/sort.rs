@@ -0,0 +1,28 @@
+use poem_openapi::Enum;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Enum)]
+#[oai(rename_all = "snake_case")]
+pub enum SortBy {
+    /// Sort by the curated position. Subtasks don't have a position and are
+    /// sorted by creation timestamp instead.
+    Position,
+    /// Sort by title. Subtasks don't have a title and are sorted by creation
+    /// timestamp instead.
+    Title,
+    /// Sort by creation timestamp.
+    Created,
+    /// Sort by the number of xp a user gets for completing the subtask(s).
+    Xp,
+    /// Sort by the number of users who have solved the subtask(s).
+    Solved,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Enum)]
+#[oai(rename_all = "snake_case")]
+pub enum SortOrder {
+    /// Ascending order.
+    #[default]
+    Asc,
+    /// Descending order.
+    Desc,
+}
@@ -1,4 +1,4 @@
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, NaiveDate, Utc};
 use entity::{
     challenges_ban, challenges_subtask_reports, challenges_subtasks,
     sea_orm_active_enums::{
@@ -7,7 +7,7 @@ use entity::{
 };
 use poem_ext::patch_value::PatchValue;
 use poem_openapi::{Enum, Object};
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
 #[derive(Debug, Clone, Object)]
@@ -35,6 +35,28 @@ pub struct Subtask {
     pub enabled: bool,
     /// Whether the subtask is retired.
     pub retired: bool,
+    /// The maximum number of attempts a user has to solve this subtask.
+    pub max_attempts: Option<u32>,
+    /// The number of attempts the user has left, if the number of attempts is
+    /// limited.
+    pub remaining_attempts: Option<u32>,
+    /// The tags of the subtask.
+    pub tags: Vec<String>,
+    /// The number of users who rated this subtask positively.
+    pub positive_ratings: u64,
+    /// The number of users who rated this subtask negatively.
+    pub negative_ratings: u64,
+    /// The fraction of users who attempted this subtask but have not solved it
+    /// yet (between `0` and `1`). `null` if not enough users have attempted
+    /// this subtask.
+    pub difficulty: Option<f64>,
+}
+
+/// The number of positive and negative ratings of a subtask.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SubtaskRatings {
+    pub positive: u64,
+    pub negative: u64,
 }
 
 #[derive(Debug, Clone, Object)]
@@ -47,6 +69,19 @@ pub struct CreateSubtaskRequest {
     /// to use the configured default value.
     #[oai(validator(maximum(value = "9223372036854775807")), default)]
     pub coins: Option<u64>,
+    /// The maximum number of attempts a user has to solve this subtask. Omit
+    /// to allow an unlimited number of attempts.
+    #[oai(
+        validator(minimum(value = "1"), maximum(value = "2147483647")),
+        default
+    )]
+    pub max_attempts: Option<u32>,
+    /// The tags of the subtask (e.g. the topics it covers).
+    #[oai(
+        validator(max_items = 16, unique_items = true, min_length = 1, max_length = 32),
+        default
+    )]
+    pub tags: Vec<String>,
 }
 
 #[derive(Debug, Clone, Object)]
@@ -63,6 +98,55 @@ pub struct UpdateSubtaskRequest {
     pub enabled: PatchValue<bool>,
     /// Whether the subtask is retired.
     pub retired: PatchValue<bool>,
+    /// The maximum number of attempts a user has to solve this subtask.
+    #[oai(validator(minimum(value = "1"), maximum(value = "2147483647")))]
+    pub max_attempts: PatchValue<Option<u32>>,
+    /// The tags of the subtask (e.g. the topics it covers).
+    #[oai(validator(max_items = 16, unique_items = true, min_length = 1, max_length = 32))]
+    pub tags: PatchValue<Vec<String>>,
+}
+
+#[derive(Debug, Clone, Object)]
+pub struct MoveSubtaskRequest {
+    /// The task to move the subtask to.
+    pub target_task_id: Uuid,
+}
+
+#[derive(Debug, Clone, Object)]
+pub struct BulkEnableSubtasksRequest {
+    /// The subtasks to update.
+    #[oai(validator(max_items = 1000, unique_items = true))]
+    pub subtask_ids: Vec<Uuid>,
+    /// Whether the subtasks should be enabled and visible to normal users.
+    pub enabled: bool,
+}
+
+#[derive(Debug, Clone, Object)]
+pub struct BulkEnableSubtasksResult {
+    /// Number of subtasks that have been updated.
+    pub updated: u64,
+    /// Number of subtask ids that do not exist.
+    pub not_found: u64,
+}
+
+#[derive(Debug, Clone, Object)]
+pub struct GetSubtaskStatesRequest {
+    /// The subtasks to return the states of.
+    #[oai(validator(max_items = 200, unique_items = true))]
+    pub subtask_ids: Vec<Uuid>,
+}
+
+#[derive(Debug, Clone, Object)]
+pub struct SubtaskState {
+    /// Whether the user can currently solve this subtask. Retired subtasks are
+    /// only unlocked for users who have already started them.
+    pub unlocked: bool,
+    /// Whether the user has completed this subtask.
+    pub solved: bool,
+    /// Whether the user has submitted feedback or reported this subtask.
+    pub rated: bool,
+    /// The number of times the user has attempted to solve this subtask.
+    pub attempts: u64,
 }
 
 #[derive(Debug, Clone, Object)]
@@ -80,6 +164,14 @@ pub struct SubtaskStats {
     pub unattempted: u64,
 }
 
+#[derive(Debug, Clone, Object)]
+pub struct SubtaskStatsByType {
+    pub coding_challenge: SubtaskStats,
+    pub matching: SubtaskStats,
+    pub multiple_choice_question: SubtaskStats,
+    pub question: SubtaskStats,
+}
+
 #[derive(Debug, Clone, Object)]
 pub struct PostFeedbackRequest {
     pub rating: ChallengesRating,
@@ -97,6 +189,34 @@ pub struct Report {
     pub comment: String,
 }
 
+#[derive(Debug, Clone, Object)]
+pub struct ReportList {
+    /// The requested page of reports in descending order of timestamp.
+    pub reports: Vec<Report>,
+    /// The total number of reports matching the filters.
+    pub total: u64,
+}
+
+#[derive(Debug, Clone, Object)]
+pub struct ReportStats {
+    /// The total number of open reports. Resolved reports are deleted and
+    /// therefore not counted.
+    pub open: u64,
+    /// The number of open reports per reason.
+    pub reasons: ReportReasonCounts,
+    /// The number of subtasks that are disabled because of open reports.
+    pub disabled_subtasks: u64,
+}
+
+#[derive(Debug, Clone, Default, Object)]
+pub struct ReportReasonCounts {
+    pub abuse: u64,
+    pub dislike: u64,
+    pub other: u64,
+    pub unrelated_skill: u64,
+    pub wrong: u64,
+}
+
 #[derive(Debug, Clone, Object)]
 pub struct CreateReportRequest {
     pub task_id: Uuid,
@@ -119,6 +239,39 @@ pub enum ResolveReportAction {
     BlockCreator,
 }
 
+#[derive(Debug, Clone, Object)]
+pub struct UserStreak {
+    /// The number of consecutive days (UTC) on which the user has solved at
+    /// least one subtask, including today or yesterday.
+    pub current_streak: u32,
+    /// The longest streak the user has ever reached.
+    pub longest_streak: u32,
+    /// The last day on which the user has solved a subtask.
+    pub last_solved_date: Option<NaiveDate>,
+}
+
+#[derive(Debug, Clone, Object, Serialize, Deserialize)]
+pub struct UserStats {
+    /// The total number of xp the user has earned by solving subtasks.
+    pub xp: u64,
+    /// The total number of morphcoins the user has earned by solving subtasks.
+    pub coins: u64,
+    /// The number of subtasks the user has solved.
+    pub solved: SolvedSubtasks,
+    /// The number of coding challenge submissions of the user.
+    pub submissions: u64,
+}
+
+#[derive(Debug, Clone, Default, Object, Serialize, Deserialize)]
+pub struct SolvedSubtasks {
+    /// The total number of solved subtasks.
+    pub total: u64,
+    pub coding_challenges: u64,
+    pub matchings: u64,
+    pub multiple_choice_questions: u64,
+    pub questions: u64,
+}
+
 #[derive(Debug, Clone, Object, Deserialize)]
 pub struct SubtasksUserConfig {
     /// The minimum level a normal user needs to have in each skill related to a
@@ -181,6 +334,56 @@ pub struct UpdateBanRequest {
     pub reason: PatchValue<String>,
 }
 
+#[derive(Debug, Clone, Object)]
+pub struct UnbanResult {
+    /// Number of active bans that have been lifted.
+    pub lifted: u64,
+}
+
+#[derive(Debug, Clone, Object)]
+pub struct ResetProgressResult {
+    /// Number of deleted progress entries (`0` or `1`).
+    pub progress: u64,
+    /// Number of deleted attempts of matchings, questions and multiple choice
+    /// questions.
+    pub attempts: u64,
+    /// Number of deleted coding challenge submissions.
+    pub submissions: u64,
+    /// Whether the xp and coins the user has received for solving the subtask
+    /// have been revoked.
+    pub rewards_revoked: bool,
+}
+
+#[derive(Debug, Clone, Object)]
+pub struct SubtaskRewards {
+    /// Whether the user would receive the rewards for solving the subtask.
+    /// Retired subtasks and subtasks created by the user don't grant any
+    /// rewards.
+    pub rewarded: bool,
+    /// The total number of xp for solving the subtask.
+    pub xp: u64,
+    /// The number of morphcoins for solving the subtask.
+    pub coins: u64,
+    /// The number of xp per skill.
+    pub skills: Vec<SkillReward>,
+}
+
+#[derive(Debug, Clone, Object)]
+pub struct SkillReward {
+    /// The id of the skill.
+    pub skill: String,
+    /// The number of xp for this skill.
+    pub xp: u64,
+}
+
+#[derive(Debug, Clone, Object)]
+pub struct RecomputeAttemptsResult {
+    /// Number of progress entries that have been checked.
+    pub checked: u64,
+    /// Number of progress entries whose attempt counter has been raised.
+    pub corrected: u64,
+}
+
 impl Report {
     pub fn from(
         report: challenges_subtask_reports::Model,
@@ -200,7 +403,15 @@ impl Report {
 }
 
 impl Subtask {
-    pub fn from(subtask: challenges_subtasks::Model, solved: bool, rated: bool) -> Self {
+    pub fn from(
+        subtask: challenges_subtasks::Model,
+        solved: bool,
+        rated: bool,
+        attempts: usize,
+        tags: Vec<String>,
+        ratings: SubtaskRatings,
+    ) -> Self {
+        let max_attempts = subtask.max_attempts.map(|x| x as u32);
         Self {
             id: subtask.id,
             task_id: subtask.task_id,
@@ -213,6 +424,13 @@ impl Subtask {
             rated,
             enabled: subtask.enabled,
             retired: subtask.retired,
+            max_attempts,
+            remaining_attempts: max_attempts
+                .map(|max| max.saturating_sub(attempts.try_into().unwrap_or(u32::MAX))),
+            tags,
+            positive_ratings: ratings.positive,
+            negative_ratings: ratings.negative,
+            difficulty: None,
         }
     }
 }